@@ -0,0 +1,125 @@
+//! Time to draw representative maps into a headless frame at several resolutions,
+//! `cargo bench --bench walls --features render`. Prints the milliseconds each frame
+//! took as a whole and in `draw_wall_system`, from [`RenderStats::walls`], to compare
+//! against a run before a change.
+
+use sector::{generate::*, prelude::*, render::*};
+
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Frames drawn before timing, for the map and frame to settle.
+const WARM_UP_FRAMES: u32 = 10;
+const FRAMES: u32 = 100;
+const SEED: u64 = 7;
+const RESOLUTIONS: [(u32, u32); 3] = [(320, 180), (640, 360), (1280, 720)];
+
+struct Scene {
+    name: &'static str,
+    /// Generated again for each resolution.
+    sectors: fn() -> Vec<Sector>,
+    direction: f32,
+}
+
+fn scenes() -> Vec<Scene> {
+    vec![
+        Scene {
+            name: "small",
+            sectors: || generate_map(SEED),
+            direction: 0.0,
+        },
+        // Looking down a corridor, every room drawn through the portals before it
+        Scene {
+            name: "corridor",
+            sectors: || generate_maze(SEED, 1, DEFAULT_MAX_PORTAL_DEPTH as i32),
+            direction: 0.0,
+        },
+        // Looking across the maze from a corner
+        Scene {
+            name: "maze",
+            sectors: || generate_maze(SEED, 32, 32),
+            direction: -std::f32::consts::FRAC_PI_4,
+        },
+    ]
+}
+
+/// Headless app drawing scene into an [`ImageFrame`] of width by height, from a
+/// quarter of a room back from the middle of its first sector. From the very middle
+/// the edges of the view pass through the room's corners.
+fn app(scene: &Scene, width: u32, height: u32) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(bevy::scene::ScenePlugin)
+        .add_plugin(SectorPlugin)
+        .add_plugin(SectorRenderPlugin::<ImageFrame>::default())
+        .insert_resource(RenderConfig::new(width, height, FOV_X_RADIANS));
+
+    let sectors = (scene.sectors)();
+    let first = &sectors[0];
+    let centre = first.vertices.iter().map(|v| v.0).sum::<Vec2>() / first.vertices.len() as f32;
+    let forward = Vec2::new(-scene.direction.sin(), scene.direction.cos());
+    let position = centre - forward * ROOM_SIZE / 4.0;
+    *app.world.resource_mut::<View>() = View {
+        position: Position3(position.extend(first.floor.0 + EYE_HEIGHT)),
+        direction: Direction(scene.direction),
+        pitch: 0.0,
+        current_sector: Some(first.id),
+    };
+    for sector in sectors {
+        app.world.spawn(sector);
+    }
+    app.world.spawn(ImageFrame::new(width, height));
+    // The view never moves, so cached walls would be all that's timed
+    app.world.resource_mut::<WallCache>().enabled = false;
+    app
+}
+
+/// Average time of a whole frame and of drawing its walls, over [`FRAMES`].
+fn bench(app: &mut App) -> (Duration, Duration) {
+    for _ in 0..WARM_UP_FRAMES {
+        app.update();
+    }
+    let (mut frame, mut walls) = (Duration::ZERO, Duration::ZERO);
+    for _ in 0..FRAMES {
+        let start = Instant::now();
+        app.update();
+        frame += start.elapsed();
+        walls += app.world.resource::<RenderStats>().walls;
+    }
+    (frame / FRAMES, walls / FRAMES)
+}
+
+fn main() {
+    // Only benches named on the command line, when any are
+    let filters: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} {:>8} {:>8}",
+        "scene", "resolution", "frame ms", "walls ms", "walls", "sectors"
+    );
+    for scene in scenes() {
+        for (width, height) in RESOLUTIONS {
+            let name = format!("{}/{width}x{height}", scene.name);
+            if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+                continue;
+            }
+
+            let mut app = app(&scene, width, height);
+            let (frame, walls) = bench(&mut app);
+            let stats = app.world.resource::<RenderStats>();
+            println!(
+                "{:<10} {:>10} {:>10.3} {:>10.3} {:>8} {:>8}",
+                scene.name,
+                format!("{width}x{height}"),
+                frame.as_secs_f64() * 1000.0,
+                walls.as_secs_f64() * 1000.0,
+                stats.walls_drawn,
+                stats.sectors_visited,
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{time_scale::*, *};
+
+use palette::{Hsv, IntoColor, Srgb};
+
+/// Part of a sector an [`Animated`] color is shown on.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum AnimatedSurface {
+    /// Wall by index within its sector.
+    Wall(usize),
+    #[default]
+    Floor,
+    Ceiling,
+}
+
+/// How an [`Animated`] color changes over its period.
+#[derive(Reflect, FromReflect, Debug, Clone, PartialEq)]
+pub enum ColorCycle {
+    /// Hue of base turned a full circle, as for glowing panels.
+    Hue { base: RawColor },
+    /// Each of colors in turn, held for an equal share of the period, as for warning
+    /// strips.
+    Steps { colors: Vec<RawColor> },
+}
+
+impl Default for ColorCycle {
+    fn default() -> Self {
+        Self::Hue {
+            base: RawColor([0xff, 0x40, 0x00]),
+        }
+    }
+}
+
+/// Color of a wall, floor or ceiling of sector cycling without a texture, such as
+/// lava or a computer panel.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct Animated {
+    pub sector: SectorId,
+    pub surface: AnimatedSurface,
+    pub cycle: ColorCycle,
+    /// Seconds for a full cycle.
+    pub period: f32,
+    /// Scaled seconds animated for, kept so animation persists with the map.
+    pub elapsed: f32,
+}
+
+impl Default for Animated {
+    fn default() -> Self {
+        Self {
+            sector: SectorId::default(),
+            surface: AnimatedSurface::default(),
+            cycle: ColorCycle::default(),
+            period: 2.0,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl Animated {
+    /// Color after elapsed, `None` for steps through no colors.
+    pub fn color(&self) -> Option<RawColor> {
+        let t = (self.elapsed / self.period.max(f32::EPSILON)).fract();
+        match &self.cycle {
+            ColorCycle::Hue { base } => {
+                let mut hsv: Hsv = Srgb::<u8>::from(*base).into_format().into_color();
+                hsv.hue += 360.0 * t;
+                Some(hsv.into())
+            }
+            ColorCycle::Steps { colors } => {
+                let step = (t * colors.len() as f32) as usize;
+                colors
+                    .get(step.min(colors.len().saturating_sub(1)))
+                    .copied()
+            }
+        }
+    }
+}
+
+/// Cycle the colors of walls, floors and ceilings with an [`Animated`].
+pub fn animated_system(
+    mut animated_query: Query<&mut Animated>,
+    mut sector_query: Query<&mut Sector>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+) {
+    let delta = time_scale.delta_seconds(&time);
+
+    for mut animated in &mut animated_query {
+        animated.elapsed += delta;
+        let Some(color) = animated.color() else { continue };
+        let sector = sector_query.iter_mut().find(|s| s.id == animated.sector);
+        let Some(mut sector) = sector else { continue };
+
+        // As with light, colors change nothing about geometry, so blockmap and
+        // validation which follow changes to sectors needn't rerun every frame
+        let sector = sector.bypass_change_detection();
+        match animated.surface {
+            AnimatedSurface::Wall(wall) => {
+                if let Some(wall_color) = sector.colors.get_mut(wall) {
+                    *wall_color = color;
+                }
+            }
+            AnimatedSurface::Floor => sector.floor_color = Some(color),
+            AnimatedSurface::Ceiling => sector.ceiling_color = Some(color),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{time_scale::*, *};
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+/// FNV-1a hash of simulation state. Unlike std's hashers its output is fixed across
+/// Rust versions and platforms, and floats are hashed by their bits so any
+/// difference between runs shows.
+#[derive(Debug, Copy, Clone)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHasher {
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_vec2(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hash of each of a set of values, combined regardless of their order, for state
+/// from queries whose iteration order isn't fixed.
+pub fn unordered_hash<T>(
+    values: impl IntoIterator<Item = T>,
+    hash: impl Fn(&T, &mut StateHasher),
+) -> u64 {
+    let mut hashes: Vec<u64> = values
+        .into_iter()
+        .map(|value| {
+            let mut hasher = StateHasher::default();
+            hash(&value, &mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    hashes.sort_unstable();
+
+    let mut hasher = StateHasher::default();
+    for value in hashes {
+        hasher.write_u64(value);
+    }
+    hasher.finish()
+}
+
+/// Writes a hash of simulation state every tick to a trace file, one line per tick
+/// with the hash of each part of the state, so traces of the same inputs from
+/// different runs or platforms can be compared with [`first_divergence`]. Sectors,
+/// things and time scale are recorded by [`determinism_audit_system`], games
+/// [`record`](Self::record) anything else, such as the player, before it.
+#[derive(Resource)]
+pub struct DeterminismAudit {
+    writer: BufWriter<File>,
+    tick: u64,
+    parts: Vec<(&'static str, u64)>,
+}
+
+impl DeterminismAudit {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            tick: 0,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Add a part of the state to this tick's line.
+    pub fn record(&mut self, name: &'static str, hash: u64) {
+        self.parts.push((name, hash));
+    }
+
+    fn end_tick(&mut self) -> io::Result<()> {
+        let mut total = StateHasher::default();
+        let mut line = String::new();
+        for (name, hash) in &self.parts {
+            total.write_u64(*hash);
+            let _ = write!(line, " {name}={hash:016x}");
+        }
+        // Flushed every tick so the trace is complete however the game exits
+        writeln!(self.writer, "{} {:016x}{line}", self.tick, total.finish())?;
+        self.writer.flush()?;
+
+        self.parts.clear();
+        self.tick += 1;
+        Ok(())
+    }
+}
+
+/// Where two traces first differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub tick: u64,
+    /// Parts that differ, empty when only one trace has the tick.
+    pub parts: Vec<String>,
+}
+
+/// First tick two traces written by [`DeterminismAudit`] differ at, if any.
+pub fn first_divergence(a: &str, b: &str) -> Option<Divergence> {
+    let parse = |line: &str| -> (u64, Vec<String>) {
+        let mut words = line.split_whitespace();
+        let tick = words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .unwrap_or(u64::MAX);
+        (tick, words.skip(1).map(str::to_string).collect())
+    };
+
+    let (mut a_lines, mut b_lines) = (a.lines(), b.lines());
+    loop {
+        match (a_lines.next(), b_lines.next()) {
+            (None, None) => return None,
+            (Some(a), Some(b)) if a == b => {}
+            (Some(a), Some(b)) => {
+                let ((tick, a_parts), (_, b_parts)) = (parse(a), parse(b));
+                let parts = a_parts
+                    .iter()
+                    .filter(|part| !b_parts.contains(part))
+                    .filter_map(|part| part.split('=').next())
+                    .map(str::to_string)
+                    .collect();
+                return Some(Divergence { tick, parts });
+            }
+            (Some(line), None) | (None, Some(line)) => {
+                return Some(Divergence {
+                    tick: parse(line).0,
+                    parts: Vec::new(),
+                })
+            }
+        }
+    }
+}
+
+/// Record sectors, things and time scale, then write the tick's line.
+pub fn determinism_audit_system(
+    audit: Option<ResMut<DeterminismAudit>>,
+    time_scale: Res<TimeScale>,
+    sector_query: Query<&Sector>,
+    thing_query: Query<&Thing>,
+) {
+    let Some(mut audit) = audit else { return };
+
+    let sectors = unordered_hash(&sector_query, |sector, hasher| {
+        hasher.write_u32(sector.id.0);
+        for vertex in &sector.vertices {
+            hasher.write_vec2(vertex.0);
+        }
+        for portal_sector in &sector.portal_sectors {
+            hasher.write_u32(portal_sector.map(|id| id.0 + 1).unwrap_or(0));
+        }
+        hasher.write_f32(sector.floor.0);
+        hasher.write_f32(sector.ceil.0);
+        hasher.write_f32(sector.bridge.map(|bridge| bridge.0).unwrap_or(f32::NAN));
+    });
+    audit.record("sectors", sectors);
+
+    let things = unordered_hash(&thing_query, |thing, hasher| {
+        hasher.write_vec2(thing.position.0);
+        hasher.write_f32(thing.radius.0);
+        hasher.write_f32(thing.height.0);
+    });
+    audit.record("things", things);
+
+    let mut hasher = StateHasher::default();
+    hasher.write_f32(time_scale.factor());
+    audit.record("time_scale", hasher.finish());
+
+    if let Err(error) = audit.end_tick() {
+        warn!("failed to write determinism trace: {error}");
+    }
+}
@@ -0,0 +1,142 @@
+use crate::walk::*;
+use sector::{music::*, prelude::*};
+
+use bevy::{prelude::*, utils::HashMap};
+
+/// Distance walked between footsteps.
+const STRIDE: f32 = 1.2;
+/// Distance moved in a frame beyond which the view is taken to have teleported,
+/// rather than walked.
+const MAX_STEP: f32 = 2.0;
+/// Height above its resting height the eye can be at and still be on the floor.
+const ON_FLOOR: f32 = 0.05;
+const FOOTSTEP_VOLUME: f32 = 0.5;
+/// Distance between the ears of the listener spatial sinks pan emitters for.
+const EAR_GAP: f32 = 1.0;
+
+/// Play a footstep for the floor underfoot each stride walked on it.
+pub fn footstep_system(
+    mut walked: Local<f32>,
+    mut last_position: Local<Option<Vec2>>,
+    view: Res<View>,
+    movement_mode: Res<MovementMode>,
+    audio: Res<Audio>,
+    asset_server: Res<AssetServer>,
+    sector_query: Query<&Sector>,
+) {
+    let position = view.position.truncate().0;
+    let moved = last_position.map_or(0.0, |last| last.distance(position));
+    *last_position = Some(position);
+
+    let sector = view
+        .current_sector
+        .and_then(|id| sector_query.iter().find(|s| s.id == id));
+    let on_floor = sector
+        .filter(|sector| view.position.0.z - rest_height(sector, view.position.0.z) < ON_FLOOR);
+    let sector = match (*movement_mode, on_floor) {
+        (MovementMode::Walk, Some(sector)) => sector,
+        _ => {
+            *walked = 0.0;
+            return;
+        }
+    };
+    if moved > MAX_STEP {
+        return;
+    }
+
+    *walked += moved;
+    if *walked >= STRIDE {
+        *walked -= STRIDE;
+        audio.play_with_settings(
+            asset_server.load(sector.floor_material.footstep_sound()),
+            PlaybackSettings::ONCE.with_volume(FOOTSTEP_VOLUME),
+        );
+    }
+}
+
+/// Play the tracks of each sound zone layer, looped at their crossfade volumes.
+pub fn sound_zone_playback_system(
+    mut sinks: Local<HashMap<(SoundLayer, String), Handle<AudioSink>>>,
+    sound_state: Res<SoundState>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut playing = HashMap::new();
+    for layer in [SoundLayer::Music, SoundLayer::Ambience] {
+        for track in sound_state.layer(layer).tracks() {
+            let key = (layer, track.track.clone());
+            let sink = sinks.remove(&key).unwrap_or_else(|| {
+                audio_sinks.get_handle(audio.play_with_settings(
+                    asset_server.load(&track.track),
+                    PlaybackSettings::LOOP.with_volume(track.volume),
+                ))
+            });
+            if let Some(sink) = audio_sinks.get(&sink) {
+                sink.set_volume(track.volume);
+            }
+            playing.insert(key, sink);
+        }
+    }
+
+    // Stop tracks faded out
+    for sink in sinks.values() {
+        if let Some(sink) = audio_sinks.get(sink) {
+            sink.stop();
+        }
+    }
+    *sinks = playing;
+}
+
+/// Play each emitter's sound looped, following its mix. Spatial sinks are only used
+/// to pan, each emitter placed a unit from the listener on the side of its pan, with
+/// distance and portals heard through its gain.
+pub fn sound_emitter_playback_system(
+    mut sinks: Local<HashMap<Entity, (String, Handle<SpatialAudioSink>)>>,
+    audio: Res<Audio>,
+    spatial_audio_sinks: Res<Assets<SpatialAudioSink>>,
+    asset_server: Res<AssetServer>,
+    emitter_query: Query<(Entity, &SoundEmitter, &EmitterMix)>,
+) {
+    let pan_position = |mix: &EmitterMix| {
+        let pan = mix.pan.clamp(-1.0, 1.0);
+        Vec3::new(pan, 0.0, -(1.0 - pan * pan).sqrt())
+    };
+
+    let mut playing = HashMap::new();
+    for (entity, emitter, mix) in &emitter_query {
+        let sink = match sinks.remove(&entity) {
+            Some((sound, sink)) if sound == emitter.sound => sink,
+            other => {
+                if let Some(sink) = other.and_then(|(_, sink)| spatial_audio_sinks.get(&sink)) {
+                    sink.stop();
+                }
+                spatial_audio_sinks.get_handle(
+                    audio.play_spatial_with_settings(
+                        asset_server.load(&emitter.sound),
+                        PlaybackSettings::LOOP
+                            .with_volume(mix.gain)
+                            .with_speed(mix.pitch),
+                        Transform::IDENTITY,
+                        EAR_GAP,
+                        pan_position(mix),
+                    ),
+                )
+            }
+        };
+        if let Some(sink) = spatial_audio_sinks.get(&sink) {
+            sink.set_volume(mix.gain);
+            sink.set_speed(mix.pitch);
+            sink.set_emitter_position(pan_position(mix));
+        }
+        playing.insert(entity, (emitter.sound.clone(), sink));
+    }
+
+    // Stop emitters since despawned
+    for (_, sink) in sinks.values() {
+        if let Some(sink) = spatial_audio_sinks.get(sink) {
+            sink.stop();
+        }
+    }
+    *sinks = playing;
+}
@@ -0,0 +1,344 @@
+use crate::{input::*, photo::*};
+use sector::{mover::*, music::*, prelude::*, render::*};
+
+use bevy::{prelude::*, window::ReceivedCharacter};
+
+/// Command line opened with the grave key, shown in the window title while open.
+#[derive(Resource, Debug, Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+}
+
+/// Command entered into the console, split on whitespace.
+#[derive(Debug, Clone)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ConsoleCommand {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(str::to_string);
+        Some(Self {
+            name: words.next()?,
+            args: words.collect(),
+        })
+    }
+
+    /// Argument at index parsed as T, logging a warning when missing or invalid.
+    pub fn arg<T: std::str::FromStr>(&self, index: usize) -> Option<T> {
+        let parsed = self.args.get(index).and_then(|arg| arg.parse().ok());
+        if parsed.is_none() {
+            warn!("{}: expected argument {}", self.name, index + 1);
+        }
+        parsed
+    }
+}
+
+pub fn console_closed(console: Res<Console>) -> bool {
+    !console.open
+}
+
+pub fn console_input_system(
+    mut console: ResMut<Console>,
+    mut received_character_events: EventReader<ReceivedCharacter>,
+    mut console_command_events: EventWriter<ConsoleCommand>,
+    key: Res<Input<KeyCode>>,
+    actions: Actions,
+) {
+    if actions.just_pressed(Action::ToggleConsole) {
+        console.open = !console.open;
+        console.input.clear();
+        received_character_events.clear();
+        return;
+    }
+
+    if !console.open {
+        received_character_events.clear();
+        return;
+    }
+
+    // Keys editing the line typed are fixed rather than bound actions
+    if key.just_pressed(KeyCode::Escape) {
+        console.open = false;
+        console.input.clear();
+        return;
+    }
+
+    for received_character in received_character_events.iter() {
+        if !received_character.char.is_control() && received_character.char != '`' {
+            console.input.push(received_character.char);
+        }
+    }
+
+    if key.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+
+    if key.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        console.open = false;
+        if let Some(command) = ConsoleCommand::parse(&line) {
+            info!("> {line}");
+            console_command_events.send(command);
+        }
+    }
+}
+
+pub fn time_scale_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut time_scale: ResMut<TimeScale>,
+) {
+    for command in console_command_events.iter() {
+        match command.name.as_str() {
+            "timescale" => {
+                if let Some(scale) = command.arg::<f32>(0) {
+                    time_scale.scale = scale.max(0.0);
+                    info!("timescale {}", time_scale.scale);
+                }
+            }
+            "pause" => {
+                time_scale.paused = !time_scale.paused;
+                info!("paused {}", time_scale.paused);
+            }
+            "step" => time_scale.step(),
+            _ => {}
+        }
+    }
+}
+
+/// Switch portal traversal order of the renderer, `traversal bfs` or `traversal dfs`.
+pub fn traversal_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut traversal: ResMut<PortalTraversal>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "traversal" {
+            continue;
+        }
+        match command.args.first().map(String::as_str) {
+            Some("bfs") => *traversal = PortalTraversal::BreadthFirst,
+            Some("dfs") => *traversal = PortalTraversal::DepthFirst,
+            _ => warn!("traversal: expected `bfs` or `dfs`"),
+        }
+        info!("traversal {:?}", *traversal);
+    }
+}
+
+/// Travel to another map, `map <path>`, starting a new hub, or `map <path> hub` to
+/// stay in the current one, keeping state of its maps.
+pub fn map_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut world_state: ResMut<WorldState>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "map" {
+            continue;
+        }
+        match command.args.first() {
+            Some(map) => {
+                let new_hub = command.args.get(1).map(String::as_str) != Some("hub");
+                world_state.travel(map, new_hub);
+            }
+            None => warn!("map: expected a scene path"),
+        }
+    }
+}
+
+/// Stack color effects over the frame, `post sepia [amount]`, `post tint <r> <g> <b>
+/// [amount]`, `post flash [seconds]` for a red flash, or `post clear`.
+pub fn post_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut post_process: ResMut<PostProcess>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "post" {
+            continue;
+        }
+        let optional = |index: usize, default: f32| {
+            command
+                .args
+                .get(index)
+                .map_or(Some(default), |_| command.arg(index))
+        };
+        let effect = match command.args.first().map(String::as_str) {
+            Some("sepia") => optional(1, 1.0).map(PostEffect::Sepia),
+            Some("tint") => match (command.arg(1), command.arg(2), command.arg(3)) {
+                (Some(r), Some(g), Some(b)) => optional(4, 0.5).map(|amount| PostEffect::Tint {
+                    color: RawColor([r, g, b]),
+                    amount,
+                }),
+                _ => None,
+            },
+            Some("flash") => optional(1, 0.5)
+                .map(|seconds| PostEffect::flash(RawColor([0xff, 0x00, 0x00]), 0.6, seconds)),
+            Some("clear") => {
+                post_process.clear();
+                info!("post cleared");
+                None
+            }
+            _ => {
+                warn!("post: expected `sepia`, `tint`, `flash` or `clear`");
+                None
+            }
+        };
+        if let Some(effect) = effect {
+            info!("post {effect:?}");
+            post_process.push(effect);
+        }
+    }
+}
+
+/// Export the automap of the current map as a PNG, `automap`.
+pub fn automap_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    automap: Res<Automap>,
+    config: Res<RenderConfig>,
+    view: Res<View>,
+    sector_query: Query<&Sector>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "automap" {
+            continue;
+        }
+        let sectors: Vec<&Sector> = sector_query.iter().collect();
+        let mut image = ImageFrame::new(config.width(), config.height());
+        draw_automap(&mut image.canvas(), &automap, &sectors, &view);
+        save_png(image.data, image.width, image.height, "automap");
+    }
+}
+
+/// Set how many positions the minimap breadcrumbs show, `breadcrumbs 64`, or turn
+/// them off with `breadcrumbs 0`.
+pub fn breadcrumbs_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut breadcrumbs: ResMut<Breadcrumbs>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "breadcrumbs" {
+            continue;
+        }
+        if let Some(length) = command.arg::<usize>(0) {
+            breadcrumbs.enabled = length > 0;
+            if length > 0 {
+                breadcrumbs.length = length;
+            }
+            info!(
+                "breadcrumbs {}",
+                if breadcrumbs.enabled { length } else { 0 }
+            );
+        }
+    }
+}
+
+/// Set how many portals deep sectors are drawn through, `portal_depth 16`.
+pub fn portal_depth_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut config: ResMut<RenderConfig>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "portal_depth" {
+            continue;
+        }
+        match command.arg::<usize>(0) {
+            Some(depth) => {
+                config.max_portal_depth = depth;
+                info!("portal_depth {depth}");
+            }
+            None => info!("portal_depth {}", config.max_portal_depth),
+        }
+    }
+}
+
+/// Move the view to a player start, `start <name>`, or the default one with `start`.
+/// Later maps are entered at a start of the same name when they have one.
+pub fn start_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut selection: ResMut<PlayerStartSelection>,
+    mut view: ResMut<View>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "start" {
+            continue;
+        }
+        selection.name = command.args.first().cloned();
+        *view = View::default();
+        info!("start {}", selection.name.as_deref().unwrap_or("default"));
+    }
+}
+
+/// Drive the mover of a sector, `mover <sector> open`, `close`, `toggle` or
+/// `goto <height> [speed]`.
+pub fn mover_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut mover_command_events: EventWriter<MoverCommand>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "mover" {
+            continue;
+        }
+        let Some(sector) = command.arg::<u32>(0) else { continue };
+        let action = match command.args.get(1).map(String::as_str) {
+            Some("open") => MoverAction::Open,
+            Some("close") => MoverAction::Close,
+            Some("toggle") => MoverAction::Toggle,
+            Some("goto") => {
+                let Some(height) = command.arg::<f32>(2) else { continue };
+                let speed = command
+                    .args
+                    .get(3)
+                    .and_then(|speed| speed.parse().ok())
+                    .unwrap_or(DEFAULT_MOVER_SPEED);
+                MoverAction::GotoHeight { height, speed }
+            }
+            _ => {
+                warn!("mover: expected `open`, `close`, `toggle` or `goto`");
+                continue;
+            }
+        };
+        mover_command_events.send(MoverCommand {
+            sector: SectorId(sector),
+            action,
+        });
+    }
+}
+
+/// Turn doppler on positional sound on or off, `doppler on` or `doppler off`,
+/// optionally scaled, `doppler on 2`.
+pub fn doppler_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "doppler" {
+            continue;
+        }
+        match command.args.first().map(String::as_str) {
+            Some("on") => audio_settings.doppler = true,
+            Some("off") => audio_settings.doppler = false,
+            _ => {
+                warn!("doppler: expected `on` or `off`");
+                continue;
+            }
+        }
+        if let Some(factor) = command.arg::<f32>(1) {
+            audio_settings.doppler_factor = factor;
+        }
+        info!(
+            "doppler {} x{}",
+            if audio_settings.doppler { "on" } else { "off" },
+            audio_settings.doppler_factor
+        );
+    }
+}
+
+/// Pause key toggles pause, F10 steps a single frame while paused.
+pub fn time_scale_key_system(mut time_scale: ResMut<TimeScale>, actions: Actions) {
+    if actions.just_pressed(Action::Pause) {
+        time_scale.paused = !time_scale.paused;
+    }
+    if actions.just_pressed(Action::Step) && time_scale.paused {
+        time_scale.step();
+    }
+}
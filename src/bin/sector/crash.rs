@@ -0,0 +1,142 @@
+use sector::{persist::*, render::*};
+
+use bevy::{
+    prelude::*,
+    utils::tracing::{
+        field::{Field, Visit},
+        Event, Subscriber,
+    },
+};
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Write as _},
+    sync::Mutex,
+    time::SystemTime,
+};
+use tracing_subscriber::{layer::Context, prelude::*, EnvFilter, Layer, Registry};
+
+/// Log lines kept for crash reports.
+const RECENT_LOG_LINES: usize = 200;
+const CRASH_DIR: &str = "crashes";
+
+static RECENT_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static CRASH_CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    map: None,
+    view: None,
+});
+
+/// Game state included in crash reports, updated every frame.
+#[derive(Debug, Default)]
+struct CrashContext {
+    map: Option<String>,
+    view: Option<String>,
+}
+
+/// Set up logging, in place of bevy's `LogPlugin`, keeping recent lines for crash
+/// reports, and install a panic hook writing them out.
+pub fn init() {
+    let filter_layer = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new("info,wgpu_core=warn,wgpu_hal=warn"))
+        .unwrap();
+    let subscriber = Registry::default()
+        .with(filter_layer)
+        .with(tracing_subscriber::fmt::Layer::default())
+        .with(RecentLogLayer);
+    bevy::utils::tracing::subscriber::set_global_default(subscriber)
+        .expect("failed to set tracing subscriber");
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        write_crash_report(info);
+    }));
+}
+
+/// Keep current map and view for crash reports.
+pub fn crash_context_system(persistence: Res<Persistence>, view: Res<View>) {
+    let Ok(mut context) = CRASH_CONTEXT.lock() else { return };
+    context.map = persistence.current_map().map(str::to_string);
+    context.view = Some(format!(
+        "position: {:?}, direction: {:?}, sector: {:?}",
+        view.position.0, view.direction.0, view.current_sector
+    ));
+}
+
+fn write_crash_report(info: &dyn Display) {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let build = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+
+    let mut report = String::new();
+    let _ = writeln!(report, "sector crash report");
+    let _ = writeln!(report, "{info}");
+    let _ = writeln!(report);
+    let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "build: {build}");
+    let _ = writeln!(report, "platform: {os} {arch}");
+    let _ = writeln!(report, "time: {timestamp}");
+
+    // Locks may be poisoned if the panic happened while holding them
+    let context = CRASH_CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    let none = "none".to_string();
+    let _ = writeln!(report, "map: {}", context.map.as_ref().unwrap_or(&none));
+    let _ = writeln!(report, "view: {}", context.view.as_ref().unwrap_or(&none));
+    drop(context);
+
+    let _ = writeln!(report);
+    let _ = writeln!(report, "recent log:");
+    for line in RECENT_LOG.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        let _ = writeln!(report, "{line}");
+    }
+
+    let path = format!("{CRASH_DIR}/crash-{timestamp}.txt");
+    let written = std::fs::create_dir_all(CRASH_DIR).and_then(|_| std::fs::write(&path, report));
+
+    let description = match written {
+        Ok(_) => format!("{info}\n\nA crash report was written to `{path}`."),
+        Err(error) => format!("{info}\n\nFailed to write crash report to `{path}`: {error}"),
+    };
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("sector crashed")
+        .set_description(&description)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+
+/// Tracing layer keeping the most recent log lines.
+struct RecentLogLayer;
+
+impl<S: Subscriber> Layer<S> for RecentLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = format!("{} {}:", metadata.level(), metadata.target());
+        event.record(&mut LineVisitor(&mut line));
+
+        let Ok(mut recent_log) = RECENT_LOG.lock() else { return };
+        if recent_log.len() == RECENT_LOG_LINES {
+            recent_log.pop_front();
+        }
+        recent_log.push_back(line);
+    }
+}
+
+struct LineVisitor<'a>(&'a mut String);
+
+impl<'a> Visit for LineVisitor<'a> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
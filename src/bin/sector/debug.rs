@@ -0,0 +1,118 @@
+use crate::{console::*, input::*, photo::fly_camera};
+use sector::{prelude::*, render::*};
+
+use bevy::{input::mouse::MouseMotion, prelude::*, window::CursorGrabMode};
+
+/// Debug cvars for inspecting maps, each toggled with its key or set with its console
+/// command, `noclip [on|off]` or `freecam [on|off]`.
+#[derive(Resource, Debug, Default)]
+pub struct DebugToggles {
+    /// Walk or fly through walls, the view keeping to whichever sector it passes
+    /// into. F7.
+    pub noclip: bool,
+    /// Fly the camera away from the player, who stays put and keeps simulating, to
+    /// look at the map from anywhere, including outside it. F8.
+    pub freecam: bool,
+}
+
+/// Camera detached from the [`View`] by freecam, in place of [`FollowView`].
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct Freecam;
+
+pub fn freecam_inactive(debug_toggles: Res<DebugToggles>) -> bool {
+    !debug_toggles.freecam
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+pub fn debug_key_system(mut debug_toggles: ResMut<DebugToggles>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleNoclip) {
+        debug_toggles.noclip = !debug_toggles.noclip;
+        info!("noclip {}", on_off(debug_toggles.noclip));
+    }
+    if actions.just_pressed(Action::ToggleFreecam) {
+        debug_toggles.freecam = !debug_toggles.freecam;
+        info!("freecam {}", on_off(debug_toggles.freecam));
+    }
+}
+
+pub fn debug_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut debug_toggles: ResMut<DebugToggles>,
+) {
+    for command in console_command_events.iter() {
+        let toggle = match command.name.as_str() {
+            "noclip" => &mut debug_toggles.noclip,
+            "freecam" => &mut debug_toggles.freecam,
+            _ => continue,
+        };
+        match command.args.first().map(String::as_str) {
+            None => *toggle = !*toggle,
+            Some("on") => *toggle = true,
+            Some("off") => *toggle = false,
+            _ => {
+                warn!("{}: expected `on`, `off` or nothing", command.name);
+                continue;
+            }
+        }
+        info!("{} {}", command.name, on_off(*toggle));
+    }
+}
+
+/// Detach cameras following the view when freecam is turned on, starting from where
+/// the view is, and snap them back when it's turned off.
+pub fn freecam_toggle_system(
+    mut commands: Commands,
+    debug_toggles: Res<DebugToggles>,
+    follow_query: Query<Entity, With<FollowView>>,
+    freecam_query: Query<Entity, With<Freecam>>,
+) {
+    if !debug_toggles.is_changed() {
+        return;
+    }
+
+    if debug_toggles.freecam {
+        for entity in &follow_query {
+            commands
+                .entity(entity)
+                .remove::<FollowView>()
+                .insert(Freecam);
+        }
+    } else {
+        for entity in &freecam_query {
+            commands
+                .entity(entity)
+                .remove::<Freecam>()
+                .insert(FollowView);
+        }
+    }
+}
+
+/// Fly detached cameras, the same way as photo mode.
+pub fn freecam_system(
+    mut camera_query: Query<&mut SectorCamera, With<Freecam>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    actions: Actions,
+    time: Res<Time>,
+    window_query: Query<&Window>,
+    sector_query: Query<&Sector>,
+) {
+    let Ok(window) = window_query.get_single() else { return };
+    let cursor_locked = window.cursor.grab_mode == CursorGrabMode::Locked;
+    for mut camera in &mut camera_query {
+        fly_camera(
+            &mut camera,
+            &actions,
+            &mut mouse_motion_events,
+            cursor_locked,
+            time.delta_seconds(),
+            &sector_query,
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{input::*, teleport::cursor_frame_point};
+use sector::{edit::*, prelude::*, render::*};
+
+use bevy::{
+    math::vec2,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+/// Distance in frame pixels within which the cursor grabs a vertex.
+const GRAB_DISTANCE: f32 = 4.0;
+/// Change in floor or ceiling height for each press of page up or page down.
+const HEIGHT_STEP: f32 = 0.25;
+const GRAB_COLOR: RawColor = RawColor([0x90, 0xee, 0x90]);
+
+/// Editing the map top down on the absolute minimap, toggled with F1. Vertices are
+/// dragged with the mouse, and page up and page down raise and lower the floor of
+/// the sector under the cursor, or its ceiling with shift held. Simulation is paused
+/// while active.
+#[derive(Resource, Debug, Default)]
+pub struct EditOverlay {
+    pub active: bool,
+    /// Vertex under the cursor, or being dragged, where it is now.
+    grabbed: Option<Vec2>,
+    dragging: bool,
+    /// Sector under the cursor.
+    hovered: Option<SectorId>,
+    saved: Option<SavedState>,
+}
+
+/// State restored on leaving the overlay.
+#[derive(Debug)]
+struct SavedState {
+    minimap: Minimap,
+    paused: bool,
+}
+
+pub fn edit_overlay_inactive(edit_overlay: Res<EditOverlay>) -> bool {
+    !edit_overlay.active
+}
+
+pub fn edit_overlay_toggle_system(
+    mut edit_overlay: ResMut<EditOverlay>,
+    mut minimap: ResMut<Minimap>,
+    mut time_scale: ResMut<TimeScale>,
+    actions: Actions,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !actions.just_pressed(Action::ToggleEditOverlay) {
+        return;
+    }
+
+    if let Some(saved) = edit_overlay.saved.take() {
+        *minimap = saved.minimap;
+        time_scale.paused = saved.paused;
+        *edit_overlay = EditOverlay::default();
+        info!("edit overlay off");
+    } else {
+        *edit_overlay = EditOverlay {
+            active: true,
+            saved: Some(SavedState {
+                minimap: *minimap,
+                paused: time_scale.paused,
+            }),
+            ..default()
+        };
+        *minimap = Minimap::Absolute;
+        time_scale.paused = true;
+        // The cursor is needed to grab vertices
+        if let Ok(mut window) = window_query.get_single_mut() {
+            window.cursor.grab_mode = CursorGrabMode::None;
+            window.cursor.visible = true;
+        }
+        info!("edit overlay on");
+    }
+}
+
+/// Grab and drag vertices under the cursor, and change heights of the sector under
+/// it.
+#[allow(clippy::too_many_arguments)]
+pub fn edit_overlay_system(
+    mut edit_overlay: ResMut<EditOverlay>,
+    config: Res<RenderConfig>,
+    minimap: Res<Minimap>,
+    rotation: Res<MinimapRotation>,
+    actions: Actions,
+    window_query: Query<(&Window, &SectorCamera), With<PrimaryWindow>>,
+    sector_query: Query<&Sector>,
+    mut geometry_edits: EventWriter<GeometryEdit>,
+) {
+    if !actions.pressed(Action::EditGrab) {
+        edit_overlay.dragging = false;
+    }
+    let Ok((window, camera)) = window_query.get_single() else { return };
+    let Some(transform) = MinimapTransform::new(&config, *minimap, camera, &rotation) else { return };
+    let Some(point) = cursor_frame_point(window, &config) else {
+        edit_overlay.hovered = None;
+        return;
+    };
+    let cursor = transform.map_to_world(point);
+    edit_overlay.hovered = sector_query
+        .iter()
+        .find(|s| s.contains(cursor))
+        .map(|s| s.id);
+
+    if edit_overlay.dragging {
+        if let Some(grabbed) = edit_overlay.grabbed.filter(|&g| g != cursor.0) {
+            geometry_edits.send(GeometryEdit::MoveVertices {
+                from: grabbed,
+                to: cursor.0,
+                sector: None,
+            });
+            edit_overlay.grabbed = Some(cursor.0);
+        }
+    } else {
+        let map_distance = |v: Vec2| {
+            let pixel = transform.world_to_map(Position2(v));
+            vec2(pixel.x as f32, pixel.y as f32).distance(point)
+        };
+        edit_overlay.grabbed = sector_query
+            .iter()
+            .flat_map(|s| s.vertices.iter().map(|v| v.0))
+            .filter(|&v| map_distance(v) < GRAB_DISTANCE)
+            .min_by(|&a, &b| map_distance(a).total_cmp(&map_distance(b)));
+        edit_overlay.dragging =
+            edit_overlay.grabbed.is_some() && actions.just_pressed(Action::EditGrab);
+    }
+
+    let step = match (
+        actions.just_pressed(Action::EditRaise),
+        actions.just_pressed(Action::EditLower),
+    ) {
+        (true, false) => HEIGHT_STEP,
+        (false, true) => -HEIGHT_STEP,
+        _ => return,
+    };
+    let Some(sector) = edit_overlay.hovered else { return };
+    let ceiling = actions.pressed(Action::EditCeiling);
+    geometry_edits.send(GeometryEdit::RaiseHeights {
+        sector,
+        floor: if ceiling { 0.0 } else { step },
+        ceil: if ceiling { step } else { 0.0 },
+    });
+}
+
+/// Clear the view from behind the minimap while editing.
+pub fn clear_edit_overlay_system<T: FrameTarget>(
+    edit_overlay: Res<EditOverlay>,
+    colors: Res<RenderColors>,
+    mut target_query: Query<&mut T, With<FollowView>>,
+) {
+    if !edit_overlay.active {
+        return;
+    }
+    let [r, g, b] = colors.background.0;
+    for mut target in &mut target_query {
+        let frame = target.frame_mut();
+        frame.copy_from_slice(&[r, g, b, 0xff].repeat(frame.len() / 4));
+    }
+}
+
+/// Mark the grabbed vertex and label the heights of the sector under the cursor.
+pub fn draw_edit_overlay_system<T: FrameTarget>(
+    edit_overlay: Res<EditOverlay>,
+    config: Res<RenderConfig>,
+    colors: Res<RenderColors>,
+    minimap: Res<Minimap>,
+    rotation: Res<MinimapRotation>,
+    mut target_query: Query<(&mut T, &SectorCamera), With<FollowView>>,
+    sector_query: Query<&Sector>,
+) {
+    if !edit_overlay.active {
+        return;
+    }
+    for (mut target, camera) in &mut target_query {
+        let Some(transform) = MinimapTransform::new(&config, *minimap, camera, &rotation) else { continue };
+        let frame = &mut target.canvas();
+
+        if let Some(grabbed) = edit_overlay.grabbed {
+            let centre = transform.world_to_map(Position2(grabbed));
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    draw_pixel(frame, Pixel::new(centre.x + x, centre.y + y), GRAB_COLOR);
+                }
+            }
+        }
+
+        let hovered = edit_overlay
+            .hovered
+            .and_then(|id| sector_query.iter().find(|s| s.id == id));
+        if let Some(sector) = hovered {
+            let text = format!(
+                "sector {} floor {:.2} ceil {:.2}",
+                sector.id.0, sector.floor.0, sector.ceil.0
+            );
+            let x = (frame.width as isize - text_width(&text)) / 2;
+            draw_text(frame, Pixel::new(x, 4), &text, colors.text);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::input::*;
+use sector::{generate::*, hub::*, load::*, prelude::*, render::*, HUD_TEXT_COLOR, PLAYER_COLOR};
+
+use bevy::prelude::*;
+
+/// Map entities not spawned as part of a map scene.
+type UnparentedFilter = (
+    Or<(
+        With<Sector>,
+        With<InitialSector>,
+        With<PlayerStart>,
+        With<CameraPose>,
+        With<WallAttachment>,
+        With<MapVersion>,
+        With<Thing>,
+        With<VertexPool>,
+    )>,
+    Without<Parent>,
+);
+
+/// Map that failed to load, leaving nothing to show but a menu to get out of it.
+#[derive(Resource, Debug, Default)]
+pub struct MapLoadFailure {
+    pub map: Option<String>,
+}
+
+pub fn map_load_failed(failure: Res<MapLoadFailure>) -> bool {
+    failure.map.is_some()
+}
+
+pub fn map_load_failure_system(
+    mut failure: ResMut<MapLoadFailure>,
+    mut map_load_events: EventReader<MapLoadEvent>,
+) {
+    for event in map_load_events.iter() {
+        match event {
+            MapLoadEvent::Failed { map } => failure.map = Some(map.clone()),
+            MapLoadEvent::Started { .. } => failure.map = None,
+            _ => {}
+        }
+    }
+}
+
+/// G generates a map to play in instead, O opens a map from a file picker.
+pub fn map_load_failure_key_system(
+    mut commands: Commands,
+    mut failure: ResMut<MapLoadFailure>,
+    mut world_state: ResMut<WorldState>,
+    mut view: ResMut<View>,
+    actions: Actions,
+    time: Res<Time>,
+    unparented_query: Query<Entity, UnparentedFilter>,
+) {
+    let generate = actions.just_pressed(Action::GenerateMap);
+    if !generate && !actions.just_pressed(Action::OpenMap) {
+        return;
+    }
+    let map = if generate { None } else { pick_map() };
+    if !generate && map.is_none() {
+        return;
+    }
+
+    for entity in &unparented_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    *view = View::default();
+    failure.map = None;
+
+    match map {
+        Some(map) => world_state.travel(&map, true),
+        None => {
+            let seed = time.elapsed().as_nanos() as u64;
+            info!("generating map from seed {seed}");
+            spawn_generated_map(&mut commands, seed);
+        }
+    }
+}
+
+/// Map scene chosen with a file picker, relative to the asset directory when within
+/// it.
+#[cfg(not(target_arch = "wasm32"))]
+fn pick_map() -> Option<String> {
+    let assets = std::env::current_dir().ok()?.join("assets");
+    let path = rfd::FileDialog::new()
+        .set_title("Open map")
+        .add_filter("map", &["ron", "mp"])
+        .set_directory(&assets)
+        .pick_file()?;
+    let path = path.strip_prefix(&assets).unwrap_or(&path);
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn pick_map() -> Option<String> {
+    warn!("opening maps from files isn't supported on the web");
+    None
+}
+
+pub fn draw_map_load_failure_system<T: FrameTarget>(
+    failure: Res<MapLoadFailure>,
+    mut target_query: Query<&mut T>,
+) {
+    let Some(map) = &failure.map else { return };
+    let Ok(mut target) = target_query.get_single_mut() else { return };
+    let frame = &mut target.canvas();
+
+    let lines = [
+        "FAILED TO LOAD MAP".to_string(),
+        map.clone(),
+        String::new(),
+        "G: GENERATE A MAP".to_string(),
+        "O: OPEN A MAP".to_string(),
+        "ESC: QUIT".to_string(),
+    ];
+    let top = (frame.height as isize - lines.len() as isize * (GLYPH_HEIGHT + 2)) / 2;
+    for (i, line) in lines.iter().enumerate() {
+        let color = if i == 0 { PLAYER_COLOR } else { HUD_TEXT_COLOR };
+        let x = (frame.width as isize - text_width(line)) / 2;
+        let y = top + i as isize * (GLYPH_HEIGHT + 2);
+        draw_text(frame, Pixel::new(x, y), line, color);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{console::*, resolution::DisplayMode};
+use sector::config::config_path;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const CONFIG_FILE: &str = "config.ron";
+
+/// Something the player does with a key or mouse button, bound by [`InputMap`].
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    /// Rise while flying.
+    FlyUp,
+    /// Sink while flying.
+    FlyDown,
+    /// Open doors and work lifts on the wall ahead.
+    Use,
+    /// Fire the current weapon while the mouse is captured.
+    Fire,
+    CycleWeapon,
+    ToggleMovementMode,
+    NarrowFov,
+    WidenFov,
+    /// Lock the mouse to the window for looking around.
+    CaptureMouse,
+    ReleaseMouse,
+    /// Release the mouse, or quit once it's released.
+    Exit,
+    ToggleConsole,
+    Pause,
+    /// Advance a single frame while paused.
+    Step,
+    ToggleMinimap,
+    ToggleMinimapColoring,
+    ToggleMinimapFill,
+    ToggleMinimapExplored,
+    ToggleMinimapRotation,
+    ToggleBreadcrumbs,
+    ToggleHud,
+    ToggleProfile,
+    ToggleRearView,
+    CycleResolution,
+    /// Switch between windowed and borderless fullscreen while
+    /// [`Action::FullscreenModifier`] is held.
+    ToggleFullscreen,
+    FullscreenModifier,
+    SavePose,
+    QuickSave,
+    QuickLoad,
+    ToggleNoclip,
+    ToggleFreecam,
+    TogglePhotoMode,
+    /// Save a photo while in photo mode.
+    CapturePhoto,
+    RollLeft,
+    RollRight,
+    ZoomIn,
+    ZoomOut,
+    Brighten,
+    Darken,
+    IncreaseContrast,
+    DecreaseContrast,
+    ToggleEditOverlay,
+    /// Grab and drag vertices in the edit overlay.
+    EditGrab,
+    EditRaise,
+    EditLower,
+    /// Held to raise and lower ceilings rather than floors in the edit overlay.
+    EditCeiling,
+    /// Click a sector on the absolute minimap to teleport there, see `teleport`.
+    Teleport,
+    /// Generate a map after one fails to load.
+    GenerateMap,
+    /// Pick a map to open after one fails to load.
+    OpenMap,
+}
+
+/// Key or mouse button an action is bound to.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    /// Binding written as in the config file, such as `Key(W)` or `Mouse(Left)`, or
+    /// just a key name.
+    pub fn parse(text: &str) -> Option<Self> {
+        ron::from_str(text)
+            .or_else(|_| ron::from_str(text).map(Self::Key))
+            .ok()
+    }
+}
+
+/// Bindings of every action, kept in `config.ron` in the user's config directory.
+/// Actions missing from the file keep their default bindings.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    /// Kept alongside the bindings as the only other setting, see
+    /// `display_mode_system`.
+    pub display_mode: DisplayMode,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::{Key, Mouse};
+
+        let bindings = [
+            (MoveForward, vec![Key(KeyCode::W), Key(KeyCode::Up)]),
+            (MoveBack, vec![Key(KeyCode::S), Key(KeyCode::Down)]),
+            (StrafeLeft, vec![Key(KeyCode::A)]),
+            (StrafeRight, vec![Key(KeyCode::D)]),
+            (TurnLeft, vec![Key(KeyCode::Q), Key(KeyCode::Left)]),
+            (TurnRight, vec![Key(KeyCode::E), Key(KeyCode::Right)]),
+            (FlyUp, vec![Key(KeyCode::Space)]),
+            (FlyDown, vec![Key(KeyCode::LControl)]),
+            (Use, vec![Key(KeyCode::F)]),
+            (Fire, vec![Mouse(MouseButton::Left)]),
+            (CycleWeapon, vec![Key(KeyCode::X)]),
+            (ToggleMovementMode, vec![Key(KeyCode::V)]),
+            (NarrowFov, vec![Key(KeyCode::Comma)]),
+            (WidenFov, vec![Key(KeyCode::Period)]),
+            (CaptureMouse, vec![Mouse(MouseButton::Left)]),
+            (ReleaseMouse, vec![Mouse(MouseButton::Right)]),
+            (Exit, vec![Key(KeyCode::Escape)]),
+            (ToggleConsole, vec![Key(KeyCode::Grave)]),
+            (Pause, vec![Key(KeyCode::Pause)]),
+            (Step, vec![Key(KeyCode::F10)]),
+            (ToggleMinimap, vec![Key(KeyCode::Tab)]),
+            (ToggleMinimapColoring, vec![Key(KeyCode::H)]),
+            (ToggleMinimapFill, vec![Key(KeyCode::J)]),
+            (ToggleMinimapExplored, vec![Key(KeyCode::K)]),
+            (ToggleMinimapRotation, vec![Key(KeyCode::N)]),
+            (ToggleBreadcrumbs, vec![Key(KeyCode::B)]),
+            (ToggleHud, vec![Key(KeyCode::F4)]),
+            (ToggleProfile, vec![Key(KeyCode::F3)]),
+            (ToggleRearView, vec![Key(KeyCode::M)]),
+            (CycleResolution, vec![Key(KeyCode::F2)]),
+            (ToggleFullscreen, vec![Key(KeyCode::Return)]),
+            (
+                FullscreenModifier,
+                vec![Key(KeyCode::LAlt), Key(KeyCode::RAlt)],
+            ),
+            (SavePose, vec![Key(KeyCode::F6)]),
+            (QuickSave, vec![Key(KeyCode::F5)]),
+            (QuickLoad, vec![Key(KeyCode::F9)]),
+            (ToggleNoclip, vec![Key(KeyCode::F7)]),
+            (ToggleFreecam, vec![Key(KeyCode::F8)]),
+            (TogglePhotoMode, vec![Key(KeyCode::P)]),
+            (CapturePhoto, vec![Key(KeyCode::Return)]),
+            (RollLeft, vec![Key(KeyCode::Z)]),
+            (RollRight, vec![Key(KeyCode::C)]),
+            (ZoomIn, vec![Key(KeyCode::R)]),
+            (ZoomOut, vec![Key(KeyCode::F)]),
+            (Brighten, vec![Key(KeyCode::RBracket)]),
+            (Darken, vec![Key(KeyCode::LBracket)]),
+            (IncreaseContrast, vec![Key(KeyCode::Equals)]),
+            (DecreaseContrast, vec![Key(KeyCode::Minus)]),
+            (ToggleEditOverlay, vec![Key(KeyCode::F1)]),
+            (EditGrab, vec![Mouse(MouseButton::Left)]),
+            (EditRaise, vec![Key(KeyCode::PageUp)]),
+            (EditLower, vec![Key(KeyCode::PageDown)]),
+            (
+                EditCeiling,
+                vec![Key(KeyCode::LShift), Key(KeyCode::RShift)],
+            ),
+            (Teleport, vec![Mouse(MouseButton::Left)]),
+            (GenerateMap, vec![Key(KeyCode::G)]),
+            (OpenMap, vec![Key(KeyCode::O)]),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+            display_mode: DisplayMode::default(),
+        }
+    }
+}
+
+impl InputMap {
+    /// Bindings from the config file over the defaults, writing the file out when
+    /// there isn't one yet so there's something to edit.
+    pub fn load() -> Self {
+        let Some(path) = config_path(CONFIG_FILE) else { return default() };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => {
+                let input_map = Self::default();
+                input_map.save();
+                return input_map;
+            }
+        };
+
+        match ron::from_str::<Self>(&text) {
+            Ok(loaded) => {
+                let mut input_map = Self::default();
+                input_map.bindings.extend(loaded.bindings);
+                input_map.display_mode = loaded.display_mode;
+                input_map
+            }
+            Err(error) => {
+                warn!("ignoring invalid config `{}`: {error}", path.display());
+                default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_path(CONFIG_FILE) else { return };
+
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+                }
+                std::fs::write(&path, text).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!("failed to save config `{}`: {error}", path.display());
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Keys and mouse buttons read as the actions they're bound to.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    input_map: Res<'w, InputMap>,
+    key: Res<'w, Input<KeyCode>>,
+    mouse_button: Res<'w, Input<MouseButton>>,
+}
+
+impl Actions<'_> {
+    /// Whether any binding of action is held.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.input_map
+            .bindings(action)
+            .iter()
+            .any(|&binding| match binding {
+                Binding::Key(key) => self.key.pressed(key),
+                Binding::Mouse(button) => self.mouse_button.pressed(button),
+            })
+    }
+
+    /// Whether any binding of action was pressed this frame.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.input_map
+            .bindings(action)
+            .iter()
+            .any(|&binding| match binding {
+                Binding::Key(key) => self.key.just_pressed(key),
+                Binding::Mouse(button) => self.mouse_button.just_pressed(button),
+            })
+    }
+}
+
+/// Show the bindings of an action, `bind <action>`, or rebind it and save the config,
+/// `bind <action> <binding>...` with bindings such as `W`, `Key(Up)` or `Mouse(Left)`.
+pub fn bind_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut input_map: ResMut<InputMap>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "bind" {
+            continue;
+        }
+        let action = match command.args.first() {
+            Some(name) => match ron::from_str::<Action>(name) {
+                Ok(action) => action,
+                Err(_) => {
+                    warn!("bind: unknown action `{name}`");
+                    continue;
+                }
+            },
+            None => {
+                warn!("bind: expected an action, such as `MoveForward`");
+                continue;
+            }
+        };
+
+        if command.args.len() > 1 {
+            let bindings: Option<Vec<Binding>> = command.args[1..]
+                .iter()
+                .map(|a| Binding::parse(a))
+                .collect();
+            match bindings {
+                Some(bindings) => {
+                    input_map.bindings.insert(action, bindings);
+                    input_map.save();
+                }
+                None => {
+                    warn!("bind: expected key names, `Key(<key>)` or `Mouse(<button>)`");
+                    continue;
+                }
+            }
+        }
+        info!("bind {action:?} {:?}", input_map.bindings(action));
+    }
+}
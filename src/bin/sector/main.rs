@@ -1,234 +1,453 @@
-mod draw;
-mod utils;
-
-use crate::{draw::*, utils::*};
-use sector::*;
+mod audio;
+mod console;
+#[cfg(not(target_arch = "wasm32"))]
+mod crash;
+mod debug;
+mod edit_mode;
+mod fallback;
+mod input;
+mod photo;
+mod pose;
+mod profile;
+mod rear_view;
+mod resolution;
+mod save;
+mod teleport;
+mod walk;
+mod weapon;
+
+use crate::{
+    audio::*, console::*, debug::*, edit_mode::*, fallback::*, input::*, photo::*, pose::*,
+    profile::*, rear_view::*, resolution::*, save::*, teleport::*, walk::*, weapon::*,
+};
+use sector::{
+    audit::*, blockmap::*, edit::*, generate::spawn_generated_map, hub::*, interact::used_wall,
+    migrate::migrate_file, mob::*, music::*, physics::*, prelude::*, render::*,
+};
 
 use bevy::{
     app::AppExit,
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     input::mouse::MouseMotion,
-    math::vec2,
+    log::LogPlugin,
     math::vec3,
     prelude::*,
     utils::Duration,
     window::{CursorGrabMode, WindowResizeConstraints, WindowResolution},
 };
 use bevy_pixels::prelude::*;
-use palette::Hsv;
-use std::collections::VecDeque;
-
-#[macro_use]
-extern crate lazy_static;
 
-const WIDTH: u32 = 320;
-const HEIGHT: u32 = 240;
 const WINDOW_SCALE: u32 = 4;
-const GAP: isize = 1;
-const FRAC_WIDTH_2: u32 = WIDTH / 2;
-const FRAC_HEIGHT_2: u32 = HEIGHT / 2;
-const ASPECT_RATIO: f32 = WIDTH as f32 / HEIGHT as f32;
-const FOV_X_RADIANS: f32 = std::f32::consts::FRAC_PI_2;
-const NEAR: f32 = 0.1;
-const FAR: f32 = 50.0;
-const BRIGHTNESS_NEAR: f32 = 1.0;
-const BRIGHTNESS_FAR: f32 = 0.0;
-const MINIMAP_SCALE: f32 = 8.0;
-
-lazy_static! {
-    static ref FOV_Y_RADIANS: f32 = 2.0 * ((FOV_X_RADIANS * 0.5).tan() / ASPECT_RATIO).atan();
-    static ref PERSPECTIVE_MATRIX: Mat4 =
-        Mat4::perspective_infinite_reverse_rh(*FOV_Y_RADIANS, ASPECT_RATIO, NEAR);
-    static ref TAN_FAC_FOV_X_2: f32 = (FOV_X_RADIANS / 2.0).tan();
-    static ref X_NEAR: f32 = NEAR * *TAN_FAC_FOV_X_2;
-    static ref X_FAR: f32 = FAR * *TAN_FAC_FOV_X_2;
-    // Clip boundaries
-    static ref BACK_CLIP_1: Vec2 = vec2(*X_NEAR, NEAR);
-    static ref BACK_CLIP_2: Vec2 = vec2(-*X_NEAR, NEAR);
-    static ref LEFT_CLIP_1: Vec2 = *BACK_CLIP_2;
-    static ref LEFT_CLIP_2: Vec2 = vec2(-*X_FAR, FAR);
-    static ref RIGHT_CLIP_1: Vec2 = vec2(*X_FAR, FAR);
-    static ref RIGHT_CLIP_2: Vec2 = *BACK_CLIP_1;
-}
+/// Change in field of view for each press of comma or period.
+const FOV_STEP_DEGREES: f32 = 5.0;
 
-/// Normalized screen coordinates, right-handed coordinate system with z towards,
-/// origin at centre.
-///
-///   +y
-///   ^
-///   |
-/// +z.---> +x
 #[derive(Debug, Copy, Clone)]
-pub struct Normalized(Vec3);
+pub struct Velocity(Vec3);
 
-impl From<Normalized> for Pixel {
-    fn from(norm: Normalized) -> Self {
-        Self {
-            x: FRAC_WIDTH_2 as isize + (FRAC_WIDTH_2 as f32 * norm.0.x).round() as isize,
-            y: FRAC_HEIGHT_2 as isize - (FRAC_HEIGHT_2 as f32 * norm.0.y).round() as isize,
-        }
-    }
+#[derive(Resource, Debug)]
+struct State {
+    velocity: Velocity,
+    /// Speed of falling while walking, in units per second.
+    fall_speed: f32,
+    update_title_timer: Timer,
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Velocity(Vec3);
+fn main() {
+    #[cfg(target_arch = "wasm32")]
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    #[cfg(not(target_arch = "wasm32"))]
+    crash::init();
+
+    let input_map = InputMap::load();
+    let asset_plugin = AssetPlugin {
+        watch_for_changes: true,
+        ..default()
+    };
+    // Maps saved in the browser by the editor load in place of those deployed with it
+    #[cfg(target_arch = "wasm32")]
+    let stored_scene_asset_server = sector::storage::stored_scene_asset_server(&asset_plugin);
+    let default_plugins = DefaultPlugins.set(asset_plugin).set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "sector".to_string(),
+            resolution: WindowResolution::new(
+                (WINDOW_SCALE * WIDTH) as f32,
+                (WINDOW_SCALE * HEIGHT) as f32,
+            ),
+            resize_constraints: WindowResizeConstraints {
+                min_width: WIDTH as f32,
+                min_height: HEIGHT as f32,
+                ..default()
+            },
+            fit_canvas_to_parent: true,
+            mode: input_map.display_mode.into(),
+            ..default()
+        }),
+        ..default()
+    });
+    // Logging is set up by `crash::init` instead
+    #[cfg(not(target_arch = "wasm32"))]
+    let default_plugins = default_plugins.disable::<LogPlugin>();
 
-/// Direction, positive right-handed around z-axis. Zero in direction of y-axis.
-///
-///   ^   ^
-///    \+θ|
-///     \ |
-///     +z.
-#[derive(Debug, Copy, Clone)]
-pub struct Direction(f32);
-
-/// Pixel location, origin at top left.
-///
-///  .---> +x
-///  |
-///  v
-///  +y
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Pixel {
-    pub x: isize,
-    pub y: isize,
-}
+    // Compare two determinism traces instead of playing
+    if let Some((a, b)) = arg_pair("--compare-audit") {
+        compare_audit(&a, &b);
+        return;
+    }
+    // Upgrade scene files to the current map version instead of playing
+    let migrate_paths: Vec<String> = std::env::args()
+        .skip_while(|a| a != "--migrate")
+        .skip(1)
+        .take_while(|a| !a.starts_with("--"))
+        .collect();
+    if !migrate_paths.is_empty() {
+        migrate_files(&migrate_paths);
+        return;
+    }
 
-impl From<Position2> for Pixel {
-    fn from(position: Position2) -> Self {
-        Self {
-            x: FRAC_WIDTH_2 as isize + (MINIMAP_SCALE * position.0.x).round() as isize,
-            y: FRAC_HEIGHT_2 as isize - (MINIMAP_SCALE * position.0.y).round() as isize,
+    let mut app = App::new();
+    #[cfg(target_arch = "wasm32")]
+    app.insert_resource(stored_scene_asset_server);
+    app.insert_resource(State {
+        velocity: Velocity(vec3(0.0, 0.0, 0.0)),
+        fall_speed: 0.0,
+        update_title_timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
+    })
+    .init_resource::<Console>()
+    .insert_resource(input_map.display_mode)
+    .insert_resource(input_map)
+    .init_resource::<PhotoMode>()
+    .init_resource::<MapLoadFailure>()
+    .init_resource::<DebugTeleport>()
+    .init_resource::<DebugToggles>()
+    .init_resource::<QuickLoad>()
+    .init_resource::<MovementMode>()
+    .init_resource::<MovementConfig>()
+    .init_resource::<Resolution>()
+    .init_resource::<EditOverlay>()
+    .init_resource::<CurrentWeapon>()
+    .insert_resource(PoseArgs::from_args())
+    .add_event::<ConsoleCommand>()
+    .add_event::<GeometryEdit>()
+    .add_plugins(default_plugins)
+    .add_plugin(PixelsPlugin {
+        primary_window: Some(PixelsOptions {
+            width: WIDTH,
+            height: HEIGHT,
+            auto_resize_buffer: false,
+            ..default()
+        }),
+    })
+    .add_plugin(FrameTimeDiagnosticsPlugin)
+    // .add_plugin(LogDiagnosticsPlugin::default())
+    .add_plugin(SectorPlugin)
+    .add_plugin(SectorRenderPlugin::<PixelsWrapper>::default())
+    .add_startup_system(load_scene_system)
+    .add_startup_system(setup_hud_system)
+    .add_system(update_title_system)
+    .add_system(
+        escape_system
+            .run_if(console_closed)
+            .before(console_input_system),
+    )
+    .add_system(console_input_system)
+    .add_system(time_scale_command_system.after(console_input_system))
+    .add_system(traversal_command_system.after(console_input_system))
+    .add_system(automap_command_system.after(console_input_system))
+    .add_system(post_command_system.after(console_input_system))
+    .add_system(map_command_system.after(console_input_system))
+    .add_system(breadcrumbs_command_system.after(console_input_system))
+    .add_system(portal_depth_command_system.after(console_input_system))
+    .add_system(start_command_system.after(console_input_system))
+    .add_system(pose_command_system.after(console_input_system))
+    .add_system(doppler_command_system.after(console_input_system))
+    .add_system(teleport_command_system.after(console_input_system))
+    .add_system(debug_command_system.after(console_input_system))
+    .add_system(bind_command_system.after(console_input_system))
+    .add_system(
+        console::mover_command_system
+            .after(console_input_system)
+            .before(SectorSet::Simulation),
+    )
+    .add_system(pose_args_system.in_set(SectorSet::Simulation))
+    .add_system(
+        quick_save_system
+            .run_if(quick_save_pressed)
+            .run_if(console_closed)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_system(
+        quick_load_system
+            .run_if(console_closed)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_system(quick_load_view_system.in_set(SectorSet::Simulation))
+    .add_system(map_load_failure_system)
+    .add_system(
+        map_load_failure_key_system
+            .run_if(map_load_failed)
+            .run_if(console_closed),
+    )
+    .add_systems(
+        (
+            map_entered_system.before(initial_sector_system),
+            initial_sector_system,
+            mouse_capture_system
+                .run_if(not(minimap_teleport_active))
+                .run_if(edit_overlay_inactive),
+            minimap_teleport_system
+                .run_if(minimap_teleport_active)
+                .run_if(edit_overlay_inactive)
+                .run_if(console_closed),
+        )
+            .in_set(SectorSet::Simulation),
+    )
+    .add_systems(
+        (
+            time_scale_key_system,
+            switch_minimap_system,
+            switch_minimap_coloring_system,
+            switch_minimap_sectors_system,
+            switch_minimap_rotation_system,
+            switch_breadcrumbs_system,
+            resolution_key_system,
+            profile_key_system,
+            hud_key_system,
+            fov_key_system,
+            rear_view_key_system,
+            use_key_system,
+            pose_key_system,
+            movement_mode_key_system.before(player_movement_system),
+            player_movement_system.run_if(freecam_inactive),
+        )
+            .distributive_run_if(console_closed)
+            .distributive_run_if(photo_mode_inactive)
+            .distributive_run_if(edit_overlay_inactive)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_systems(
+        (
+            cycle_weapon_system,
+            fire_system.before(mouse_capture_system),
+        )
+            .distributive_run_if(console_closed)
+            .distributive_run_if(photo_mode_inactive)
+            .distributive_run_if(edit_overlay_inactive)
+            .distributive_run_if(freecam_inactive)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_system(weapon_hit_system.in_set(SectorSet::PreDraw))
+    .add_systems(
+        (
+            debug_key_system,
+            freecam_system.run_if(not(freecam_inactive)),
+        )
+            .chain()
+            .distributive_run_if(console_closed)
+            .distributive_run_if(photo_mode_inactive)
+            .distributive_run_if(edit_overlay_inactive)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_systems(
+        (photo_mode_toggle_system, photo_camera_system)
+            .chain()
+            .distributive_run_if(console_closed)
+            .distributive_run_if(edit_overlay_inactive)
+            .distributive_run_if(freecam_inactive)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_systems(
+        (
+            edit_overlay_toggle_system.run_if(photo_mode_inactive),
+            edit_overlay_system.run_if(not(edit_overlay_inactive)),
+            geometry_edit_system,
+        )
+            .chain()
+            .distributive_run_if(console_closed)
+            .in_set(SectorSet::Simulation),
+    )
+    .add_system(sound_listener_system.in_set(SectorSet::PreDraw))
+    .add_system(mob_target_system.in_set(SectorSet::PreDraw))
+    .add_system(footstep_system.in_set(SectorSet::PreDraw))
+    .add_systems(
+        (
+            sound_zone_playback_system.after(sound_zone_system),
+            sound_emitter_playback_system.after(sound_emitter_system),
+        )
+            .in_base_set(CoreSet::PostUpdate),
+    )
+    .add_system(rear_view_system.in_set(SectorSet::PreDraw))
+    .add_system(
+        freecam_toggle_system
+            .before(follow_view_system)
+            .in_set(SectorSet::PreDraw),
+    )
+    .add_system(
+        resolution_system
+            .before(resize_frame_system::<PixelsWrapper>)
+            .in_set(SectorSet::PreDraw),
+    )
+    .add_systems(
+        (
+            display_mode_key_system.run_if(console_closed),
+            display_mode_system,
+        )
+            .chain()
+            .in_set(SectorSet::Simulation),
+    )
+    .add_system(
+        clear_edit_overlay_system::<PixelsWrapper>
+            .before(draw_minimap_system::<PixelsWrapper>)
+            .in_set(SectorSet::Overlay),
+    )
+    .add_system(
+        draw_edit_overlay_system::<PixelsWrapper>
+            .after(draw_minimap_system::<PixelsWrapper>)
+            .before(draw_hud_system::<PixelsWrapper>)
+            .in_set(SectorSet::Overlay),
+    )
+    .add_systems(
+        (
+            draw_map_load_failure_system::<PixelsWrapper>,
+            photo_post_process_system::<PixelsWrapper>,
+            photo_capture_system::<PixelsWrapper>,
+            pose_capture_system::<PixelsWrapper>,
+        )
+            .chain()
+            .after(draw_hud_system::<PixelsWrapper>)
+            .in_set(SectorSet::Overlay),
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_system(crash::crash_context_system);
+    if let Some(path) = std::env::args().skip_while(|a| a != "--audit").nth(1) {
+        match DeterminismAudit::create(&path) {
+            Ok(audit) => {
+                info!("writing determinism trace to `{path}`");
+                app.insert_resource(audit);
+            }
+            Err(error) => warn!("failed to create determinism trace `{path}`: {error}"),
         }
     }
+    app.add_system(
+        audit_view_system
+            .before(determinism_audit_system)
+            .in_base_set(CoreSet::Last),
+    );
+    app.run();
 }
 
-impl Pixel {
-    pub fn new(x: isize, y: isize) -> Self {
-        Self { x, y }
-    }
+/// The two arguments following name on the command line.
+fn arg_pair(name: &str) -> Option<(String, String)> {
+    let mut args = std::env::args().skip_while(|a| a != name).skip(1);
+    Some((args.next()?, args.next()?))
+}
 
-    pub fn to_tuple(self) -> (isize, isize) {
-        (self.x, self.y)
+fn compare_audit(a: &str, b: &str) {
+    let read = |path: &str| {
+        std::fs::read_to_string(path).unwrap_or_else(|error| {
+            eprintln!("failed to read `{path}`: {error}");
+            std::process::exit(2);
+        })
+    };
+    match first_divergence(&read(a), &read(b)) {
+        None => println!("traces match"),
+        Some(divergence) if divergence.parts.is_empty() => {
+            println!("traces differ in length at tick {}", divergence.tick);
+            std::process::exit(1);
+        }
+        Some(divergence) => {
+            println!(
+                "traces diverge at tick {} in {}",
+                divergence.tick,
+                divergence.parts.join(", ")
+            );
+            std::process::exit(1);
+        }
     }
+}
 
-    pub fn to_offset(self) -> Option<usize> {
-        if self.x >= 0 && self.x < WIDTH as isize && self.y >= 0 && self.y < HEIGHT as isize {
-            Some((self.y as u32 * WIDTH * 4 + self.x as u32 * 4) as usize)
-        } else {
-            None
+fn migrate_files(paths: &[String]) {
+    let mut failed = false;
+    for path in paths {
+        match migrate_file(path) {
+            Ok(None) => println!("`{path}` is already at version {MAP_VERSION}"),
+            Ok(Some(version)) => {
+                println!("`{path}` upgraded from version {version} to {MAP_VERSION}")
+            }
+            Err(error) => {
+                eprintln!("failed to migrate `{path}`: {error}");
+                failed = true;
+            }
         }
     }
-
-    pub fn to_offset_unchecked(self) -> usize {
-        (self.y as u32 * WIDTH * 4 + self.x as u32 * 4) as usize
+    if failed {
+        std::process::exit(1);
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum Minimap {
-    Off,
-    FirstPerson,
-    Absolute,
+/// Record the view for the determinism audit, when enabled.
+fn audit_view_system(audit: Option<ResMut<DeterminismAudit>>, view: Res<View>) {
+    let Some(mut audit) = audit else { return };
+
+    let mut hasher = StateHasher::default();
+    hasher.write_f32(view.position.0.x);
+    hasher.write_f32(view.position.0.y);
+    hasher.write_f32(view.position.0.z);
+    hasher.write_f32(view.direction.0);
+    hasher.write_u32(view.current_sector.map(|id| id.0 + 1).unwrap_or(0));
+    audit.record("view", hasher.finish());
 }
 
-#[derive(Resource, Debug)]
-struct State {
-    minimap: Minimap,
-    position: Position3,
-    velocity: Velocity,
-    direction: Direction,
-    update_title_timer: Timer,
-    current_sector: Option<SectorId>,
+fn mob_target_system(mut target: ResMut<MobTarget>, view: Res<View>) {
+    target.position = view.position.truncate();
+    target.sector = view.current_sector;
 }
 
-fn main() {
-    #[cfg(target_arch = "wasm32")]
-    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+fn sound_listener_system(mut listener: ResMut<SoundListener>, view: Res<View>) {
+    listener.position = view.position.truncate();
+    listener.height = Length(view.position.0.z);
+    listener.direction = view.direction;
+    listener.sector = view.current_sector;
+}
 
-    App::new()
-        .register_type::<SectorId>()
-        .register_type::<Option<SectorId>>()
-        .register_type::<Vec<Option<SectorId>>>()
-        .register_type::<Sector>()
-        .register_type::<InitialSector>()
-        .register_type::<Sector>()
-        .register_type::<Position2>()
-        .register_type::<Vec<Position2>>()
-        .register_type::<Length>()
-        .register_type::<RawColor>()
-        .register_type::<Vec<RawColor>>()
-        .register_type::<[u8; 3]>()
-        .insert_resource(State {
-            minimap: Minimap::Off,
-            position: Position3(vec3(0.0, 0.0, 2.0)),
-            velocity: Velocity(vec3(0.0, 0.0, 0.0)),
-            direction: Direction(0.0),
-            update_title_timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
-            current_sector: None,
-        })
-        .add_plugins(
-            DefaultPlugins
-                .set(AssetPlugin {
-                    watch_for_changes: true,
-                    ..default()
-                })
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "sector".to_string(),
-                        resolution: WindowResolution::new(
-                            (WINDOW_SCALE * WIDTH) as f32,
-                            (WINDOW_SCALE * HEIGHT) as f32,
-                        ),
-                        resize_constraints: WindowResizeConstraints {
-                            min_width: WIDTH as f32,
-                            min_height: HEIGHT as f32,
-                            ..default()
-                        },
-                        fit_canvas_to_parent: true,
-                        ..default()
-                    }),
-                    ..default()
-                }),
-        )
-        .add_plugin(PixelsPlugin {
-            primary_window: Some(PixelsOptions {
-                width: WIDTH,
-                height: HEIGHT,
-                auto_resize_buffer: false,
-                ..default()
-            }),
-        })
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        // .add_plugin(LogDiagnosticsPlugin::default())
-        .add_startup_system(load_scene_system)
-        .add_system(initial_sector_system)
-        .add_system(update_title_system)
-        .add_system(mouse_capture_system)
-        .add_system(escape_system)
-        .add_system(switch_minimap_system)
-        .add_system(player_movement_system)
-        .add_systems(
-            (
-                draw_background_system,
-                draw_wall_system,
-                draw_minimap_system,
-            )
-                .chain()
-                .in_set(PixelsSet::Draw),
-        )
-        .run();
+/// Play the default map, or one generated from the seed given with `--generate`.
+fn load_scene_system(mut commands: Commands, mut world_state: ResMut<WorldState>) {
+    let seed = std::env::args().skip_while(|a| a != "--generate").nth(1);
+    match seed.map(|seed| seed.parse::<u64>()) {
+        Some(Ok(seed)) => {
+            info!("generating map from seed {seed}");
+            spawn_generated_map(&mut commands, seed);
+        }
+        Some(Err(error)) => {
+            warn!("ignoring invalid --generate seed: {error}");
+            world_state.travel(DEFAULT_SCENE_RON_FILE_PATH, true);
+        }
+        None => world_state.travel(DEFAULT_SCENE_RON_FILE_PATH, true),
+    }
 }
 
-fn load_scene_system(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn(asset_server.load::<DynamicScene, _>(DEFAULT_SCENE_RON_FILE_PATH));
+fn setup_hud_system(mut hud: ResMut<Hud>, colors: Res<RenderColors>) {
+    let row = |row: isize| Pixel::new(4, 4 + row * (GLYPH_HEIGHT + 2));
+    hud.widgets = vec![
+        HudWidget::new(row(0), "SECTOR {sector}"),
+        HudWidget::new(row(1), "TIME {map_time:.1}"),
+        HudWidget::new(row(2), "POS {x:.1} {y:.1} {z:.1}"),
+        HudWidget::new(row(3), "HEADING {heading:.0}"),
+    ];
+    hud.crosshair = Some(colors.text);
 }
 
-fn initial_sector_system(mut state: ResMut<State>, query: Query<&InitialSector>) {
-    if state.current_sector.is_none() {
-        if let Ok(initial_sector) = query.get_single() {
-            state.current_sector = Some(initial_sector.0);
-        }
+/// Show or hide the HUD with F4.
+fn hud_key_system(mut hud: ResMut<Hud>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleHud) {
+        hud.visible = !hud.visible;
+        info!("hud {}", hud.visible);
+    }
+}
+
+fn map_entered_system(mut map_entered_events: EventReader<MapEntered>, mut view: ResMut<View>) {
+    // Start over in the initial sector of the new map
+    if map_entered_events.iter().last().is_some() {
+        *view = View::default();
     }
 }
 
@@ -236,8 +455,15 @@ fn update_title_system(
     mut state: ResMut<State>,
     time: Res<Time>,
     diagnostics: Res<Diagnostics>,
+    console: Res<Console>,
     mut window_query: Query<&mut Window>,
 ) {
+    if console.open {
+        let Ok(mut window) = window_query.get_single_mut() else { return };
+        window.title = format!("sector > {}_", console.input);
+        return;
+    }
+
     if state.update_title_timer.tick(time.delta()).finished() {
         let Ok(mut window) = window_query.get_single_mut() else { return };
 
@@ -249,19 +475,16 @@ fn update_title_system(
     }
 }
 
-fn mouse_capture_system(
-    mouse_button: Res<Input<MouseButton>>,
-    mut window_query: Query<&mut Window>,
-) {
+fn mouse_capture_system(actions: Actions, mut window_query: Query<&mut Window>) {
     let Ok(mut window) = window_query.get_single_mut() else { return };
 
     if window.cursor.grab_mode == CursorGrabMode::None {
-        if mouse_button.just_pressed(MouseButton::Left) {
+        if actions.just_pressed(Action::CaptureMouse) {
             window.cursor.grab_mode = CursorGrabMode::Locked;
             window.cursor.visible = false;
         }
     } else {
-        if mouse_button.just_pressed(MouseButton::Right) {
+        if actions.just_pressed(Action::ReleaseMouse) {
             window.cursor.grab_mode = CursorGrabMode::None;
             window.cursor.visible = true;
         }
@@ -270,10 +493,10 @@ fn mouse_capture_system(
 
 fn escape_system(
     mut app_exit_events: EventWriter<AppExit>,
-    key: Res<Input<KeyCode>>,
+    actions: Actions,
     mut window_query: Query<&mut Window>,
 ) {
-    if key.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(Action::Exit) {
         let Ok(mut window) = window_query.get_single_mut() else { return };
 
         if window.cursor.grab_mode == CursorGrabMode::None {
@@ -286,9 +509,9 @@ fn escape_system(
     }
 }
 
-fn switch_minimap_system(mut state: ResMut<State>, key: Res<Input<KeyCode>>) {
-    if key.just_pressed(KeyCode::Tab) {
-        state.minimap = match state.minimap {
+fn switch_minimap_system(mut minimap: ResMut<Minimap>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleMinimap) {
+        *minimap = match *minimap {
             Minimap::Off => Minimap::FirstPerson,
             Minimap::FirstPerson => Minimap::Absolute,
             Minimap::Absolute => Minimap::Off,
@@ -296,399 +519,210 @@ fn switch_minimap_system(mut state: ResMut<State>, key: Res<Input<KeyCode>>) {
     }
 }
 
-fn player_movement_system(
-    mut state: ResMut<State>,
-    mut mouse_motion_events: EventReader<MouseMotion>,
-    key: Res<Input<KeyCode>>,
-    window_query: Query<&mut Window>,
-) {
-    let Ok(window) = window_query.get_single() else { return };
-
-    if window.cursor.grab_mode == CursorGrabMode::Locked {
-        for mouse_motion in mouse_motion_events.iter() {
-            state.direction.0 += -mouse_motion.delta.x * 0.005;
+fn switch_minimap_coloring_system(mut coloring: ResMut<MinimapColoring>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleMinimapColoring) {
+        *coloring = match *coloring {
+            MinimapColoring::Walls => MinimapColoring::Height,
+            MinimapColoring::Height => MinimapColoring::Walls,
         }
     }
+}
 
-    if key.pressed(KeyCode::Left) || key.pressed(KeyCode::Q) {
-        state.direction.0 += 0.0001;
+/// Fill minimap sectors with J, show only explored sectors with K.
+fn switch_minimap_sectors_system(mut minimap_sectors: ResMut<MinimapSectors>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleMinimapFill) {
+        minimap_sectors.fill = !minimap_sectors.fill;
     }
-    if key.pressed(KeyCode::Right) || key.pressed(KeyCode::E) {
-        state.direction.0 -= 0.0001;
+    if actions.just_pressed(Action::ToggleMinimapExplored) {
+        minimap_sectors.explored_only = !minimap_sectors.explored_only;
     }
+}
 
-    state.velocity.0.x = 0.0;
-    state.velocity.0.y = 0.0;
-    state.velocity.0.z = 0.0;
-
-    if key.pressed(KeyCode::Up) || key.pressed(KeyCode::W) {
-        state.velocity.0.x -= state.direction.0.sin();
-        state.velocity.0.y += state.direction.0.cos();
-    }
-    if key.pressed(KeyCode::Down) || key.pressed(KeyCode::S) {
-        state.velocity.0.x += state.direction.0.sin();
-        state.velocity.0.y -= state.direction.0.cos();
+fn switch_minimap_rotation_system(mut rotation: ResMut<MinimapRotation>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleMinimapRotation) {
+        rotation.align_to_heading = !rotation.align_to_heading;
     }
-    if key.pressed(KeyCode::A) {
-        state.velocity.0.x -= state.direction.0.cos();
-        state.velocity.0.y -= state.direction.0.sin();
-    }
-    if key.pressed(KeyCode::D) {
-        state.velocity.0.x += state.direction.0.cos();
-        state.velocity.0.y += state.direction.0.sin();
+}
+
+fn switch_breadcrumbs_system(mut breadcrumbs: ResMut<Breadcrumbs>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleBreadcrumbs) {
+        breadcrumbs.enabled = !breadcrumbs.enabled;
     }
-    if key.pressed(KeyCode::Space) {
-        state.velocity.0.z += 1.0;
+}
+
+/// Use the wall in front of the view with F, opening or closing doors and lifts and
+/// firing triggers.
+fn use_key_system(
+    view: Res<View>,
+    actions: Actions,
+    sector_query: Query<&Sector>,
+    interactable_query: Query<&Interactable>,
+    trigger_query: Query<&Trigger>,
+    mover_query: Query<&Mover>,
+    mut wall_used_events: EventWriter<WallUsed>,
+) {
+    if !actions.just_pressed(Action::Use) {
+        return;
     }
-    if key.pressed(KeyCode::LControl) {
-        state.velocity.0.z -= 1.0;
+    let Some(current) = view.current_sector else { return };
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let trigger_walls: Vec<Interactable> = trigger_query
+        .iter()
+        .filter_map(Trigger::interactable)
+        .collect();
+    let interactables: Vec<&Interactable> =
+        interactable_query.iter().chain(&trigger_walls).collect();
+    let movers: Vec<&Mover> = mover_query.iter().collect();
+    let position = view.position.truncate();
+    let used = used_wall(
+        &sectors,
+        &interactables,
+        &movers,
+        current,
+        position,
+        view.direction,
+    );
+    if let Some(used) = used {
+        debug!("used sector {} wall {}", used.sector.0, used.wall_index);
+        wall_used_events.send(used);
     }
-
-    state.position.0.x += 0.05 * state.velocity.0.x;
-    state.position.0.y += 0.05 * state.velocity.0.y;
-    state.position.0.z += 0.05 * state.velocity.0.z;
 }
 
-fn draw_background_system(mut wrapper_query: Query<&mut PixelsWrapper>) {
-    let Ok(mut wrapper) = wrapper_query.get_single_mut() else { return };
-    let frame = wrapper.pixels.frame_mut();
-
-    frame.copy_from_slice(&[0x00, 0x00, 0x00, 0xff].repeat(frame.len() / 4));
+/// Narrow the field of view with comma, widen it with period.
+fn fov_key_system(mut camera: ResMut<CameraConfig>, actions: Actions) {
+    let step = if actions.just_pressed(Action::NarrowFov) {
+        -FOV_STEP_DEGREES
+    } else if actions.just_pressed(Action::WidenFov) {
+        FOV_STEP_DEGREES
+    } else { return };
+    let fov = camera.fov_x_degrees();
+    camera.set_fov_x_degrees(fov + step);
+    info!("fov {:.0}", camera.fov_x_degrees());
 }
 
-fn draw_wall_system(
-    state: Res<State>,
-    mut wrapper_query: Query<&mut PixelsWrapper>,
+#[allow(clippy::too_many_arguments)]
+fn player_movement_system(
+    mut state: ResMut<State>,
+    mut view: ResMut<View>,
+    movement_mode: Res<MovementMode>,
+    movement_config: Res<MovementConfig>,
+    mut mouse_turn_rate: Local<f32>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    actions: Actions,
+    window_query: Query<&mut Window>,
+    blockmap: Res<Blockmap>,
+    debug_toggles: Res<DebugToggles>,
     sector_query: Query<&Sector>,
 ) {
-    // Return early if current sector is not available
-    let Some(current_sector) = state.current_sector.and_then(|id| {
-        // TODO: Improve this query, might be slow with lots of sectors
-        sector_query.iter().find(|&s| s.id == id)
-    }) else { return };
-
-    let Ok(mut wrapper) = wrapper_query.get_single_mut() else { return };
-    let frame = wrapper.pixels.frame_mut();
-    let view_matrix = Mat3::from_rotation_z(-state.direction.0)
-        * Mat3::from_translation(-vec2(state.position.0.x, state.position.0.y));
-
-    let mut portal_queue = VecDeque::<Portal>::new();
-    let mut y_min_vec = vec![GAP; WIDTH as usize];
-    let mut y_max_vec = vec![HEIGHT as isize; WIDTH as usize];
-
-    // Push current sector on portal queue
-    portal_queue.push_back(Portal {
-        sector: current_sector,
-        x_min: GAP,
-        x_max: WIDTH as isize,
-    });
+    let Ok(window) = window_query.get_single() else { return };
 
-    // Process all portals until queue is empty, processing a portal may enqueue more
-    '_portals: while !portal_queue.is_empty() {
-        let self_portal = portal_queue.pop_front().unwrap();
-        let sector = self_portal.sector;
-
-        // View relative floor and ceiling locations
-        let view_floor = Length(sector.floor.0 - state.position.0.z);
-        let view_ceil = Length(sector.ceil.0 - state.position.0.z);
-
-        // Iterate through each wall within the sector
-        'walls: for wall in sector.to_walls() {
-            // Transform wall ends to view relative positions
-            let view_left = wall.left.transform(view_matrix);
-            let view_right = wall.right.transform(view_matrix);
-
-            // Clip wall by view frustum, will be `None` if outside of frustum
-            if let Some((view_left, view_right)) = clip_wall(view_left, view_right) {
-                // Project from view to normalized screen coordinates
-                let norm_left_top = project(view_left, view_ceil);
-                let norm_left_bottom = project(view_left, view_floor);
-                let norm_right_top = project(view_right, view_ceil);
-                let norm_right_bottom = project(view_right, view_floor);
-
-                // Convert to pixel locations
-                let left_top: Pixel = norm_left_top.into();
-                let left_bottom: Pixel = norm_left_bottom.into();
-                let right_top: Pixel = norm_right_top.into();
-                let right_bottom: Pixel = norm_right_bottom.into();
-
-                let dx = right_top.x - left_top.x;
-
-                // Skip drawing wall if looking at backside
-                if dx <= 0 {
-                    continue 'walls;
-                }
-
-                // TODO: Use `view_y_middle` in `distance` calculation below
-                // let view_y_middle = view_left_bottom.y + (view_y_top - view_left_bottom.y) / 2.0;
-
-                // Clip x by portal sides
-                let x_left = left_top.x.clamp(self_portal.x_min, self_portal.x_max);
-                let x_right = right_top.x.clamp(self_portal.x_min, self_portal.x_max);
-
-                // Fetch adjacent portal sector
-                let portal_sector = wall
-                    .portal_sector
-                    .and_then(|id| sector_query.iter().find(|&s| s.id == id));
-
-                // Process adjacent portal sector
-                let (y_portal_top, y_portal_bottom) = if let Some(portal_sector) = portal_sector {
-                    // Push adjacent sector on portal queue to render later
-                    portal_queue.push_back(Portal {
-                        sector: portal_sector,
-                        x_min: x_left,
-                        x_max: x_right,
-                    });
-
-                    let view_portal_ceil = Length(portal_sector.ceil.0 - state.position.0.z);
-                    let view_portal_floor = Length(portal_sector.floor.0 - state.position.0.z);
-
-                    let y_portal_top = if view_portal_ceil.0 < view_ceil.0 {
-                        let portal_ceil_t =
-                            (view_portal_ceil.0 - view_ceil.0) / (view_floor.0 - view_ceil.0);
-                        Some((
-                            lerpi(left_top.y, left_bottom.y, portal_ceil_t),
-                            lerpi(right_top.y, right_bottom.y, portal_ceil_t),
-                        ))
-                    } else {
-                        None
-                    };
-
-                    let y_portal_bottom = if view_portal_floor.0 > view_floor.0 {
-                        let portal_floor_t =
-                            (view_portal_floor.0 - view_ceil.0) / (view_floor.0 - view_ceil.0);
-                        Some((
-                            lerpi(left_top.y, left_bottom.y, portal_floor_t),
-                            lerpi(right_top.y, right_bottom.y, portal_floor_t),
-                        ))
-                    } else {
-                        None
-                    };
-
-                    (y_portal_top, y_portal_bottom)
-                } else {
-                    (None, None)
-                };
-
-                // Iterate through pixel columns
-                '_columns: for x in x_left..x_right {
-                    let skip_floor_ceil = x >= self_portal.x_max as isize - GAP;
-                    let skip_wall = x >= x_right - GAP;
-
-                    let x_t = (x - left_top.x) as f32 / dx as f32;
-
-                    // Interpolate z for distance
-                    let view_z = lerp(view_left.0.y, view_right.0.y, x_t);
-                    let distance = view_z.abs();
-
-                    // Brightness for distance
-                    let brightness = if distance > FAR {
-                        BRIGHTNESS_FAR
-                    } else if distance < NEAR {
-                        BRIGHTNESS_NEAR
-                    } else {
-                        // Interpolate brightness
-                        let distance_t = (distance - NEAR) / (FAR - NEAR);
-                        lerp(BRIGHTNESS_NEAR, BRIGHTNESS_FAR, distance_t)
-                    };
-                    let brightness_rounded = (brightness * 100.0).round() / 100.0;
-
-                    // Color for brightness
-                    let color: RawColor =
-                        Hsv::new(wall.color.hue, wall.color.saturation, brightness_rounded).into();
-
-                    // Interpolate y
-                    let y_top = lerpi(left_top.y, right_top.y, x_t);
-                    let y_bottom = lerpi(left_bottom.y, right_bottom.y, x_t);
-
-                    // Get y bounds
-                    let y_min = y_min_vec[x as usize];
-                    let y_max = y_max_vec[x as usize];
-
-                    // Clip y
-                    let y_top = y_top.clamp(y_min, y_max);
-                    let y_bottom = y_bottom.clamp(y_min, y_max);
-
-                    let y_ceil_top = y_min;
-                    let y_ceil_bottom = y_top;
-                    let y_floor_top = y_bottom;
-                    let y_floor_bottom = y_max;
-
-                    // Draw ceiling
-                    if !skip_floor_ceil {
-                        draw_vertical_line(
-                            frame,
-                            x,
-                            y_ceil_top,
-                            y_ceil_bottom - GAP,
-                            *CEILING_COLOR,
-                        );
-                    }
-
-                    // if join_gap_column {
-                    //     continue '_columns;
-                    // }
-
-                    if portal_sector.is_some() {
-                        // Draw wall above portal if required
-                        if let Some((y_portal_left_top, y_portal_right_top)) = y_portal_top {
-                            let y_portal_top = lerpi(y_portal_left_top, y_portal_right_top, x_t)
-                                .clamp(y_min, y_bottom);
-                            if !skip_wall {
-                                draw_vertical_line(frame, x, y_top, y_portal_top - GAP, color);
-                            }
-                            y_min_vec[x as usize] = y_portal_top;
-                        } else {
-                            y_min_vec[x as usize] = y_top;
-                        }
-
-                        // Draw wall below portal if required
-                        if let Some((portal_left_bottom_y, portal_right_bottom_y)) = y_portal_bottom
-                        {
-                            let y_portal_bottom =
-                                lerpi(portal_left_bottom_y, portal_right_bottom_y, x_t)
-                                    .clamp(y_top, y_max);
-                            if !skip_wall {
-                                draw_vertical_line(
-                                    frame,
-                                    x,
-                                    y_portal_bottom,
-                                    y_bottom - GAP,
-                                    color,
-                                );
-                            }
-                            y_max_vec[x as usize] = y_portal_bottom;
-                        } else {
-                            y_max_vec[x as usize] = y_bottom;
-                        }
-                    } else {
-                        // Draw complete wall
-                        if !skip_wall {
-                            draw_vertical_line(frame, x, y_top, y_bottom - GAP, color);
-                        }
-                    }
-
-                    // Draw floor
-                    if !skip_floor_ceil {
-                        draw_vertical_line(
-                            frame,
-                            x,
-                            y_floor_top,
-                            y_floor_bottom - GAP,
-                            *FLOOR_COLOR,
-                        );
-                    }
-                }
-            };
-        }
+    // Mouse look follows real time, easing towards the rate the mouse moved at
+    let real_delta = time.delta_seconds();
+    let mouse_x: f32 = mouse_motion_events.iter().map(|m| m.delta.x).sum();
+    if window.cursor.grab_mode == CursorGrabMode::Locked && real_delta > 0.0 {
+        let target_rate = -mouse_x * movement_config.mouse_sensitivity / real_delta;
+        let t = if movement_config.mouse_smoothing > 0.0 {
+            1.0 - (-real_delta / movement_config.mouse_smoothing).exp()
+        } else {
+            1.0
+        };
+        *mouse_turn_rate += (target_rate - *mouse_turn_rate) * t;
+        view.direction.0 += *mouse_turn_rate * real_delta;
+    } else {
+        *mouse_turn_rate = 0.0;
     }
-}
 
-fn draw_minimap_system(
-    state: Res<State>,
-    mut wrapper_query: Query<&mut PixelsWrapper>,
-    sector_query: Query<&Sector>,
-) {
-    if state.minimap == Minimap::Off {
-        return;
+    let delta = time_scale.delta_seconds(&time);
+    if actions.pressed(Action::TurnLeft) {
+        view.direction.0 += movement_config.turn_speed * delta;
+    }
+    if actions.pressed(Action::TurnRight) {
+        view.direction.0 -= movement_config.turn_speed * delta;
     }
 
-    let Ok(mut wrapper) = wrapper_query.get_single_mut() else { return };
-    let frame = wrapper.pixels.frame_mut();
-    let view_matrix = Mat3::from_rotation_z(-state.direction.0)
-        * Mat3::from_translation(-vec2(state.position.0.x, state.position.0.y));
-    let reverse_view_matrix = Mat3::from_translation(vec2(state.position.0.x, state.position.0.y))
-        * Mat3::from_rotation_z(state.direction.0);
-
-    // Draw walls
-    for sector in &sector_query {
-        for wall in sector.to_walls() {
-            let color: RawColor = wall.color.into();
-            let view_left = wall.left.transform(view_matrix);
-            let view_right = wall.right.transform(view_matrix);
-
-            let mut view_left_after_clip = view_left;
-            let mut view_right_after_clip = view_right;
-
-            let clipping = clip_wall(view_left, view_right);
-            if let Some((l, r)) = clipping {
-                view_left_after_clip = l;
-                view_right_after_clip = r;
-            }
+    state.velocity.0.x = 0.0;
+    state.velocity.0.y = 0.0;
+    state.velocity.0.z = 0.0;
 
-            if let Some((left, right, left_after_clip, right_after_clip)) = match state.minimap {
-                Minimap::Off => None,
-                Minimap::FirstPerson => Some((
-                    view_left.into(),
-                    view_right.into(),
-                    view_left_after_clip.into(),
-                    view_right_after_clip.into(),
-                )),
-                Minimap::Absolute => {
-                    let abs_left = wall.left;
-                    let abs_right = wall.right;
-
-                    let abs_left_after_clip = view_left_after_clip.transform(reverse_view_matrix);
-                    let abs_right_after_clip = view_right_after_clip.transform(reverse_view_matrix);
-
-                    Some((
-                        abs_left.into(),
-                        abs_right.into(),
-                        abs_left_after_clip.into(),
-                        abs_right_after_clip.into(),
-                    ))
-                }
-            } {
-                if clipping.is_none() {
-                    draw_line(frame, left, right, *WALL_CLIPPED_COLOR);
-                    continue;
-                }
-                if left_after_clip != left {
-                    draw_line(frame, left, left_after_clip, *WALL_CLIPPED_COLOR);
-                }
-                if right_after_clip != right {
-                    draw_line(frame, right_after_clip, right, *WALL_CLIPPED_COLOR);
-                }
-                draw_line(frame, left_after_clip, right_after_clip, color);
-            }
+    if actions.pressed(Action::MoveForward) {
+        state.velocity.0.x -= view.direction.0.sin();
+        state.velocity.0.y += view.direction.0.cos();
+    }
+    if actions.pressed(Action::MoveBack) {
+        state.velocity.0.x += view.direction.0.sin();
+        state.velocity.0.y -= view.direction.0.cos();
+    }
+    if actions.pressed(Action::StrafeLeft) {
+        state.velocity.0.x -= view.direction.0.cos();
+        state.velocity.0.y -= view.direction.0.sin();
+    }
+    if actions.pressed(Action::StrafeRight) {
+        state.velocity.0.x += view.direction.0.cos();
+        state.velocity.0.y += view.direction.0.sin();
+    }
+    if *movement_mode == MovementMode::Fly {
+        if actions.pressed(Action::FlyUp) {
+            state.velocity.0.z += 1.0;
+        }
+        if actions.pressed(Action::FlyDown) {
+            state.velocity.0.z -= 1.0;
         }
     }
 
-    // Draw frustum and player
-    let view_player = Position2(vec2(0.0, 0.0));
-    let view_near_left = Position2(*LEFT_CLIP_1);
-    let view_near_right = Position2(*RIGHT_CLIP_2);
-    let view_far_left = Position2(*LEFT_CLIP_2);
-    let view_far_right = Position2(*RIGHT_CLIP_1);
-
-    if let Some((player, near_left, near_right, far_left, far_right)) = match state.minimap {
-        Minimap::Off => None,
-        Minimap::FirstPerson => Some((
-            view_player.into(),
-            view_near_left.into(),
-            view_near_right.into(),
-            view_far_left.into(),
-            view_far_right.into(),
-        )),
-        Minimap::Absolute => {
-            let abs_player = state.position.truncate();
-            let abs_near_left = view_near_left.transform(reverse_view_matrix);
-            let abs_near_right = view_near_right.transform(reverse_view_matrix);
-            let abs_far_left = view_far_left.transform(reverse_view_matrix);
-            let abs_far_right = view_far_right.transform(reverse_view_matrix);
-
-            Some((
-                abs_player.into(),
-                abs_near_left.into(),
-                abs_near_right.into(),
-                abs_far_left.into(),
-                abs_far_right.into(),
-            ))
+    // Wading or swimming through liquid is slower
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let feet = view.position.0.z - EYE_HEIGHT;
+    let in_liquid = view
+        .current_sector
+        .and_then(|id| sectors.iter().find(|s| s.id == id))
+        .and_then(|sector| sector.liquid_at(feet))
+        .is_some();
+    let liquid_factor = if in_liquid { Liquid::SPEED_FACTOR } else { 1.0 };
+
+    let speed = movement_config.walk_speed * delta * liquid_factor;
+    let start = view.position.truncate();
+    let end = Position2(start.0 + speed * state.velocity.0.truncate());
+
+    // Collide with walls once the starting sector is known. Noclip passes through
+    // them into whichever sector is reached, staying in the last outside the map
+    let position = match view.current_sector {
+        Some(_) if debug_toggles.noclip => {
+            if let Some(sector) = sectors.iter().find(|s| s.contains(end)) {
+                view.current_sector = Some(sector.id);
+            }
+            end
         }
-    } {
-        draw_line(frame, near_left, far_left, *FRUSTUM_COLOR);
-        draw_line(frame, near_right, far_right, *FRUSTUM_COLOR);
-        draw_line(frame, near_left, near_right, *FRUSTUM_COLOR);
-        draw_pixel(frame, player, *PLAYER_COLOR);
+        Some(sector) => {
+            // Walls up to floors too high to step onto are solid, flying rises over them
+            let step = match (*movement_mode, sectors.iter().find(|s| s.id == sector)) {
+                (MovementMode::Walk, Some(current)) => walk_step(current, view.position.0.z),
+                _ => f32::INFINITY,
+            };
+            let body = BodySize {
+                step,
+                ..BodySize::PLAYER
+            };
+            let sweep = slide_circle(&blockmap, &sectors, sector, start, end, body);
+            view.current_sector = Some(sweep.sector);
+            sweep.position
+        }
+        None => end,
+    };
+
+    view.position.0.x = position.0.x;
+    view.position.0.y = position.0.y;
+    view.position.0.z += movement_config.fly_speed * delta * liquid_factor * state.velocity.0.z;
+
+    // Walking keeps to the floor of the sector the view ends up in
+    let sector = view
+        .current_sector
+        .and_then(|id| sectors.iter().find(|s| s.id == id));
+    if let (MovementMode::Walk, Some(sector)) = (*movement_mode, sector) {
+        view.position.0.z = walk_height(sector, view.position.0.z, &mut state.fall_speed, delta);
+    } else {
+        state.fall_speed = 0.0;
     }
 }
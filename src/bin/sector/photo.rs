@@ -0,0 +1,332 @@
+use crate::input::*;
+use sector::{prelude::*, render::*};
+
+use bevy::{input::mouse::MouseMotion, prelude::*, window::CursorGrabMode};
+use std::time::SystemTime;
+
+const PHOTO_DIR: &str = "screenshots";
+/// Factor captures are scaled up by, the renderer only draws at its internal
+/// resolution.
+const CAPTURE_SCALE: u32 = 4;
+/// Free camera speed in units per second.
+const FLY_SPEED: f32 = 3.0;
+const TURN_SPEED: f32 = 1.0;
+const ROLL_SPEED: f32 = 1.0;
+const ZOOM_SPEED: f32 = 1.0;
+const MAX_ZOOM: f32 = 8.0;
+
+/// Freeze frame with a free camera, toggled with P. Simulation is paused and
+/// the minimap and HUD hidden while active.
+#[derive(Resource, Debug)]
+pub struct PhotoMode {
+    pub active: bool,
+    /// Roll of the camera in radians.
+    pub roll: f32,
+    /// Zoom factor, narrowing the field of view.
+    pub zoom: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    saved: Option<SavedState>,
+}
+
+impl Default for PhotoMode {
+    fn default() -> Self {
+        Self {
+            active: false,
+            roll: 0.0,
+            zoom: 1.0,
+            brightness: 1.0,
+            contrast: 1.0,
+            saved: None,
+        }
+    }
+}
+
+impl PhotoMode {
+    fn is_identity(&self) -> bool {
+        self.roll == 0.0 && self.zoom == 1.0 && self.brightness == 1.0 && self.contrast == 1.0
+    }
+}
+
+/// State restored on leaving photo mode.
+#[derive(Debug)]
+struct SavedState {
+    position: Position3,
+    direction: Direction,
+    current_sector: Option<SectorId>,
+    minimap: Minimap,
+    hud_visible: bool,
+    paused: bool,
+}
+
+pub fn photo_mode_inactive(photo_mode: Res<PhotoMode>) -> bool {
+    !photo_mode.active
+}
+
+pub fn photo_mode_toggle_system(
+    mut photo_mode: ResMut<PhotoMode>,
+    mut view: ResMut<View>,
+    mut minimap: ResMut<Minimap>,
+    mut hud: ResMut<Hud>,
+    mut time_scale: ResMut<TimeScale>,
+    actions: Actions,
+) {
+    if !actions.just_pressed(Action::TogglePhotoMode) {
+        return;
+    }
+
+    if let Some(saved) = photo_mode.saved.take() {
+        view.position = saved.position;
+        view.direction = saved.direction;
+        view.current_sector = saved.current_sector;
+        *minimap = saved.minimap;
+        hud.visible = saved.hud_visible;
+        time_scale.paused = saved.paused;
+        *photo_mode = PhotoMode::default();
+    } else {
+        *photo_mode = PhotoMode {
+            active: true,
+            saved: Some(SavedState {
+                position: view.position,
+                direction: view.direction,
+                current_sector: view.current_sector,
+                minimap: *minimap,
+                hud_visible: hud.visible,
+                paused: time_scale.paused,
+            }),
+            ..default()
+        };
+        *minimap = Minimap::Off;
+        hud.visible = false;
+        time_scale.paused = true;
+    }
+}
+
+/// Fly the camera freely, ignoring walls. Z/C roll, R/F zoom, [/] brightness and
+/// -/= contrast.
+pub fn photo_camera_system(
+    mut photo_mode: ResMut<PhotoMode>,
+    mut view: ResMut<View>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    actions: Actions,
+    time: Res<Time>,
+    window_query: Query<&Window>,
+    sector_query: Query<&Sector>,
+) {
+    if !photo_mode.active {
+        return;
+    }
+    let Ok(window) = window_query.get_single() else { return };
+    let delta = time.delta_seconds();
+
+    let mut camera = SectorCamera::from(&*view);
+    let cursor_locked = window.cursor.grab_mode == CursorGrabMode::Locked;
+    fly_camera(
+        &mut camera,
+        &actions,
+        &mut mouse_motion_events,
+        cursor_locked,
+        delta,
+        &sector_query,
+    );
+    view.position = camera.position;
+    view.direction = camera.direction;
+    view.current_sector = camera.current_sector;
+
+    let adjust = |value: &mut f32, less: Action, more: Action, speed: f32| {
+        if actions.pressed(less) {
+            *value -= speed * delta;
+        }
+        if actions.pressed(more) {
+            *value += speed * delta;
+        }
+    };
+    adjust(
+        &mut photo_mode.roll,
+        Action::RollRight,
+        Action::RollLeft,
+        ROLL_SPEED,
+    );
+    adjust(
+        &mut photo_mode.zoom,
+        Action::ZoomOut,
+        Action::ZoomIn,
+        ZOOM_SPEED,
+    );
+    adjust(
+        &mut photo_mode.brightness,
+        Action::Darken,
+        Action::Brighten,
+        0.5,
+    );
+    adjust(
+        &mut photo_mode.contrast,
+        Action::DecreaseContrast,
+        Action::IncreaseContrast,
+        0.5,
+    );
+
+    photo_mode.zoom = photo_mode.zoom.clamp(1.0, MAX_ZOOM);
+    photo_mode.brightness = photo_mode.brightness.clamp(0.0, 2.0);
+    photo_mode.contrast = photo_mode.contrast.clamp(0.0, 2.0);
+}
+
+/// Fly camera freely with the movement actions, turning with them or the mouse while
+/// the cursor is locked. Walls are ignored, the camera drawing from whichever sector
+/// it flies into.
+pub fn fly_camera(
+    camera: &mut SectorCamera,
+    actions: &Actions,
+    mouse_motion_events: &mut EventReader<MouseMotion>,
+    cursor_locked: bool,
+    delta: f32,
+    sector_query: &Query<&Sector>,
+) {
+    if cursor_locked {
+        for mouse_motion in mouse_motion_events.iter() {
+            camera.direction.0 += -mouse_motion.delta.x * 0.005;
+        }
+    }
+    if actions.pressed(Action::TurnLeft) {
+        camera.direction.0 += TURN_SPEED * delta;
+    }
+    if actions.pressed(Action::TurnRight) {
+        camera.direction.0 -= TURN_SPEED * delta;
+    }
+
+    let (sin, cos) = camera.direction.0.sin_cos();
+    let mut velocity = Vec3::ZERO;
+    if actions.pressed(Action::MoveForward) {
+        velocity += Vec3::new(-sin, cos, 0.0);
+    }
+    if actions.pressed(Action::MoveBack) {
+        velocity -= Vec3::new(-sin, cos, 0.0);
+    }
+    if actions.pressed(Action::StrafeLeft) {
+        velocity -= Vec3::new(cos, sin, 0.0);
+    }
+    if actions.pressed(Action::StrafeRight) {
+        velocity += Vec3::new(cos, sin, 0.0);
+    }
+    if actions.pressed(Action::FlyUp) {
+        velocity.z += 1.0;
+    }
+    if actions.pressed(Action::FlyDown) {
+        velocity.z -= 1.0;
+    }
+    camera.position.0 += velocity * FLY_SPEED * delta;
+
+    // Keep drawing from whichever sector the camera flew into
+    let position = camera.position.truncate();
+    let in_current = sector_query
+        .iter()
+        .any(|s| Some(s.id) == camera.current_sector && s.contains(position));
+    if !in_current {
+        if let Some(sector) = sector_query.iter().find(|s| s.contains(position)) {
+            camera.current_sector = Some(sector.id);
+        }
+    }
+}
+
+/// Apply roll, zoom and color tweaks to the finished frame.
+pub fn photo_post_process_system<T: FrameTarget>(
+    photo_mode: Res<PhotoMode>,
+    mut target_query: Query<&mut T>,
+) {
+    if !photo_mode.active || photo_mode.is_identity() {
+        return;
+    }
+    let Ok(mut target) = target_query.get_single_mut() else { return };
+
+    let (width, height) = (target.width() as usize, target.height() as usize);
+    let source = target.frame().to_vec();
+    let frame = target.frame_mut();
+
+    let (sin, cos) = photo_mode.roll.sin_cos();
+    let (centre_x, centre_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    let adjust = |c: u8| {
+        let c = ((c as f32 / 255.0 - 0.5) * photo_mode.contrast + 0.5) * photo_mode.brightness;
+        (c.clamp(0.0, 1.0) * 255.0) as u8
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f32 + 0.5 - centre_x;
+            let dy = y as f32 + 0.5 - centre_y;
+            let source_x = (dx * cos - dy * sin) / photo_mode.zoom + centre_x;
+            let source_y = (dx * sin + dy * cos) / photo_mode.zoom + centre_y;
+
+            let offset = (y * width + x) * 4;
+            if source_x < 0.0
+                || source_y < 0.0
+                || source_x >= width as f32
+                || source_y >= height as f32
+            {
+                frame[offset..offset + 4].copy_from_slice(&[0, 0, 0, 0xff]);
+                continue;
+            }
+
+            let source_offset = (source_y as usize * width + source_x as usize) * 4;
+            for i in 0..3 {
+                frame[offset + i] = adjust(source[source_offset + i]);
+            }
+            frame[offset + 3] = 0xff;
+        }
+    }
+}
+
+/// Save the current frame to a PNG on Enter while in photo mode.
+pub fn photo_capture_system<T: FrameTarget>(
+    photo_mode: Res<PhotoMode>,
+    actions: Actions,
+    target_query: Query<&T>,
+) {
+    // Not while switching to or from fullscreen, on the same key by default
+    if !photo_mode.active
+        || !actions.just_pressed(Action::CapturePhoto)
+        || actions.pressed(Action::FullscreenModifier)
+    {
+        return;
+    }
+    let Ok(target) = target_query.get_single() else { return };
+
+    save_png(
+        target.frame().to_vec(),
+        target.width(),
+        target.height(),
+        "photo",
+    );
+}
+
+/// Save an RGBA8 frame scaled up by `CAPTURE_SCALE` as a PNG in the screenshots
+/// directory, named with prefix and a timestamp.
+pub fn save_png(frame: Vec<u8>, width: u32, height: u32, prefix: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = format!("{PHOTO_DIR}/{prefix}-{timestamp}.png");
+    write_png(frame, width, height, &path);
+}
+
+/// Save an RGBA8 frame scaled up by `CAPTURE_SCALE` as a PNG at path.
+pub fn write_png(frame: Vec<u8>, width: u32, height: u32, path: &str) {
+    let Some(image) = image::RgbaImage::from_raw(width, height, frame) else { return };
+    let image = image::imageops::resize(
+        &image,
+        width * CAPTURE_SCALE,
+        height * CAPTURE_SCALE,
+        image::imageops::FilterType::Nearest,
+    );
+
+    let dir = std::path::Path::new(path).parent();
+    match dir
+        .map(std::fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .map_err(image::ImageError::IoError)
+        .and_then(|_| image.save(path))
+    {
+        Ok(_) => info!("saved `{path}`"),
+        Err(error) => warn!("failed to save `{path}`: {error}"),
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{console::*, input::*, photo::*};
+use sector::{load::*, prelude::*, render::*};
+
+use bevy::{app::AppExit, prelude::*};
+
+/// Camera pose to start at given with `--pose <name>`, and where to save a capture
+/// of it given with `--capture <path>`, exiting once saved. Captures are taken with
+/// the HUD hidden so they can be compared against golden images.
+#[derive(Resource, Debug, Default)]
+pub struct PoseArgs {
+    pub pose: Option<String>,
+    pub capture: Option<String>,
+    /// Whether the view is at the pose, ready to capture.
+    posed: bool,
+}
+
+impl PoseArgs {
+    pub fn from_args() -> Self {
+        let arg = |name: &str| std::env::args().skip_while(|a| a != name).nth(1);
+        Self {
+            pose: arg("--pose"),
+            capture: arg("--capture"),
+            posed: false,
+        }
+    }
+}
+
+/// Poses of the current map, ordered by name.
+fn sorted_poses<'a>(pose_query: &'a Query<&CameraPose>) -> Vec<&'a CameraPose> {
+    let mut poses: Vec<&CameraPose> = pose_query.iter().collect();
+    poses.sort_by(|a, b| a.name.cmp(&b.name));
+    poses
+}
+
+/// F6 moves the view to the next camera pose of the map.
+pub fn pose_key_system(
+    mut index: Local<usize>,
+    mut view: ResMut<View>,
+    actions: Actions,
+    pose_query: Query<&CameraPose>,
+) {
+    if !actions.just_pressed(Action::SavePose) {
+        return;
+    }
+    let poses = sorted_poses(&pose_query);
+    if poses.is_empty() {
+        info!("no camera poses in map");
+        return;
+    }
+
+    *index = (*index + 1) % poses.len();
+    view.set_pose(poses[*index]);
+    info!("pose {}", poses[*index].name);
+}
+
+/// Move the view to a camera pose, `pose <name>`, or list them with `pose`.
+pub fn pose_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut view: ResMut<View>,
+    pose_query: Query<&CameraPose>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "pose" {
+            continue;
+        }
+        let poses = sorted_poses(&pose_query);
+        if let Some(name) = command.args.first() {
+            match poses.iter().find(|pose| &pose.name == name) {
+                Some(pose) => view.set_pose(pose),
+                None => warn!("pose: no pose named `{name}`"),
+            }
+        } else {
+            let names: Vec<&str> = poses.iter().map(|pose| pose.name.as_str()).collect();
+            info!("poses: {}", names.join(", "));
+        }
+    }
+}
+
+/// Move the view to the pose given on the command line once the map has loaded.
+pub fn pose_args_system(
+    mut pose_args: ResMut<PoseArgs>,
+    mut map_load_events: EventReader<MapLoadEvent>,
+    mut view: ResMut<View>,
+    mut hud: ResMut<Hud>,
+    mut app_exit_events: EventWriter<AppExit>,
+    pose_query: Query<&CameraPose>,
+) {
+    let finished = map_load_events
+        .iter()
+        .any(|event| matches!(event, MapLoadEvent::Finished { .. }));
+    if !finished {
+        return;
+    }
+    let Some(name) = pose_args.pose.clone() else { return };
+
+    match pose_query.iter().find(|pose| pose.name == name) {
+        Some(pose) => {
+            view.set_pose(pose);
+            pose_args.posed = true;
+            if pose_args.capture.is_some() {
+                hud.visible = false;
+            }
+        }
+        None => {
+            warn!("no pose named `{name}`");
+            if pose_args.capture.is_some() {
+                app_exit_events.send(AppExit);
+            }
+        }
+    }
+}
+
+/// Save the frame at the pose given on the command line and exit.
+pub fn pose_capture_system<T: FrameTarget>(
+    mut pose_args: ResMut<PoseArgs>,
+    mut app_exit_events: EventWriter<AppExit>,
+    target_query: Query<&T>,
+) {
+    if !pose_args.posed {
+        return;
+    }
+    let Some(path) = pose_args.capture.clone() else { return };
+    let Ok(target) = target_query.get_single() else { return };
+
+    write_png(
+        target.frame().to_vec(),
+        target.width(),
+        target.height(),
+        &path,
+    );
+    pose_args.posed = false;
+    app_exit_events.send(AppExit);
+}
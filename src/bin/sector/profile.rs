@@ -0,0 +1,46 @@
+use crate::input::*;
+use sector::render::*;
+
+use bevy::prelude::*;
+
+/// Row the profile overlay starts at, under the default HUD widgets.
+const FIRST_ROW: isize = 5;
+
+/// Widgets of the profile overlay, showing time spent in each draw system in
+/// milliseconds and how much was drawn in the last frame.
+fn profile_widgets() -> Vec<HudWidget> {
+    [
+        "DRAW {draw_ms:.2} MS",
+        "BACKGROUND {background_ms:.2}",
+        "WALLS {walls_ms:.2}",
+        "SPRITES {sprites_ms:.2}",
+        "MINIMAP {minimap_ms:.2}",
+        "WALLS DRAWN {walls_drawn}",
+        "SECTORS {sectors_visited}",
+        "SPANS {column_spans}",
+        "CACHED {walls_cached}",
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(row, text)| {
+        let y = 4 + (FIRST_ROW + row as isize) * (GLYPH_HEIGHT + 2);
+        HudWidget::new(Pixel::new(4, y), text)
+    })
+    .collect()
+}
+
+/// Show or hide the profile overlay with F3.
+pub fn profile_key_system(mut hud: ResMut<Hud>, actions: Actions) {
+    if !actions.just_pressed(Action::ToggleProfile) {
+        return;
+    }
+    let widgets = profile_widgets();
+    let shown = hud.widgets.iter().any(|w| w.text == widgets[0].text);
+    if shown {
+        hud.widgets
+            .retain(|w| !widgets.iter().any(|profile| profile.text == w.text));
+    } else {
+        hud.widgets.extend(widgets);
+    }
+    info!("profile overlay {}", !shown);
+}
@@ -0,0 +1,45 @@
+use crate::input::*;
+use sector::render::*;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use std::f32::consts::PI;
+
+/// Picture in picture at the top of the window, looking behind the player. Toggled
+/// with M.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct RearView;
+
+const REAR_VIEWPORT: Viewport = Viewport::new(0.35, 0.03, 0.65, 0.28);
+
+pub fn rear_view_key_system(
+    mut commands: Commands,
+    actions: Actions,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+    rear_view_query: Query<Entity, With<RearView>>,
+) {
+    if !actions.just_pressed(Action::ToggleRearView) {
+        return;
+    }
+    if let Ok(rear_view) = rear_view_query.get_single() {
+        commands.entity(rear_view).despawn_recursive();
+        info!("rear view off");
+    } else if let Ok(window) = window_query.get_single() {
+        let rear_view = commands
+            .spawn((SectorCamera::default(), REAR_VIEWPORT, RearView))
+            .id();
+        commands.entity(window).add_child(rear_view);
+        info!("rear view on");
+    }
+}
+
+/// Keep the rear view camera at the player, facing the other way.
+pub fn rear_view_system(
+    view: Res<View>,
+    mut camera_query: Query<&mut SectorCamera, With<RearView>>,
+) {
+    for mut camera in &mut camera_query {
+        *camera = SectorCamera::from(&*view);
+        camera.direction.0 += PI;
+        camera.pitch = 0.0;
+    }
+}
@@ -0,0 +1,109 @@
+use crate::input::*;
+use sector::render::*;
+
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use bevy_pixels::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Resolution the frame is rendered at, cycled with F2. Native follows the window's
+/// size in physical pixels.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    #[default]
+    Low,
+    Medium,
+    Native,
+}
+
+pub fn resolution_key_system(mut resolution: ResMut<Resolution>, actions: Actions) {
+    if actions.just_pressed(Action::CycleResolution) {
+        *resolution = match *resolution {
+            Resolution::Low => Resolution::Medium,
+            Resolution::Medium => Resolution::Native,
+            Resolution::Native => Resolution::Low,
+        };
+        info!("resolution {:?}", *resolution);
+    }
+}
+
+/// Set the render resolution when it's switched, or when the window is resized while
+/// native.
+pub fn resolution_system(
+    resolution: Res<Resolution>,
+    mut config: ResMut<RenderConfig>,
+    mut window_query: Query<(Ref<Window>, Option<&mut PixelsOptions>), With<PrimaryWindow>>,
+) {
+    let Ok((window, options)) = window_query.get_single_mut() else { return };
+    let native_resized = *resolution == Resolution::Native && window.is_changed();
+    if !resolution.is_changed() && !native_resized {
+        return;
+    }
+
+    let (width, height) = match *resolution {
+        Resolution::Low => (WIDTH, HEIGHT),
+        Resolution::Medium => (2 * WIDTH, 2 * HEIGHT),
+        Resolution::Native => (window.physical_width(), window.physical_height()),
+    };
+    if (config.width(), config.height()) == (width, height) {
+        return;
+    }
+    config.set_resolution(width, height);
+
+    // Kept matching so `bevy_pixels` doesn't size the buffer back
+    if let Some(mut options) = options {
+        options.width = width;
+        options.height = height;
+    }
+}
+
+/// Whether the window is borderless fullscreen, toggled with Alt+Enter and kept in the
+/// config file.
+#[derive(Resource, Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    Fullscreen,
+}
+
+impl From<DisplayMode> for WindowMode {
+    fn from(display_mode: DisplayMode) -> Self {
+        match display_mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Fullscreen => WindowMode::BorderlessFullscreen,
+        }
+    }
+}
+
+pub fn display_mode_key_system(mut display_mode: ResMut<DisplayMode>, actions: Actions) {
+    if actions.pressed(Action::FullscreenModifier) && actions.just_pressed(Action::ToggleFullscreen)
+    {
+        *display_mode = match *display_mode {
+            DisplayMode::Windowed => DisplayMode::Fullscreen,
+            DisplayMode::Fullscreen => DisplayMode::Windowed,
+        };
+        info!("display mode {:?}", *display_mode);
+    }
+}
+
+/// Put the window in the display mode when it's switched, saving it to the config.
+/// The frame is scaled to the new window size by `bevy_pixels`, or re-rendered at it
+/// by [`resolution_system`] while native.
+pub fn display_mode_system(
+    display_mode: Res<DisplayMode>,
+    mut input_map: ResMut<InputMap>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !display_mode.is_changed() {
+        return;
+    }
+    if let Ok(mut window) = window_query.get_single_mut() {
+        window.mode = (*display_mode).into();
+    }
+    if input_map.display_mode != *display_mode {
+        input_map.display_mode = *display_mode;
+        input_map.save();
+    }
+}
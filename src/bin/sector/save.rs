@@ -0,0 +1,118 @@
+use crate::input::*;
+use sector::{config::config_path, hub::*, load::*, persist::*, prelude::*};
+
+use bevy::prelude::*;
+
+const QUICK_SAVE_FILE: &str = "quicksave.ron";
+
+/// Player of a quick load waiting for its map to finish loading.
+#[derive(Resource, Debug, Default)]
+pub struct QuickLoad {
+    player: Option<SavedPlayer>,
+}
+
+pub fn quick_save_pressed(actions: Actions) -> bool {
+    actions.just_pressed(Action::QuickSave)
+}
+
+/// Save the view and the state of every map visited in the hub to the quick save
+/// file, F5.
+pub fn quick_save_system(world: &mut World) {
+    let path = match config_path(QUICK_SAVE_FILE) {
+        Some(path) => path,
+        None => {
+            warn!("quick save isn't supported here");
+            return;
+        }
+    };
+    let world_state = world.resource::<WorldState>();
+    let map = match world_state.current_map() {
+        Some(map) => map,
+        None => {
+            warn!("quick save: no map to save");
+            return;
+        }
+    };
+    let view = world.resource::<View>();
+    let player = SavedPlayer {
+        map: map.to_string(),
+        hub_maps: world_state.hub_maps().to_vec(),
+        position: view.position.0.to_array(),
+        direction: view.direction.0,
+        pitch: view.pitch,
+        sector: view.current_sector.map(|id| id.0),
+    };
+
+    capture_map(world);
+    let registry = world.resource::<AppTypeRegistry>().0.clone();
+    let result = world
+        .resource::<Persistence>()
+        .save_game(&registry, &player)
+        .map_err(|error| error.to_string())
+        .and_then(|text| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+            }
+            std::fs::write(&path, text).map_err(|error| error.to_string())
+        });
+    match result {
+        Ok(()) => info!("saved game to `{}`", path.display()),
+        Err(error) => warn!("failed to save game to `{}`: {error}", path.display()),
+    }
+}
+
+/// Load the quick save file, F9, travelling to the map it was saved in.
+pub fn quick_load_system(
+    mut quick_load: ResMut<QuickLoad>,
+    mut persistence: ResMut<Persistence>,
+    mut world_state: ResMut<WorldState>,
+    registry: Res<AppTypeRegistry>,
+    actions: Actions,
+) {
+    if !actions.just_pressed(Action::QuickLoad) {
+        return;
+    }
+    let path = match config_path(QUICK_SAVE_FILE) {
+        Some(path) => path,
+        None => {
+            warn!("quick load isn't supported here");
+            return;
+        }
+    };
+
+    let result = std::fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| {
+            persistence
+                .load_game(&registry.read(), &text)
+                .map_err(|error| error.to_string())
+        });
+    match result {
+        Ok(player) => {
+            info!("loading game from `{}`", path.display());
+            world_state.resume(&player.map, player.hub_maps.clone());
+            quick_load.player = Some(player);
+        }
+        Err(error) => warn!("failed to load game from `{}`: {error}", path.display()),
+    }
+}
+
+/// Put the view back where it was saved once the quick loaded map has loaded.
+pub fn quick_load_view_system(
+    mut quick_load: ResMut<QuickLoad>,
+    mut map_load_events: EventReader<MapLoadEvent>,
+    mut view: ResMut<View>,
+) {
+    let finished = map_load_events
+        .iter()
+        .any(|event| matches!(event, MapLoadEvent::Finished { .. }));
+    if !finished {
+        return;
+    }
+    let Some(player) = quick_load.player.take() else { return };
+
+    view.position = Position3(Vec3::from_array(player.position));
+    view.direction = Direction(player.direction);
+    view.pitch = player.pitch;
+    view.current_sector = player.sector.map(SectorId);
+}
@@ -0,0 +1,104 @@
+use crate::{console::*, input::*};
+use sector::{prelude::*, render::*};
+
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+/// Gap kept between the view and the floor or ceiling of the sector teleported to.
+const TELEPORT_CLEARANCE: f32 = 0.1;
+
+/// Debug cvar letting a click on the absolute minimap teleport the view there, set
+/// with `teleport on` or `teleport off`. The mouse isn't captured by clicks while
+/// it applies.
+#[derive(Resource, Debug, Default)]
+pub struct DebugTeleport {
+    pub enabled: bool,
+}
+
+pub fn teleport_command_system(
+    mut console_command_events: EventReader<ConsoleCommand>,
+    mut debug_teleport: ResMut<DebugTeleport>,
+) {
+    for command in console_command_events.iter() {
+        if command.name != "teleport" {
+            continue;
+        }
+        match command.args.first().map(String::as_str) {
+            Some("on") => debug_teleport.enabled = true,
+            Some("off") => debug_teleport.enabled = false,
+            _ => {
+                warn!("teleport: expected `on` or `off`");
+                continue;
+            }
+        }
+        info!(
+            "teleport {}",
+            if debug_teleport.enabled { "on" } else { "off" }
+        );
+    }
+}
+
+/// Whether clicks on the minimap teleport rather than capture the mouse.
+pub fn minimap_teleport_active(debug_teleport: Res<DebugTeleport>, minimap: Res<Minimap>) -> bool {
+    debug_teleport.enabled && *minimap == Minimap::Absolute
+}
+
+/// Point on the frame under the cursor, in pixels from its top left. Pixels scales
+/// the frame by a whole number of physical pixels to fit the window, centred.
+pub fn cursor_frame_point(window: &Window, config: &RenderConfig) -> Option<Vec2> {
+    let scale_factor = window.scale_factor() as f32;
+    let cursor = window.cursor_position()? * scale_factor;
+    let (width, height) = (
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    let frame = vec2(config.width() as f32, config.height() as f32);
+    let scale = (width / frame.x).min(height / frame.y).floor().max(1.0);
+    let offset = (vec2(width, height) - frame * scale) / 2.0;
+
+    // Cursor position is from the bottom left of the window
+    let point = (vec2(cursor.x, height - cursor.y) - offset) / scale;
+    let inside = point.x >= 0.0 && point.y >= 0.0 && point.x < frame.x && point.y < frame.y;
+    inside.then_some(point)
+}
+
+/// Move the view to the sector clicked on the minimap.
+pub fn minimap_teleport_system(
+    mut view: ResMut<View>,
+    config: Res<RenderConfig>,
+    minimap: Res<Minimap>,
+    rotation: Res<MinimapRotation>,
+    actions: Actions,
+    window_query: Query<(&Window, &SectorCamera), With<PrimaryWindow>>,
+    sector_query: Query<&Sector>,
+) {
+    if !actions.just_pressed(Action::Teleport) {
+        return;
+    }
+    let Ok((window, camera)) = window_query.get_single() else { return };
+    let Some(point) = cursor_frame_point(window, &config) else { return };
+    let Some(transform) = MinimapTransform::new(&config, *minimap, camera, &rotation) else { return };
+
+    let position = transform.map_to_world(point);
+    let sector = match sector_query.iter().find(|s| s.contains(position)) {
+        Some(sector) => sector,
+        None => {
+            info!(
+                "teleport: ({:.2}, {:.2}) is outside every sector",
+                position.0.x, position.0.y
+            );
+            return;
+        }
+    };
+
+    view.position.0.x = position.0.x;
+    view.position.0.y = position.0.y;
+    view.position.0.z = view.position.0.z.clamp(
+        sector.floor.0 + TELEPORT_CLEARANCE,
+        (sector.ceil.0 - TELEPORT_CLEARANCE).max(sector.floor.0 + TELEPORT_CLEARANCE),
+    );
+    view.current_sector = Some(sector.id);
+    info!(
+        "teleport: ({:.2}, {:.2}) in sector {}",
+        position.0.x, position.0.y, sector.id.0
+    );
+}
@@ -0,0 +1,94 @@
+use crate::input::*;
+use sector::{
+    physics::{GRAVITY, STEP_HEIGHT},
+    prelude::*,
+};
+
+use bevy::prelude::*;
+
+/// Fraction of the way to a higher floor stepped up each second, roughly.
+const STEP_UP_RATE: f32 = 12.0;
+/// Gap kept between the eye and the ceiling.
+const HEAD_CLEARANCE: f32 = 0.1;
+
+/// Speeds the player moves and turns at, in units and radians per second of scaled
+/// time, and how mouse look responds.
+#[derive(Resource, Debug, Copy, Clone)]
+pub struct MovementConfig {
+    pub walk_speed: f32,
+    /// Speed rising and sinking while flying.
+    pub fly_speed: f32,
+    /// Turn speed with keys.
+    pub turn_speed: f32,
+    /// Radians turned per pixel of mouse motion.
+    pub mouse_sensitivity: f32,
+    /// Seconds for mouse look to catch up most of the way to the mouse, smoothing
+    /// out uneven motion. Zero turns with the mouse exactly.
+    pub mouse_smoothing: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            walk_speed: 3.0,
+            fly_speed: 3.0,
+            turn_speed: 2.0,
+            mouse_sensitivity: 0.005,
+            mouse_smoothing: 0.03,
+        }
+    }
+}
+
+/// Whether the view flies freely with Space and Ctrl, or walks on the floor under
+/// gravity. V switches between them.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum MovementMode {
+    #[default]
+    Fly,
+    Walk,
+}
+
+pub fn movement_mode_key_system(mut movement_mode: ResMut<MovementMode>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleMovementMode) {
+        *movement_mode = match *movement_mode {
+            MovementMode::Fly => MovementMode::Walk,
+            MovementMode::Walk => MovementMode::Fly,
+        };
+        info!("movement mode {:?}", *movement_mode);
+    }
+}
+
+/// Height the eye rests at in sector, on its bridge deck when the eye at z is over
+/// it, otherwise on its floor.
+pub fn rest_height(sector: &Sector, z: f32) -> f32 {
+    let feet = z - EYE_HEIGHT;
+    match sector.bridge {
+        Some(bridge) if feet >= bridge.0 - STEP_HEIGHT => bridge.0 + EYE_HEIGHT,
+        _ => sector.floor.0 + EYE_HEIGHT,
+    }
+}
+
+/// Most the floor of the next sector can rise above the floor of sector for the eye
+/// at z to walk into it. Feet off the floor, on a bridge deck or falling, clear
+/// that much more.
+pub fn walk_step(sector: &Sector, z: f32) -> f32 {
+    STEP_HEIGHT + (z - EYE_HEIGHT - sector.floor.0).max(0.0)
+}
+
+/// Height of the eye at z after delta seconds of walking in sector, falling with
+/// fall speed when above its resting height or stepping up smoothly when below.
+pub fn walk_height(sector: &Sector, z: f32, fall_speed: &mut f32, delta: f32) -> f32 {
+    let rest = rest_height(sector, z);
+    let z = if z > rest {
+        *fall_speed += GRAVITY * delta;
+        let z = (z - *fall_speed * delta).max(rest);
+        if z <= rest {
+            *fall_speed = 0.0;
+        }
+        z
+    } else {
+        *fall_speed = 0.0;
+        z + (rest - z) * (STEP_UP_RATE * delta).min(1.0)
+    };
+    z.min(sector.ceil.0 - HEAD_CLEARANCE)
+}
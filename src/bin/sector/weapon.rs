@@ -0,0 +1,62 @@
+use crate::input::*;
+use sector::{render::*, weapons::*};
+
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+/// Index of the weapon fired, within [`Weapon::ALL`]. Cycled with X.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+pub struct CurrentWeapon(usize);
+
+impl CurrentWeapon {
+    pub fn weapon(self) -> Weapon {
+        Weapon::ALL[self.0 % Weapon::ALL.len()]
+    }
+}
+
+pub fn cycle_weapon_system(mut current_weapon: ResMut<CurrentWeapon>, actions: Actions) {
+    if actions.just_pressed(Action::CycleWeapon) {
+        current_weapon.0 = (current_weapon.0 + 1) % Weapon::ALL.len();
+        info!("weapon {}", current_weapon.weapon().label());
+    }
+}
+
+/// Fire the current weapon from the eye, where the view is facing, while the mouse is
+/// captured. The click capturing it doesn't fire.
+pub fn fire_system(
+    view: Res<View>,
+    current_weapon: Res<CurrentWeapon>,
+    actions: Actions,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut fire_weapon_events: EventWriter<FireWeapon>,
+) {
+    if !actions.just_pressed(Action::Fire) {
+        return;
+    }
+    let captured = window_query
+        .get_single()
+        .is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None);
+    let Some(sector) = view.current_sector.filter(|_| captured) else { return };
+
+    fire_weapon_events.send(FireWeapon {
+        weapon: current_weapon.weapon(),
+        origin: view.position,
+        sector,
+        direction: view.direction,
+        pitch: view.pitch,
+        shooter: None,
+    });
+}
+
+pub fn weapon_hit_system(mut weapon_hit_events: EventReader<WeaponHit>) {
+    for hit in weapon_hit_events.iter() {
+        debug!(
+            "{} hit {:?} at {:.2}",
+            hit.weapon.label(),
+            hit.target,
+            hit.point.0
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::open::*;
+use sector::{hub::*, import::doom::*, render};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// WAD holding several maps, waiting on one to be chosen.
+#[derive(Debug)]
+pub struct WadChoice {
+    pub name: String,
+    pub wad: Vec<u8>,
+    pub maps: Vec<String>,
+}
+
+/// Doom map being imported in place of the map being edited.
+#[derive(Resource, Debug, Default)]
+pub struct DoomImport {
+    pub choice: Option<WadChoice>,
+    /// Map converted and ready to spawn, with the name to save it under.
+    pending: Option<(String, ImportedMap)>,
+}
+
+impl DoomImport {
+    /// Import the map in a WAD or UDMF file, asking which one when a WAD has several.
+    pub fn import_file(&mut self, name: &str, bytes: Vec<u8>) {
+        let name = name.trim_end_matches(".wad").trim_end_matches(".udmf");
+        if !bytes.starts_with(b"IWAD") && !bytes.starts_with(b"PWAD") {
+            let map = std::str::from_utf8(&bytes)
+                .map_err(|error| error.to_string())
+                .and_then(parse_udmf);
+            self.convert(name, map);
+            return;
+        }
+
+        match wad_maps(&bytes) {
+            Ok(maps) if maps.len() == 1 => {
+                let map = parse_wad(&bytes, &maps[0]);
+                self.convert(&format!("{name}_{}", maps[0]), map);
+            }
+            Ok(maps) if maps.is_empty() => error!("no maps in `{name}`"),
+            Ok(maps) => {
+                self.choice = Some(WadChoice {
+                    name: name.to_string(),
+                    wad: bytes,
+                    maps,
+                })
+            }
+            Err(error) => error!("failed to read `{name}`: {error}"),
+        }
+    }
+
+    fn convert(&mut self, name: &str, map: Result<DoomMap, String>) {
+        match map {
+            Ok(map) => {
+                let imported = convert_doom_map(&map);
+                for warning in &imported.warnings {
+                    warn!("importing `{name}`: {warning}");
+                }
+                self.pending = Some((name.to_lowercase(), imported));
+            }
+            Err(error) => error!("failed to import `{name}`: {error}"),
+        }
+    }
+}
+
+/// Doom map file chosen with a file picker, with its name.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_doom_file() -> Option<(String, Vec<u8>)> {
+    let path = rfd::FileDialog::new()
+        .set_title("Import Doom map")
+        .add_filter("Doom map", &["wad", "udmf", "txt"])
+        .pick_file()?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    match std::fs::read(&path) {
+        Ok(bytes) => Some((name, bytes)),
+        Err(error) => {
+            error!("failed to read `{}`: {error}", path.display());
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn pick_doom_file() -> Option<(String, Vec<u8>)> {
+    warn!("importing maps from files isn't supported on the web");
+    None
+}
+
+/// Ask which map of a WAD to import.
+pub fn wad_choice_system(mut contexts: EguiContexts, mut doom_import: ResMut<DoomImport>) {
+    let Some(choice) = &doom_import.choice else { return };
+    let ctx = contexts.ctx_mut();
+
+    let mut chosen: Option<String> = None;
+    let mut open = true;
+    egui::Window::new(format!("import from {}", choice.name))
+        .collapsible(false)
+        .open(&mut open)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for map in &choice.maps {
+                    if ui.selectable_label(false, map).clicked() {
+                        chosen = Some(map.clone());
+                    }
+                }
+            });
+        });
+
+    if let Some(map) = chosen {
+        let choice = doom_import.choice.take().unwrap();
+        let parsed = parse_wad(&choice.wad, &map);
+        doom_import.convert(&format!("{}_{map}", choice.name), parsed);
+    } else if !open {
+        doom_import.choice = None;
+    }
+}
+
+/// Replace the map being edited with the one imported, to be saved as a new map.
+pub fn spawn_doom_import_system(
+    mut commands: Commands,
+    mut doom_import: ResMut<DoomImport>,
+    mut editor_file: ResMut<EditorFile>,
+    mut view: ResMut<render::View>,
+    map_query: Query<Entity, Or<(UnparentedFilter, With<MapRoot>)>>,
+) {
+    let Some((name, imported)) = doom_import.pending.take() else { return };
+
+    for entity in &map_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    info!("imported `{name}` as {} sectors", imported.sectors.len());
+    spawn_imported_map(&mut commands, imported);
+    editor_file.path = format!("scenes/{name}.scn.ron");
+    // Never saved
+    editor_file.dirty = true;
+    view.current_sector = None;
+}
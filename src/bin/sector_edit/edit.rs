@@ -0,0 +1,561 @@
+use sector::{edit::*, prelude::*};
+
+use bevy::{math::vec2, prelude::*};
+use bevy_egui::egui;
+
+/// Distance in screen points within which clicks pick a vertex or wall.
+const PICK_DISTANCE: f32 = 8.0;
+/// Distance in map units two vertex positions are taken as the same within.
+const SAME_VERTEX: f32 = 1e-4;
+
+/// What clicks on the plot do to the map.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum EditMode {
+    /// Drag vertices, moving every sector sharing them unless welds are broken.
+    #[default]
+    Move,
+    /// Select vertices and sectors by clicking or dragging a box around them, with
+    /// Shift adding to the selection, and drag a selected vertex to move them all.
+    Select,
+    /// Add a vertex where a wall is clicked.
+    SplitWall,
+    /// Remove the vertex clicked.
+    RemoveVertex,
+    /// Add corners of a new sector, closing it by clicking the first again.
+    DrawSector,
+    /// Delete the sector clicked.
+    DeleteSector,
+    /// Move the player start to where clicked, inside a sector.
+    PlaceStart,
+}
+
+impl EditMode {
+    pub const ALL: [Self; 7] = [
+        Self::Move,
+        Self::Select,
+        Self::SplitWall,
+        Self::RemoveVertex,
+        Self::DrawSector,
+        Self::DeleteSector,
+        Self::PlaceStart,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Move => "move",
+            Self::Select => "select",
+            Self::SplitWall => "split wall",
+            Self::RemoveVertex => "remove vertex",
+            Self::DrawSector => "draw sector",
+            Self::DeleteSector => "delete sector",
+            Self::PlaceStart => "place start",
+        }
+    }
+}
+
+/// Tool editing map geometry by clicking on the plot.
+#[derive(Resource, Debug, Default)]
+pub struct EditTool {
+    pub mode: EditMode,
+    /// Corners of the sector being drawn.
+    pub drawing: Vec<Vec2>,
+    /// Drag only the corner of the sector grabbed, parting it from neighbours
+    /// sharing the vertex.
+    pub break_welds: bool,
+    /// Where a start was placed by the last click, and the sector containing it,
+    /// until taken.
+    pub placed_start: Option<(SectorId, Position2)>,
+    pub selection: Selection,
+    pub group: GroupEdit,
+    /// Vertex under the pointer when last drawn.
+    hovered: Option<Vec2>,
+    drag: Option<VertexDrag>,
+    /// Corners of the box being dragged out to select in.
+    band: Option<(Vec2, Vec2)>,
+    /// Where the selected vertex grabbed to move the selection is now.
+    group_drag: Option<Vec2>,
+}
+
+impl EditTool {
+    pub fn set_mode(&mut self, mode: EditMode) {
+        self.mode = mode;
+        self.drawing.clear();
+        self.drag = None;
+        self.band = None;
+        self.group_drag = None;
+    }
+
+    /// Whether dragging on the plot should pan it, rather than move a vertex or select.
+    pub fn allows_plot_drag(&self) -> bool {
+        match self.mode {
+            EditMode::Move => self.hovered.is_none() && self.drag.is_none(),
+            EditMode::Select => false,
+            _ => true,
+        }
+    }
+}
+
+/// Vertices selected for group edits, by position, and sectors selected with all
+/// their vertices.
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+    pub vertices: Vec<Vec2>,
+    pub sectors: Vec<SectorId>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty() && self.sectors.is_empty()
+    }
+
+    pub fn contains(&self, vertex: Vec2) -> bool {
+        self.vertices
+            .iter()
+            .any(|v| v.distance(vertex) < SAME_VERTEX)
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.sectors.clear();
+    }
+
+    /// Select vertices and sectors, in place of those selected unless adding.
+    fn select(&mut self, vertices: Vec<Vec2>, sectors: Vec<SectorId>, adding: bool) {
+        if !adding {
+            self.clear();
+        }
+        for vertex in vertices {
+            if !self.contains(vertex) {
+                self.vertices.push(vertex);
+            }
+        }
+        for sector in sectors {
+            if !self.sectors.contains(&sector) {
+                self.sectors.push(sector);
+            }
+        }
+    }
+
+    /// Middle of the box around the selected vertices.
+    pub fn centre(&self) -> Option<Vec2> {
+        let first = *self.vertices.first()?;
+        let (min, max) = self
+            .vertices
+            .iter()
+            .fold((first, first), |(min, max), &v| (min.min(v), max.max(v)));
+        Some((min + max) / 2.0)
+    }
+
+    /// Move each selected vertex to where f takes it, along with every sector sharing
+    /// it, and keep it selected there.
+    pub fn transform(
+        &mut self,
+        f: impl Fn(Vec2) -> Vec2,
+        geometry_edits: &mut EventWriter<GeometryEdit>,
+    ) {
+        let moves: Vec<(Vec2, Vec2)> = self
+            .vertices
+            .iter()
+            .map(|&v| (v, f(v)))
+            .filter(|(from, to)| from != to)
+            .collect();
+        if moves.is_empty() {
+            return;
+        }
+        self.vertices = self.vertices.iter().map(|&v| f(v)).collect();
+        geometry_edits.send(GeometryEdit::MoveVertexGroup(moves));
+    }
+}
+
+/// Amounts the selection is moved, turned, scaled and raised by from the panel.
+#[derive(Debug, Copy, Clone)]
+pub struct GroupEdit {
+    pub offset: Vec2,
+    /// Anticlockwise, about the middle of the selection.
+    pub angle_degrees: f32,
+    /// About the middle of the selection.
+    pub scale: f32,
+    pub floor: f32,
+    pub ceil: f32,
+}
+
+impl Default for GroupEdit {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            angle_degrees: 90.0,
+            scale: 2.0,
+            floor: 0.5,
+            ceil: 0.5,
+        }
+    }
+}
+
+/// Vertex being dragged, where it is now.
+#[derive(Debug, Copy, Clone)]
+struct VertexDrag {
+    at: Vec2,
+    /// Only sector whose corner is moved, when welds are broken.
+    sector: Option<SectorId>,
+}
+
+/// Draw the sector being drawn on the plot and send edits for clicks and drags while
+/// the tool is in use. Clicks land on nearby vertices, otherwise at cursor.
+pub fn edit_plot(
+    plot_ui: &mut egui::plot::PlotUi,
+    edit_tool: &mut EditTool,
+    sectors: &[&Sector],
+    cursor: Option<Vec2>,
+    geometry_edits: &mut EventWriter<GeometryEdit>,
+) {
+    let (pressed, down) = plot_ui
+        .ctx()
+        .input(|i| (i.pointer.primary_pressed(), i.pointer.primary_down()));
+    if !down {
+        edit_tool.drag = None;
+        edit_tool.group_drag = None;
+    }
+    edit_tool.hovered = None;
+    let pointer = match plot_ui.pointer_coordinate() {
+        Some(pointer) => pointer,
+        None => {
+            // Released outside the plot
+            if !down {
+                edit_tool.band = None;
+            }
+            return;
+        }
+    };
+    let pointer = vec2(pointer.x as f32, pointer.y as f32);
+
+    // Distance between plot points in screen points, the plot keeping its aspect
+    let scale = {
+        let origin = plot_ui.screen_from_plot(egui::plot::PlotPoint::new(0.0, 0.0));
+        let unit = plot_ui.screen_from_plot(egui::plot::PlotPoint::new(1.0, 0.0));
+        unit.x - origin.x
+    };
+    let screen_distance = |a: Vec2, b: Vec2| a.distance(b) * scale;
+    let vertex = sectors
+        .iter()
+        .flat_map(|s| s.vertices.iter().map(|v| v.0))
+        .filter(|&v| screen_distance(v, pointer) < PICK_DISTANCE)
+        .min_by(|a, b| a.distance(pointer).total_cmp(&b.distance(pointer)));
+    let point = vertex.or(cursor).unwrap_or(pointer);
+    edit_tool.hovered = vertex;
+
+    if edit_tool.mode == EditMode::Move {
+        if let (true, true, Some(vertex)) = (pressed, plot_ui.plot_hovered(), vertex) {
+            // Corner of the sector clicked inside, when there's a choice
+            let sector = edit_tool.break_welds.then(|| {
+                let mut sharing = sectors
+                    .iter()
+                    .filter(|s| s.vertices.iter().any(|v| v.0 == vertex));
+                let first = sharing.clone().next().map(|s| s.id);
+                sharing
+                    .find(|s| s.contains(Position2(pointer)))
+                    .map(|s| s.id)
+                    .or(first)
+            });
+            edit_tool.drag = Some(VertexDrag {
+                at: vertex,
+                sector: sector.flatten(),
+            });
+        }
+        if let (Some(drag), Some(cursor)) = (&mut edit_tool.drag, cursor) {
+            if drag.at != cursor {
+                geometry_edits.send(GeometryEdit::MoveVertices {
+                    from: drag.at,
+                    to: cursor,
+                    sector: drag.sector,
+                });
+                drag.at = cursor;
+            }
+        }
+
+        let grabbable = edit_tool.drag.map(|drag| drag.at).or(vertex);
+        if let Some(grabbable) = grabbable {
+            plot_ui.points(
+                egui::plot::Points::new(vec![[grabbable.x as f64, grabbable.y as f64]])
+                    .color(egui::Color32::LIGHT_GREEN)
+                    .radius(5.0),
+            );
+        }
+    }
+
+    if edit_tool.mode == EditMode::Select {
+        select_plot(
+            plot_ui,
+            edit_tool,
+            sectors,
+            (pointer, scale),
+            cursor,
+            vertex,
+            geometry_edits,
+        );
+    }
+
+    if plot_ui.plot_clicked() {
+        match edit_tool.mode {
+            EditMode::Move | EditMode::Select => {}
+            EditMode::SplitWall => {
+                let wall = sectors
+                    .iter()
+                    .flat_map(|s| s.to_walls())
+                    .map(|wall| closest_on_wall(&wall, point))
+                    .filter(|&on_wall| screen_distance(on_wall, pointer) < PICK_DISTANCE)
+                    .min_by(|a, b| a.distance(pointer).total_cmp(&b.distance(pointer)));
+                if let Some(on_wall) = wall {
+                    geometry_edits.send(GeometryEdit::SplitWalls(on_wall));
+                }
+            }
+            EditMode::RemoveVertex => {
+                if let Some(vertex) = vertex {
+                    geometry_edits.send(GeometryEdit::RemoveVertices(vertex));
+                }
+            }
+            EditMode::DrawSector => {
+                let closing = edit_tool.drawing.first() == Some(&point);
+                if closing && edit_tool.drawing.len() >= 3 {
+                    geometry_edits.send(GeometryEdit::AddSector(std::mem::take(
+                        &mut edit_tool.drawing,
+                    )));
+                } else if !edit_tool.drawing.contains(&point) {
+                    edit_tool.drawing.push(point);
+                }
+            }
+            EditMode::DeleteSector => {
+                if let Some(sector) = sectors.iter().find(|s| s.contains(Position2(pointer))) {
+                    geometry_edits.send(GeometryEdit::DeleteSector(sector.id));
+                }
+            }
+            EditMode::PlaceStart => {
+                let at = Position2(cursor.unwrap_or(pointer));
+                if let Some(sector) = sectors.iter().find(|s| s.contains(at)) {
+                    edit_tool.placed_start = Some((sector.id, at));
+                }
+            }
+        }
+    }
+
+    if edit_tool.mode == EditMode::DrawSector && !edit_tool.drawing.is_empty() {
+        let points: Vec<[f64; 2]> = edit_tool
+            .drawing
+            .iter()
+            .chain(std::iter::once(&point))
+            .map(|p| [p.x as f64, p.y as f64])
+            .collect();
+        plot_ui.line(
+            egui::plot::Line::new(egui::plot::PlotPoints::new(points.clone()))
+                .color(egui::Color32::LIGHT_GREEN)
+                .width(2.0),
+        );
+        plot_ui.points(
+            egui::plot::Points::new(points)
+                .color(egui::Color32::LIGHT_GREEN)
+                .radius(3.0),
+        );
+    }
+}
+
+/// Select with clicks and boxes dragged out on the plot, or move the selection when a
+/// selected vertex is dragged, drawing the selection and box. Scale is the distance
+/// between plot points in screen points.
+fn select_plot(
+    plot_ui: &mut egui::plot::PlotUi,
+    edit_tool: &mut EditTool,
+    sectors: &[&Sector],
+    (pointer, scale): (Vec2, f32),
+    cursor: Option<Vec2>,
+    vertex: Option<Vec2>,
+    geometry_edits: &mut EventWriter<GeometryEdit>,
+) {
+    let (pressed, released, adding) = plot_ui.ctx().input(|i| {
+        (
+            i.pointer.primary_pressed(),
+            i.pointer.primary_released(),
+            i.modifiers.shift,
+        )
+    });
+
+    if pressed && plot_ui.plot_hovered() {
+        match vertex.filter(|&v| edit_tool.selection.contains(v)) {
+            Some(grabbed) => edit_tool.group_drag = Some(grabbed),
+            None => edit_tool.band = Some((pointer, pointer)),
+        }
+    }
+
+    if let (Some(at), Some(cursor)) = (edit_tool.group_drag, cursor) {
+        let offset = cursor - at;
+        edit_tool
+            .selection
+            .transform(|v| v + offset, geometry_edits);
+        edit_tool.group_drag = Some(cursor);
+    }
+
+    if let Some((_, end)) = &mut edit_tool.band {
+        *end = pointer;
+    }
+    if released {
+        if let Some((start, end)) = edit_tool.band.take() {
+            let (vertices, selected_sectors) = if start.distance(end) * scale < PICK_DISTANCE {
+                // A click selects the vertex under it, otherwise the sector
+                match (vertex, sectors.iter().find(|s| s.contains(Position2(end)))) {
+                    (Some(vertex), _) => (vec![vertex], Vec::new()),
+                    (None, Some(sector)) => (
+                        sector.vertices.iter().map(|v| v.0).collect(),
+                        vec![sector.id],
+                    ),
+                    (None, None) => (Vec::new(), Vec::new()),
+                }
+            } else {
+                let (min, max) = (start.min(end), start.max(end));
+                let inside = |v: &Position2| v.0.cmpge(min).all() && v.0.cmple(max).all();
+                let vertices = sectors
+                    .iter()
+                    .flat_map(|s| s.vertices.iter().filter(|v| inside(v)).map(|v| v.0))
+                    .collect();
+                let selected_sectors = sectors
+                    .iter()
+                    .filter(|s| s.vertices.iter().all(inside))
+                    .map(|s| s.id)
+                    .collect();
+                (vertices, selected_sectors)
+            };
+            edit_tool
+                .selection
+                .select(vertices, selected_sectors, adding);
+        }
+    }
+
+    if !edit_tool.selection.vertices.is_empty() {
+        let points: Vec<[f64; 2]> = edit_tool
+            .selection
+            .vertices
+            .iter()
+            .map(|v| [v.x as f64, v.y as f64])
+            .collect();
+        plot_ui.points(
+            egui::plot::Points::new(points)
+                .color(egui::Color32::LIGHT_BLUE)
+                .filled(true)
+                .radius(4.0),
+        );
+    }
+    if let Some((start, end)) = edit_tool.band {
+        let corners = [
+            start,
+            vec2(start.x, end.y),
+            end,
+            vec2(end.x, start.y),
+            start,
+        ];
+        plot_ui.line(
+            egui::plot::Line::new(egui::plot::PlotPoints::new(
+                corners.iter().map(|p| [p.x as f64, p.y as f64]).collect(),
+            ))
+            .color(egui::Color32::LIGHT_BLUE)
+            .width(1.0),
+        );
+    }
+}
+
+/// Size of the selection and edits to it as a group: moving, rotating and scaling
+/// about its middle, aligning it to grid_size and raising the floors and ceilings of
+/// its sectors.
+pub fn selection_ui(
+    ui: &mut egui::Ui,
+    edit_tool: &mut EditTool,
+    grid_size: f32,
+    geometry_edits: &mut EventWriter<GeometryEdit>,
+) {
+    let EditTool {
+        selection, group, ..
+    } = edit_tool;
+
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "selected: {} vertices, {} sectors",
+            selection.vertices.len(),
+            selection.sectors.len()
+        ));
+        if ui.button("clear").clicked() {
+            selection.clear();
+        }
+    });
+    if selection.is_empty() {
+        return;
+    }
+    let centre = selection.centre().unwrap_or_default();
+
+    ui.horizontal(|ui| {
+        let clicked = ui.button("move").clicked();
+        ui.add(
+            egui::DragValue::new(&mut group.offset.x)
+                .speed(0.1)
+                .prefix("x: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut group.offset.y)
+                .speed(0.1)
+                .prefix("y: "),
+        );
+        if clicked {
+            let offset = group.offset;
+            selection.transform(|v| v + offset, geometry_edits);
+        }
+    });
+    ui.horizontal(|ui| {
+        let clicked = ui.button("rotate").clicked();
+        ui.add(
+            egui::DragValue::new(&mut group.angle_degrees)
+                .speed(1.0)
+                .suffix("°"),
+        );
+        if clicked {
+            let rotation = Vec2::from_angle(group.angle_degrees.to_radians());
+            selection.transform(|v| centre + rotation.rotate(v - centre), geometry_edits);
+        }
+    });
+    ui.horizontal(|ui| {
+        let clicked = ui.button("scale").clicked();
+        ui.add(
+            egui::DragValue::new(&mut group.scale)
+                .speed(0.05)
+                .clamp_range(0.05..=20.0)
+                .prefix("×"),
+        );
+        if clicked {
+            let scale = group.scale;
+            selection.transform(|v| centre + (v - centre) * scale, geometry_edits);
+        }
+        if ui.button("align to grid").clicked() && grid_size > 0.0 {
+            selection.transform(|v| (v / grid_size).round() * grid_size, geometry_edits);
+        }
+    });
+
+    if selection.sectors.is_empty() {
+        return;
+    }
+    ui.horizontal(|ui| {
+        let clicked = ui.button("raise").clicked();
+        ui.add(
+            egui::DragValue::new(&mut group.floor)
+                .speed(0.1)
+                .prefix("floor: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut group.ceil)
+                .speed(0.1)
+                .prefix("ceil: "),
+        );
+        if clicked {
+            for &sector in &selection.sectors {
+                geometry_edits.send(GeometryEdit::RaiseHeights {
+                    sector,
+                    floor: group.floor,
+                    ceil: group.ceil,
+                });
+            }
+        }
+    });
+}
@@ -1,22 +1,43 @@
-use sector::*;
+mod doom;
+mod edit;
+mod measure;
+mod open;
+mod preview;
+mod recolor;
+mod script;
+mod settings;
+
+use crate::{
+    doom::*, edit::*, measure::*, open::*, preview::*, recolor::*, script::*, settings::*,
+};
+use sector::{
+    edit::*,
+    hub::*,
+    load::{MapLoad, MapLoadEvent},
+    pathfind::*,
+    prelude::*,
+    render::*,
+    storage::*,
+    validate::*,
+};
 
 use bevy::{
     app::AppExit,
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     math::vec2,
     prelude::*,
-    scene::serde::SceneSerializer,
     tasks::IoTaskPool,
     utils::Duration,
-    window::WindowResolution,
+    window::{PrimaryWindow, WindowResolution},
 };
-use bevy_egui::{egui, EguiContexts, EguiPlugin};
+use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiSet};
+use bevy_pixels::prelude::*;
 use palette::named::*;
-use std::fs::File;
-use std::io::Write;
 
 const WIDTH: f32 = 1280.0;
 const HEIGHT: f32 = 960.0;
+/// Length of the line showing which way player starts face on the plot.
+const START_FACING_LENGTH: f32 = 0.75;
 
 #[derive(Resource, Debug)]
 struct State {
@@ -24,34 +45,70 @@ struct State {
 }
 
 fn main() {
-    App::new()
-        .register_type::<SectorId>()
-        .register_type::<Option<SectorId>>()
-        .register_type::<Sector>()
-        .register_type::<InitialSector>()
-        .register_type::<Position2>()
-        .register_type::<Length>()
-        .register_type::<RawColor>()
-        .insert_resource(State {
-            update_title_timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
-        })
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "sector_edit".to_string(),
-                resolution: WindowResolution::new(WIDTH, HEIGHT),
-                fit_canvas_to_parent: true,
-                ..default()
-            }),
+    let mut app = App::new();
+    // Maps saved in the browser load in place of those deployed with it
+    #[cfg(target_arch = "wasm32")]
+    app.insert_resource(stored_scene_asset_server(&AssetPlugin::default()));
+    app.insert_resource(State {
+        update_title_timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
+    })
+    .insert_resource(EditorSettings::load())
+    .init_resource::<EditorFile>()
+    .init_resource::<QuickOpen>()
+    .init_resource::<Measure>()
+    .init_resource::<Recolor>()
+    .init_resource::<EditTool>()
+    .init_resource::<DoomImport>()
+    .add_event::<OpenMap>()
+    .add_event::<SaveMap>()
+    .add_event::<GeometryEdit>()
+    .add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "sector_edit".to_string(),
+            resolution: WindowResolution::new(WIDTH, HEIGHT),
+            fit_canvas_to_parent: true,
             ..default()
-        }))
-        .add_plugin(EguiPlugin)
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .add_startup_system(init_scene_system)
-        .add_system(save_scene_system)
-        .add_system(update_title_system)
-        .add_system(escape_system)
-        .add_system(egui_system)
-        .run();
+        }),
+        ..default()
+    }))
+    .add_plugin(EguiPlugin)
+    .add_plugin(FrameTimeDiagnosticsPlugin)
+    .add_plugin(SectorPlugin)
+    .add_plugin(SectorRenderPlugin::<ImageFrame>::default())
+    .add_startup_system(init_scene_system)
+    .add_startup_system(spawn_preview_frame_system)
+    .add_startup_system(load_script_system)
+    .add_system(
+        save_scene_system
+            .after(file_menu_system)
+            .after(save_key_system),
+    )
+    .add_system(dirty_system.in_base_set(CoreSet::Last))
+    .add_system(update_title_system)
+    .add_system(escape_system)
+    .add_system(quick_open_key_system)
+    .add_system(save_key_system)
+    .add_system(measure_key_system.before(egui_system))
+    .add_systems((file_menu_system, egui_system, quick_open_system).chain())
+    .add_system(open_map_system.after(quick_open_system))
+    .add_system(wad_choice_system.after(file_menu_system))
+    .add_system(spawn_doom_import_system.after(wad_choice_system))
+    .add_system(
+        preview_window_system
+            .after(egui_system)
+            .after(PixelsSet::Draw),
+    )
+    .add_system(geometry_edit_system.after(egui_system))
+    .add_system(save_settings_system.after(escape_system).after(egui_system))
+    .add_system(preview_sector_system.in_set(SectorSet::Simulation))
+    .add_system(
+        script_system
+            .run_if(resource_exists::<ScriptRunner>())
+            .in_base_set(CoreSet::PreUpdate)
+            .after(EguiSet::ProcessInput)
+            .before(EguiSet::BeginFrame),
+    )
+    .run();
 }
 
 fn init_scene_system(world: &mut World) {
@@ -67,155 +124,759 @@ fn init_scene_system(world: &mut World) {
     let v8 = Position2(vec2(-7.0, -9.0));
     let v9 = Position2(vec2(-10.0, -5.0));
 
+    // Saved to the default scene until saved elsewhere
+    world
+        .resource_mut::<EditorSettings>()
+        .add_recent_file(DEFAULT_SCENE_RON_FILE_PATH);
+
     // Spawn singleton component entity
     world.spawn(InitialSector(SectorId(0)));
-
-    world.spawn(Sector {
-        id: SectorId(0),
-        vertices: vec![v0, v1, v2, v3, v4, v5],
-        portal_sectors: vec![None, None, None, Some(SectorId(2)), None, Some(SectorId(1))],
-        colors: vec![
-            BLUE.into(),
-            GREEN.into(),
-            ORANGE.into(),
-            FUCHSIA.into(),
-            YELLOW.into(),
-            RED.into(),
-        ],
-        floor: Length(0.0),
-        ceil: Length(4.0),
+    world.spawn(PlayerStart {
+        name: "start".to_string(),
+        sector: SectorId(0),
+        ..default()
     });
-
-    world.spawn(Sector {
-        id: SectorId(1),
-        vertices: vec![v0, v5, v6, v7],
-        portal_sectors: vec![Some(SectorId(0)), None, None, None],
-        colors: vec![RED.into(), FUCHSIA.into(), GREEN.into(), YELLOW.into()],
-        floor: Length(0.25),
-        ceil: Length(3.75),
+    world.spawn(CameraPose {
+        name: "overview".to_string(),
+        sector: SectorId(1),
+        position: Position2(vec2(0.0, 12.0)),
+        height: Length(2.0),
+        direction: Direction(std::f32::consts::PI),
     });
-
-    world.spawn(Sector {
-        id: SectorId(2),
-        vertices: vec![v4, v3, v8, v9],
-        portal_sectors: vec![Some(SectorId(0)), None, None, None],
-        colors: vec![RED.into(), FUCHSIA.into(), GREEN.into(), BLUE.into()],
-        floor: Length(-0.5),
-        ceil: Length(4.5),
+    world.spawn(WallAttachment {
+        sector: SectorId(0),
+        wall: 1,
+        u: 0.5,
+        height: Length(1.5),
+        width: Length(0.4),
+        tall: Length(0.8),
+        color: ORANGE.into(),
     });
+    world.spawn(MapVersion::current());
+    world.spawn((
+        Thing {
+            position: Position2(vec2(1.5, 4.0)),
+            radius: Length(0.4),
+            height: Length(1.2),
+        },
+        Billboard {
+            color: RawColor([140, 85, 40]),
+            texture: Some("textures/barrel.png".to_string()),
+        },
+    ));
+
+    world.spawn((
+        StableId(0),
+        Sector {
+            id: SectorId(0),
+            tag: 0,
+            vertices: vec![v0, v1, v2, v3, v4, v5],
+            portal_sectors: vec![None, None, None, Some(SectorId(2)), None, Some(SectorId(1))],
+            colors: vec![
+                BLUE.into(),
+                GREEN.into(),
+                ORANGE.into(),
+                FUCHSIA.into(),
+                YELLOW.into(),
+                RED.into(),
+            ],
+            floor: Length(0.0),
+            ceil: Length(4.0),
+            bridge: None,
+            wall_textures: vec![
+                None,
+                None,
+                Some("textures/brick.png".to_string()),
+                None,
+                None,
+                None,
+            ],
+            mid_textures: vec![
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(MidTexture {
+                    path: "textures/grate.png".to_string(),
+                    translucent: false,
+                }),
+            ],
+            upper_walls: Vec::new(),
+            lower_walls: Vec::new(),
+            texture_alignments: Vec::new(),
+            light: DEFAULT_LIGHT,
+            liquid: None,
+            floor_material: FloorMaterial::default(),
+            fog: None,
+            floor_color: None,
+            ceiling_color: None,
+            vertex_ids: Vec::new(),
+        },
+    ));
+
+    world.spawn((
+        StableId(1),
+        Sector {
+            id: SectorId(1),
+            tag: 0,
+            vertices: vec![v0, v5, v6, v7],
+            portal_sectors: vec![Some(SectorId(0)), None, None, None],
+            colors: vec![RED.into(), FUCHSIA.into(), GREEN.into(), YELLOW.into()],
+            floor: Length(0.25),
+            ceil: Length(3.75),
+            bridge: None,
+            wall_textures: vec![None; 4],
+            mid_textures: vec![
+                Some(MidTexture {
+                    path: "textures/grate.png".to_string(),
+                    translucent: false,
+                }),
+                None,
+                None,
+                None,
+            ],
+            upper_walls: Vec::new(),
+            lower_walls: Vec::new(),
+            texture_alignments: Vec::new(),
+            light: DEFAULT_LIGHT,
+            liquid: None,
+            floor_material: FloorMaterial::default(),
+            fog: None,
+            floor_color: None,
+            ceiling_color: None,
+            vertex_ids: Vec::new(),
+        },
+    ));
+
+    world.spawn((
+        StableId(2),
+        Sector {
+            id: SectorId(2),
+            tag: 0,
+            vertices: vec![v4, v3, v8, v9],
+            portal_sectors: vec![Some(SectorId(0)), None, None, None],
+            colors: vec![RED.into(), FUCHSIA.into(), GREEN.into(), BLUE.into()],
+            floor: Length(-0.5),
+            ceil: Length(4.5),
+            bridge: None,
+            wall_textures: vec![None; 4],
+            mid_textures: Vec::new(),
+            upper_walls: Vec::new(),
+            lower_walls: Vec::new(),
+            texture_alignments: Vec::new(),
+            light: DEFAULT_LIGHT,
+            liquid: None,
+            floor_material: FloorMaterial::default(),
+            fog: None,
+            floor_color: None,
+            ceiling_color: None,
+            vertex_ids: Vec::new(),
+        },
+    ));
 }
 
-fn save_scene_system(world: &mut World) {
+/// Keep the preview in whichever sector contains it, as it is moved around freely.
+fn preview_sector_system(mut view: ResMut<View>, sector_query: Query<&Sector>) {
+    let position = view.position.truncate();
+    if let Some(sector) = sector_query.iter().find(|s| s.contains(position)) {
+        if view.current_sector != Some(sector.id) {
+            view.current_sector = Some(sector.id);
+        }
+    }
+}
+
+/// Scene of the world as edited, without the hierarchy of maps opened through the
+/// load path.
+fn map_scene(world: &mut World) -> DynamicScene {
+    let mut query = world.query_filtered::<Entity, Without<MapRoot>>();
+    let entities: Vec<Entity> = query.iter(world).collect();
+
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let mut builder = DynamicSceneBuilder::from_world_with_type_registry(world, type_registry);
+    builder.extract_entities(entities.into_iter());
+    let mut scene = builder.build();
+
+    let hierarchy = [
+        std::any::type_name::<Parent>(),
+        std::any::type_name::<Children>(),
+    ];
+    for entity in &mut scene.entities {
+        entity
+            .components
+            .retain(|component| !hierarchy.contains(&component.type_name()));
+    }
+    scene
+        .entities
+        .retain(|entity| !entity.components.is_empty());
+
+    scene
+}
+
+fn scene_ron(world: &mut World) -> String {
+    let scene = map_scene(world);
     let type_registry = world.resource::<AppTypeRegistry>();
-    let scene = DynamicScene::from_world(&world, type_registry);
 
+    scene.serialize_ron(type_registry).unwrap()
+}
+
+/// Save the map when asked, to the path given with it if any.
+fn save_scene_system(world: &mut World) {
+    let Some(SaveMap(path)) = world.resource_mut::<Events<SaveMap>>().drain().last() else { return };
+
+    // Recolor previews are only saved once applied
+    if world.resource::<Recolor>().previewing() {
+        warn!("apply or cancel the recolor preview before saving");
+        return;
+    }
+
+    if let Some(path) = path {
+        world
+            .resource_mut::<EditorSettings>()
+            .add_recent_file(&path);
+        world.resource_mut::<EditorFile>().path = path;
+    }
+
+    let scene = map_scene(world);
+    let type_registry = world.resource::<AppTypeRegistry>();
     let scene_ron = scene.serialize_ron(type_registry).unwrap();
+    let editor_file = world.resource::<EditorFile>();
+    let (ron_path, mp_path) = (editor_file.path.clone(), editor_file.mp_path());
+    let save_format = world.resource::<EditorSettings>().save_format;
+    info!("saving map `{ron_path}`");
 
-    #[cfg(not(target_arch = "wasm32"))]
+    // The browser only keeps text, so maps are saved there as RON in any format
+    let web = cfg!(target_arch = "wasm32");
+    let mut files = Vec::new();
+    if save_format.writes_ron() || web {
+        files.push((ron_path, scene_ron.into_bytes()));
+    }
+    if save_format.writes_mp() && !web {
+        let scene_mp = sector::load::serialize_scene_mp(&scene, type_registry).unwrap();
+        files.push((mp_path, scene_mp));
+    }
     IoTaskPool::get()
         .spawn(async move {
-            File::create(format!("assets/{DEFAULT_SCENE_RON_FILE_PATH}"))
-                .and_then(|mut file| file.write(scene_ron.as_bytes()))
-                .expect("failed to write `scene_ron` to file");
+            for (path, bytes) in files {
+                if let Err(error) = save_scene(&path, &bytes) {
+                    error!("failed to write `{path}`: {error}");
+                }
+            }
         })
         .detach();
 
-    let scene_serializer = SceneSerializer::new(&scene, type_registry);
-    let scene_mp: Vec<u8> = rmp_serde::to_vec(&scene_serializer).unwrap();
+    world.resource_mut::<EditorFile>().dirty = false;
+}
+
+/// Mark the map dirty when it's edited, once opening it has settled: loaded, then a
+/// frame without changes. Recolor previews only count once applied.
+fn dirty_system(
+    mut editor_file: ResMut<EditorFile>,
+    map_load: Res<MapLoad>,
+    recolor: Res<Recolor>,
+    mut map_load_events: EventReader<MapLoadEvent>,
+    mut changes: MapChanges,
+) {
+    // Maps reloaded from disk are opened again
+    let started = map_load_events
+        .iter()
+        .any(|event| matches!(event, MapLoadEvent::Started { .. }));
+    if started {
+        editor_file.opened = false;
+        editor_file.dirty = false;
+    }
 
-    #[cfg(not(target_arch = "wasm32"))]
-    IoTaskPool::get()
-        .spawn(async move {
-            File::create(format!("assets/{DEFAULT_SCENE_MP_FILE_PATH}"))
-                .and_then(|mut file| file.write(&scene_mp))
-                .expect("failed to write `scene_mp` to file");
-        })
-        .detach();
+    let changed = changes.any();
+    if map_load.is_loading() || recolor.previewing() {
+        return;
+    }
+    if !editor_file.opened {
+        if !changed {
+            editor_file.opened = true;
+        }
+    } else if changed && !editor_file.dirty {
+        editor_file.dirty = true;
+    }
 }
 
 fn update_title_system(
     mut state: ResMut<State>,
     time: Res<Time>,
     diagnostics: Res<Diagnostics>,
-    mut window_query: Query<&mut Window>,
+    editor_file: Res<EditorFile>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
 ) {
     if state.update_title_timer.tick(time.delta()).finished() {
         let Ok(mut window) = window_query.get_single_mut() else { return };
 
         if let Some(fps) = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS) {
             if let Some(value) = fps.value() {
-                window.title = format!("sector_edit: {value:.0} fps");
+                window.title = format!("sector_edit: {} - {value:.0} fps", editor_file.title());
             }
         }
     }
 }
 
-fn escape_system(mut app_exit_events: EventWriter<AppExit>, key: Res<Input<KeyCode>>) {
+fn escape_system(
+    mut app_exit_events: EventWriter<AppExit>,
+    key: Res<Input<KeyCode>>,
+    editor_file: Res<EditorFile>,
+) {
     if key.just_pressed(KeyCode::Escape) {
+        if editor_file.dirty {
+            warn!("quitting with unsaved changes to `{}`", editor_file.path);
+        }
         app_exit_events.send(AppExit);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn egui_system(
+    mut commands: Commands,
     mut contexts: EguiContexts,
     mut _state: ResMut<State>,
+    mut view: ResMut<View>,
+    mut camera: ResMut<CameraConfig>,
+    mut settings: ResMut<EditorSettings>,
+    mut measure: ResMut<Measure>,
+    mut recolor: ResMut<Recolor>,
+    mut edit_tool: ResMut<EditTool>,
+    mut geometry_edits: EventWriter<GeometryEdit>,
+    mut plot_restored: Local<bool>,
     mut sector_query: Query<&mut Sector>,
+    (thing_query, mover_query): (Query<&Thing>, Query<&Mover>),
+    mut start_query: Query<&mut PlayerStart>,
+    initial_sector_query: Query<&InitialSector>,
+    start_selection: Res<PlayerStartSelection>,
 ) {
     let ctx = contexts.ctx_mut();
 
-    ctx.set_visuals(egui::Visuals::light());
+    ctx.set_visuals(settings.visuals());
 
     let mut highligted_sector: Option<SectorId> = None;
     let mut highligted_wall: Option<Wall> = None;
     let mut highligted_vertex: Option<Position2> = None;
 
-    // egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-    //     egui::menu::bar(ui, |ui| {
-    //         ui.menu_button("File", |ui| {
-    //             if ui.button("About...").clicked() {
-    //                 ui.close_menu();
-    //             }
-    //         })
-    //     });
-    // });
-
-    egui::SidePanel::left("left_panel")
-        .default_width(250.0)
-        .show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.heading("🔷 sector_edit");
-            });
+    let left_panel_response =
+        egui::SidePanel::left("left_panel")
+            .default_width(settings.left_panel_width)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("🔷 sector_edit");
+                });
+
+                ui.horizontal(|ui| {
+                    let mut dark = settings.dark;
+                    if ui.checkbox(&mut dark, "dark theme").changed() {
+                        settings.dark = dark;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let mut snap_to_grid = settings.snap_to_grid;
+                    if ui.checkbox(&mut snap_to_grid, "snap to grid").changed() {
+                        settings.snap_to_grid = snap_to_grid;
+                    }
+                    let mut grid_size = settings.grid_size;
+                    let grid_response = ui.add(
+                        egui::DragValue::new(&mut grid_size)
+                            .speed(0.05)
+                            .clamp_range(0.05..=10.0),
+                    );
+                    if grid_response.changed() {
+                        settings.grid_size = grid_size;
+                    }
+                    let mut show_grid = settings.show_grid;
+                    if ui.checkbox(&mut show_grid, "grid").changed() {
+                        settings.show_grid = show_grid;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let mut measuring = measure.active;
+                    if ui.checkbox(&mut measuring, "measure (M)").changed() {
+                        measure.toggle();
+                    }
+                });
+
+                // Plot clicks go to the measure tool instead while it's on
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("edit:");
+                    for mode in EditMode::ALL {
+                        if ui
+                            .selectable_label(edit_tool.mode == mode, mode.label())
+                            .clicked()
+                        {
+                            edit_tool.set_mode(mode);
+                        }
+                    }
+                    ui.checkbox(&mut edit_tool.break_welds, "break welds");
+                });
+                if edit_tool.mode == EditMode::Select || !edit_tool.selection.is_empty() {
+                    selection_ui(ui, &mut edit_tool, settings.grid_size, &mut geometry_edits);
+                }
+
+                ui.separator();
+
+                let preview_response = egui::CollapsingHeader::new("preview")
+                    .default_open(settings.header_open("preview", true))
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut view.position.0.x)
+                                    .speed(0.1)
+                                    .clamp_range(-100.0..=100.0)
+                                    .prefix("x: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut view.position.0.y)
+                                    .speed(0.1)
+                                    .clamp_range(-100.0..=100.0)
+                                    .prefix("y: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut view.position.0.z)
+                                    .speed(0.1)
+                                    .clamp_range(-10.0..=10.0)
+                                    .prefix("z: "),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut view.direction.0)
+                                    .speed(0.05)
+                                    .prefix("direction: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut view.pitch)
+                                    .speed(0.02)
+                                    .clamp_range(-MAX_PITCH..=MAX_PITCH)
+                                    .prefix("pitch: "),
+                            );
+                        });
+                        let mut fov = camera.fov_x_degrees();
+                        let fov_slider =
+                            egui::Slider::new(&mut fov, MIN_FOV_X_DEGREES..=MAX_FOV_X_DEGREES)
+                                .suffix("°")
+                                .text("fov");
+                        if ui.add(fov_slider).changed() {
+                            camera.set_fov_x_degrees(fov);
+                        }
+                    });
+                settings.set_header_open("preview", preview_response.openness > 0.5);
+
+                ui.separator();
+
+                let sectors: Vec<&Sector> = sector_query.iter().collect();
+                let shapes = sector_findings(&sectors);
+                let moving: Vec<SectorId> = mover_query.iter().map(|m| m.sector).collect();
+                let closed = closed_portals(&sectors, &moving);
+                let blocked: Vec<String> = thing_query
+                    .iter()
+                    .filter_map(|thing| {
+                        let problem = check_clearance(&sectors, thing).err()?;
+                        Some(ThingFinding(thing, problem).to_string())
+                    })
+                    .collect();
+                let splits = split_walls_at_portals(&sectors);
+                let starts: Vec<&PlayerStart> = start_query.iter().collect();
+                let spawns = spawn_findings(&sectors, &starts, initial_sector_query.iter().next());
+                // Sectors the view can't walk to from where it enters the map
+                let spawn_sector = match starts.first() {
+                    Some(start) => Some(start.sector),
+                    None => initial_sector_query.iter().next().map(|initial| initial.0),
+                };
+                let unreachable: Vec<SectorId> = match spawn_sector {
+                    Some(from) if sectors.iter().any(|s| s.id == from) => {
+                        let reached = reachable(&sectors, from, BodySize::PLAYER);
+                        sectors
+                            .iter()
+                            .map(|s| s.id)
+                            .filter(|id| !reached.contains(id))
+                            .collect()
+                    }
+                    _ => Vec::new(),
+                };
+                let mut fix: Option<PortalFix> = None;
+                let mut split = false;
+                let mut spawn_fix: Option<(Option<String>, SectorId, Position2)> = None;
+
+                let lint_count = shapes.len()
+                    + closed.len()
+                    + blocked.len()
+                    + splits.len()
+                    + spawns.len()
+                    + unreachable.len();
+                egui::CollapsingHeader::new(format!("lint: {lint_count}"))
+                    .default_open(lint_count > 0)
+                    .show(ui, |ui| {
+                        for finding in &shapes {
+                            let text = finding.to_string();
+                            if finding.problem.is_warning() {
+                                ui.label(text);
+                            } else {
+                                ui.colored_label(ui.visuals().error_fg_color, text);
+                            }
+                        }
+                        for portal in &closed {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "sector {} wall {}: closed portal ({:.2})",
+                                    portal.sector.0, portal.wall, portal.opening
+                                ));
+                                if let Some(portal_fix) = portal.fix {
+                                    if ui.button("fix").clicked() {
+                                        fix = Some(portal_fix);
+                                    }
+                                }
+                            });
+                        }
+                        for finding in &blocked {
+                            ui.label(finding);
+                        }
+                        for sector in &splits {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "sector {}: walls face several sectors",
+                                    sector.id.0
+                                ));
+                                if ui.button("split").clicked() {
+                                    split = true;
+                                }
+                            });
+                        }
+                        for finding in &spawns {
+                            ui.horizontal(|ui| {
+                                ui.label(finding.to_string());
+                                let sector = match finding.problem {
+                                    SpawnProblem::OutsideSector(sector) => sector,
+                                    _ => return,
+                                };
+                                if let Some(centroid) = finding.fix {
+                                    if ui.button("move to centre").clicked() {
+                                        spawn_fix = Some((finding.start.clone(), sector, centroid));
+                                    }
+                                }
+                            });
+                        }
+                        for sector in &unreachable {
+                            ui.label(format!("sector {}: unreachable from the start", sector.0));
+                        }
+                    });
+
+                if let Some(fix) = fix {
+                    if let Some(mut sector) = sector_query.iter_mut().find(|s| s.id == fix.sector()) {
+                        fix.apply(&mut sector);
+                    }
+                }
+                match spawn_fix {
+                    // Maps without starts get one, the initial sector has no position
+                    Some((None, sector, position)) => {
+                        commands.spawn(PlayerStart {
+                            name: "start".to_string(),
+                            sector,
+                            position,
+                            ..default()
+                        });
+                    }
+                    Some((Some(name), _, position)) => {
+                        if let Some(mut start) = start_query.iter_mut().find(|s| s.name == name) {
+                            start.position = position;
+                        }
+                    }
+                    None => {}
+                }
+                if split {
+                    apply_wall_splits(&mut sector_query, splits);
+                }
+
+                let link_button = egui::Button::new("link portals");
+                let link_response = ui.add(link_button).on_hover_text(
+                    "make every wall shared with another sector a portal into it, \
+                    and the rest solid",
+                );
+                if link_response.clicked() {
+                    link_all_portals(&mut sector_query);
+                }
+
+                ui.separator();
+
+                let starts_response = egui::CollapsingHeader::new("player starts")
+                    .default_open(settings.header_open("player starts", false))
+                    .show(ui, |ui| {
+                        if start_query.is_empty() {
+                            ui.label("none, add one with the place start tool");
+                        }
+                        for mut start in &mut start_query {
+                            ui.label(format!("{} ({:?})", start.name, start.mode));
+                            let (response, moved) =
+                                vertex_drag_row(ui, "position", start.position);
+                            if response.changed() {
+                                let sector = sector_query.iter().find(|s| s.contains(moved));
+                                if let Some(sector) = sector {
+                                    start.sector = sector.id;
+                                }
+                                start.position = moved;
+                            }
+                            // Only marked changed when turned
+                            let mut direction = start.direction.0;
+                            let response = ui.add(
+                                egui::DragValue::new(&mut direction)
+                                    .speed(0.05)
+                                    .prefix("direction: "),
+                            );
+                            if response.changed() {
+                                start.direction.0 = direction;
+                            }
+                        }
+                    });
+                settings.set_header_open("player starts", starts_response.openness > 0.5);
+
+                ui.separator();
+
+                let recolor_response = egui::CollapsingHeader::new("recolor")
+                    .default_open(settings.header_open("recolor", false))
+                    .show(ui, |ui| recolor_ui(ui, &mut recolor, &mut sector_query));
+                settings.set_header_open("recolor", recolor_response.openness > 0.5);
 
-            ui.separator();
+                ui.separator();
 
-            egui::ScrollArea::vertical()
-                .auto_shrink([false; 2])
-                .show(ui, |ui| {
-                    for mut sector in &mut sector_query {
-                        let sector_frame_response = egui::Frame::none()
+                let sector_ids: Vec<SectorId> = sector_query.iter().map(|s| s.id).collect();
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        for mut sector in &mut sector_query {
+                            let header = format!("sector: {}", sector.id.0);
+                            let header_id = ui.make_persistent_id(&header);
+                            let sector_frame_response = egui::Frame::none()
                             .show(ui, |ui| {
                                 egui::collapsing_header::CollapsingState::load_with_default_open(
                                     ui.ctx(),
-                                    ui.make_persistent_id(format!("sector: {}", sector.id.0)),
-                                    false,
+                                    header_id,
+                                    settings.header_open(&header, false),
                                 )
                                 .show_header(ui, |ui| {
-                                    ui.checkbox(&mut true, format!("sector: {}", sector.id.0));
+                                    // Selects the sector for recoloring
+                                    let mut selected = recolor.selected.contains(&sector.id);
+                                    let checkbox = egui::Checkbox::new(
+                                        &mut selected,
+                                        format!("sector: {}", sector.id.0),
+                                    );
+                                    if ui.add_enabled(!recolor.previewing(), checkbox).changed() {
+                                        if selected {
+                                            recolor.selected.insert(sector.id);
+                                        } else {
+                                            recolor.selected.remove(&sector.id);
+                                        }
+                                    }
                                 })
                                 .body(|ui| {
-                                    ui.add(
-                                        egui::DragValue::new(&mut sector.floor.0)
-                                            .speed(0.1)
-                                            .clamp_range(-10.0..=(10.0 - 0.1))
-                                            .prefix("floor: "),
-                                    );
-                                    let floor = sector.floor.0;
-                                    ui.add(
-                                        egui::DragValue::new(&mut sector.ceil.0)
-                                            .speed(0.1)
-                                            .clamp_range((floor + 0.1)..=10.0)
-                                            .prefix("ceil: "),
-                                    );
+                                    // Widgets are given the sector every frame, so it's
+                                    // only marked changed when one is
+                                    let mut edited = false;
+                                    let fields = sector.bypass_change_detection();
+                                    edited |= ui
+                                        .add(
+                                            egui::DragValue::new(&mut fields.floor.0)
+                                                .speed(0.1)
+                                                .clamp_range(-10.0..=(10.0 - 0.1))
+                                                .prefix("floor: "),
+                                        )
+                                        .changed();
+                                    let floor = fields.floor.0;
+                                    edited |= ui
+                                        .add(
+                                            egui::DragValue::new(&mut fields.ceil.0)
+                                                .speed(0.1)
+                                                .clamp_range((floor + 0.1)..=10.0)
+                                                .prefix("ceil: "),
+                                        )
+                                        .changed();
+                                    let ceil = fields.ceil.0;
+                                    edited |= ui
+                                        .add(
+                                            egui::DragValue::new(&mut fields.light)
+                                                .speed(0.01)
+                                                .clamp_range(0.0..=1.0)
+                                                .prefix("light: "),
+                                        )
+                                        .changed();
+                                    edited |= ui
+                                        .add(
+                                            egui::DragValue::new(&mut fields.tag)
+                                                .speed(0.1)
+                                                .prefix("tag: "),
+                                        )
+                                        .changed();
+                                    ui.horizontal(|ui| {
+                                        let mut bridge = fields.bridge.is_some();
+                                        if ui.checkbox(&mut bridge, "bridge").changed() {
+                                            fields.bridge =
+                                                bridge.then_some(Length((floor + ceil) / 2.0));
+                                            edited = true;
+                                        }
+                                        if let Some(bridge) = &mut fields.bridge {
+                                            edited |= ui
+                                                .add(
+                                                    egui::DragValue::new(&mut bridge.0)
+                                                        .speed(0.1)
+                                                        .clamp_range(floor..=ceil)
+                                                        .prefix("height: "),
+                                                )
+                                                .changed();
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        let id = fields.id.0;
+                                        let mut liquid = fields.liquid.is_some();
+                                        if ui.checkbox(&mut liquid, "liquid").changed() {
+                                            fields.liquid = liquid.then_some(Liquid {
+                                                kind: LiquidKind::default(),
+                                                surface: Length((floor + ceil) / 2.0),
+                                            });
+                                            edited = true;
+                                        }
+                                        if let Some(liquid) = &mut fields.liquid {
+                                            egui::ComboBox::from_id_source(("liquid", id))
+                                            .selected_text(liquid.kind.label())
+                                            .show_ui(ui, |ui| {
+                                                for kind in LiquidKind::ALL {
+                                                    edited |= ui
+                                                        .selectable_value(
+                                                            &mut liquid.kind,
+                                                            kind,
+                                                            kind.label(),
+                                                        )
+                                                        .changed();
+                                                }
+                                            });
+                                            edited |= ui
+                                                .add(
+                                                    egui::DragValue::new(&mut liquid.surface.0)
+                                                        .speed(0.1)
+                                                        .clamp_range(floor..=ceil)
+                                                        .prefix("surface: "),
+                                                )
+                                                .changed();
+                                        }
+                                    });
+                                    let id = fields.id.0;
+                                    egui::ComboBox::new(("floor material", id), "floor")
+                                        .selected_text(fields.floor_material.label())
+                                        .show_ui(ui, |ui| {
+                                            for material in FloorMaterial::ALL {
+                                                edited |= ui
+                                                    .selectable_value(
+                                                        &mut fields.floor_material,
+                                                        material,
+                                                        material.label(),
+                                                    )
+                                                    .changed();
+                                            }
+                                        });
+                                    if edited {
+                                        sector.set_changed();
+                                    }
 
                                     egui::CollapsingHeader::new("walls")
                                         .default_open(true)
@@ -229,75 +890,154 @@ fn egui_system(
                                                         ))
                                                         .default_open(true)
                                                         .show(ui, |ui| {
-                                                            let vertex_response = ui
-                                                                .horizontal(|ui| {
-                                                                    ui.label(format!("left:"));
-                                                                    let mut x = wall.left.0.x;
-                                                                    let mut y = wall.left.0.y;
-                                                                    ui.add(
-                                                                        egui::DragValue::new(
-                                                                            &mut x,
-                                                                        )
-                                                                        .speed(0.1)
-                                                                        .clamp_range(-100.0..=100.0)
-                                                                        .prefix("x: "),
+                                                            // Moved through the pool like
+                                                            // the vertices below
+                                                            for (label, vertex) in
+                                                                [("left:", wall.left), ("right:", wall.right)]
+                                                            {
+                                                                let (vertex_response, moved) =
+                                                                    vertex_drag_row(ui, label, vertex);
+                                                                if vertex_response.hovered() {
+                                                                    highligted_vertex = Some(vertex);
+                                                                }
+                                                                if moved.0 != vertex.0 {
+                                                                    geometry_edits.send(
+                                                                        GeometryEdit::MoveVertices {
+                                                                            from: vertex.0,
+                                                                            to: moved.0,
+                                                                            sector: edit_tool
+                                                                                .break_welds
+                                                                                .then_some(sector.id),
+                                                                        },
                                                                     );
-                                                                    ui.add(
-                                                                        egui::DragValue::new(
-                                                                            &mut y,
-                                                                        )
-                                                                        .speed(0.1)
-                                                                        .clamp_range(-100.0..=100.0)
-                                                                        .prefix("y: "),
+                                                                }
+                                                            }
+
+                                                            let [r, g, b] = wall.raw_color.0;
+                                                            let mut color32 =
+                                                                egui::Color32::from_rgb(r, g, b);
+                                                            ui.horizontal(|ui| {
+                                                                ui.label("color:");
+                                                                let response = ui
+                                                                    .color_edit_button_srgba(
+                                                                        &mut color32,
+                                                                    );
+                                                                if response.changed() {
+                                                                    sector.set_wall_color(
+                                                                        i,
+                                                                        RawColor([
+                                                                            color32.r(),
+                                                                            color32.g(),
+                                                                            color32.b(),
+                                                                        ]),
                                                                     );
-                                                                })
-                                                                .response;
+                                                                }
+                                                            });
 
-                                                            if vertex_response.hovered() {
-                                                                highligted_vertex = Some(wall.left);
+                                                            let mut portal_sector =
+                                                                wall.portal_sector;
+                                                            ui.horizontal(|ui| {
+                                                                ui.label("portal:");
+                                                                let label = |id: Option<SectorId>| {
+                                                                    id.map_or("none".to_string(), |id| {
+                                                                        format!("sector {}", id.0)
+                                                                    })
+                                                                };
+                                                                egui::ComboBox::from_id_source((
+                                                                    "portal", sector.id.0, i,
+                                                                ))
+                                                                .selected_text(label(portal_sector))
+                                                                .show_ui(ui, |ui| {
+                                                                    let others = sector_ids
+                                                                        .iter()
+                                                                        .filter(|&&id| id != sector.id);
+                                                                    for id in std::iter::once(None)
+                                                                        .chain(others.copied().map(Some))
+                                                                    {
+                                                                        ui.selectable_value(
+                                                                            &mut portal_sector,
+                                                                            id,
+                                                                            label(id),
+                                                                        );
+                                                                    }
+                                                                });
+                                                            });
+                                                            if portal_sector != wall.portal_sector {
+                                                                sector.set_portal_sector(
+                                                                    i,
+                                                                    portal_sector,
+                                                                );
                                                             }
 
-                                                            let vertex_response = ui
-                                                                .horizontal(|ui| {
-                                                                    ui.label(format!("right:"));
-                                                                    let mut x = wall.right.0.x;
-                                                                    let mut y = wall.right.0.y;
-                                                                    ui.add(
-                                                                        egui::DragValue::new(
-                                                                            &mut x,
-                                                                        )
-                                                                        .speed(0.1)
-                                                                        .clamp_range(-100.0..=100.0)
-                                                                        .prefix("x: "),
+                                                            // Asset path, none when empty
+                                                            let mut texture = sector
+                                                                .wall_texture(i)
+                                                                .unwrap_or_default()
+                                                                .to_string();
+                                                            ui.horizontal(|ui| {
+                                                                ui.label("texture:");
+                                                                let response = ui
+                                                                    .text_edit_singleline(
+                                                                        &mut texture,
                                                                     );
-                                                                    ui.add(
-                                                                        egui::DragValue::new(
-                                                                            &mut y,
-                                                                        )
-                                                                        .speed(0.1)
-                                                                        .clamp_range(-100.0..=100.0)
-                                                                        .prefix("y: "),
+                                                                if response.changed() {
+                                                                    sector.set_wall_texture(
+                                                                        i,
+                                                                        (!texture.is_empty())
+                                                                            .then_some(texture),
                                                                     );
-                                                                })
-                                                                .response;
+                                                                }
+                                                            });
 
-                                                            if vertex_response.hovered() {
-                                                                highligted_vertex =
-                                                                    Some(wall.right);
+                                                            if let Some(alignment) = texture_alignment_row(
+                                                                ui,
+                                                                sector.texture_alignment(i),
+                                                            ) {
+                                                                sector.set_texture_alignment(i, alignment);
                                                             }
 
-                                                            let mut color32 =
-                                                                egui::Color32::from_rgb(
-                                                                    wall.raw_color.0[0],
-                                                                    wall.raw_color.0[1],
-                                                                    wall.raw_color.0[2],
-                                                                );
+                                                            // Only drawn across portals
+                                                            if portal_sector.is_none() {
+                                                                return;
+                                                            }
+                                                            let mut mid_texture = sector
+                                                                .mid_texture(i)
+                                                                .cloned()
+                                                                .unwrap_or_default();
                                                             ui.horizontal(|ui| {
-                                                                ui.label("color:");
-                                                                ui.color_edit_button_srgba(
-                                                                    &mut color32,
+                                                                ui.label("mid-texture:");
+                                                                let path = ui.text_edit_singleline(
+                                                                    &mut mid_texture.path,
                                                                 );
-                                                            })
+                                                                let translucent = ui.checkbox(
+                                                                    &mut mid_texture.translucent,
+                                                                    "translucent",
+                                                                );
+                                                                if path.changed() || translucent.changed() {
+                                                                    sector.set_mid_texture(
+                                                                        i,
+                                                                        (!mid_texture.path.is_empty())
+                                                                            .then_some(mid_texture),
+                                                                    );
+                                                                }
+                                                            });
+
+                                                            if let Some(upper_wall) = wall_surface_row(
+                                                                ui,
+                                                                "upper:",
+                                                                sector.upper_wall(i),
+                                                                wall.raw_color,
+                                                            ) {
+                                                                sector.set_upper_wall(i, upper_wall);
+                                                            }
+                                                            if let Some(lower_wall) = wall_surface_row(
+                                                                ui,
+                                                                "lower:",
+                                                                sector.lower_wall(i),
+                                                                wall.raw_color,
+                                                            ) {
+                                                                sector.set_lower_wall(i, lower_wall);
+                                                            }
                                                         });
                                                     })
                                                     .response;
@@ -311,17 +1051,20 @@ fn egui_system(
                                     egui::CollapsingHeader::new("vertices")
                                         .default_open(true)
                                         .show(ui, |ui| {
-                                            for vertex in &mut sector.vertices {
+                                            // Moved through the pool, taking sectors sharing
+                                            // the vertex along
+                                            for &vertex in &sector.vertices {
+                                                let mut moved = vertex;
                                                 let vertex_response = ui
                                                     .horizontal(|ui| {
                                                         ui.add(
-                                                            egui::DragValue::new(&mut vertex.0.x)
+                                                            egui::DragValue::new(&mut moved.0.x)
                                                                 .speed(0.1)
                                                                 .clamp_range(-100.0..=100.0)
                                                                 .prefix("x: "),
                                                         );
                                                         ui.add(
-                                                            egui::DragValue::new(&mut vertex.0.y)
+                                                            egui::DragValue::new(&mut moved.0.y)
                                                                 .speed(0.1)
                                                                 .clamp_range(-100.0..=100.0)
                                                                 .prefix("y: "),
@@ -330,7 +1073,16 @@ fn egui_system(
                                                     .response;
 
                                                 if vertex_response.hovered() {
-                                                    highligted_vertex = Some(*vertex);
+                                                    highligted_vertex = Some(vertex);
+                                                }
+                                                if moved.0 != vertex.0 {
+                                                    geometry_edits.send(GeometryEdit::MoveVertices {
+                                                        from: vertex.0,
+                                                        to: moved.0,
+                                                        sector: edit_tool
+                                                            .break_welds
+                                                            .then_some(sector.id),
+                                                    });
                                                 }
                                             }
                                         });
@@ -338,18 +1090,29 @@ fn egui_system(
                             })
                             .response;
 
-                        if sector_frame_response.hovered() {
-                            highligted_sector = Some(sector.id);
+                            if sector_frame_response.hovered() {
+                                highligted_sector = Some(sector.id);
+                            }
+                            if let Some(state) =
+                                egui::collapsing_header::CollapsingState::load(ui.ctx(), header_id)
+                            {
+                                settings.set_header_open(&header, state.is_open());
+                            }
                         }
-                    }
-                });
-        });
+                    });
+            });
+
+    let left_panel_width = left_panel_response.response.rect.width();
+    if (left_panel_width - settings.left_panel_width).abs() > 0.5 {
+        settings.left_panel_width = left_panel_width;
+    }
 
     let polygons: Vec<egui::plot::Polygon> = sector_query
         .iter()
         .map(|sector| {
-            let highlighted =
-                highligted_sector.is_some() && highligted_sector.unwrap() == sector.id;
+            let highlighted = (highligted_sector.is_some()
+                && highligted_sector.unwrap() == sector.id)
+                || edit_tool.selection.sectors.contains(&sector.id);
 
             egui::plot::Polygon::new(egui::plot::PlotPoints::new(
                 sector
@@ -362,20 +1125,73 @@ fn egui_system(
         })
         .collect();
 
+    let starts: Vec<(Position2, Direction)> = start_query
+        .iter()
+        .map(|start| (start.position, start.direction))
+        .collect();
+
     egui::CentralPanel::default()
         .frame(egui::Frame::none())
         .show(ctx, |ui| {
-            egui::plot::Plot::new("plot")
+            let plot_response = egui::plot::Plot::new("plot")
                 .data_aspect(1.0)
                 .show_axes([true, true])
                 .auto_bounds_x()
+                .x_grid_spacer(grid_spacer(&settings))
+                .y_grid_spacer(grid_spacer(&settings))
+                .allow_drag(measure.active || edit_tool.allows_plot_drag())
                 .show(ui, |plot_ui| {
+                    // Restore the last session's view once
+                    if !*plot_restored {
+                        if let Some((min, max)) = settings.plot_bounds {
+                            plot_ui.set_plot_bounds(egui::plot::PlotBounds::from_min_max(min, max));
+                        }
+                        *plot_restored = true;
+                    }
+                    let bounds = plot_ui.plot_bounds();
+                    let plot_bounds = Some((bounds.min(), bounds.max()));
+                    if bounds.is_valid() && settings.plot_bounds != plot_bounds {
+                        settings.plot_bounds = plot_bounds;
+                    }
+
                     for polygon in polygons {
                         plot_ui.polygon(polygon);
                     }
 
-                    if highligted_wall.is_some() {
-                        let wall = highligted_wall.unwrap();
+                    // Player starts with a line facing their direction
+                    for (position, direction) in &starts {
+                        let facing = vec2(-direction.0.sin(), direction.0.cos());
+                        let tip = position.0 + facing * START_FACING_LENGTH;
+                        plot_ui.line(
+                            egui::plot::Line::new(egui::plot::PlotPoints::new(vec![
+                                [position.0.x as f64, position.0.y as f64],
+                                [tip.x as f64, tip.y as f64],
+                            ]))
+                            .color(egui::Color32::GOLD)
+                            .width(2.0),
+                        );
+                        plot_ui.points(
+                            egui::plot::Points::new(vec![[
+                                position.0.x as f64,
+                                position.0.y as f64,
+                            ]])
+                            .color(egui::Color32::GOLD)
+                            .filled(true)
+                            .radius(4.0),
+                        );
+                    }
+
+                    plot_ui.points(
+                        egui::plot::Points::new(vec![[
+                            view.position.0.x as f64,
+                            view.position.0.y as f64,
+                        ]])
+                        .color(egui::Color32::RED)
+                        .filled(true)
+                        .radius(4.0),
+                    );
+
+                    if let Some(wall) = highligted_wall {
                         let wall_points = egui::plot::PlotPoints::new(vec![
                             [wall.left.0.x as f64, wall.left.0.y as f64],
                             [wall.right.0.x as f64, wall.right.0.y as f64],
@@ -393,8 +1209,7 @@ fn egui_system(
                         );
                     }
 
-                    if highligted_vertex.is_some() {
-                        let vertex = highligted_vertex.unwrap();
+                    if let Some(vertex) = highligted_vertex {
                         plot_ui.points(
                             egui::plot::Points::new(vec![[vertex.0.x as f64, vertex.0.y as f64]])
                                 .color(egui::Color32::BLUE)
@@ -405,14 +1220,318 @@ fn egui_system(
                         );
                     }
 
-                    // if plot_ui.plot_clicked() {
-                    //     println!("Clicked {:?}", plot_ui.pointer_coordinate().unwrap());
-                    // }
-
                     // if plot_ui.plot_hovered() {
                     //     println!("Bounds {:?}", plot_ui.plot_bounds());
                     //     println!("Drag delta {:?}", plot_ui.pointer_coordinate_drag_delta());
                     // }
+
+                    let cursor = plot_ui
+                        .pointer_coordinate()
+                        .map(|point| snap(vec2(point.x as f32, point.y as f32), &settings));
+                    measure_plot(plot_ui, &mut measure, cursor);
+                    if !measure.active {
+                        let sectors: Vec<&Sector> = sector_query.iter().collect();
+                        edit_plot(
+                            plot_ui,
+                            &mut edit_tool,
+                            &sectors,
+                            cursor,
+                            &mut geometry_edits,
+                        );
+                    }
+                    cursor
                 });
+
+            // Cursor position, snapped while snapping, and measurement while measuring,
+            // in the plot's corner
+            let cursor = plot_response.inner;
+            let mut readout = match cursor {
+                Some(cursor) => format!("x: {:.2}  y: {:.2}", cursor.x, cursor.y),
+                None => "x: -  y: -".to_string(),
+            };
+            if settings.snap_to_grid {
+                readout = format!("{readout}  (snap {})", settings.grid_size);
+            }
+            if let Some((start, end)) = measure.segment(cursor).filter(|_| measure.active) {
+                readout = format!("{}\n{readout}", measure_label(start, end));
+            }
+            ui.painter().text(
+                plot_response.response.rect.right_bottom() - egui::vec2(8.0, 24.0),
+                egui::Align2::RIGHT_BOTTOM,
+                readout,
+                egui::FontId::monospace(14.0),
+                ui.visuals().text_color(),
+            );
+        });
+    // Placing moves the start the game would enter at, or adds one to maps without
+    if let Some((sector, position)) = edit_tool.placed_start.take() {
+        let chosen = start_selection.choose(&start_query).map(|s| s.name.clone());
+        let start = start_query
+            .iter_mut()
+            .find(|s| Some(&s.name) == chosen.as_ref());
+        match start {
+            Some(mut start) => {
+                start.sector = sector;
+                start.position = position;
+            }
+            None => {
+                commands.spawn(PlayerStart {
+                    name: "start".to_string(),
+                    sector,
+                    position,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+/// Replace sectors with the replacements from `split_walls_at_portals`.
+fn apply_wall_splits(sector_query: &mut Query<&mut Sector>, splits: Vec<Sector>) {
+    for replacement in splits {
+        let id = replacement.id;
+        if let Some(mut sector) = sector_query.iter_mut().find(|s| s.id == id) {
+            *sector = replacement;
+        }
+    }
+}
+
+/// Make every wall shared with another sector a portal into it, and the rest solid.
+fn link_all_portals(sector_query: &mut Query<&mut Sector>) {
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let links = link_portals(&sectors);
+    info!("linked portals of {} sector(s)", links.len());
+    for (id, portal_sectors) in links {
+        if let Some(mut sector) = sector_query.iter_mut().find(|s| s.id == id) {
+            sector.portal_sectors = portal_sectors;
+        }
+    }
+}
+
+/// Row of drag values for a vertex, with where they moved it.
+fn vertex_drag_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    vertex: Position2,
+) -> (egui::Response, Position2) {
+    let mut moved = vertex;
+    let response = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            ui.add(
+                egui::DragValue::new(&mut moved.0.x)
+                    .speed(0.1)
+                    .clamp_range(-100.0..=100.0)
+                    .prefix("x: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut moved.0.y)
+                    .speed(0.1)
+                    .clamp_range(-100.0..=100.0)
+                    .prefix("y: "),
+            );
+        })
+        .response;
+    (response, moved)
+}
+
+/// Row editing the look of the wall above or below a portal's opening, with the new
+/// look when changed, `None` within it when it's back to the wall's own.
+fn wall_surface_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    surface: Option<&WallSurface>,
+    wall_color: RawColor,
+) -> Option<Option<WallSurface>> {
+    let mut surface = surface.cloned().unwrap_or_default();
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut own_color = surface.color.is_some();
+        changed |= ui.checkbox(&mut own_color, "color").changed();
+        let [r, g, b] = surface.color.unwrap_or(wall_color).0;
+        let mut color32 = egui::Color32::from_rgb(r, g, b);
+        ui.add_enabled_ui(own_color, |ui| {
+            changed |= ui.color_edit_button_srgba(&mut color32).changed();
+        });
+        surface.color = own_color.then_some(RawColor([color32.r(), color32.g(), color32.b()]));
+
+        // Asset path, the wall's texture when empty
+        let mut texture = surface.texture.clone().unwrap_or_default();
+        let response = ui.add(egui::TextEdit::singleline(&mut texture).hint_text("texture"));
+        if response.changed() {
+            changed = true;
+            surface.texture = (!texture.is_empty()).then_some(texture);
+        }
+    });
+    changed.then(|| (surface != WallSurface::default()).then_some(surface))
+}
+
+/// Row of drag values for where a wall's textures sit, with the new alignment when
+/// changed.
+fn texture_alignment_row(
+    ui: &mut egui::Ui,
+    alignment: TextureAlignment,
+) -> Option<TextureAlignment> {
+    let mut aligned = alignment;
+    ui.horizontal(|ui| {
+        ui.label("align:");
+        ui.add(
+            egui::DragValue::new(&mut aligned.u_offset)
+                .speed(0.05)
+                .prefix("u: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut aligned.v_offset)
+                .speed(0.05)
+                .prefix("v: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut aligned.scale)
+                .speed(0.05)
+                .clamp_range(0.125..=16.0)
+                .prefix("scale: "),
+        );
+    });
+    (aligned != alignment).then_some(aligned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::{ecs::system::SystemState, scene::ScenePlugin};
+
+    /// Headless app applying geometry edits as the editor does, minus its UI.
+    fn headless_app(asset_folder: String) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin {
+                asset_folder,
+                watch_for_changes: false,
+            })
+            .add_plugin(ScenePlugin)
+            .add_plugin(SectorPlugin)
+            .init_resource::<EditorSettings>()
+            .add_event::<GeometryEdit>()
+            .add_system(geometry_edit_system);
+        app
+    }
+
+    fn with_sectors(world: &mut World, f: impl FnOnce(&mut Query<&mut Sector>)) {
+        let mut state = SystemState::<Query<&mut Sector>>::new(world);
+        f(&mut state.get_mut(world));
+        state.apply(world);
+    }
+
+    /// Id, corners and portals of every sector, by id.
+    fn sector_outlines(world: &mut World) -> Vec<(u32, Vec<Vec2>, Vec<Option<SectorId>>)> {
+        let mut outlines: Vec<_> = world
+            .query::<&Sector>()
+            .iter(world)
+            .map(|s| {
+                let corners = s.vertices.iter().map(|v| v.0).collect();
+                (s.id.0, corners, s.portal_sectors.clone())
+            })
+            .collect();
+        outlines.sort_by_key(|(id, ..)| *id);
+        outlines
+    }
+
+    #[test]
+    fn draw_sector_link_portals_and_save() {
+        let mut app = headless_app("assets".to_string());
+        init_scene_system(&mut app.world);
+        app.update();
+
+        // Draw a room below the first sector, its top wall along the middle of that
+        // sector's bottom wall, as the edit tool does when a drawn outline is closed
+        app.world.send_event(GeometryEdit::AddSector(vec![
+            vec2(0.0, -8.0),
+            vec2(7.0, -8.0),
+            vec2(7.0, -12.0),
+            vec2(0.0, -12.0),
+        ]));
+        app.update();
+        app.update();
+        let outlines = sector_outlines(&mut app.world);
+        assert_eq!(outlines.len(), 4);
+        let (_, room, room_portals) = &outlines[3];
+        assert_eq!(room.len(), 4);
+        assert!(room_portals.iter().all(Option::is_none));
+
+        // Split the walls it meets and link portals through them
+        with_sectors(&mut app.world, |sector_query| {
+            let sectors: Vec<&Sector> = sector_query.iter().collect();
+            let splits = split_walls_at_portals(&sectors);
+            apply_wall_splits(sector_query, splits);
+            link_all_portals(sector_query);
         });
+        app.update();
+
+        let outlines = sector_outlines(&mut app.world);
+        let (_, first, first_portals) = &outlines[0];
+        let (_, room, room_portals) = &outlines[3];
+        assert_eq!(first.len(), 8);
+        let along = |corners: &[Vec2], a: Vec2, b: Vec2| {
+            (0..corners.len()).find(|&i| corners[i] == a && corners[(i + 1) % corners.len()] == b)
+        };
+        let first_wall = along(first, vec2(7.0, -8.0), vec2(0.0, -8.0)).unwrap();
+        let room_wall = along(room, vec2(0.0, -8.0), vec2(7.0, -8.0)).unwrap();
+        assert_eq!(first_portals[first_wall], Some(SectorId(3)));
+        assert_eq!(room_portals[room_wall], Some(SectorId(0)));
+        assert_eq!(room_portals.iter().flatten().count(), 1);
+
+        // Both sides of the portal share pool vertices
+        let mut sector_query = app.world.query::<&Sector>();
+        let shared: Vec<Vec<VertexId>> = sector_query
+            .iter(&app.world)
+            .filter(|s| s.id.0 == 0 || s.id.0 == 3)
+            .map(|s| s.vertex_ids.clone())
+            .collect();
+        let common = shared[0].iter().filter(|id| shared[1].contains(id)).count();
+        assert_eq!(common, 2);
+
+        // Save, then load it back through the map load path
+        let dir = std::env::temp_dir().join(format!("sector_edit_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("scenes")).unwrap();
+        std::fs::write(dir.join("scenes/drawn.scn.ron"), scene_ron(&mut app.world)).unwrap();
+        let saved = sector_outlines(&mut app.world);
+
+        let mut loaded = headless_app(dir.display().to_string());
+        loaded
+            .world
+            .resource_mut::<WorldState>()
+            .travel("scenes/drawn.scn.ron", true);
+        for _ in 0..1000 {
+            loaded.update();
+            if !loaded.world.resource::<MapLoad>().is_loading() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let log = loaded.world.resource::<MapLoad>().log().to_vec();
+        assert!(
+            matches!(log.first(), Some(MapLoadEvent::Started { map }) if map == "scenes/drawn.scn.ron"),
+            "{log:?}"
+        );
+        assert!(
+            log.contains(&MapLoadEvent::SectorsSpawned { count: 4 }),
+            "{log:?}"
+        );
+        assert!(
+            log.contains(&MapLoadEvent::PortalsResolved {
+                resolved: 6,
+                unresolved: 0
+            }),
+            "{log:?}"
+        );
+        assert!(
+            matches!(log.last(), Some(MapLoadEvent::Finished { .. })),
+            "{log:?}"
+        );
+        assert_eq!(sector_outlines(&mut loaded.world), saved);
+    }
 }
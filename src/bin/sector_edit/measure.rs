@@ -0,0 +1,118 @@
+use crate::settings::*;
+
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{
+        self,
+        plot::{GridInput, GridMark},
+    },
+    EguiContexts,
+};
+
+/// Tool measuring between two points clicked on the plot, toggled with M. Until the
+/// second point is clicked it measures to the cursor.
+#[derive(Resource, Debug, Default)]
+pub struct Measure {
+    pub active: bool,
+    start: Option<Vec2>,
+    end: Option<Vec2>,
+}
+
+impl Measure {
+    pub fn toggle(&mut self) {
+        *self = Self {
+            active: !self.active,
+            ..default()
+        };
+    }
+
+    /// Start a new measurement at point, or finish the current one there.
+    pub fn click(&mut self, point: Vec2) {
+        if self.start.is_some() && self.end.is_none() {
+            self.end = Some(point);
+        } else {
+            self.start = Some(point);
+            self.end = None;
+        }
+    }
+
+    /// Points measured between, ending at cursor while unfinished.
+    pub fn segment(&self, cursor: Option<Vec2>) -> Option<(Vec2, Vec2)> {
+        Some((self.start?, self.end.or(cursor)?))
+    }
+}
+
+/// Point snapped to the grid when snapping is on.
+pub fn snap(point: Vec2, settings: &EditorSettings) -> Vec2 {
+    if settings.snap_to_grid && settings.grid_size > 0.0 {
+        (point / settings.grid_size).round() * settings.grid_size
+    } else {
+        point
+    }
+}
+
+/// Plot grid lines at multiples of the grid size, stronger every fourth and sixteenth.
+/// Spacing doubles while lines would be too close to see, and there are none while
+/// the grid is hidden.
+pub fn grid_spacer(settings: &EditorSettings) -> Box<dyn Fn(GridInput) -> Vec<GridMark>> {
+    if !settings.show_grid {
+        return Box::new(|_| Vec::new());
+    }
+    if settings.grid_size <= 0.0 {
+        return egui::plot::log_grid_spacer(10);
+    }
+    let grid_size = settings.grid_size as f64;
+    egui::plot::uniform_grid_spacer(move |input| {
+        let mut step = grid_size;
+        while step < input.base_step_size {
+            step *= 2.0;
+        }
+        [step, step * 4.0, step * 16.0]
+    })
+}
+
+/// Distance from start to end, and its angle in degrees anticlockwise from +y, the
+/// same as a view direction.
+pub fn measure_label(start: Vec2, end: Vec2) -> String {
+    let delta = end - start;
+    let angle = (-delta.x).atan2(delta.y).to_degrees().rem_euclid(360.0);
+    format!("{:.2} at {angle:.1}°", delta.length())
+}
+
+pub fn measure_key_system(
+    mut contexts: EguiContexts,
+    mut measure: ResMut<Measure>,
+    key: Res<Input<KeyCode>>,
+) {
+    if key.just_pressed(KeyCode::M) && !contexts.ctx_mut().wants_keyboard_input() {
+        measure.toggle();
+    }
+}
+
+/// Draw the measurement on the plot, taking clicks while the tool is active.
+pub fn measure_plot(plot_ui: &mut egui::plot::PlotUi, measure: &mut Measure, cursor: Option<Vec2>) {
+    if !measure.active {
+        return;
+    }
+    if plot_ui.plot_clicked() {
+        if let Some(cursor) = cursor {
+            measure.click(cursor);
+        }
+    }
+
+    let Some((start, end)) = measure.segment(cursor) else { return };
+    let points = vec![
+        [start.x as f64, start.y as f64],
+        [end.x as f64, end.y as f64],
+    ];
+    plot_ui.line(
+        egui::plot::Line::new(egui::plot::PlotPoints::new(points.clone()))
+            .color(egui::Color32::GOLD)
+            .width(2.0),
+    );
+    plot_ui.points(
+        egui::plot::Points::new(points)
+            .color(egui::Color32::GOLD)
+            .radius(3.0),
+    );
+}
@@ -0,0 +1,386 @@
+use crate::{doom::*, settings::*};
+use sector::{edit::GeometryEdit, hub::*, prelude::*, render, storage::stored_scenes};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use std::path::Path;
+
+const ASSET_DIR: &str = "assets";
+const MAP_EXTENSION: &str = ".scn.ron";
+/// Most matches listed by quick-open.
+const QUICK_OPEN_RESULTS: usize = 20;
+
+/// Map entities not spawned as part of a map scene.
+pub type UnparentedFilter = (
+    Or<(
+        With<Sector>,
+        With<InitialSector>,
+        With<PlayerStart>,
+        With<CameraPose>,
+        With<WallAttachment>,
+        With<MapVersion>,
+        With<Thing>,
+        With<VertexPool>,
+    )>,
+    Without<Parent>,
+);
+
+/// Map being edited, as a path under the asset directory.
+#[derive(Resource, Debug)]
+pub struct EditorFile {
+    pub path: String,
+    /// Whether the map opened has loaded and settled, before which changes to it are
+    /// part of opening it rather than edits.
+    pub opened: bool,
+    /// Whether the map has changed since it was opened or saved.
+    pub dirty: bool,
+}
+
+impl Default for EditorFile {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_SCENE_RON_FILE_PATH.to_string(),
+            opened: false,
+            dirty: false,
+        }
+    }
+}
+
+impl EditorFile {
+    /// MessagePack copy of the map, saved alongside it.
+    pub fn mp_path(&self) -> String {
+        match self.path.strip_suffix(".ron") {
+            Some(stem) => format!("{stem}.mp"),
+            None => format!("{}.mp", self.path),
+        }
+    }
+
+    /// Name of the map shown in the title bar, marked when it has unsaved changes.
+    pub fn title(&self) -> String {
+        let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        format!("{name}{}", if self.dirty { "*" } else { "" })
+    }
+}
+
+/// Map entities added or changed, see [`MapChanges`].
+pub type MapChangedFilter = Or<(
+    Changed<Sector>,
+    Changed<InitialSector>,
+    Changed<PlayerStart>,
+    Changed<CameraPose>,
+    Changed<WallAttachment>,
+    Added<Thing>,
+)>;
+
+/// Edits to the map since last looked at. Things are only watched coming and going,
+/// as mobs moving about while editing aren't edits.
+#[derive(SystemParam)]
+pub struct MapChanges<'w, 's> {
+    changed_query: Query<'w, 's, (), MapChangedFilter>,
+    removed_sectors: RemovedComponents<'w, 's, Sector>,
+    removed_starts: RemovedComponents<'w, 's, PlayerStart>,
+    removed_attachments: RemovedComponents<'w, 's, WallAttachment>,
+    removed_things: RemovedComponents<'w, 's, Thing>,
+    geometry_edits: EventReader<'w, 's, GeometryEdit>,
+}
+
+impl MapChanges<'_, '_> {
+    /// Whether map entities were added, changed or removed, or geometry edited.
+    pub fn any(&mut self) -> bool {
+        // Each read through so they aren't seen again next frame
+        let removed = self.removed_sectors.iter().count() > 0;
+        let removed = self.removed_starts.iter().count() > 0 || removed;
+        let removed = self.removed_attachments.iter().count() > 0 || removed;
+        let removed = self.removed_things.iter().count() > 0 || removed;
+        let edited = self.geometry_edits.iter().count() > 0;
+        removed || edited || !self.changed_query.is_empty()
+    }
+}
+
+/// Request to open the map at path, under the asset directory.
+#[derive(Debug, Clone)]
+pub struct OpenMap(pub String);
+
+/// Request to save the map being edited, to a new path under the asset directory
+/// when one is given.
+#[derive(Debug, Clone)]
+pub struct SaveMap(pub Option<String>);
+
+/// Fuzzy finder over maps in the asset directory, toggled with Ctrl+O.
+#[derive(Resource, Debug, Default)]
+pub struct QuickOpen {
+    pub open: bool,
+    pub query: String,
+    maps: Vec<String>,
+}
+
+/// Map scenes under the asset directory, as paths relative to it, along with those
+/// saved in the browser on the web.
+pub fn find_maps() -> Vec<String> {
+    fn visit(dir: &Path, maps: &mut Vec<String>) {
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                visit(&path, maps);
+            } else if let Ok(relative) = path.strip_prefix(ASSET_DIR) {
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                if relative.ends_with(MAP_EXTENSION) {
+                    maps.push(relative);
+                }
+            }
+        }
+    }
+
+    let mut maps = stored_scenes();
+    visit(Path::new(ASSET_DIR), &mut maps);
+    maps.sort();
+    maps.dedup();
+    maps
+}
+
+/// Score of candidate containing every character of query in order, ignoring case,
+/// `None` when it doesn't. Consecutive matches and matches starting a path segment
+/// or word score higher.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut last_match: Option<usize> = None;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let index = position + candidate[position..].iter().position(|&d| d == c)?;
+        score += 1;
+        if last_match.map(|last| last + 1 == index).unwrap_or(false) {
+            score += 5;
+        }
+        if index == 0 || matches!(candidate[index - 1], '/' | '_' | '-' | '.') {
+            score += 3;
+        }
+        last_match = Some(index);
+        position = index + 1;
+    }
+
+    // Prefer shorter paths among equal matches
+    Some(score * 100 - candidate.len() as i32)
+}
+
+pub fn quick_open_key_system(mut quick_open: ResMut<QuickOpen>, key: Res<Input<KeyCode>>) {
+    let control = key.pressed(KeyCode::LControl) || key.pressed(KeyCode::RControl);
+    if control && key.just_pressed(KeyCode::O) {
+        quick_open.open = !quick_open.open;
+        quick_open.query.clear();
+        if quick_open.open {
+            quick_open.maps = find_maps();
+        }
+    }
+}
+
+pub fn save_key_system(mut save_map_events: EventWriter<SaveMap>, key: Res<Input<KeyCode>>) {
+    let control = key.pressed(KeyCode::LControl) || key.pressed(KeyCode::RControl);
+    if control && key.just_pressed(KeyCode::S) {
+        save_map_events.send(SaveMap(None));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn asset_dir() -> std::path::PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(ASSET_DIR))
+        .unwrap_or_else(|_| ASSET_DIR.into())
+}
+
+/// Path of a picked file, relative to the asset directory when within it.
+#[cfg(not(target_arch = "wasm32"))]
+fn asset_path(path: &Path) -> String {
+    let path = path.strip_prefix(asset_dir()).unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Map scene chosen with a file picker.
+#[cfg(not(target_arch = "wasm32"))]
+fn pick_open_path() -> Option<String> {
+    let path = rfd::FileDialog::new()
+        .set_title("Open map")
+        .add_filter("map", &["ron"])
+        .set_directory(asset_dir())
+        .pick_file()?;
+    Some(asset_path(&path))
+}
+
+/// Path to save the map to chosen with a file picker.
+#[cfg(not(target_arch = "wasm32"))]
+fn pick_save_path(current: &str) -> Option<String> {
+    let file_name = current.rsplit('/').next().unwrap_or(current);
+    let path = rfd::FileDialog::new()
+        .set_title("Save map")
+        .add_filter("map", &["ron"])
+        .set_directory(asset_dir())
+        .set_file_name(file_name)
+        .save_file()?;
+    let path = asset_path(&path);
+    Some(if path.ends_with(MAP_EXTENSION) {
+        path
+    } else {
+        format!("{}{MAP_EXTENSION}", path.trim_end_matches(".ron"))
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn pick_open_path() -> Option<String> {
+    warn!("opening maps from files isn't supported on the web");
+    None
+}
+
+#[cfg(target_arch = "wasm32")]
+fn pick_save_path(_current: &str) -> Option<String> {
+    warn!("saving maps to files isn't supported on the web");
+    None
+}
+
+pub fn quick_open_system(
+    mut contexts: EguiContexts,
+    mut quick_open: ResMut<QuickOpen>,
+    mut open_map_events: EventWriter<OpenMap>,
+) {
+    if !quick_open.open {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+
+    let mut matches: Vec<(i32, &String)> = quick_open
+        .maps
+        .iter()
+        .filter_map(|map| Some((fuzzy_score(&quick_open.query, map)?, map)))
+        .collect();
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    let matches: Vec<String> = matches
+        .into_iter()
+        .take(QUICK_OPEN_RESULTS)
+        .map(|(_, map)| map.clone())
+        .collect();
+
+    let mut chosen: Option<String> = None;
+    let mut query = quick_open.query.clone();
+    egui::Window::new("open map")
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .show(ctx, |ui| {
+            let response = ui.text_edit_singleline(&mut query);
+            response.request_focus();
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                chosen = matches.first().cloned();
+            }
+
+            ui.separator();
+            if matches.is_empty() {
+                ui.label("no maps found");
+            }
+            for map in &matches {
+                if ui.selectable_label(false, map).clicked() {
+                    chosen = Some(map.clone());
+                }
+            }
+        });
+    quick_open.query = query;
+
+    if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+        quick_open.open = false;
+    }
+    if let Some(map) = chosen {
+        quick_open.open = false;
+        open_map_events.send(OpenMap(map));
+    }
+}
+
+/// File menu with quick-open, opening, saving and importing Doom maps through file
+/// pickers, recently opened maps and the format maps are saved in.
+pub fn file_menu_system(
+    mut contexts: EguiContexts,
+    mut quick_open: ResMut<QuickOpen>,
+    mut open_map_events: EventWriter<OpenMap>,
+    mut save_map_events: EventWriter<SaveMap>,
+    mut settings: ResMut<EditorSettings>,
+    mut doom_import: ResMut<DoomImport>,
+    editor_file: Res<EditorFile>,
+) {
+    let ctx = contexts.ctx_mut();
+
+    egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Open...    Ctrl+O").clicked() {
+                    quick_open.open = true;
+                    quick_open.query.clear();
+                    quick_open.maps = find_maps();
+                    ui.close_menu();
+                }
+                if ui.button("Open file...").clicked() {
+                    ui.close_menu();
+                    if let Some(path) = pick_open_path() {
+                        open_map_events.send(OpenMap(path));
+                    }
+                }
+                if ui.button("Save    Ctrl+S").clicked() {
+                    save_map_events.send(SaveMap(None));
+                    ui.close_menu();
+                }
+                if ui.button("Save as...").clicked() {
+                    ui.close_menu();
+                    if let Some(path) = pick_save_path(&editor_file.path) {
+                        save_map_events.send(SaveMap(Some(path)));
+                    }
+                }
+                if ui.button("Import Doom map...").clicked() {
+                    ui.close_menu();
+                    if let Some((name, bytes)) = pick_doom_file() {
+                        doom_import.import_file(&name, bytes);
+                    }
+                }
+                ui.separator();
+                ui.menu_button("Recent", |ui| {
+                    if settings.recent_files.is_empty() {
+                        ui.label("no recent files");
+                    }
+                    for path in &settings.recent_files {
+                        if ui.button(path).clicked() {
+                            open_map_events.send(OpenMap(path.clone()));
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.menu_button("Save format", |ui| {
+                    for format in SaveFormat::ALL {
+                        ui.radio_value(&mut settings.save_format, format, format.label());
+                    }
+                });
+            })
+        });
+    });
+}
+
+/// Replace the map being edited with the one requested, through the same load path
+/// as the game.
+pub fn open_map_system(
+    mut commands: Commands,
+    mut open_map_events: EventReader<OpenMap>,
+    mut editor_file: ResMut<EditorFile>,
+    mut settings: ResMut<EditorSettings>,
+    mut world_state: ResMut<WorldState>,
+    mut view: ResMut<render::View>,
+    unparented_query: Query<Entity, UnparentedFilter>,
+) {
+    let Some(OpenMap(path)) = open_map_events.iter().last() else { return };
+
+    // Remove any map not spawned through the load path, like the starting one
+    for entity in &unparented_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    info!("opening map `{path}`");
+    world_state.travel(path, true);
+    editor_file.path = path.clone();
+    editor_file.opened = false;
+    editor_file.dirty = false;
+    settings.add_recent_file(path);
+    view.current_sector = None;
+}
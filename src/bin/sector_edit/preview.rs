@@ -0,0 +1,75 @@
+use sector::{prelude::*, render::*};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// Screen points the preview is shown at per pixel rendered, until resized.
+const PREVIEW_SCALE: f32 = 2.0;
+/// Units per second the preview camera moves at.
+const MOVE_SPEED: f32 = 4.0;
+/// Radians the preview camera turns, and pitches, per screen point dragged.
+const TURN_PER_POINT: f32 = 0.01;
+
+pub fn spawn_preview_frame_system(mut commands: Commands) {
+    commands.spawn(ImageFrame::new(WIDTH, HEIGHT));
+}
+
+/// First person preview of the map, drawn into an [`ImageFrame`] and shown in a
+/// window over the plot. While hovered, WASD moves the view and dragging turns it.
+pub fn preview_window_system(
+    mut contexts: EguiContexts,
+    mut texture: Local<Option<egui::TextureHandle>>,
+    mut view: ResMut<View>,
+    time: Res<Time>,
+    frame_query: Query<&ImageFrame>,
+) {
+    let Ok(frame) = frame_query.get_single() else { return };
+    let ctx = contexts.ctx_mut();
+
+    let size = [frame.width as usize, frame.height as usize];
+    let image = egui::ColorImage::from_rgba_unmultiplied(size, &frame.data);
+    let texture = match &mut *texture {
+        Some(texture) => {
+            texture.set(image, egui::TextureOptions::NEAREST);
+            texture
+        }
+        None => texture.insert(ctx.load_texture("preview", image, egui::TextureOptions::NEAREST)),
+    };
+
+    egui::Window::new("preview")
+        .default_size(texture.size_vec2() * PREVIEW_SCALE)
+        .resizable(true)
+        .show(ctx, |ui| {
+            // Fill the window, keeping the frame's aspect
+            let available = ui.available_size();
+            let aspect = texture.aspect_ratio();
+            let size = if available.x / available.y > aspect {
+                egui::vec2(available.y * aspect, available.y)
+            } else {
+                egui::vec2(available.x, available.x / aspect)
+            };
+            let response =
+                ui.add(egui::Image::new(texture.id(), size).sense(egui::Sense::click_and_drag()));
+
+            if response.dragged() {
+                let delta = response.drag_delta();
+                view.direction.0 -= delta.x * TURN_PER_POINT;
+                view.pitch = (view.pitch - delta.y * TURN_PER_POINT).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            if response.hovered() && !ui.ctx().wants_keyboard_input() {
+                let axis = |positive, negative| {
+                    ui.input(|i| i.key_down(positive) as i32 - i.key_down(negative) as i32) as f32
+                };
+                let forward = axis(egui::Key::W, egui::Key::S);
+                let right = axis(egui::Key::D, egui::Key::A);
+                let (sin, cos) = view.direction.0.sin_cos();
+                let step = (Vec2::new(-sin, cos) * forward + Vec2::new(cos, sin) * right)
+                    .normalize_or_zero()
+                    * MOVE_SPEED
+                    * time.delta_seconds();
+                if step != Vec2::ZERO {
+                    view.position.0 += step.extend(0.0);
+                }
+            }
+        });
+}
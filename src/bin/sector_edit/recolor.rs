@@ -0,0 +1,158 @@
+use sector::prelude::*;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::egui;
+use palette::{Hsv, IntoColor, Srgb};
+
+/// Bulk recolor of the walls of the selected sectors, swapping colors for others and
+/// shifting hue and saturation, as when retheming an imported map. Changes are
+/// previewed on the map until applied or cancelled, and the map isn't saved
+/// meanwhile.
+#[derive(Resource, Debug, Default)]
+pub struct Recolor {
+    pub selected: HashSet<SectorId>,
+    /// Colors swapped for others, before shifting.
+    pub swaps: Vec<(RawColor, RawColor)>,
+    /// Degrees added to hue.
+    pub hue_shift: f32,
+    /// Added to saturation, from -1 to 1.
+    pub saturation_shift: f32,
+    /// Colors of previewed sectors before recoloring, restored on cancel.
+    original: HashMap<SectorId, Vec<RawColor>>,
+}
+
+impl Recolor {
+    pub fn previewing(&self) -> bool {
+        !self.original.is_empty()
+    }
+
+    /// Color swapped for color, itself when not swapped.
+    pub fn swapped(&self, color: RawColor) -> RawColor {
+        self.swaps
+            .iter()
+            .find(|(from, _)| *from == color)
+            .map(|(_, to)| *to)
+            .unwrap_or(color)
+    }
+
+    pub fn set_swap(&mut self, from: RawColor, to: RawColor) {
+        self.swaps.retain(|(f, _)| *f != from);
+        if from != to {
+            self.swaps.push((from, to));
+        }
+    }
+
+    /// Color swapped and then shifted.
+    pub fn recolor(&self, color: RawColor) -> RawColor {
+        let color = self.swapped(color);
+        if self.hue_shift == 0.0 && self.saturation_shift == 0.0 {
+            return color;
+        }
+
+        let mut hsv: Hsv = Srgb::<u8>::from(color).into_format().into_color();
+        hsv.hue += self.hue_shift;
+        hsv.saturation = (hsv.saturation + self.saturation_shift).clamp(0.0, 1.0);
+        hsv.into()
+    }
+
+    fn reset(&mut self) {
+        self.swaps.clear();
+        self.hue_shift = 0.0;
+        self.saturation_shift = 0.0;
+    }
+}
+
+fn color32(color: RawColor) -> egui::Color32 {
+    egui::Color32::from_rgb(color.0[0], color.0[1], color.0[2])
+}
+
+/// Recolor controls, previewing on sectors while the preview is open.
+pub fn recolor_ui(ui: &mut egui::Ui, recolor: &mut Recolor, sector_query: &mut Query<&mut Sector>) {
+    ui.horizontal(|ui| {
+        ui.label(format!("selected: {}", recolor.selected.len()));
+        ui.add_enabled_ui(!recolor.previewing(), |ui| {
+            if ui.button("all").clicked() {
+                recolor.selected = sector_query.iter().map(|s| s.id).collect();
+            }
+            if ui.button("none").clicked() {
+                recolor.selected.clear();
+            }
+        });
+    });
+
+    // Colors the selection uses, as they were before any preview
+    let mut used: Vec<RawColor> = Vec::new();
+    for sector in sector_query.iter() {
+        if !recolor.selected.contains(&sector.id) {
+            continue;
+        }
+        let colors = recolor.original.get(&sector.id).unwrap_or(&sector.colors);
+        for color in colors {
+            if !used.contains(color) {
+                used.push(*color);
+            }
+        }
+    }
+
+    for color in used {
+        ui.horizontal(|ui| {
+            egui::color_picker::show_color(ui, color32(color), egui::vec2(32.0, 16.0));
+            ui.label("→");
+            let mut to = color32(recolor.swapped(color));
+            if ui.color_edit_button_srgba(&mut to).changed() {
+                recolor.set_swap(color, RawColor([to.r(), to.g(), to.b()]));
+            }
+        });
+    }
+    ui.add(egui::Slider::new(&mut recolor.hue_shift, -180.0..=180.0).text("hue"));
+    ui.add(egui::Slider::new(&mut recolor.saturation_shift, -1.0..=1.0).text("saturation"));
+
+    ui.horizontal(|ui| {
+        if !recolor.previewing() {
+            let can_preview = !recolor.selected.is_empty();
+            if ui
+                .add_enabled(can_preview, egui::Button::new("preview"))
+                .clicked()
+            {
+                recolor.original = sector_query
+                    .iter()
+                    .filter(|s| recolor.selected.contains(&s.id))
+                    .map(|s| (s.id, s.colors.clone()))
+                    .collect();
+            }
+        } else {
+            // Changes while previewing don't make the map dirty, so applying marks the
+            // sectors changed again and cancelling leaves them as they were
+            if ui.button("apply").clicked() {
+                for mut sector in sector_query.iter_mut() {
+                    if recolor.original.contains_key(&sector.id) {
+                        sector.set_changed();
+                    }
+                }
+                recolor.original.clear();
+                recolor.reset();
+            }
+            if ui.button("cancel").clicked() {
+                for mut sector in sector_query.iter_mut() {
+                    if let Some(colors) = recolor.original.remove(&sector.id) {
+                        sector.bypass_change_detection().colors = colors;
+                    }
+                }
+                recolor.original.clear();
+            }
+        }
+    });
+
+    // Follow changes to the mapping while previewing
+    for mut sector in sector_query.iter_mut() {
+        if let Some(original) = recolor.original.get(&sector.id) {
+            let colors: Vec<RawColor> = original.iter().map(|c| recolor.recolor(*c)).collect();
+            if colors != sector.colors {
+                sector.colors = colors;
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::*;
+
+use bevy::window::PrimaryWindow;
+use bevy_egui::{egui, EguiInput};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+/// Frames taken to move the pointer between the ends of a `Drag` step.
+const DRAG_FRAMES: u32 = 10;
+
+/// Single step of an editor input script. Positions are in egui points, origin at
+/// top left of the window.
+#[derive(Deserialize, Debug, Clone)]
+pub enum Step {
+    /// Do nothing for a number of frames.
+    Wait(u32),
+    PointerMove(f32, f32),
+    Click(f32, f32),
+    Drag {
+        from: (f32, f32),
+        to: (f32, f32),
+    },
+    Key(Key),
+    Text(String),
+    /// Write the current world to a scene file at path.
+    Save(String),
+    AssertSectorCount(usize),
+    AssertSector {
+        id: u32,
+        floor: Option<f32>,
+        ceil: Option<f32>,
+        vertices: Option<usize>,
+    },
+    AssertPortal {
+        sector: u32,
+        wall: usize,
+        target: Option<u32>,
+    },
+}
+
+/// Keys available to scripts, mapped to `egui::Key`.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum Key {
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Delete,
+    Space,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    Home,
+    End,
+}
+
+impl From<Key> for egui::Key {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Enter => Self::Enter,
+            Key::Escape => Self::Escape,
+            Key::Tab => Self::Tab,
+            Key::Backspace => Self::Backspace,
+            Key::Delete => Self::Delete,
+            Key::Space => Self::Space,
+            Key::ArrowUp => Self::ArrowUp,
+            Key::ArrowDown => Self::ArrowDown,
+            Key::ArrowLeft => Self::ArrowLeft,
+            Key::ArrowRight => Self::ArrowRight,
+            Key::Home => Self::Home,
+            Key::End => Self::End,
+        }
+    }
+}
+
+/// Replays a script of synthetic egui input, one step at a time, checking
+/// assertions against the world as it goes.
+#[derive(Resource, Debug)]
+pub struct ScriptRunner {
+    path: String,
+    steps: VecDeque<Step>,
+    /// Events to inject, one entry per frame.
+    pending: VecDeque<Vec<egui::Event>>,
+    wait: u32,
+    pointer: egui::Pos2,
+    failures: Vec<String>,
+}
+
+impl ScriptRunner {
+    pub fn load(path: &str) -> Self {
+        let script = std::fs::read_to_string(path).expect("failed to read script file");
+        let steps: Vec<Step> = ron::from_str(&script).expect("failed to parse script file");
+
+        Self {
+            path: path.to_string(),
+            steps: steps.into(),
+            pending: VecDeque::new(),
+            wait: 0,
+            pointer: egui::Pos2::ZERO,
+            failures: Vec::new(),
+        }
+    }
+
+    fn queue_pointer_button(&mut self, pressed: bool) {
+        self.pending.push_back(vec![egui::Event::PointerButton {
+            pos: self.pointer,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: egui::Modifiers::NONE,
+        }]);
+    }
+
+    fn queue_pointer_move(&mut self, x: f32, y: f32) {
+        self.pointer = egui::pos2(x, y);
+        self.pending
+            .push_back(vec![egui::Event::PointerMoved(self.pointer)]);
+    }
+
+    fn check(&mut self, passed: bool, message: String) {
+        if !passed {
+            self.failures.push(message);
+        }
+    }
+}
+
+pub fn load_script_system(mut commands: Commands) {
+    // Replay an input script instead of waiting on the user, `--script <path>`
+    if let Some(path) = std::env::args().skip_while(|a| a != "--script").nth(1) {
+        commands.insert_resource(ScriptRunner::load(&path));
+    }
+}
+
+pub fn script_system(world: &mut World) {
+    let Some(mut runner) = world.remove_resource::<ScriptRunner>() else { return };
+
+    if runner.wait > 0 {
+        runner.wait -= 1;
+    } else if let Some(events) = runner.pending.pop_front() {
+        let mut input_query = world.query_filtered::<&mut EguiInput, With<PrimaryWindow>>();
+        if let Ok(mut input) = input_query.get_single_mut(world) {
+            input.0.events.extend(events);
+        }
+    } else if let Some(step) = runner.steps.pop_front() {
+        run_step(world, &mut runner, step);
+    } else {
+        finish(world, &runner);
+    }
+
+    world.insert_resource(runner);
+}
+
+fn run_step(world: &mut World, runner: &mut ScriptRunner, step: Step) {
+    debug!("script step: {step:?}");
+
+    match step {
+        Step::Wait(frames) => runner.wait = frames,
+        Step::PointerMove(x, y) => runner.queue_pointer_move(x, y),
+        Step::Click(x, y) => {
+            runner.queue_pointer_move(x, y);
+            runner.queue_pointer_button(true);
+            runner.queue_pointer_button(false);
+        }
+        Step::Drag { from, to } => {
+            runner.queue_pointer_move(from.0, from.1);
+            runner.queue_pointer_button(true);
+            for i in 1..=DRAG_FRAMES {
+                let t = i as f32 / DRAG_FRAMES as f32;
+                runner
+                    .queue_pointer_move(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+            }
+            runner.queue_pointer_button(false);
+        }
+        Step::Key(key) => {
+            for pressed in [true, false] {
+                runner.pending.push_back(vec![egui::Event::Key {
+                    key: key.into(),
+                    pressed,
+                    repeat: false,
+                    modifiers: egui::Modifiers::NONE,
+                }]);
+            }
+        }
+        Step::Text(text) => runner.pending.push_back(vec![egui::Event::Text(text)]),
+        Step::Save(path) => {
+            let scene_ron = scene_ron(world);
+            if let Err(error) = std::fs::write(&path, scene_ron) {
+                runner
+                    .failures
+                    .push(format!("failed to save scene to `{path}`: {error}"));
+            }
+        }
+        Step::AssertSectorCount(count) => {
+            let actual = world.query::<&Sector>().iter(world).count();
+            runner.check(
+                actual == count,
+                format!("expected {count} sectors, found {actual}"),
+            );
+        }
+        Step::AssertSector {
+            id,
+            floor,
+            ceil,
+            vertices,
+        } => {
+            let mut sector_query = world.query::<&Sector>();
+            let Some(sector) = sector_query.iter(world).find(|s| s.id == SectorId(id)) else {
+                runner.check(false, format!("sector {id} not found"));
+                return;
+            };
+            let (actual_floor, actual_ceil) = (sector.floor.0, sector.ceil.0);
+            let actual_vertices = sector.vertices.len();
+
+            if let Some(floor) = floor {
+                runner.check(
+                    (actual_floor - floor).abs() < f32::EPSILON,
+                    format!("sector {id}: expected floor {floor}, found {actual_floor}"),
+                );
+            }
+            if let Some(ceil) = ceil {
+                runner.check(
+                    (actual_ceil - ceil).abs() < f32::EPSILON,
+                    format!("sector {id}: expected ceil {ceil}, found {actual_ceil}"),
+                );
+            }
+            if let Some(vertices) = vertices {
+                runner.check(
+                    actual_vertices == vertices,
+                    format!("sector {id}: expected {vertices} vertices, found {actual_vertices}"),
+                );
+            }
+        }
+        Step::AssertPortal {
+            sector,
+            wall,
+            target,
+        } => {
+            let mut sector_query = world.query::<&Sector>();
+            let actual = sector_query
+                .iter(world)
+                .find(|s| s.id == SectorId(sector))
+                .and_then(|s| s.portal_sectors.get(wall).copied())
+                .flatten()
+                .map(|id| id.0);
+            runner.check(
+                actual == target,
+                format!(
+                    "sector {sector} wall {wall}: expected portal {target:?}, found {actual:?}"
+                ),
+            );
+        }
+    }
+}
+
+fn finish(world: &mut World, runner: &ScriptRunner) {
+    if runner.failures.is_empty() {
+        info!("script `{}` passed", runner.path);
+        world.send_event(AppExit);
+    } else {
+        for failure in &runner.failures {
+            error!("{failure}");
+        }
+        error!(
+            "script `{}` failed with {} failure(s)",
+            runner.path,
+            runner.failures.len()
+        );
+        std::process::exit(1);
+    }
+}
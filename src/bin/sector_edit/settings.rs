@@ -0,0 +1,183 @@
+use sector::config::config_path;
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+const SETTINGS_FILE: &str = "sector_edit.ron";
+/// Most recent files remembered.
+pub const MAX_RECENT_FILES: usize = 10;
+/// Least time between writes of changed settings, in seconds.
+const SAVE_INTERVAL: f32 = 1.0;
+
+/// Formats maps are saved in.
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// Diffable, for maps under version control.
+    Ron,
+    /// Smaller and faster to load.
+    MessagePack,
+    /// RON with a MessagePack copy alongside, loaded in its place while up to date.
+    #[default]
+    Both,
+}
+
+impl SaveFormat {
+    pub const ALL: [Self; 3] = [Self::Ron, Self::MessagePack, Self::Both];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ron => "RON",
+            Self::MessagePack => "MessagePack",
+            Self::Both => "RON and MessagePack",
+        }
+    }
+
+    /// Format given with `--save-format ron|mp|both`, if any.
+    pub fn from_args() -> Option<Self> {
+        let arg = std::env::args()
+            .skip_while(|a| a != "--save-format")
+            .nth(1)?;
+        match arg.as_str() {
+            "ron" => Some(Self::Ron),
+            "mp" => Some(Self::MessagePack),
+            "both" => Some(Self::Both),
+            _ => {
+                warn!("ignoring unknown save format `{arg}`, expected ron, mp or both");
+                None
+            }
+        }
+    }
+
+    pub fn writes_ron(self) -> bool {
+        self != Self::MessagePack
+    }
+
+    pub fn writes_mp(self) -> bool {
+        self != Self::Ron
+    }
+}
+
+/// Editor UI state kept between sessions in the user's config directory.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EditorSettings {
+    pub dark: bool,
+    pub left_panel_width: f32,
+    /// Open state of collapsing headers by id.
+    pub open_headers: BTreeMap<String, bool>,
+    /// Visible area of the plot, as min and max corners.
+    pub plot_bounds: Option<([f64; 2], [f64; 2])>,
+    /// Most recent first.
+    pub recent_files: Vec<String>,
+    pub snap_to_grid: bool,
+    /// Whether plot grid lines are drawn at multiples of the grid size.
+    pub show_grid: bool,
+    pub grid_size: f32,
+    pub save_format: SaveFormat,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            dark: false,
+            left_panel_width: 250.0,
+            open_headers: BTreeMap::new(),
+            plot_bounds: None,
+            recent_files: Vec::new(),
+            snap_to_grid: true,
+            show_grid: true,
+            grid_size: 0.5,
+            save_format: SaveFormat::default(),
+        }
+    }
+}
+
+impl EditorSettings {
+    /// Settings file in the user's config directory, `None` where there isn't one.
+    pub fn path() -> Option<PathBuf> {
+        config_path(SETTINGS_FILE)
+    }
+
+    /// Settings from the settings file, or defaults when missing or invalid, with
+    /// any given on the command line in their place.
+    pub fn load() -> Self {
+        let mut settings = Self::load_file();
+        if let Some(save_format) = SaveFormat::from_args() {
+            settings.save_format = save_format;
+        }
+        settings
+    }
+
+    fn load_file() -> Self {
+        let Some(path) = Self::path() else { return default() };
+        let Ok(text) = std::fs::read_to_string(&path) else { return default() };
+
+        ron::from_str(&text).unwrap_or_else(|error| {
+            warn!("ignoring invalid settings `{}`: {error}", path.display());
+            default()
+        })
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Self::path() else { return };
+
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+                }
+                std::fs::write(&path, text).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!("failed to save settings `{}`: {error}", path.display());
+        }
+    }
+
+    pub fn visuals(&self) -> egui::Visuals {
+        if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        }
+    }
+
+    pub fn header_open(&self, id: &str, default: bool) -> bool {
+        self.open_headers.get(id).copied().unwrap_or(default)
+    }
+
+    pub fn set_header_open(&mut self, id: &str, open: bool) {
+        if self.open_headers.get(id) != Some(&open) {
+            self.open_headers.insert(id.to_string(), open);
+        }
+    }
+
+    /// Move path to the front of the recent files.
+    pub fn add_recent_file(&mut self, path: &str) {
+        self.recent_files.retain(|p| p != path);
+        self.recent_files.insert(0, path.to_string());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+}
+
+/// Write settings once they have changed, no more than once a second, and on exit.
+pub fn save_settings_system(
+    settings: Res<EditorSettings>,
+    mut saved: Local<Option<EditorSettings>>,
+    mut since_save: Local<f32>,
+    mut app_exit_events: EventReader<AppExit>,
+    time: Res<Time>,
+) {
+    // Settings as loaded don't need writing back
+    let saved = saved.get_or_insert_with(|| settings.clone());
+    *since_save += time.delta_seconds();
+
+    let exiting = app_exit_events.iter().count() > 0;
+    if *saved != *settings && (*since_save >= SAVE_INTERVAL || exiting) {
+        settings.save();
+        *saved = settings.clone();
+        *since_save = 0.0;
+    }
+}
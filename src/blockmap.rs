@@ -0,0 +1,197 @@
+use crate::{load::par_chunk_map, *};
+
+/// Width and height of a blockmap cell in world units.
+pub const BLOCK_SIZE: f32 = 4.0;
+
+/// Wall as stored in the blockmap, in world space.
+#[derive(Debug, Copy, Clone)]
+pub struct BlockWall {
+    /// Sector owning the wall.
+    pub sector: SectorId,
+    /// Index of the wall within its sector.
+    pub wall: usize,
+    pub left: Vec2,
+    pub right: Vec2,
+    pub portal_sector: Option<SectorId>,
+}
+
+/// Uniform grid over the map listing the walls crossing each cell, so collision and
+/// ray queries only look at walls near them. Rebuilt by `blockmap_system` whenever
+/// sectors change.
+#[derive(Resource, Debug, Default)]
+pub struct Blockmap {
+    origin: Vec2,
+    columns: usize,
+    rows: usize,
+    walls: Vec<BlockWall>,
+    /// Indices into `walls`, row major.
+    cells: Vec<Vec<usize>>,
+}
+
+impl Blockmap {
+    pub fn build(sectors: &[&Sector]) -> Self {
+        // Walls of large maps are built in parallel
+        let walls: Vec<BlockWall> = par_chunk_map(sectors, |chunk| {
+            chunk
+                .iter()
+                .flat_map(|sector| {
+                    sector
+                        .to_walls()
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, wall)| BlockWall {
+                            sector: sector.id,
+                            wall: index,
+                            left: wall.left.0,
+                            right: wall.right.0,
+                            portal_sector: wall.portal_sector,
+                        })
+                })
+                .collect()
+        });
+
+        if walls.is_empty() {
+            return Self::default();
+        }
+
+        let (min, max) = walls.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), wall| {
+                (
+                    min.min(wall.left).min(wall.right),
+                    max.max(wall.left).max(wall.right),
+                )
+            },
+        );
+        let size = ((max - min) / BLOCK_SIZE).floor();
+
+        let mut blockmap = Self {
+            origin: min,
+            columns: size.x as usize + 1,
+            rows: size.y as usize + 1,
+            walls,
+            cells: Vec::new(),
+        };
+        blockmap.cells = vec![Vec::new(); blockmap.columns * blockmap.rows];
+
+        for (index, wall) in blockmap.walls.iter().enumerate() {
+            let (first, last) =
+                blockmap.cell_range(wall.left.min(wall.right), wall.left.max(wall.right));
+            for row in first.1..=last.1 {
+                for column in first.0..=last.0 {
+                    let cell_min =
+                        blockmap.origin + Vec2::new(column as f32, row as f32) * BLOCK_SIZE;
+                    if segment_crosses_box(wall.left, wall.right, cell_min, cell_min + BLOCK_SIZE) {
+                        blockmap.cells[row * blockmap.columns + column].push(index);
+                    }
+                }
+            }
+        }
+
+        blockmap
+    }
+
+    /// Walls crossing any cell overlapping the box from min to max, each once.
+    pub fn walls_in(&self, min: Vec2, max: Vec2) -> Vec<&BlockWall> {
+        if self.cells.is_empty() {
+            return Vec::new();
+        }
+
+        let (first, last) = self.cell_range(min, max);
+        let mut indices: Vec<usize> = (first.1..=last.1)
+            .flat_map(|row| (first.0..=last.0).map(move |column| (column, row)))
+            .flat_map(|(column, row)| self.cells[row * self.columns + column].iter().copied())
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices
+            .into_iter()
+            .map(|index| &self.walls[index])
+            .collect()
+    }
+
+    /// Walls a circle of radius could touch moving in a straight line from start to
+    /// end.
+    pub fn walls_along(&self, start: Vec2, end: Vec2, radius: f32) -> Vec<&BlockWall> {
+        self.walls_in(start.min(end) - radius, start.max(end) + radius)
+    }
+
+    /// First wall crossed by the ray from start to end for which solid returns true,
+    /// with the fraction of the ray travelled to reach it.
+    pub fn raycast(
+        &self,
+        start: Vec2,
+        end: Vec2,
+        solid: impl Fn(&BlockWall) -> bool,
+    ) -> Option<(f32, &BlockWall)> {
+        let delta = end - start;
+        self.walls_along(start, end, 0.0)
+            .into_iter()
+            .filter(|wall| solid(wall))
+            .filter_map(|wall| {
+                let edge = wall.right - wall.left;
+                let denominator = delta.perp_dot(edge);
+                if denominator == 0.0 {
+                    return None;
+                }
+                let offset = wall.left - start;
+                let time = offset.perp_dot(edge) / denominator;
+                let along = offset.perp_dot(delta) / denominator;
+                ((0.0..=1.0).contains(&time) && (0.0..=1.0).contains(&along))
+                    .then_some((time, wall))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// Cells, as column and row, at the corners of the box from min to max, clamped
+    /// to the grid.
+    fn cell_range(&self, min: Vec2, max: Vec2) -> ((usize, usize), (usize, usize)) {
+        let cell = |point: Vec2| {
+            let cell = ((point - self.origin) / BLOCK_SIZE).floor();
+            (
+                (cell.x.max(0.0) as usize).min(self.columns - 1),
+                (cell.y.max(0.0) as usize).min(self.rows - 1),
+            )
+        };
+        (cell(min), cell(max))
+    }
+}
+
+/// Whether segment a-b touches the axis aligned box from min to max.
+fn segment_crosses_box(a: Vec2, b: Vec2, min: Vec2, max: Vec2) -> bool {
+    // Clip the segment against each slab in turn
+    let delta = b - a;
+    let (mut enter, mut exit) = (0.0_f32, 1.0_f32);
+    for axis in 0..2 {
+        if delta[axis] == 0.0 {
+            if a[axis] < min[axis] || a[axis] > max[axis] {
+                return false;
+            }
+            continue;
+        }
+        let t1 = (min[axis] - a[axis]) / delta[axis];
+        let t2 = (max[axis] - a[axis]) / delta[axis];
+        enter = enter.max(t1.min(t2));
+        exit = exit.min(t1.max(t2));
+        if enter > exit {
+            return false;
+        }
+    }
+    true
+}
+
+/// Rebuild the blockmap when sectors are added, changed or removed.
+pub fn blockmap_system(
+    mut blockmap: ResMut<Blockmap>,
+    mut removed: RemovedComponents<Sector>,
+    changed_query: Query<(), Changed<Sector>>,
+    sector_query: Query<&Sector>,
+) {
+    if changed_query.is_empty() && removed.iter().count() == 0 {
+        return;
+    }
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    *blockmap = Blockmap::build(&sectors);
+}
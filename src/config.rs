@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+/// Path of file_name in the `sector` directory of the user's config directory, `None`
+/// where there isn't one, such as on the web.
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    #[cfg(target_arch = "wasm32")]
+    return None;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let env_path = |name: &str| std::env::var_os(name).map(PathBuf::from);
+        let config_dir = if cfg!(windows) {
+            env_path("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            env_path("HOME").map(|home| home.join("Library/Application Support"))
+        } else {
+            env_path("XDG_CONFIG_HOME")
+                .or_else(|| env_path("HOME").map(|home| home.join(".config")))
+        };
+        config_dir.map(|dir| dir.join("sector").join(file_name))
+    }
+}
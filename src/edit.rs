@@ -0,0 +1,463 @@
+use crate::{vertex_pool::*, *};
+
+/// Distance within which points are taken to be the same or to lie on a wall.
+pub const SPLIT_EPSILON: f32 = 1e-4;
+
+pub const NEW_SECTOR_FLOOR: f32 = 0.0;
+pub const NEW_SECTOR_CEIL: f32 = 4.0;
+pub const NEW_SECTOR_COLOR: RawColor = RawColor::from_srgb(LIGHTGRAY);
+
+/// Change to map geometry, made with the editor's edit tool or in game. Vertices and
+/// walls are found by position and vertices moved in the `VertexPool`, so sectors
+/// sharing them are changed together and stay joined.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometryEdit {
+    /// Move every vertex at from to to, or only that of sector.
+    MoveVertices {
+        from: Vec2,
+        to: Vec2,
+        sector: Option<SectorId>,
+    },
+    /// Move every vertex at each from to its to, all at once, so vertices moved onto
+    /// where others were aren't moved again.
+    MoveVertexGroup(Vec<(Vec2, Vec2)>),
+    /// Add a vertex at point to every wall it lies inside.
+    SplitWalls(Vec2),
+    /// Remove every vertex at point, joining the walls either side.
+    RemoveVertices(Vec2),
+    /// Add a sector with corners, joined by portals to sectors with walls matching its
+    /// own end to end.
+    AddSector(Vec<Vec2>),
+    DeleteSector(SectorId),
+    /// Raise the floor and ceiling of a sector by amounts, lowering when negative,
+    /// keeping the floor below the ceiling.
+    RaiseHeights {
+        sector: SectorId,
+        floor: f32,
+        ceil: f32,
+    },
+}
+
+/// Point on wall closest to point, kept off its ends.
+pub fn closest_on_wall(wall: &Wall, point: Vec2) -> Vec2 {
+    let (a, b) = (wall.left.0, wall.right.0);
+    let edge = b - a;
+    let t = ((point - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+    let inset = SPLIT_EPSILON * 2.0 / edge.length();
+    a + edge * t.clamp(inset, 1.0 - inset)
+}
+
+/// Apply geometry edits, moving vertices in the map's pool.
+pub fn geometry_edit_system(
+    mut commands: Commands,
+    mut geometry_edits: EventReader<GeometryEdit>,
+    mut sector_query: Query<(Entity, &mut Sector)>,
+    stable_id_query: Query<&StableId>,
+    mut pool_query: Query<&mut VertexPool>,
+) {
+    let same = |p: Vec2, q: Vec2| p.distance(q) < SPLIT_EPSILON;
+    // None until the map's first sector is welded
+    let mut vertex_pool = pool_query.get_single_mut().ok();
+
+    for edit in geometry_edits.iter() {
+        debug!("geometry edit: {edit:?}");
+
+        match edit {
+            GeometryEdit::MoveVertices {
+                from,
+                to,
+                sector: None,
+            } => {
+                let Some(vertex_pool) = vertex_pool.as_mut() else { continue };
+                let ids: Vec<VertexId> = vertex_pool.find_all(Position2(*from)).collect();
+                for id in ids {
+                    vertex_pool.set(id, Position2(*to));
+                }
+            }
+            GeometryEdit::MoveVertices {
+                from,
+                to,
+                sector: Some(sector_id),
+            } => {
+                let Some(vertex_pool) = vertex_pool.as_mut() else { continue };
+                let corner = sector_query
+                    .iter()
+                    .find(|(_, s)| s.id == *sector_id)
+                    .and_then(|(_, s)| {
+                        let index = s.vertex_ids.iter().position(|&id| {
+                            vertex_pool.get(id).is_some_and(|p| same(p.0, *from))
+                        })?;
+                        Some((index, s.vertex_ids[index]))
+                    });
+                let Some((index, id)) = corner else { continue };
+                let users = sector_query
+                    .iter()
+                    .flat_map(|(_, s)| &s.vertex_ids)
+                    .filter(|&&other| other == id)
+                    .count();
+                if users > 1 {
+                    // Part the corner onto a vertex of its own
+                    let parted = vertex_pool.add(Position2(*to));
+                    for (_, mut s) in &mut sector_query {
+                        if s.id == *sector_id {
+                            s.vertex_ids[index] = parted;
+                        }
+                    }
+                } else {
+                    vertex_pool.set(id, Position2(*to));
+                }
+            }
+            GeometryEdit::MoveVertexGroup(moves) => {
+                let Some(vertex_pool) = vertex_pool.as_mut() else { continue };
+                let found: Vec<(VertexId, Vec2)> = moves
+                    .iter()
+                    .flat_map(|&(from, to)| {
+                        vertex_pool
+                            .find_all(Position2(from))
+                            .map(move |id| (id, to))
+                    })
+                    .collect();
+                for (id, to) in found {
+                    vertex_pool.set(id, Position2(to));
+                }
+            }
+            GeometryEdit::SplitWalls(point) => {
+                let Some(vertex_pool) = vertex_pool.as_mut() else { continue };
+                let mut id = None;
+                for (_, mut sector) in &mut sector_query {
+                    let walls = sector.to_walls();
+                    // Back to front, so indices of walls still to split don't move
+                    for (index, wall) in walls.iter().enumerate().rev() {
+                        let on_wall = closest_on_wall(wall, *point);
+                        let inside = !same(*point, wall.left.0) && !same(*point, wall.right.0);
+                        if inside && same(on_wall, *point) {
+                            let id = *id.get_or_insert_with(|| vertex_pool.add(Position2(*point)));
+                            sector.split_wall(index, id, Position2(*point));
+                        }
+                    }
+                }
+            }
+            GeometryEdit::RemoveVertices(point) => {
+                let Some(vertex_pool) = vertex_pool.as_mut() else { continue };
+                for (_, mut sector) in &mut sector_query {
+                    let at_point =
+                        |id: &VertexId| vertex_pool.get(*id).is_some_and(|p| same(p.0, *point));
+                    while let Some(index) = sector.vertex_ids.iter().position(at_point) {
+                        // Sectors keep at least a triangle
+                        if sector.vertices.len() <= 3 {
+                            break;
+                        }
+                        sector.remove_vertex(index);
+                    }
+                }
+            }
+            GeometryEdit::AddSector(corners) => {
+                // Sectors wind clockwise
+                let mut corners = corners.clone();
+                let area: f32 = (0..corners.len())
+                    .map(|i| corners[i].perp_dot(corners[(i + 1) % corners.len()]))
+                    .sum();
+                if area > 0.0 {
+                    corners.reverse();
+                }
+
+                let id = SectorId(
+                    sector_query
+                        .iter()
+                        .map(|(_, s)| s.id.0 + 1)
+                        .max()
+                        .unwrap_or(0),
+                );
+                let walls = corners.len();
+                let mut portal_sectors = vec![None; walls];
+                for (i, portal_sector) in portal_sectors.iter_mut().enumerate() {
+                    let (a, b) = (corners[i], corners[(i + 1) % walls]);
+                    for (_, mut other) in &mut sector_query {
+                        let matching = other
+                            .to_walls()
+                            .iter()
+                            .position(|w| same(w.left.0, b) && same(w.right.0, a));
+                        if let Some(index) = matching {
+                            if other.portal_sectors.len() <= index {
+                                other.portal_sectors.resize(index + 1, None);
+                            }
+                            other.portal_sectors[index] = Some(id);
+                            *portal_sector = Some(other.id);
+                        }
+                    }
+                }
+
+                // Welded by vertex_pool_system instead when the map has no pool yet
+                let vertex_ids = match vertex_pool.as_mut() {
+                    Some(pool) => corners.iter().map(|&c| pool.weld(Position2(c))).collect(),
+                    None => Vec::new(),
+                };
+                let stable_id = stable_id_query.iter().map(|s| s.0 + 1).max().unwrap_or(0);
+                info!("adding sector {}", id.0);
+                commands.spawn((
+                    StableId(stable_id),
+                    Sector {
+                        id,
+                        tag: 0,
+                        vertex_ids,
+                        vertices: corners.into_iter().map(Position2).collect(),
+                        portal_sectors,
+                        colors: vec![NEW_SECTOR_COLOR; walls],
+                        floor: Length(NEW_SECTOR_FLOOR),
+                        ceil: Length(NEW_SECTOR_CEIL),
+                        bridge: None,
+                        wall_textures: vec![None; walls],
+                        mid_textures: vec![None; walls],
+                        upper_walls: vec![None; walls],
+                        lower_walls: vec![None; walls],
+                        texture_alignments: vec![TextureAlignment::default(); walls],
+                        light: DEFAULT_LIGHT,
+                        liquid: None,
+                        floor_material: FloorMaterial::default(),
+                        fog: None,
+                        floor_color: None,
+                        ceiling_color: None,
+                    },
+                ));
+            }
+            GeometryEdit::RaiseHeights {
+                sector,
+                floor,
+                ceil,
+            } => {
+                for (_, mut s) in &mut sector_query {
+                    if s.id != *sector {
+                        continue;
+                    }
+                    let new_floor = s.floor.0 + floor;
+                    let new_ceil = s.ceil.0 + ceil;
+                    if new_floor < new_ceil {
+                        s.floor = Length(new_floor);
+                        s.ceil = Length(new_ceil);
+                    }
+                }
+            }
+            GeometryEdit::DeleteSector(id) => {
+                info!("deleting sector {}", id.0);
+                for (entity, mut sector) in &mut sector_query {
+                    if sector.id == *id {
+                        commands.entity(entity).despawn_recursive();
+                    } else if sector.portal_sectors.contains(&Some(*id)) {
+                        // Portals into it become solid walls
+                        for portal_sector in &mut sector.portal_sectors {
+                            if *portal_sector == Some(*id) {
+                                *portal_sector = None;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // So the next edit finds sectors where this one left them
+        if let Some(vertex_pool) = &vertex_pool {
+            for (_, mut sector) in &mut sector_query {
+                vertex_pool.update_vertices(&mut sector);
+            }
+        }
+    }
+}
+
+/// Split walls at vertices of other sectors lying along them, so that each wall
+/// faces at most one sector, as needed by maps imported from formats where a long
+/// wall can face several. Pieces of a portal wall lead to the sector sharing that
+/// piece, or become solid where none does. Solid walls stay solid.
+///
+/// Returns replacements for sectors that changed, with the same ids. Pieces start at
+/// the pool vertices they were cut at, so replacements stay welded, unless sectors
+/// weren't welded to begin with.
+pub fn split_walls_at_portals(sectors: &[&Sector]) -> Vec<Sector> {
+    let all_vertices: Vec<(Vec2, Option<VertexId>)> = sectors
+        .iter()
+        .flat_map(|s| {
+            let ids = s.vertex_ids.iter().map(Some).chain(std::iter::repeat(None));
+            s.vertices.iter().zip(ids).map(|(v, id)| (v.0, id.copied()))
+        })
+        .collect();
+
+    // Split every wall at the vertices lying inside it
+    #[allow(clippy::type_complexity)]
+    let split: Vec<(
+        Vec<Position2>,
+        Vec<Option<VertexId>>,
+        Vec<bool>,
+        Vec<RawColor>,
+        Vec<Option<String>>,
+        Vec<Option<MidTexture>>,
+        Vec<Option<WallSurface>>,
+        Vec<Option<WallSurface>>,
+        Vec<TextureAlignment>,
+    )> = sectors
+        .iter()
+        .map(|sector| {
+            let mut vertices = Vec::new();
+            let mut vertex_ids = Vec::new();
+            let mut was_portal = Vec::new();
+            let mut colors = Vec::new();
+            let mut wall_textures = Vec::new();
+            let mut mid_textures = Vec::new();
+            let mut upper_walls = Vec::new();
+            let mut lower_walls = Vec::new();
+            let mut texture_alignments = Vec::new();
+            for (i, wall) in sector.to_walls().into_iter().enumerate() {
+                let (a, b) = (wall.left.0, wall.right.0);
+                let mut cuts: Vec<(f32, Option<VertexId>)> = all_vertices
+                    .iter()
+                    .filter_map(|&(p, id)| {
+                        let edge = b - a;
+                        let t = (p - a).dot(edge) / edge.length_squared();
+                        let on_wall = (a + edge * t).distance(p) < SPLIT_EPSILON;
+                        let inside = t * edge.length() > SPLIT_EPSILON
+                            && (1.0 - t) * edge.length() > SPLIT_EPSILON;
+                        (on_wall && inside).then_some((t, id))
+                    })
+                    .collect();
+                cuts.sort_by(|x, y| x.0.total_cmp(&y.0));
+                cuts.dedup_by(|x, y| (x.0 - y.0) * (b - a).length() < SPLIT_EPSILON);
+
+                let start = (0.0, sector.vertex_ids.get(i).copied());
+                for (t, id) in std::iter::once(start).chain(cuts) {
+                    vertices.push(Position2(a.lerp(b, t)));
+                    vertex_ids.push(id);
+                    was_portal.push(wall.portal_sector.is_some());
+                    colors.push(wall.raw_color);
+                    wall_textures.push(sector.wall_texture(i).map(str::to_string));
+                    mid_textures.push(sector.mid_texture(i).cloned());
+                    upper_walls.push(sector.upper_wall(i).cloned());
+                    lower_walls.push(sector.lower_wall(i).cloned());
+                    // Textures carry on across the pieces
+                    let mut alignment = sector.texture_alignment(i);
+                    alignment.u_offset += t * (b - a).length();
+                    texture_alignments.push(alignment);
+                }
+            }
+            (
+                vertices,
+                vertex_ids,
+                was_portal,
+                colors,
+                wall_textures,
+                mid_textures,
+                upper_walls,
+                lower_walls,
+                texture_alignments,
+            )
+        })
+        .collect();
+
+    // Pieces that were portals lead to whichever sector has the same wall reversed
+    let walls_of = |vertices: &[Position2]| -> Vec<(Vec2, Vec2)> {
+        (0..vertices.len())
+            .map(|i| (vertices[i].0, vertices[(i + 1) % vertices.len()].0))
+            .collect()
+    };
+    let all_walls: Vec<(SectorId, Vec<(Vec2, Vec2)>)> = sectors
+        .iter()
+        .zip(&split)
+        .map(|(sector, (vertices, ..))| (sector.id, walls_of(vertices)))
+        .collect();
+    let same = |p: Vec2, q: Vec2| p.distance(q) < SPLIT_EPSILON;
+
+    sectors
+        .iter()
+        .zip(split)
+        .filter_map(
+            |(
+                sector,
+                (
+                    vertices,
+                    vertex_ids,
+                    was_portal,
+                    colors,
+                    wall_textures,
+                    mid_textures,
+                    upper_walls,
+                    lower_walls,
+                    texture_alignments,
+                ),
+            )| {
+                let portal_sectors: Vec<Option<SectorId>> = walls_of(&vertices)
+                    .into_iter()
+                    .zip(was_portal)
+                    .map(|((a, b), was_portal)| {
+                        if !was_portal {
+                            return None;
+                        }
+                        all_walls
+                            .iter()
+                            .filter(|(id, _)| *id != sector.id)
+                            .find(|(_, walls)| walls.iter().any(|&(c, d)| same(a, d) && same(b, c)))
+                            .map(|(id, _)| *id)
+                    })
+                    .collect();
+
+                let changed = vertices.len() != sector.vertices.len()
+                    || portal_sectors != sector.portal_sectors;
+                changed.then_some(Sector {
+                    id: sector.id,
+                    tag: sector.tag,
+                    // Only when every piece has a pool vertex
+                    vertex_ids: vertex_ids
+                        .into_iter()
+                        .collect::<Option<_>>()
+                        .unwrap_or_default(),
+                    vertices,
+                    portal_sectors,
+                    colors,
+                    floor: sector.floor,
+                    ceil: sector.ceil,
+                    bridge: sector.bridge,
+                    wall_textures,
+                    mid_textures,
+                    upper_walls,
+                    lower_walls,
+                    texture_alignments,
+                    light: sector.light,
+                    liquid: sector.liquid,
+                    floor_material: sector.floor_material,
+                    fog: sector.fog,
+                    floor_color: sector.floor_color,
+                    ceiling_color: sector.ceiling_color,
+                })
+            },
+        )
+        .collect()
+}
+
+/// Portals of every sector worked out from geometry, each wall leading to the sector
+/// with the same wall reversed and walls shared with no sector solid. Both sides of a
+/// shared edge are found, so portals always lead back.
+///
+/// Returns portal sectors for sectors whose portals changed, one for each wall.
+pub fn link_portals(sectors: &[&Sector]) -> Vec<(SectorId, Vec<Option<SectorId>>)> {
+    let all_walls: Vec<(SectorId, Vec<Wall>)> =
+        sectors.iter().map(|s| (s.id, s.to_walls())).collect();
+    let same = |p: Vec2, q: Vec2| p.distance(q) < SPLIT_EPSILON;
+
+    all_walls
+        .iter()
+        .zip(sectors)
+        .filter_map(|((id, walls), sector)| {
+            let portal_sectors: Vec<Option<SectorId>> = walls
+                .iter()
+                .map(|wall| {
+                    all_walls
+                        .iter()
+                        .filter(|(other, _)| other != id)
+                        .find(|(_, others)| {
+                            others.iter().any(|w| {
+                                same(w.left.0, wall.right.0) && same(w.right.0, wall.left.0)
+                            })
+                        })
+                        .map(|(other, _)| *other)
+                })
+                .collect();
+            (portal_sectors != sector.portal_sectors).then_some((*id, portal_sectors))
+        })
+        .collect()
+}
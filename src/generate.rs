@@ -0,0 +1,276 @@
+use crate::*;
+
+use bevy::utils::HashSet;
+
+/// Width and depth of each room of a generated maze.
+pub const ROOM_SIZE: f32 = 8.0;
+/// Most rooms in a generated map, fewer when the walk boxes itself in.
+pub const GENERATED_ROOMS: usize = 8;
+/// Spacing of the grid the rooms of a generated map are placed on, leaving room for
+/// corridors between them.
+const CELL_SIZE: f32 = 14.0;
+/// Smallest and largest width and depth of the rooms of a generated map.
+const ROOM_SIZES: (f32, f32) = (5.0, 10.0);
+const CORRIDOR_WIDTH: f32 = 2.0;
+/// Height of a corridor, above a floor halfway between those of the rooms it joins.
+const CORRIDOR_HEIGHT: f32 = 3.0;
+
+/// Offsets to the room beyond each wall of a room, in wall order.
+const NEIGHBOURS: [(i32, i32); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+/// Xorshift random numbers, good enough for map generation and wandering mobs.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Zero would only ever produce zero
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Number from min up to max.
+    pub(crate) fn range(&mut self, min: f32, max: f32) -> f32 {
+        let t = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * t
+    }
+}
+
+/// Sectors of a map generated from seed, a random walk of rectangular rooms of
+/// varying size joined by corridors, each room with its own floor, ceiling and wall
+/// colors. The first room is centred on the origin and is the initial sector, rooms
+/// are numbered in the order walked and followed by the corridors between them.
+pub fn generate_map(seed: u64) -> Vec<Sector> {
+    let mut rng = Rng::new(seed);
+
+    // Walk the grid, never returning to a room already placed
+    let mut cells = vec![(0, 0)];
+    while cells.len() < GENERATED_ROOMS {
+        let (x, y) = *cells.last().unwrap();
+        let free: Vec<(i32, i32)> = NEIGHBOURS
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|cell| !cells.contains(cell))
+            .collect();
+        if free.is_empty() {
+            break;
+        }
+        cells.push(free[rng.next() as usize % free.len()]);
+    }
+
+    // Corners of each room and the wall of it leading on to the next room
+    let corners: Vec<[Vec2; 4]> = cells
+        .iter()
+        .map(|&(x, y)| {
+            let centre = Vec2::new(x as f32, y as f32) * CELL_SIZE;
+            let (min_size, max_size) = ROOM_SIZES;
+            let size = Vec2::new(rng.range(min_size, max_size), rng.range(min_size, max_size));
+            let (min, max) = (centre - size / 2.0, centre + size / 2.0);
+            [min, Vec2::new(min.x, max.y), max, Vec2::new(max.x, min.y)]
+        })
+        .collect();
+    let sides: Vec<usize> = cells
+        .windows(2)
+        .map(|pair| {
+            let (x, y) = pair[0];
+            NEIGHBOURS
+                .iter()
+                .position(|(dx, dy)| (x + dx, y + dy) == pair[1])
+                .unwrap()
+        })
+        .collect();
+    let rooms = cells.len();
+    let corridor_id = |index: usize| SectorId((rooms + index) as u32);
+
+    let mut sectors: Vec<Sector> = corners
+        .iter()
+        .enumerate()
+        .map(|(index, corners)| {
+            // Corridors to the rooms before and after in the walk
+            let before = index
+                .checked_sub(1)
+                .map(|before| ((sides[before] + 2) % 4, corridor_id(before)));
+            let after = sides.get(index).map(|&side| (side, corridor_id(index)));
+            let corridors: Vec<(usize, SectorId)> = [before, after].into_iter().flatten().collect();
+
+            let hue = rng.range(0.0, 360.0);
+            let (mut vertices, mut portal_sectors, mut colors) =
+                (Vec::new(), Vec::new(), Vec::new());
+            for (side, &start) in corners.iter().enumerate() {
+                let color: RawColor = Hsv::new(hue + side as f32 * 30.0, 0.6, 0.9).into();
+                vertices.push(start);
+                match corridors.iter().find(|&&(s, _)| s == side) {
+                    Some(&(_, corridor)) => {
+                        let (left, right) = opening(corners, side);
+                        vertices.extend([left, right]);
+                        portal_sectors.extend([None, Some(corridor), None]);
+                        colors.extend([color; 3]);
+                    }
+                    None => {
+                        portal_sectors.push(None);
+                        colors.push(color);
+                    }
+                }
+            }
+            let floor = rng.range(-0.5, 0.5);
+            let ceil = floor + rng.range(3.5, 4.5);
+            plain_sector(index, vertices, portal_sectors, colors, floor, ceil)
+        })
+        .collect();
+
+    for (index, &side) in sides.iter().enumerate() {
+        let (a_left, a_right) = opening(&corners[index], side);
+        let (b_left, b_right) = opening(&corners[index + 1], (side + 2) % 4);
+        let color: RawColor = Hsv::new(rng.range(0.0, 360.0), 0.2, 0.7).into();
+        let floor = (sectors[index].floor.0 + sectors[index + 1].floor.0) / 2.0;
+        sectors.push(plain_sector(
+            rooms + index,
+            vec![a_right, a_left, b_right, b_left],
+            vec![
+                Some(SectorId(index as u32)),
+                None,
+                Some(SectorId(index as u32 + 1)),
+                None,
+            ],
+            vec![color; 4],
+            floor,
+            floor + CORRIDOR_HEIGHT,
+        ));
+    }
+    sectors
+}
+
+/// Ends of the opening a corridor leaves a room by, in the middle of the wall
+/// starting at the given corner.
+fn opening(corners: &[Vec2; 4], side: usize) -> (Vec2, Vec2) {
+    let (start, end) = (corners[side], corners[(side + 1) % 4]);
+    let along = (end - start).normalize();
+    let middle = (start + end) / 2.0;
+    (
+        middle - along * CORRIDOR_WIDTH / 2.0,
+        middle + along * CORRIDOR_WIDTH / 2.0,
+    )
+}
+
+/// Sectors of a maze of columns by rows square rooms generated from seed, with one
+/// way through portals between any two rooms. The first room is centred on the
+/// origin and is the initial sector. A single column or row is a corridor, every
+/// room seen through the portals of those before it.
+pub fn generate_maze(seed: u64, columns: i32, rows: i32) -> Vec<Sector> {
+    let mut rng = Rng::new(seed);
+    let index = move |(x, y): (i32, i32)| (y * columns + x) as usize;
+    let inside = move |(x, y): (i32, i32)| (0..columns).contains(&x) && (0..rows).contains(&y);
+
+    // Walk the grid depth first, joining each room to the one it was reached from
+    let mut visited = vec![false; (columns * rows).max(0) as usize];
+    let mut joined = HashSet::new();
+    let mut stack = vec![(0, 0)];
+    if let Some(first) = visited.first_mut() {
+        *first = true;
+    }
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited: Vec<(i32, i32)> = NEIGHBOURS
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&cell| inside(cell) && !visited[index(cell)])
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let next = unvisited[rng.next() as usize % unvisited.len()];
+        visited[index(next)] = true;
+        let (a, b) = (index((x, y)), index(next));
+        joined.insert((a.min(b), a.max(b)));
+        stack.push(next);
+    }
+
+    (0..rows)
+        .flat_map(|y| (0..columns).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let portal_sectors = NEIGHBOURS
+                .iter()
+                .map(|(dx, dy)| {
+                    let neighbour = (x + dx, y + dy);
+                    let (a, b) = (index((x, y)), index(neighbour));
+                    let open = inside(neighbour) && joined.contains(&(a.min(b), a.max(b)));
+                    open.then_some(SectorId(b as u32))
+                })
+                .collect();
+            room(index((x, y)), (x, y), portal_sectors, &mut rng)
+        })
+        .collect()
+}
+
+/// Square room at a cell of the grid, with portals into the sectors given for each
+/// wall and its own floor, ceiling and wall colors.
+fn room(
+    index: usize,
+    (x, y): (i32, i32),
+    portal_sectors: Vec<Option<SectorId>>,
+    rng: &mut Rng,
+) -> Sector {
+    let min = Vec2::new(x as f32 - 0.5, y as f32 - 0.5) * ROOM_SIZE;
+    let max = min + ROOM_SIZE;
+
+    let hue = rng.range(0.0, 360.0);
+    let colors = (0..4)
+        .map(|wall| Hsv::new(hue + wall as f32 * 30.0, 0.6, 0.9).into())
+        .collect();
+    let floor = rng.range(-0.5, 0.5);
+    let ceil = floor + rng.range(3.5, 4.5);
+    plain_sector(
+        index,
+        vec![min, Vec2::new(min.x, max.y), max, Vec2::new(max.x, min.y)],
+        portal_sectors,
+        colors,
+        floor,
+        ceil,
+    )
+}
+
+/// Sector of the given shape with nothing but colored walls.
+fn plain_sector(
+    index: usize,
+    vertices: Vec<Vec2>,
+    portal_sectors: Vec<Option<SectorId>>,
+    colors: Vec<RawColor>,
+    floor: f32,
+    ceil: f32,
+) -> Sector {
+    Sector {
+        id: SectorId(index as u32),
+        tag: 0,
+        vertices: vertices.into_iter().map(Position2).collect(),
+        portal_sectors,
+        colors,
+        floor: Length(floor),
+        ceil: Length(ceil),
+        bridge: None,
+        wall_textures: Vec::new(),
+        mid_textures: Vec::new(),
+        upper_walls: Vec::new(),
+        lower_walls: Vec::new(),
+        texture_alignments: Vec::new(),
+        light: DEFAULT_LIGHT,
+        liquid: None,
+        floor_material: FloorMaterial::default(),
+        fog: None,
+        floor_color: None,
+        ceiling_color: None,
+        vertex_ids: Vec::new(),
+    }
+}
+
+/// Spawn a map generated from seed, outside any map scene.
+pub fn spawn_generated_map(commands: &mut Commands, seed: u64) {
+    commands.spawn(InitialSector(SectorId(0)));
+    for sector in generate_map(seed) {
+        commands.spawn((StableId(sector.id.0), sector));
+    }
+}
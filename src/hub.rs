@@ -0,0 +1,162 @@
+use crate::{load::*, persist::*, *};
+
+use bevy::scene::SceneInstance;
+
+/// Leads to another map once the view enters sector, or when wall is given, once it
+/// leaves sector through that portal wall.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct MapExit {
+    pub sector: SectorId,
+    /// Index of a portal wall of sector to exit through, rather than entering sector.
+    pub wall: Option<usize>,
+    /// Scene asset path of the map.
+    pub map: String,
+    /// Whether the map starts a new hub, forgetting state of maps in the current one.
+    pub new_hub: bool,
+}
+
+/// Entity the current map is spawned as children of.
+#[derive(Component, Debug, Default)]
+pub struct MapRoot;
+
+/// Sent once a map has been loaded in place of the last one.
+#[derive(Debug, Clone)]
+pub struct MapEntered {
+    pub map: String,
+    /// Whether the map was visited before in this hub, its state restored.
+    pub revisit: bool,
+}
+
+/// Tracks maps visited in the current hub and moves between maps. Maps within a hub
+/// keep their state between visits, Hexen-style.
+#[derive(Resource, Debug, Default)]
+pub struct WorldState {
+    pending: Option<(String, bool)>,
+    /// Maps of the hub of a saved game being resumed by the pending travel.
+    resuming: Option<Vec<String>>,
+    /// Whether the pending travel enters the current map again as changed on disk.
+    reloading: bool,
+    /// Map last travelled to.
+    current: Option<String>,
+    /// Maps visited in the current hub, in order of first visit.
+    hub_maps: Vec<String>,
+}
+
+impl WorldState {
+    /// Travel to map at the end of this frame.
+    pub fn travel(&mut self, map: &str, new_hub: bool) {
+        self.pending = Some((map.to_string(), new_hub));
+        self.reloading = false;
+    }
+
+    /// Enter the current map again at the end of this frame, as changed on disk,
+    /// forgetting its state rather than keeping it.
+    pub fn reload(&mut self) {
+        if let Some(map) = self.current.clone() {
+            self.pending = Some((map, false));
+            self.reloading = true;
+        }
+    }
+
+    /// Travel to map at the end of this frame to resume a saved game, its state
+    /// already loaded by `Persistence::load_game`, with hub maps visited in order.
+    pub fn resume(&mut self, map: &str, hub_maps: Vec<String>) {
+        self.pending = Some((map.to_string(), false));
+        self.resuming = Some(hub_maps);
+        self.reloading = false;
+    }
+
+    pub fn hub_maps(&self) -> &[String] {
+        &self.hub_maps
+    }
+
+    pub fn current_map(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+}
+
+impl MapExit {
+    /// Whether moving between sectors passes through the exit. An exit through a wall
+    /// is taken entering the sector its portal leads to from sector.
+    pub fn is_taken(&self, sectors: &[&Sector], entered: &SectorEntered) -> bool {
+        let Some(wall) = self.wall else { return entered.to == self.sector };
+        let portal_sector = sectors
+            .iter()
+            .find(|s| s.id == self.sector)
+            .and_then(|s| s.portal_sectors.get(wall).copied().flatten());
+        entered.from == Some(self.sector) && portal_sector == Some(entered.to)
+    }
+}
+
+/// Travel through any exit passed through on changing sector.
+pub fn map_exit_system(
+    mut sector_entered_events: EventReader<SectorEntered>,
+    mut world_state: ResMut<WorldState>,
+    exit_query: Query<&MapExit>,
+    sector_query: Query<&Sector>,
+) {
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    for sector_entered in sector_entered_events.iter() {
+        let exit = exit_query
+            .iter()
+            .find(|e| e.is_taken(&sectors, sector_entered));
+        if let Some(exit) = exit {
+            info!("leaving through exit to `{}`", exit.map);
+            world_state.travel(&exit.map, exit.new_hub);
+        }
+    }
+}
+
+/// Swap the current map for the one travelled to, keeping state of the map left.
+pub fn map_transition_system(world: &mut World) {
+    let Some((map, new_hub)) = world.resource_mut::<WorldState>().pending.take() else { return };
+    let resuming = world.resource_mut::<WorldState>().resuming.take();
+    let reloading = std::mem::take(&mut world.resource_mut::<WorldState>().reloading);
+
+    // State of a saved game replaces that of the map being left, and a map reloaded
+    // starts again from how it's saved, rather than how it was left
+    if resuming.is_none() && !reloading {
+        capture_map(world);
+    }
+
+    let revisit = world.resource_scope(|world, mut world_state: Mut<WorldState>| {
+        let mut persistence = world.resource_mut::<Persistence>();
+        if let Some(hub_maps) = resuming {
+            world_state.hub_maps = hub_maps;
+        } else if new_hub {
+            world_state.hub_maps.clear();
+            persistence.forget_maps();
+        } else if reloading {
+            persistence.forget_map(&map);
+        }
+        persistence.enter_map(&map);
+
+        let revisit = world_state.hub_maps.contains(&map) && !reloading;
+        if !revisit {
+            world_state.hub_maps.push(map.clone());
+        }
+        world_state.current = Some(map.clone());
+        revisit
+    });
+
+    // Replacing the handle despawns the old scene instance
+    let handle = world
+        .resource::<AssetServer>()
+        .load::<DynamicScene, _>(scene_asset_path(&map));
+    let mut root_query = world.query_filtered::<(Entity, Option<&SceneInstance>), With<MapRoot>>();
+    let previous_instance = if let Ok((root, instance)) = root_query.get_single(world) {
+        let previous_instance = instance.map(|instance| **instance);
+        world.entity_mut(root).insert(handle.clone());
+        previous_instance
+    } else {
+        world.spawn((MapRoot, handle.clone()));
+        None
+    };
+    world
+        .resource_mut::<MapLoad>()
+        .begin(&map, handle, previous_instance);
+
+    info!("entering map `{map}`");
+    world.send_event(MapEntered { map, revisit });
+}
@@ -0,0 +1,3 @@
+//! Maps converted from other formats.
+
+pub mod doom;
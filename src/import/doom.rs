@@ -0,0 +1,1133 @@
+//! Doom maps, read from the lumps of a WAD or from UDMF text maps, with each Doom
+//! sector cut into the convex sectors the renderer needs.
+
+use crate::{edit::split_walls_at_portals, *};
+use bevy::{math::DVec2, utils::HashMap};
+
+/// World units per Doom map unit, so a 128 unit tall Doom room is 4 units tall.
+pub const DOOM_SCALE: f64 = 1.0 / 32.0;
+/// Doom thing type of the player 1 start.
+pub const PLAYER_1_START: u16 = 1;
+/// Sidedef index meaning no side in binary linedefs.
+const NO_SIDE: u16 = 0xffff;
+/// Lumps that may follow a map marker in a WAD, in any order.
+const MAP_LUMPS: [&str; 12] = [
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT",
+    "BLOCKMAP", "BEHAVIOR", "SCRIPTS",
+];
+
+/// Map as stored by Doom, in Doom map units.
+#[derive(Debug, Clone, Default)]
+pub struct DoomMap {
+    pub vertices: Vec<DVec2>,
+    pub lines: Vec<LineDef>,
+    pub sides: Vec<SideDef>,
+    pub sectors: Vec<DoomSector>,
+    pub things: Vec<DoomThing>,
+}
+
+/// Line between two vertices, with the sector its front side faces on the right going
+/// from `v1` to `v2`.
+#[derive(Debug, Copy, Clone)]
+pub struct LineDef {
+    pub v1: usize,
+    pub v2: usize,
+    pub front: Option<usize>,
+    pub back: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SideDef {
+    pub sector: usize,
+    pub upper: String,
+    pub lower: String,
+    pub middle: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DoomSector {
+    pub floor: f64,
+    pub ceil: f64,
+    pub light: u8,
+    pub tag: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DoomThing {
+    pub position: DVec2,
+    /// Degrees anticlockwise from +x.
+    pub angle: f64,
+    pub kind: u16,
+}
+
+/// Sectors and start of an imported map.
+#[derive(Debug, Default)]
+pub struct ImportedMap {
+    pub sectors: Vec<Sector>,
+    pub start: Option<PlayerStart>,
+    pub initial_sector: SectorId,
+    /// Parts of the map that couldn't be imported and were left out.
+    pub warnings: Vec<String>,
+}
+
+struct Lump<'a> {
+    name: String,
+    data: &'a [u8],
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_i16(data: &[u8], offset: usize) -> i16 {
+    read_u16(data, offset) as i16
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Eight byte name, padded with zeros.
+fn read_name(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_uppercase()
+}
+
+fn lumps(wad: &[u8]) -> Result<Vec<Lump<'_>>, String> {
+    if wad.len() < 12 || !matches!(&wad[..4], b"IWAD" | b"PWAD") {
+        return Err("not a WAD file".to_string());
+    }
+    let count = read_u32(wad, 4) as usize;
+    let directory = read_u32(wad, 8) as usize;
+    let directory_end = count.checked_mul(16).and_then(|n| n.checked_add(directory));
+    if !matches!(directory_end, Some(end) if end <= wad.len()) {
+        return Err("WAD directory runs past the end of the file".to_string());
+    }
+
+    (0..count)
+        .map(|i| {
+            let entry = &wad[directory + i * 16..][..16];
+            let (offset, size) = (read_u32(entry, 0) as usize, read_u32(entry, 4) as usize);
+            let name = read_name(&entry[8..16]);
+            let data = offset
+                .checked_add(size)
+                .and_then(|end| wad.get(offset..end))
+                .ok_or_else(|| format!("lump `{name}` runs past the end of the file"))?;
+            Ok(Lump { name, data })
+        })
+        .collect()
+}
+
+/// Names of the maps in a WAD, in order.
+pub fn wad_maps(wad: &[u8]) -> Result<Vec<String>, String> {
+    let lumps = lumps(wad)?;
+    Ok(lumps
+        .windows(2)
+        .filter(|pair| matches!(pair[1].name.as_str(), "THINGS" | "TEXTMAP"))
+        .map(|pair| pair[0].name.clone())
+        .collect())
+}
+
+/// Map named map from a WAD, in binary Doom or Hexen format, or UDMF.
+pub fn parse_wad(wad: &[u8], map: &str) -> Result<DoomMap, String> {
+    let lumps = lumps(wad)?;
+    let marker = lumps
+        .iter()
+        .position(|lump| lump.name.eq_ignore_ascii_case(map))
+        .ok_or_else(|| format!("no map `{map}` in WAD"))?;
+    let map_lumps = &lumps[marker + 1..];
+
+    if let Some(textmap) = map_lumps.first().filter(|lump| lump.name == "TEXTMAP") {
+        let text = std::str::from_utf8(textmap.data)
+            .map_err(|error| format!("TEXTMAP isn't UTF-8: {error}"))?;
+        return parse_udmf(text);
+    }
+
+    let map_lumps: Vec<&Lump> = map_lumps
+        .iter()
+        .take_while(|lump| MAP_LUMPS.contains(&lump.name.as_str()) || lump.name.starts_with("GL_"))
+        .collect();
+    let lump = |name: &str| {
+        map_lumps
+            .iter()
+            .find(|lump| lump.name == name)
+            .map(|lump| lump.data)
+            .ok_or_else(|| format!("map `{map}` has no {name} lump"))
+    };
+    let hexen = map_lumps.iter().any(|lump| lump.name == "BEHAVIOR");
+
+    let vertices = lump("VERTEXES")?
+        .chunks_exact(4)
+        .map(|v| DVec2::new(read_i16(v, 0) as f64, read_i16(v, 2) as f64))
+        .collect();
+
+    let side = |index: u16| (index != NO_SIDE).then_some(index as usize);
+    let lines = if hexen {
+        lump("LINEDEFS")?
+            .chunks_exact(16)
+            .map(|l| LineDef {
+                v1: read_u16(l, 0) as usize,
+                v2: read_u16(l, 2) as usize,
+                front: side(read_u16(l, 12)),
+                back: side(read_u16(l, 14)),
+            })
+            .collect()
+    } else {
+        lump("LINEDEFS")?
+            .chunks_exact(14)
+            .map(|l| LineDef {
+                v1: read_u16(l, 0) as usize,
+                v2: read_u16(l, 2) as usize,
+                front: side(read_u16(l, 10)),
+                back: side(read_u16(l, 12)),
+            })
+            .collect()
+    };
+
+    let sides = lump("SIDEDEFS")?
+        .chunks_exact(30)
+        .map(|s| SideDef {
+            upper: read_name(&s[4..12]),
+            lower: read_name(&s[12..20]),
+            middle: read_name(&s[20..28]),
+            sector: read_u16(s, 28) as usize,
+        })
+        .collect();
+
+    let sectors = lump("SECTORS")?
+        .chunks_exact(26)
+        .map(|s| DoomSector {
+            floor: read_i16(s, 0) as f64,
+            ceil: read_i16(s, 2) as f64,
+            light: read_i16(s, 20).clamp(0, 255) as u8,
+            tag: read_u16(s, 24) as u32,
+        })
+        .collect();
+
+    let things = if hexen {
+        lump("THINGS")?
+            .chunks_exact(20)
+            .map(|t| DoomThing {
+                position: DVec2::new(read_i16(t, 2) as f64, read_i16(t, 4) as f64),
+                angle: read_i16(t, 8) as f64,
+                kind: read_u16(t, 10),
+            })
+            .collect()
+    } else {
+        lump("THINGS")?
+            .chunks_exact(10)
+            .map(|t| DoomThing {
+                position: DVec2::new(read_i16(t, 0) as f64, read_i16(t, 2) as f64),
+                angle: read_i16(t, 4) as f64,
+                kind: read_u16(t, 6),
+            })
+            .collect()
+    };
+
+    let map = DoomMap {
+        vertices,
+        lines,
+        sides,
+        sectors,
+        things,
+    };
+    map.check()?;
+    Ok(map)
+}
+
+/// Fields of a UDMF block, values kept as written apart from string quotes.
+type UdmfBlock = HashMap<String, String>;
+
+/// Blocks of a UDMF text map in order, with the kind of each.
+fn udmf_blocks(text: &str) -> Result<Vec<(String, UdmfBlock)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {}
+            '/' if text[start..].starts_with("//") => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '/' if text[start..].starts_with("/*") => {
+                let end = text[start + 2..].find("*/").ok_or("comment never ends")?;
+                while chars.next_if(|&(i, _)| i < start + end + 4).is_some() {}
+            }
+            '{' | '}' | '=' | ';' => tokens.push(c.to_string()),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => string.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, c)) => string.push(c),
+                        None => return Err("string never ends".to_string()),
+                    }
+                }
+                tokens.push(string);
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|&(_, c)| {
+                    !c.is_whitespace() && !matches!(c, '{' | '}' | '=' | ';' | '"' | '/')
+                }) {
+                    end = i + c.len_utf8();
+                }
+                tokens.push(text[start..end].to_string());
+            }
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(name) = tokens.next() {
+        match tokens.next().as_deref() {
+            // Global field, like the namespace
+            Some("=") => {
+                tokens.next();
+                if tokens.next().as_deref() != Some(";") {
+                    return Err(format!("expected `;` after `{name}`"));
+                }
+            }
+            Some("{") => {
+                let mut block = UdmfBlock::new();
+                loop {
+                    let key = match tokens.next() {
+                        Some(token) if token == "}" => break,
+                        Some(token) => token.to_lowercase(),
+                        None => return Err(format!("`{name}` block never ends")),
+                    };
+                    let (equals, value, semicolon) = (tokens.next(), tokens.next(), tokens.next());
+                    if equals.as_deref() != Some("=") || semicolon.as_deref() != Some(";") {
+                        return Err(format!("expected `{key} = value;` in `{name}` block"));
+                    }
+                    block.insert(key, value.unwrap_or_default());
+                }
+                blocks.push((name.to_lowercase(), block));
+            }
+            _ => return Err(format!("expected `=` or `{{` after `{name}`")),
+        }
+    }
+    Ok(blocks)
+}
+
+/// Map from a UDMF text map.
+pub fn parse_udmf(text: &str) -> Result<DoomMap, String> {
+    let mut map = DoomMap::default();
+    for (index, (kind, block)) in udmf_blocks(text)?.iter().enumerate() {
+        let number = |key: &str, default: Option<f64>| -> Result<f64, String> {
+            match block.get(key) {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("{kind} (block {index}) `{key}` isn't a number")),
+                None => default.ok_or_else(|| format!("{kind} (block {index}) has no `{key}`")),
+            }
+        };
+        let texture = |key: &str| block.get(key).cloned().unwrap_or_else(|| "-".to_string());
+        let side = |key: &str, default| -> Result<Option<usize>, String> {
+            let side = number(key, default)?;
+            Ok((side >= 0.0).then_some(side as usize))
+        };
+
+        match kind.as_str() {
+            "vertex" => map
+                .vertices
+                .push(DVec2::new(number("x", None)?, number("y", None)?)),
+            "linedef" => map.lines.push(LineDef {
+                v1: number("v1", None)? as usize,
+                v2: number("v2", None)? as usize,
+                front: side("sidefront", None)?,
+                back: side("sideback", Some(-1.0))?,
+            }),
+            "sidedef" => map.sides.push(SideDef {
+                sector: number("sector", None)? as usize,
+                upper: texture("texturetop"),
+                lower: texture("texturebottom"),
+                middle: texture("texturemiddle"),
+            }),
+            "sector" => map.sectors.push(DoomSector {
+                floor: number("heightfloor", Some(0.0))?,
+                ceil: number("heightceiling", Some(0.0))?,
+                light: number("lightlevel", Some(160.0))?.clamp(0.0, 255.0) as u8,
+                tag: number("id", Some(0.0))?.max(0.0) as u32,
+            }),
+            "thing" => map.things.push(DoomThing {
+                position: DVec2::new(number("x", None)?, number("y", None)?),
+                angle: number("angle", Some(0.0))?,
+                kind: number("type", None)? as u16,
+            }),
+            _ => {}
+        }
+    }
+    map.check()?;
+    Ok(map)
+}
+
+impl DoomMap {
+    /// Check every index refers to something that exists.
+    fn check(&self) -> Result<(), String> {
+        for (i, line) in self.lines.iter().enumerate() {
+            if line.v1.max(line.v2) >= self.vertices.len() {
+                return Err(format!("linedef {i} refers to a missing vertex"));
+            }
+            if line
+                .front
+                .into_iter()
+                .chain(line.back)
+                .any(|s| s >= self.sides.len())
+            {
+                return Err(format!("linedef {i} refers to a missing sidedef"));
+            }
+        }
+        for (i, side) in self.sides.iter().enumerate() {
+            if side.sector >= self.sectors.len() {
+                return Err(format!(
+                    "sidedef {i} refers to missing sector {}",
+                    side.sector
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What an edge of a sector outline or piece of one is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum EdgeKind {
+    /// Side of a linedef, facing another Doom sector when two-sided.
+    Side { side: usize, two_sided: bool },
+    /// Cut through the inside of a Doom sector, joining two of its pieces.
+    Cut,
+}
+
+/// Corner of a polygon, with the edge from it to the next.
+#[derive(Debug, Copy, Clone)]
+struct Corner {
+    position: DVec2,
+    edge: EdgeKind,
+}
+
+fn area(polygon: &[Corner]) -> f64 {
+    (0..polygon.len())
+        .map(|i| {
+            let next = polygon[(i + 1) % polygon.len()].position;
+            polygon[i].position.perp_dot(next)
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn contains(polygon: &[Corner], point: DVec2) -> bool {
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let (a, b) = (
+            polygon[i].position,
+            polygon[(i + 1) % polygon.len()].position,
+        );
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether segments ab and cd cross at a point inside both.
+fn segments_cross(a: DVec2, b: DVec2, c: DVec2, d: DVec2) -> bool {
+    let side = |p: DVec2, q: DVec2, r: DVec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(a, b, c), side(a, b, d));
+    let (d3, d4) = (side(c, d, a), side(c, d, b));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Whether point lies on segment ab, between its ends.
+fn on_segment(a: DVec2, b: DVec2, point: DVec2) -> bool {
+    let along = (point - a).dot(b - a);
+    (b - a).perp_dot(point - a).abs() <= 1e-9 && along > 0.0 && along < a.distance_squared(b)
+}
+
+/// Whether direction from corner at index points into a clockwise polygon.
+fn points_inside(polygon: &[Corner], index: usize, direction: DVec2) -> bool {
+    let n = polygon.len();
+    let previous = polygon[(index + n - 1) % n].position;
+    let (corner, next) = (polygon[index].position, polygon[(index + 1) % n].position);
+    let (incoming, outgoing) = (corner - previous, next - corner);
+    let right_of_incoming = incoming.perp_dot(direction) < 0.0;
+    let right_of_outgoing = outgoing.perp_dot(direction) < 0.0;
+    if incoming.perp_dot(outgoing) <= 0.0 {
+        right_of_incoming && right_of_outgoing
+    } else {
+        right_of_incoming || right_of_outgoing
+    }
+}
+
+/// Closed outlines of a Doom sector, outer ones clockwise and holes anticlockwise,
+/// followed from the sides facing it. Sides that don't join up are left out.
+fn sector_outlines(map: &DoomMap, vertices: &[DVec2], sector: usize) -> (Vec<Vec<Corner>>, usize) {
+    // Sides facing the sector, going clockwise around it
+    let mut edges: Vec<(usize, usize, EdgeKind)> = Vec::new();
+    for line in &map.lines {
+        let sector_of = |side: Option<usize>| side.map(|s| map.sides[s].sector);
+        let (front, back) = (sector_of(line.front), sector_of(line.back));
+        // Lines within a sector don't bound it
+        if front == back || vertices[line.v1] == vertices[line.v2] {
+            continue;
+        }
+        let two_sided = front.is_some() && back.is_some();
+        if front == Some(sector) {
+            let side = line.front.unwrap();
+            edges.push((line.v1, line.v2, EdgeKind::Side { side, two_sided }));
+        }
+        if back == Some(sector) {
+            let side = line.back.unwrap();
+            edges.push((line.v2, line.v1, EdgeKind::Side { side, two_sided }));
+        }
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut outlines = Vec::new();
+    let mut dropped = 0;
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut outline = vec![first];
+        let mut current = first;
+        let closed = loop {
+            let (from, to, _) = edges[current];
+            if to == edges[first].0 {
+                break true;
+            }
+            // Turn furthest right where outlines meet, to keep the sector on the right
+            let incoming = vertices[to] - vertices[from];
+            let next = (0..edges.len())
+                .filter(|&e| !used[e] && edges[e].0 == to)
+                .min_by(|&a, &b| {
+                    let turn = |e: usize| {
+                        let outgoing = vertices[edges[e].1] - vertices[to];
+                        let angle = incoming.perp_dot(outgoing).atan2(incoming.dot(outgoing));
+                        if angle <= -std::f64::consts::PI + 1e-9 {
+                            std::f64::consts::PI
+                        } else {
+                            angle
+                        }
+                    };
+                    turn(a).total_cmp(&turn(b))
+                });
+            let Some(next) = next else { break false };
+            used[next] = true;
+            outline.push(next);
+            current = next;
+        };
+
+        let outline: Vec<Corner> = outline
+            .into_iter()
+            .map(|e| Corner {
+                position: vertices[edges[e].0],
+                edge: edges[e].2,
+            })
+            .collect();
+        if closed && area(&outline).abs() > f64::EPSILON {
+            outlines.push(outline);
+        } else {
+            dropped += 1;
+        }
+    }
+    (outlines, dropped)
+}
+
+/// Join holes into the clockwise outline around them, each along a cut there and back
+/// from its rightmost corner to a corner of the outline it can see.
+fn bridge_holes(mut outline: Vec<Corner>, mut holes: Vec<Vec<Corner>>) -> Vec<Corner> {
+    let rightmost = |hole: &[Corner]| {
+        (0..hole.len())
+            .max_by(|&a, &b| hole[a].position.x.total_cmp(&hole[b].position.x))
+            .unwrap()
+    };
+    holes.sort_by(|a, b| {
+        let (a, b) = (a[rightmost(a)].position.x, b[rightmost(b)].position.x);
+        b.total_cmp(&a)
+    });
+
+    for (index, hole) in holes.iter().enumerate() {
+        let h = rightmost(hole);
+        let from = hole[h].position;
+        let blocked = |to: DVec2| {
+            let edges = |polygon: &[Corner]| {
+                (0..polygon.len())
+                    .map(|i| {
+                        (
+                            polygon[i].position,
+                            polygon[(i + 1) % polygon.len()].position,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let others = holes[index..].iter().flat_map(|hole| edges(hole));
+            // A cut through a corner would touch the edges there without crossing them
+            edges(&outline)
+                .into_iter()
+                .chain(others)
+                .any(|(a, b)| segments_cross(from, to, a, b) || on_segment(from, to, a))
+        };
+
+        let mut candidates: Vec<usize> = (0..outline.len())
+            .filter(|&i| outline[i].position != from)
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            let distance = |i: usize| outline[i].position.distance_squared(from);
+            distance(a).total_cmp(&distance(b))
+        });
+        let Some(&o) = candidates.iter().find(|&&o| {
+            let to = outline[o].position;
+            points_inside(&outline, o, from - to) && !blocked(to)
+        }) else { continue };
+
+        let cut = |position| Corner {
+            position,
+            edge: EdgeKind::Cut,
+        };
+        let mut joined = outline[..o].to_vec();
+        joined.push(cut(outline[o].position));
+        joined.extend(hole[h..].iter().chain(&hole[..h]).copied());
+        joined.push(cut(from));
+        joined.extend(outline[o..].iter().copied());
+        outline = joined;
+    }
+    outline
+}
+
+/// Triangles of a clockwise polygon, by clipping ears.
+fn triangulate(polygon: Vec<Corner>) -> Vec<Vec<Corner>> {
+    let mut remaining = polygon;
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (
+                remaining[(i + n - 1) % n].position,
+                remaining[i].position,
+                remaining[(i + 1) % n].position,
+            );
+            if (b - a).perp_dot(c - b) >= 0.0 {
+                return false;
+            }
+            // No other corner inside, corners repeated by cuts aside
+            remaining.iter().all(|corner| {
+                let p = corner.position;
+                p == a
+                    || p == b
+                    || p == c
+                    || (b - a).perp_dot(p - a) > 0.0
+                    || (c - b).perp_dot(p - b) > 0.0
+                    || (a - c).perp_dot(p - c) > 0.0
+            })
+        });
+        // Clip any corner when none is an ear, degenerate outlines aside
+        let i = ear.unwrap_or(0);
+        let previous = (i + n - 1) % n;
+        let triangle = vec![
+            remaining[previous],
+            remaining[i],
+            Corner {
+                position: remaining[(i + 1) % n].position,
+                edge: EdgeKind::Cut,
+            },
+        ];
+        if area(&triangle) < -f64::EPSILON {
+            triangles.push(triangle);
+        }
+        remaining[previous].edge = EdgeKind::Cut;
+        remaining.remove(i);
+    }
+    if area(&remaining) < -f64::EPSILON {
+        triangles.push(remaining);
+    }
+    triangles
+}
+
+fn is_convex(polygon: &[Corner]) -> bool {
+    let n = polygon.len();
+    (0..n).all(|i| {
+        let (a, b, c) = (
+            polygon[i].position,
+            polygon[(i + 1) % n].position,
+            polygon[(i + 2) % n].position,
+        );
+        (b - a).perp_dot(c - b) <= 1e-9
+    })
+}
+
+/// Merge pieces across cuts while they stay convex, each into its neighbours in turn.
+fn merge_convex(mut pieces: Vec<Vec<Corner>>) -> Vec<Vec<Corner>> {
+    let mut i = 0;
+    while i < pieces.len() {
+        match merge_neighbour(&pieces, i) {
+            Some((j, joined)) => {
+                pieces[i] = joined;
+                pieces.remove(j);
+                if j < i {
+                    i -= 1;
+                }
+            }
+            None => i += 1,
+        }
+    }
+    pieces
+}
+
+/// Piece i merged with a neighbour across a cut, where the two make a convex piece,
+/// with the index of the neighbour.
+fn merge_neighbour(pieces: &[Vec<Corner>], i: usize) -> Option<(usize, Vec<Corner>)> {
+    let (piece, n) = (&pieces[i], pieces[i].len());
+    for k in (0..n).filter(|&k| piece[k].edge == EdgeKind::Cut) {
+        let (a, b) = (piece[k].position, piece[(k + 1) % n].position);
+        for (j, other) in pieces.iter().enumerate().filter(|&(j, _)| j != i) {
+            let len = other.len();
+            let Some(m) =
+                (0..len).find(|&m| other[m].position == b && other[(m + 1) % len].position == a)
+            else { continue };
+
+            // Round this piece from b to before a, then the other from a to before b
+            let mut joined: Vec<Corner> = (1..n).map(|s| piece[(k + s) % n]).collect();
+            joined.extend((1..len).map(|s| other[(m + s) % len]));
+            if is_convex(&joined) {
+                return Some((j, joined));
+            }
+        }
+    }
+    None
+}
+
+/// Wall color standing in for a Doom texture, a hue picked by its name.
+fn texture_color(name: &str) -> RawColor {
+    if name.is_empty() || name == "-" {
+        return Hsv::new(0.0, 0.0, 1.0).into();
+    }
+    let hash = name.bytes().fold(2166136261u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(16777619)
+    });
+    Hsv::new((hash % 360) as f32, 0.35, 1.0).into()
+}
+
+/// Upper or lower wall of a two-sided line's side, colored for its texture.
+fn side_surface(
+    map: &DoomMap,
+    edge: EdgeKind,
+    texture: impl Fn(&SideDef) -> &String,
+) -> Option<WallSurface> {
+    let EdgeKind::Side {
+        side,
+        two_sided: true,
+    } = edge
+    else { return None };
+    let name = texture(&map.sides[side]);
+    (!name.is_empty() && name != "-").then(|| WallSurface {
+        color: Some(texture_color(name)),
+        texture: None,
+    })
+}
+
+/// Sector light level for a Doom light level, never quite black.
+fn sector_light(light: u8) -> f32 {
+    0.25 + 0.75 * light as f32 / 255.0
+}
+
+/// Sectors of a Doom map, each Doom sector cut into convex pieces joined by portals,
+/// with the player 1 start.
+pub fn convert_doom_map(map: &DoomMap) -> ImportedMap {
+    let mut imported = ImportedMap::default();
+    let scale = |p: DVec2| Position2((p * DOOM_SCALE).as_vec2());
+
+    // Weld vertices at the same position, so outlines join up through them
+    let mut welded: Vec<DVec2> = Vec::with_capacity(map.vertices.len());
+    let mut seen: HashMap<(u64, u64), usize> = HashMap::default();
+    let vertex_index: Vec<usize> = map
+        .vertices
+        .iter()
+        .map(|v| {
+            *seen
+                .entry((v.x.to_bits(), v.y.to_bits()))
+                .or_insert_with(|| {
+                    welded.push(*v);
+                    welded.len() - 1
+                })
+        })
+        .collect();
+    let map = DoomMap {
+        lines: map
+            .lines
+            .iter()
+            .map(|line| LineDef {
+                v1: vertex_index[line.v1],
+                v2: vertex_index[line.v2],
+                ..*line
+            })
+            .collect(),
+        vertices: welded,
+        ..map.clone()
+    };
+
+    let mut sectors = Vec::new();
+    for (index, doom_sector) in map.sectors.iter().enumerate() {
+        let (outlines, dropped) = sector_outlines(&map, &map.vertices, index);
+        if dropped > 0 {
+            imported
+                .warnings
+                .push(format!("sector {index}: {dropped} outlines don't close"));
+        }
+        let (outers, holes): (Vec<_>, Vec<_>) = outlines.into_iter().partition(|o| area(o) < 0.0);
+
+        // Each hole belongs to the smallest outline around it
+        let mut holes_of: Vec<Vec<Vec<Corner>>> = vec![Vec::new(); outers.len()];
+        for hole in holes {
+            let around = (0..outers.len())
+                .filter(|&o| contains(&outers[o], hole[0].position))
+                .min_by(|&a, &b| area(&outers[b]).total_cmp(&area(&outers[a])));
+            match around {
+                Some(o) => holes_of[o].push(hole),
+                None => imported
+                    .warnings
+                    .push(format!("sector {index}: hole outside any outline")),
+            }
+        }
+
+        for (outer, holes) in outers.into_iter().zip(holes_of) {
+            let pieces = merge_convex(triangulate(bridge_holes(outer, holes)));
+            for piece in pieces {
+                let id = SectorId(sectors.len() as u32);
+                sectors.push(Sector {
+                    id,
+                    tag: doom_sector.tag,
+                    vertices: piece.iter().map(|c| scale(c.position)).collect(),
+                    // Resolved to the sector sharing each wall below, this one meaning
+                    // any sector
+                    portal_sectors: piece
+                        .iter()
+                        .map(|c| match c.edge {
+                            EdgeKind::Side {
+                                two_sided: false, ..
+                            } => None,
+                            _ => Some(id),
+                        })
+                        .collect(),
+                    colors: piece
+                        .iter()
+                        .map(|c| match c.edge {
+                            EdgeKind::Side { side, .. } => {
+                                let side = &map.sides[side];
+                                let name = [&side.middle, &side.upper, &side.lower]
+                                    .into_iter()
+                                    .find(|name| !name.is_empty() && *name != "-")
+                                    .map_or("-", |name| name.as_str());
+                                texture_color(name)
+                            }
+                            EdgeKind::Cut => texture_color("-"),
+                        })
+                        .collect(),
+                    floor: Length((doom_sector.floor * DOOM_SCALE) as f32),
+                    ceil: Length((doom_sector.ceil * DOOM_SCALE) as f32),
+                    bridge: None,
+                    wall_textures: Vec::new(),
+                    mid_textures: Vec::new(),
+                    upper_walls: piece
+                        .iter()
+                        .map(|c| side_surface(&map, c.edge, |side| &side.upper))
+                        .collect(),
+                    lower_walls: piece
+                        .iter()
+                        .map(|c| side_surface(&map, c.edge, |side| &side.lower))
+                        .collect(),
+                    texture_alignments: Vec::new(),
+                    light: sector_light(doom_sector.light),
+                    liquid: None,
+                    floor_material: FloorMaterial::default(),
+                    fog: None,
+                    floor_color: None,
+                    ceiling_color: None,
+                    vertex_ids: Vec::new(),
+                });
+            }
+        }
+    }
+
+    let sector_refs: Vec<&Sector> = sectors.iter().collect();
+    let replacements = split_walls_at_portals(&sector_refs);
+    for replacement in replacements {
+        let index = replacement.id.0 as usize;
+        sectors[index] = replacement;
+    }
+
+    if let Some(thing) = map.things.iter().find(|t| t.kind == PLAYER_1_START) {
+        let position = scale(thing.position);
+        match sectors.iter().find(|s| s.contains(position)) {
+            Some(sector) => {
+                imported.initial_sector = sector.id;
+                imported.start = Some(PlayerStart {
+                    name: "start".to_string(),
+                    sector: sector.id,
+                    position,
+                    direction: Direction((thing.angle - 90.0).to_radians() as f32),
+                    ..default()
+                });
+            }
+            None => imported
+                .warnings
+                .push("player 1 start is outside every sector".to_string()),
+        }
+    } else {
+        imported.warnings.push("no player 1 start".to_string());
+    }
+
+    imported.sectors = sectors;
+    imported
+}
+
+/// Spawn an imported map, outside any map scene.
+pub fn spawn_imported_map(commands: &mut Commands, imported: ImportedMap) {
+    commands.spawn(InitialSector(imported.initial_sector));
+    commands.spawn(migrate::MapVersion::current());
+    if let Some(start) = imported.start {
+        commands.spawn(start);
+    }
+    for sector in imported.sectors {
+        commands.spawn((StableId(sector.id.0), sector));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UDMF text map of vertices, linedefs as `(v1, v2, sidefront, sideback)`, the
+    /// sector of each sidedef, sector count and player 1 start.
+    fn udmf(
+        vertices: &[(f64, f64)],
+        lines: &[(usize, usize, usize, Option<usize>)],
+        sides: &[usize],
+        sectors: usize,
+        start: (f64, f64),
+    ) -> String {
+        let mut text = "namespace = \"doom\";\n".to_string();
+        for (x, y) in vertices {
+            text += &format!("vertex {{ x = {x:.1}; y = {y:.1}; }}\n");
+        }
+        for (v1, v2, front, back) in lines {
+            let back = back.map_or(-1, |back| back as i64);
+            text += &format!(
+                "linedef {{ v1 = {v1}; v2 = {v2}; sidefront = {front}; sideback = {back}; }}\n"
+            );
+        }
+        for sector in sides {
+            text += &format!("sidedef {{ sector = {sector}; texturemiddle = \"STARTAN2\"; }}\n");
+        }
+        for _ in 0..sectors {
+            text += "sector { heightfloor = 0; heightceiling = 128; }\n";
+        }
+        text += &format!(
+            "thing {{ x = {:.1}; y = {:.1}; angle = 90; type = 1; }}\n",
+            start.0, start.1
+        );
+        text
+    }
+
+    /// Area of a sector, positive however it winds.
+    fn sector_area(sector: &Sector) -> f32 {
+        let v = &sector.vertices;
+        (0..v.len())
+            .map(|i| v[i].0.perp_dot(v[(i + 1) % v.len()].0))
+            .sum::<f32>()
+            .abs()
+            / 2.0
+    }
+
+    /// Corners of a polygon with its every edge a one-sided line.
+    fn corners(points: &[(f64, f64)]) -> Vec<Corner> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(side, &(x, y))| Corner {
+                position: DVec2::new(x, y),
+                edge: EdgeKind::Side {
+                    side,
+                    two_sided: false,
+                },
+            })
+            .collect()
+    }
+
+    /// WAD header for count lumps with the directory at offset.
+    fn wad_header(count: u32, directory: u32) -> Vec<u8> {
+        let mut wad = b"PWAD".to_vec();
+        wad.extend(count.to_le_bytes());
+        wad.extend(directory.to_le_bytes());
+        wad
+    }
+
+    #[test]
+    fn square_room() {
+        let text = udmf(
+            &[(0.0, 0.0), (0.0, 128.0), (128.0, 128.0), (128.0, 0.0)],
+            &[
+                (0, 1, 0, None),
+                (1, 2, 1, None),
+                (2, 3, 2, None),
+                (3, 0, 3, None),
+            ],
+            &[0; 4],
+            1,
+            (64.0, 32.0),
+        );
+        let imported = convert_doom_map(&parse_udmf(&text).unwrap());
+
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(imported.sectors.len(), 1);
+        let sector = &imported.sectors[0];
+        assert_eq!(sector.vertices.len(), 4);
+        assert!(sector.portal_sectors.iter().all(Option::is_none));
+        assert_eq!(sector.ceil.0, 4.0);
+        assert_eq!(sector_area(sector), 16.0);
+
+        let start = imported.start.expect("player 1 start");
+        assert_eq!(start.sector, sector.id);
+        assert_eq!(start.position.0, Vec2::new(2.0, 1.0));
+        assert_eq!(imported.initial_sector, sector.id);
+    }
+
+    #[test]
+    fn two_sided_line_is_portal() {
+        // Two rooms side by side, the line between them fronting the left one
+        let text = udmf(
+            &[
+                (0.0, 0.0),
+                (0.0, 128.0),
+                (128.0, 128.0),
+                (128.0, 0.0),
+                (256.0, 128.0),
+                (256.0, 0.0),
+            ],
+            &[
+                (0, 1, 0, None),
+                (1, 2, 1, None),
+                (3, 0, 2, None),
+                (2, 3, 3, Some(4)),
+                (2, 4, 5, None),
+                (4, 5, 6, None),
+                (5, 3, 7, None),
+            ],
+            &[0, 0, 0, 0, 1, 1, 1, 1],
+            2,
+            (192.0, 64.0),
+        );
+        let imported = convert_doom_map(&parse_udmf(&text).unwrap());
+
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(imported.sectors.len(), 2);
+        for sector in &imported.sectors {
+            let portals = sector.portal_sectors.iter().flatten();
+            assert_eq!(portals.count(), 1, "one portal in {:?}", sector.id);
+        }
+        let (left, right) = (&imported.sectors[0], &imported.sectors[1]);
+        assert!(left.portal_sectors.contains(&Some(right.id)));
+        assert!(right.portal_sectors.contains(&Some(left.id)));
+        assert_eq!(imported.start.expect("player 1 start").sector, right.id);
+    }
+
+    #[test]
+    fn holes_are_bridged() {
+        // Clockwise room around an anticlockwise pillar
+        let outline = corners(&[(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+        let hole = corners(&[(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0)]);
+        let bridged = bridge_holes(outline, vec![hole]);
+
+        assert_eq!(bridged.len(), 10, "both outlines and a cut there and back");
+        let cuts = bridged.iter().filter(|c| c.edge == EdgeKind::Cut).count();
+        assert_eq!(cuts, 2);
+        assert_eq!(area(&bridged), -15.0);
+
+        let pieces = merge_convex(triangulate(bridged));
+        assert!(pieces.iter().all(|piece| is_convex(piece)));
+        let total: f64 = pieces.iter().map(|piece| -area(piece)).sum();
+        assert_eq!(total, 15.0);
+    }
+
+    #[test]
+    fn sector_with_pillar() {
+        let text = udmf(
+            &[
+                (0.0, 0.0),
+                (0.0, 128.0),
+                (128.0, 128.0),
+                (128.0, 0.0),
+                (32.0, 32.0),
+                (64.0, 32.0),
+                (64.0, 64.0),
+                (32.0, 64.0),
+            ],
+            &[
+                (0, 1, 0, None),
+                (1, 2, 1, None),
+                (2, 3, 2, None),
+                (3, 0, 3, None),
+                (4, 5, 4, None),
+                (5, 6, 5, None),
+                (6, 7, 6, None),
+                (7, 4, 7, None),
+            ],
+            &[0; 8],
+            1,
+            (96.0, 96.0),
+        );
+        let imported = convert_doom_map(&parse_udmf(&text).unwrap());
+
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert!(imported.sectors.len() > 1);
+        let total: f32 = imported.sectors.iter().map(sector_area).sum();
+        assert_eq!(total, 15.0);
+        let pillar = Position2(Vec2::new(1.5, 1.5));
+        assert!(imported.sectors.iter().all(|s| !s.contains(pillar)));
+    }
+
+    #[test]
+    fn malformed_udmf() {
+        assert_eq!(
+            parse_udmf("sidedef { texturetop = \"STARTAN2; }").unwrap_err(),
+            "string never ends"
+        );
+        assert_eq!(
+            parse_udmf("vertex { x = 0; y = 0;").unwrap_err(),
+            "`vertex` block never ends"
+        );
+        let text = udmf(&[(0.0, 0.0)], &[(0, 1, 0, None)], &[0], 1, (0.0, 0.0));
+        assert_eq!(
+            parse_udmf(&text).unwrap_err(),
+            "linedef 0 refers to a missing vertex"
+        );
+    }
+
+    #[test]
+    fn malformed_wad() {
+        assert_eq!(wad_maps(b"PWAD\x01\0").unwrap_err(), "not a WAD file");
+        assert_eq!(
+            wad_maps(&wad_header(u32::MAX, u32::MAX)).unwrap_err(),
+            "WAD directory runs past the end of the file"
+        );
+
+        // One lump named MAP01 reaching far past the end
+        let mut wad = wad_header(1, 12);
+        wad.extend(u32::MAX.to_le_bytes());
+        wad.extend(u32::MAX.to_le_bytes());
+        wad.extend(b"MAP01\0\0\0");
+        assert_eq!(
+            wad_maps(&wad).unwrap_err(),
+            "lump `MAP01` runs past the end of the file"
+        );
+        assert!(wad_maps(&wad_header(0, 12)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{mover::*, *};
+
+use bevy::math::vec2;
+
+/// Furthest in front of the view a wall can be used from.
+pub const USE_DISTANCE: f32 = 1.5;
+
+/// Wall that can be used, such as a switch, sending [`WallUsed`] when it is. Portal
+/// walls with one are used rather than looked through.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct Interactable {
+    pub sector: SectorId,
+    /// Index of the wall within its sector.
+    pub wall: usize,
+}
+
+/// Sent when the view uses an interactable wall, or a portal into a door or lift.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WallUsed {
+    pub sector: SectorId,
+    pub wall_index: usize,
+}
+
+/// Wall looked at from position facing direction in sector current, within
+/// [`USE_DISTANCE`]: the first solid wall reached through portals, or portal stops is
+/// true for.
+pub fn looked_at_wall(
+    sectors: &[&Sector],
+    current: SectorId,
+    position: Position2,
+    direction: Direction,
+    stops: impl Fn(SectorId, usize, &Wall) -> bool,
+) -> Option<(SectorId, usize)> {
+    let from = position.0;
+    let to = from + vec2(-direction.0.sin(), direction.0.cos()) * USE_DISTANCE;
+
+    let (mut sector, mut entered) = (current, -1.0);
+    // Each sector passed through once at most
+    for _ in 0..sectors.len() {
+        let walls = sectors.iter().find(|s| s.id == sector)?.to_walls();
+        let (index, wall, t) = walls
+            .iter()
+            .enumerate()
+            .filter_map(|(index, wall)| Some((index, wall, ray_crossing(from, to, wall)?)))
+            .filter(|&(_, _, t)| t > entered + f32::EPSILON)
+            .min_by(|a, b| a.2.total_cmp(&b.2))?;
+
+        match wall.portal_sector {
+            Some(target) if !stops(sector, index, wall) => (sector, entered) = (target, t),
+            _ => return Some((sector, index)),
+        }
+    }
+    None
+}
+
+/// Fraction of the way from from to to where the segment crosses wall.
+pub(crate) fn ray_crossing(from: Vec2, to: Vec2, wall: &Wall) -> Option<f32> {
+    let (ray, edge) = (to - from, wall.right.0 - wall.left.0);
+    let denominator = ray.perp_dot(edge);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+    let offset = wall.left.0 - from;
+    let t = offset.perp_dot(edge) / denominator;
+    let u = offset.perp_dot(ray) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+/// Wall used from position facing direction in sector current, `None` when the wall
+/// looked at is neither interactable nor a portal into a sector with a mover.
+pub fn used_wall(
+    sectors: &[&Sector],
+    interactables: &[&Interactable],
+    movers: &[&Mover],
+    current: SectorId,
+    position: Position2,
+    direction: Direction,
+) -> Option<WallUsed> {
+    let usable = |sector: SectorId, index: usize, wall: &Wall| {
+        interactables
+            .iter()
+            .any(|i| i.sector == sector && i.wall == index)
+            || wall
+                .portal_sector
+                .is_some_and(|target| movers.iter().any(|m| m.sector == target))
+    };
+
+    let (sector, index) = looked_at_wall(sectors, current, position, direction, usable)?;
+    let wall = sectors
+        .iter()
+        .find(|s| s.id == sector)?
+        .to_walls()
+        .into_iter()
+        .nth(index)?;
+    usable(sector, index, &wall).then_some(WallUsed {
+        sector,
+        wall_index: index,
+    })
+}
+
+/// Toggle the door or lift in the sector a used portal leads into.
+pub fn mover_use_system(
+    mut wall_used_events: EventReader<WallUsed>,
+    mut mover_command_events: EventWriter<MoverCommand>,
+    sector_query: Query<&Sector>,
+    mover_query: Query<&Mover>,
+) {
+    for used in wall_used_events.iter() {
+        let target = sector_query
+            .iter()
+            .find(|s| s.id == used.sector)
+            .and_then(|s| *s.portal_sectors.get(used.wall_index)?);
+        let Some(target) = target else { continue };
+        if mover_query.iter().any(|m| m.sector == target) {
+            mover_command_events.send(MoverCommand {
+                sector: target,
+                action: MoverAction::Toggle,
+            });
+        }
+    }
+}
@@ -1,25 +1,76 @@
 use bevy::prelude::*;
 use palette::{named::*, FromColor, Hsv, IntoColor, Pixel, Srgb};
 
-#[macro_use]
-extern crate lazy_static;
+pub mod animate;
+pub mod audit;
+pub mod blockmap;
+pub mod config;
+pub mod edit;
+pub mod generate;
+pub mod hub;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod interact;
+pub mod light;
+pub mod load;
+pub mod migrate;
+pub mod mob;
+pub mod mover;
+pub mod music;
+pub mod pathfind;
+pub mod persist;
+pub mod physics;
+pub mod prelude;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod script;
+pub mod storage;
+#[cfg(test)]
+mod test_util;
+pub mod time_scale;
+pub mod trigger;
+pub mod validate;
+pub mod vertex_pool;
+pub mod weapons;
 
 pub const DEFAULT_SCENE_RON_FILE_PATH: &str = "scenes/default.scn.ron";
 pub const DEFAULT_SCENE_MP_FILE_PATH: &str = "scenes/default.scn.mp";
+/// Height of the eye above z zero the view enters a map at.
+pub const EYE_HEIGHT: f32 = 2.0;
+/// Light level of sectors not given one, fully lit.
+pub const DEFAULT_LIGHT: f32 = 1.0;
 
-lazy_static! {
-    // Colors
-    pub static ref CEILING_COLOR: RawColor = SILVER.into();
-    pub static ref FLOOR_COLOR: RawColor = GRAY.into();
-    pub static ref WALL_CLIPPED_COLOR: RawColor = WHITE.into();
-    pub static ref FRUSTUM_COLOR: RawColor = DARKGRAY.into();
-    pub static ref PLAYER_COLOR: RawColor = RED.into();
-    pub static ref MISSING_WALL_COLOR: RawColor = RED.into();
-}
+// Colors
+pub const CEILING_COLOR: RawColor = RawColor::from_srgb(SILVER);
+pub const FLOOR_COLOR: RawColor = RawColor::from_srgb(GRAY);
+pub const WALL_CLIPPED_COLOR: RawColor = RawColor::from_srgb(WHITE);
+pub const FRUSTUM_COLOR: RawColor = RawColor::from_srgb(DARKGRAY);
+pub const PLAYER_COLOR: RawColor = RawColor::from_srgb(RED);
+pub const MISSING_WALL_COLOR: RawColor = RawColor::from_srgb(RED);
+pub const HUD_TEXT_COLOR: RawColor = RawColor::from_srgb(WHITE);
+/// Behind HUD text, darkening the world so it reads over bright walls.
+pub const HUD_BACKGROUND_COLOR: RawColorA = RawColorA([0x00, 0x00, 0x00, 0x80]);
 
-#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default)]
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub struct RawColor(pub [u8; 3]);
 
+impl RawColor {
+    pub const fn from_srgb(srgb: Srgb<u8>) -> Self {
+        Self([srgb.red, srgb.green, srgb.blue])
+    }
+
+    /// Color with alpha, from 0 for transparent to 255 for opaque.
+    pub const fn with_alpha(self, alpha: u8) -> RawColorA {
+        let [r, g, b] = self.0;
+        RawColorA([r, g, b, alpha])
+    }
+
+    /// Color with alpha from opacity, from 0 for transparent to 1 for opaque.
+    pub fn with_opacity(self, opacity: f32) -> RawColorA {
+        self.with_alpha((opacity.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
 impl From<Srgb<u8>> for RawColor {
     fn from(srgb: Srgb<u8>) -> Self {
         Self(srgb.into_raw())
@@ -38,25 +89,551 @@ impl From<RawColor> for Srgb<u8> {
     }
 }
 
+/// [`RawColor`] with alpha, blended over what's beneath by it when drawn.
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RawColorA(pub [u8; 4]);
+
+impl RawColorA {
+    pub const fn color(self) -> RawColor {
+        let [r, g, b, _] = self.0;
+        RawColor([r, g, b])
+    }
+
+    pub const fn alpha(self) -> u8 {
+        self.0[3]
+    }
+}
+
+impl From<RawColor> for RawColorA {
+    fn from(color: RawColor) -> Self {
+        color.with_alpha(0xff)
+    }
+}
+
 #[derive(Component, Reflect, Debug, Default, Clone, Copy)]
 #[reflect(Component)]
 pub struct InitialSector(pub SectorId);
 
-#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq)]
+/// Game a player start is for.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    #[default]
+    SinglePlayer,
+    Deathmatch,
+    Coop,
+}
+
+/// Where a player enters a map and which way they face. Maps can have several, told
+/// apart by mode and name, with the view starting at the one chosen by
+/// [`PlayerStartSelection`]. Maps without any start at the origin of
+/// `InitialSector`.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct PlayerStart {
+    pub name: String,
+    pub mode: GameMode,
+    pub sector: SectorId,
+    pub position: Position2,
+    pub direction: Direction,
+}
+
+/// Which player start the view enters maps at.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct PlayerStartSelection {
+    pub mode: GameMode,
+    /// Start of this name, if there is one for mode, otherwise the first for mode.
+    pub name: Option<String>,
+}
+
+impl PlayerStartSelection {
+    /// Start to use out of starts, falling back to any single player start when none
+    /// are for mode.
+    pub fn choose<'a>(
+        &self,
+        starts: impl IntoIterator<Item = &'a PlayerStart>,
+    ) -> Option<&'a PlayerStart> {
+        let starts: Vec<&PlayerStart> = starts.into_iter().collect();
+        let for_mode = |mode: GameMode| starts.iter().copied().filter(move |s| s.mode == mode);
+
+        self.name
+            .as_ref()
+            .and_then(|name| for_mode(self.mode).find(|s| &s.name == name))
+            .or_else(|| for_mode(self.mode).next())
+            .or_else(|| for_mode(GameMode::SinglePlayer).next())
+    }
+}
+
+/// Named viewpoint stored in a map, for screenshots, golden tests and bookmarks.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct CameraPose {
+    pub name: String,
+    pub sector: SectorId,
+    pub position: Position2,
+    /// Height of the eye above z zero, not the floor.
+    pub height: Length,
+    pub direction: Direction,
+}
+
+/// Flat art fixed to a wall, such as a torch, sign or switch, drawn on the wall's
+/// face rather than turned towards the view. Placed relative to the wall's ends, so
+/// it follows when they are moved.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct WallAttachment {
+    pub sector: SectorId,
+    /// Index of the wall within its sector.
+    pub wall: usize,
+    /// Centre along the wall, 0 at its left end and 1 at its right.
+    pub u: f32,
+    /// Height of the bottom edge above z zero, not the floor.
+    pub height: Length,
+    pub width: Length,
+    pub tall: Length,
+    pub color: RawColor,
+}
+
+impl WallAttachment {
+    /// Centre of the attachment on its wall in the map, `None` when the wall is gone.
+    pub fn position(&self, sector: &Sector) -> Option<Position2> {
+        let wall = sector.to_walls().into_iter().nth(self.wall)?;
+        Some(Position2(wall.left.0.lerp(wall.right.0, self.u)))
+    }
+}
+
+/// Object placed in a map, standing on the floor of the sector it's in. Actors
+/// spawned for a thing are checked to fit with `validate::check_clearance`.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Thing {
+    pub position: Position2,
+    pub radius: Length,
+    pub height: Length,
+}
+
+/// Look of a [`Thing`] drawn as a sprite turned to face the view, as wide and tall
+/// as the thing.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct Billboard {
+    pub color: RawColor,
+    /// Asset path of the texture drawn in place of color, if any. Transparent
+    /// pixels are left undrawn.
+    pub texture: Option<String>,
+}
+
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct SectorId(pub u32);
 
-#[derive(Component, Reflect, Debug, Default)]
+/// What a sector is flooded with.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LiquidKind {
+    #[default]
+    Water,
+    Slime,
+    Lava,
+}
+
+impl LiquidKind {
+    pub const ALL: [Self; 3] = [Self::Water, Self::Slime, Self::Lava];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Water => "water",
+            Self::Slime => "slime",
+            Self::Lava => "lava",
+        }
+    }
+
+    pub fn color(self) -> RawColor {
+        match self {
+            Self::Water => RawColor([0x20, 0x60, 0xc0]),
+            Self::Slime => RawColor([0x40, 0xa0, 0x20]),
+            Self::Lava => RawColor([0xe0, 0x40, 0x00]),
+        }
+    }
+}
+
+/// Liquid filling a sector from its floor up to surface, drawn as a translucent flat
+/// that tints the view and slows movement below it.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone)]
+pub struct Liquid {
+    pub kind: LiquidKind,
+    /// Height of the surface above z zero, not the floor.
+    pub surface: Length,
+}
+
+impl Liquid {
+    /// Opacity of the surface drawn over what's beneath it.
+    pub const SURFACE_OPACITY: f32 = 0.5;
+    /// Strength of the tint over the view with the eye below the surface.
+    pub const TINT: f32 = 0.4;
+    /// Speed of movement with the feet below the surface, relative to out of it.
+    pub const SPEED_FACTOR: f32 = 0.5;
+}
+
+/// How surfaces fade into fog color with distance from the view. As a resource it
+/// fogs the whole map, and as a sector's `fog` it replaces that in the sector.
+#[derive(Resource, Reflect, FromReflect, Debug, Copy, Clone, PartialEq)]
+pub struct FogConfig {
+    /// Distance fog starts at, surfaces nearer being clear.
+    pub start: f32,
+    /// Distance surfaces are hidden in fog from.
+    pub end: f32,
+    /// Power the fade from start to end is raised to, 1 for linear and higher for
+    /// fog that thickens late.
+    pub curve: f32,
+    pub color: RawColor,
+}
+
+impl Default for FogConfig {
+    /// Fading to black over the renderer's whole draw distance.
+    fn default() -> Self {
+        Self {
+            start: 0.1,
+            end: 50.0,
+            curve: 1.0,
+            color: RawColor([0x00, 0x00, 0x00]),
+        }
+    }
+}
+
+impl FogConfig {
+    /// How fogged a surface at distance is, from 0 for clear to 1 for only fog.
+    pub fn amount(&self, distance: f32) -> f32 {
+        let t =
+            ((distance - self.start) / (self.end - self.start).max(f32::EPSILON)).clamp(0.0, 1.0);
+        t.powf(self.curve.max(f32::EPSILON))
+    }
+}
+
+/// What a sector's floor is made of, setting the sound of footsteps on it.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FloorMaterial {
+    #[default]
+    Stone,
+    Metal,
+    Wood,
+    Dirt,
+}
+
+impl FloorMaterial {
+    pub const ALL: [Self; 4] = [Self::Stone, Self::Metal, Self::Wood, Self::Dirt];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Stone => "stone",
+            Self::Metal => "metal",
+            Self::Wood => "wood",
+            Self::Dirt => "dirt",
+        }
+    }
+
+    /// Asset path of the sound of a footstep on the floor.
+    pub fn footstep_sound(self) -> &'static str {
+        match self {
+            Self::Stone => "sounds/step_stone.wav",
+            Self::Metal => "sounds/step_metal.wav",
+            Self::Wood => "sounds/step_wood.wav",
+            Self::Dirt => "sounds/step_dirt.wav",
+        }
+    }
+}
+
+/// Texture drawn across the opening of a portal wall, over what's seen through it,
+/// such as a grate or window. Transparent pixels of the texture are left open.
+#[derive(Reflect, FromReflect, Debug, Default, Clone, PartialEq)]
+pub struct MidTexture {
+    /// Asset path of the texture.
+    pub path: String,
+    /// Blend the texture over the portal by [`MidTexture::OPACITY`] rather than
+    /// drawing it solid.
+    pub translucent: bool,
+}
+
+impl MidTexture {
+    pub const OPACITY: f32 = 0.5;
+
+    pub fn opacity(&self) -> f32 {
+        if self.translucent {
+            Self::OPACITY
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Look of the wall above or below a portal's opening in place of the wall's own,
+/// like the upper and lower textures of a Doom sidedef. Each part left `None` keeps
+/// the wall's, a texture drawn in place of color as on walls.
+#[derive(Reflect, FromReflect, Debug, Default, Clone, PartialEq)]
+pub struct WallSurface {
+    pub color: Option<RawColor>,
+    /// Asset path of the texture.
+    pub texture: Option<String>,
+}
+
+/// Where a wall's textures sit on it, lining them up across walls.
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, PartialEq)]
+pub struct TextureAlignment {
+    /// World units the textures are scrolled along the wall, towards its left end.
+    pub u_offset: f32,
+    /// World units the textures are scrolled up the wall.
+    pub v_offset: f32,
+    /// Size of the textures, 1 for one repeat every `TEXTURE_REPEAT` world units.
+    pub scale: f32,
+}
+
+impl Default for TextureAlignment {
+    fn default() -> Self {
+        Self {
+            u_offset: 0.0,
+            v_offset: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Identifies an entity within a map across loads, unlike `Entity`. Entities with one
+/// have their dynamic state kept by [`persist::Persistence`].
+#[derive(Component, Reflect, Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct StableId(pub u32);
+
+#[derive(Component, Reflect, Debug)]
 #[reflect(Component)]
 pub struct Sector {
     pub id: SectorId,
+    /// Tag shared with other sectors acted on together by [`trigger::TagEffect`]s,
+    /// 0 for none.
+    pub tag: u32,
+    /// Corners in the map's [`vertex_pool::VertexPool`], saved in place of their
+    /// positions so sectors sharing a corner stay joined.
+    pub vertex_ids: Vec<vertex_pool::VertexId>,
+    /// Position of each corner, derived from the pool by
+    /// [`vertex_pool::vertex_pool_system`] and not saved. Sectors spawned with
+    /// these and no vertex ids are welded into the pool.
+    #[reflect(ignore)]
     pub vertices: Vec<Position2>,
     pub portal_sectors: Vec<Option<SectorId>>,
     pub colors: Vec<RawColor>,
     pub floor: Length,
     pub ceil: Length,
+    /// Height of a walkable bridge deck spanning the whole sector, drawn as a thin
+    /// strip between floor and ceiling.
+    pub bridge: Option<Length>,
+    /// Asset path of the texture drawn on each wall in place of its color, if any.
+    pub wall_textures: Vec<Option<String>>,
+    /// Texture drawn across each portal wall's opening, if any. Ignored on solid
+    /// walls.
+    pub mid_textures: Vec<Option<MidTexture>>,
+    /// Look of the wall above each portal wall's opening, if not the wall's own.
+    /// Ignored on solid walls.
+    pub upper_walls: Vec<Option<WallSurface>>,
+    /// Look of the wall below each portal wall's opening, if not the wall's own.
+    /// Ignored on solid walls.
+    pub lower_walls: Vec<Option<WallSurface>>,
+    /// Alignment of the textures of each wall, the default where missing.
+    pub texture_alignments: Vec<TextureAlignment>,
+    /// Light level scaling the brightness of walls, floor and ceiling, from 0 for
+    /// black to 1 for fully lit. Animated by [`light::FlickerLight`] and
+    /// [`light::PulseLight`].
+    pub light: f32,
+    /// Liquid flooding the sector, if any.
+    pub liquid: Option<Liquid>,
+    pub floor_material: FloorMaterial,
+    /// Fog in place of the [`FogConfig`] resource, such as thicker fog in caves.
+    pub fog: Option<FogConfig>,
+    /// Color of the floor, the renderer's floor color when `None`. Animated by
+    /// [`animate::Animated`], as are wall colors.
+    pub floor_color: Option<RawColor>,
+    /// Color of the ceiling, the renderer's ceiling color when `None`.
+    pub ceiling_color: Option<RawColor>,
+}
+
+impl Default for Sector {
+    fn default() -> Self {
+        Self {
+            id: SectorId::default(),
+            tag: 0,
+            vertex_ids: Vec::new(),
+            vertices: Vec::new(),
+            portal_sectors: Vec::new(),
+            colors: Vec::new(),
+            floor: Length::default(),
+            ceil: Length::default(),
+            bridge: None,
+            wall_textures: Vec::new(),
+            mid_textures: Vec::new(),
+            upper_walls: Vec::new(),
+            lower_walls: Vec::new(),
+            texture_alignments: Vec::new(),
+            light: DEFAULT_LIGHT,
+            liquid: None,
+            floor_material: FloorMaterial::default(),
+            fog: None,
+            floor_color: None,
+            ceiling_color: None,
+        }
+    }
 }
 
 impl Sector {
+    /// Liquid of the sector when height is below its surface.
+    pub fn liquid_at(&self, height: f32) -> Option<&Liquid> {
+        self.liquid.as_ref().filter(|l| height < l.surface.0)
+    }
+
+    /// Asset path of the texture on wall at index, if any.
+    pub fn wall_texture(&self, index: usize) -> Option<&str> {
+        self.wall_textures.get(index)?.as_deref()
+    }
+
+    /// Mid-texture of the portal wall at index, if any.
+    pub fn mid_texture(&self, index: usize) -> Option<&MidTexture> {
+        self.mid_textures.get(index)?.as_ref()
+    }
+
+    /// Look of the wall above the opening of the portal wall at index, if not its own.
+    pub fn upper_wall(&self, index: usize) -> Option<&WallSurface> {
+        self.upper_walls.get(index)?.as_ref()
+    }
+
+    /// Look of the wall below the opening of the portal wall at index, if not its own.
+    pub fn lower_wall(&self, index: usize) -> Option<&WallSurface> {
+        self.lower_walls.get(index)?.as_ref()
+    }
+
+    pub fn texture_alignment(&self, index: usize) -> TextureAlignment {
+        self.texture_alignments
+            .get(index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Split wall at index in two at pool vertex id lying at point, both halves keeping
+    /// its portal, color and textures, lined up as they were.
+    pub fn split_wall(&mut self, index: usize, id: vertex_pool::VertexId, point: Position2) {
+        // The far half's textures carry on from where the near half's leave off
+        let mut alignment = self.texture_alignment(index);
+        alignment.u_offset += self.vertices[index].0.distance(point.0);
+        if index < self.texture_alignments.len() {
+            self.texture_alignments.insert(index + 1, alignment);
+        } else {
+            self.set_texture_alignment(index + 1, alignment);
+        }
+        self.vertices.insert(index + 1, point);
+        if self.vertex_ids.len() + 1 == self.vertices.len() {
+            self.vertex_ids.insert(index + 1, id);
+        }
+        if let Some(&portal_sector) = self.portal_sectors.get(index) {
+            self.portal_sectors.insert(index + 1, portal_sector);
+        }
+        if let Some(&color) = self.colors.get(index) {
+            self.colors.insert(index + 1, color);
+        }
+        if let Some(texture) = self.wall_textures.get(index).cloned() {
+            self.wall_textures.insert(index + 1, texture);
+        }
+        if let Some(mid_texture) = self.mid_textures.get(index).cloned() {
+            self.mid_textures.insert(index + 1, mid_texture);
+        }
+        if let Some(upper_wall) = self.upper_walls.get(index).cloned() {
+            self.upper_walls.insert(index + 1, upper_wall);
+        }
+        if let Some(lower_wall) = self.lower_walls.get(index).cloned() {
+            self.lower_walls.insert(index + 1, lower_wall);
+        }
+    }
+
+    /// Remove vertex at index, joining the walls either side of it into the one
+    /// before. The joined wall is only a portal when both walls led to the same sector.
+    pub fn remove_vertex(&mut self, index: usize) {
+        let before = (index + self.vertices.len() - 1) % self.vertices.len();
+        if self.portal_sectors.get(before) != self.portal_sectors.get(index) {
+            if let Some(portal_sector) = self.portal_sectors.get_mut(before) {
+                *portal_sector = None;
+            }
+        }
+
+        self.vertices.remove(index);
+        if index < self.vertex_ids.len() {
+            self.vertex_ids.remove(index);
+        }
+        if index < self.portal_sectors.len() {
+            self.portal_sectors.remove(index);
+        }
+        if index < self.colors.len() {
+            self.colors.remove(index);
+        }
+        if index < self.wall_textures.len() {
+            self.wall_textures.remove(index);
+        }
+        if index < self.mid_textures.len() {
+            self.mid_textures.remove(index);
+        }
+        if index < self.upper_walls.len() {
+            self.upper_walls.remove(index);
+        }
+        if index < self.lower_walls.len() {
+            self.lower_walls.remove(index);
+        }
+        if index < self.texture_alignments.len() {
+            self.texture_alignments.remove(index);
+        }
+    }
+
+    pub fn set_wall_color(&mut self, index: usize, color: RawColor) {
+        if self.colors.len() <= index {
+            self.colors.resize(index + 1, MISSING_WALL_COLOR);
+        }
+        self.colors[index] = color;
+    }
+
+    /// Make wall at index a portal into sector, or solid when `None`.
+    pub fn set_portal_sector(&mut self, index: usize, portal_sector: Option<SectorId>) {
+        if self.portal_sectors.len() <= index {
+            self.portal_sectors.resize(index + 1, None);
+        }
+        self.portal_sectors[index] = portal_sector;
+    }
+
+    pub fn set_wall_texture(&mut self, index: usize, texture: Option<String>) {
+        if self.wall_textures.len() <= index {
+            self.wall_textures.resize(index + 1, None);
+        }
+        self.wall_textures[index] = texture;
+    }
+
+    pub fn set_mid_texture(&mut self, index: usize, mid_texture: Option<MidTexture>) {
+        if self.mid_textures.len() <= index {
+            self.mid_textures.resize(index + 1, None);
+        }
+        self.mid_textures[index] = mid_texture;
+    }
+
+    pub fn set_upper_wall(&mut self, index: usize, upper_wall: Option<WallSurface>) {
+        if self.upper_walls.len() <= index {
+            self.upper_walls.resize(index + 1, None);
+        }
+        self.upper_walls[index] = upper_wall;
+    }
+
+    pub fn set_lower_wall(&mut self, index: usize, lower_wall: Option<WallSurface>) {
+        if self.lower_walls.len() <= index {
+            self.lower_walls.resize(index + 1, None);
+        }
+        self.lower_walls[index] = lower_wall;
+    }
+
+    pub fn set_texture_alignment(&mut self, index: usize, alignment: TextureAlignment) {
+        if self.texture_alignments.len() <= index {
+            self.texture_alignments
+                .resize(index + 1, TextureAlignment::default());
+        }
+        self.texture_alignments[index] = alignment;
+    }
+
     pub fn to_walls(&self) -> Vec<Wall> {
         let mut walls = Vec::with_capacity(self.vertices.len());
 
@@ -87,16 +664,47 @@ impl Sector {
 
         walls
     }
+
+    /// Centre of area of the sector polygon, or the average of its vertices when it
+    /// has no area.
+    pub fn centroid(&self) -> Position2 {
+        let (mut area, mut centre) = (0.0, Vec2::ZERO);
+        for wall in self.to_walls() {
+            let cross = wall.left.0.perp_dot(wall.right.0);
+            area += cross;
+            centre += (wall.left.0 + wall.right.0) * cross;
+        }
+        if area.abs() > f32::EPSILON {
+            Position2(centre / (3.0 * area))
+        } else {
+            let sum: Vec2 = self.vertices.iter().map(|v| v.0).sum();
+            Position2(sum / self.vertices.len().max(1) as f32)
+        }
+    }
+
+    /// Whether a point lies within the sector polygon, by ray casting.
+    pub fn contains(&self, point: Position2) -> bool {
+        let mut inside = false;
+        for wall in self.to_walls() {
+            let (a, b) = (wall.left.0, wall.right.0);
+            if (a.y > point.0.y) != (b.y > point.0.y)
+                && point.0.x < a.x + (point.0.y - a.y) / (b.y - a.y) * (b.x - a.x)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
 }
 
+#[derive(Debug, Copy, Clone)]
 pub struct Portal<'a> {
     pub sector: &'a Sector,
     pub x_min: isize,
     pub x_max: isize,
 }
 
-#[derive(Copy, Clone)]
-
+#[derive(Debug, Copy, Clone)]
 pub struct Wall {
     pub left: Position2,
     pub right: Position2,
@@ -105,7 +713,7 @@ pub struct Wall {
     pub color: Hsv,
 }
 
-#[derive(Reflect, Debug, Copy, Clone, Default)]
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default, PartialEq)]
 pub struct Length(pub f32);
 
 /// World position in 3D, right-handed coordinate system with z up.
@@ -114,7 +722,7 @@ pub struct Length(pub f32);
 ///   ^
 ///   |
 /// +z.---> +x
-#[derive(Debug, Copy, Clone)]
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
 pub struct Position3(pub Vec3);
 
 impl Position3 {
@@ -123,6 +731,15 @@ impl Position3 {
     }
 }
 
+/// Direction, positive right-handed around z-axis. Zero in direction of y-axis.
+///
+///   ^   ^
+///    \+θ|
+///     \ |
+///     +z.
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default, PartialEq)]
+pub struct Direction(pub f32);
+
 /// World position in 2D.
 ///
 ///  +y
@@ -137,3 +754,192 @@ impl Position2 {
         Position2(matrix.transform_point2(self.0))
     }
 }
+
+/// System sets downstream games can order their own systems against, relative to the
+/// renderer.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectorSet {
+    /// Input, movement and gameplay, anything mutating sectors or the view.
+    Simulation,
+    /// After simulation but before anything is drawn.
+    PreDraw,
+    /// Drawing of the world itself.
+    Draw,
+    /// Drawing over the top of the world, minimap and the like.
+    Overlay,
+}
+
+/// Sent when the view moves into a different sector.
+#[derive(Debug, Clone, Copy)]
+pub struct SectorEntered {
+    pub from: Option<SectorId>,
+    pub to: SectorId,
+}
+
+/// Registers sector types, events and system sets.
+pub struct SectorPlugin;
+
+impl Plugin for SectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SectorId>()
+            .register_type::<Option<SectorId>>()
+            .register_type::<Vec<Option<SectorId>>>()
+            .register_type::<Sector>()
+            .register_type::<InitialSector>()
+            .register_type::<Thing>()
+            .register_type::<GameMode>()
+            .register_type::<PlayerStart>()
+            .register_type::<CameraPose>()
+            .register_type::<Direction>()
+            .register_type::<Position2>()
+            .register_type::<Vec<Position2>>()
+            .register_type::<Length>()
+            .register_type::<Option<Length>>()
+            .register_type::<Option<usize>>()
+            .register_type::<Liquid>()
+            .register_type::<Option<Liquid>>()
+            .register_type::<LiquidKind>()
+            .register_type::<FloorMaterial>()
+            .register_type::<Option<RawColor>>()
+            .register_type::<FogConfig>()
+            .register_type::<Option<FogConfig>>()
+            .register_type::<Option<String>>()
+            .register_type::<Vec<Option<String>>>()
+            .register_type::<MidTexture>()
+            .register_type::<Option<MidTexture>>()
+            .register_type::<Vec<Option<MidTexture>>>()
+            .register_type::<WallSurface>()
+            .register_type::<Option<WallSurface>>()
+            .register_type::<Vec<Option<WallSurface>>>()
+            .register_type::<TextureAlignment>()
+            .register_type::<Vec<TextureAlignment>>()
+            .register_type::<RawColor>()
+            .register_type::<Vec<RawColor>>()
+            .register_type::<[u8; 3]>()
+            .register_type::<StableId>()
+            .register_type::<WallAttachment>()
+            .register_type::<Billboard>()
+            .register_type::<persist::Despawned>()
+            .register_type::<hub::MapExit>()
+            .register_type::<music::SoundZone>()
+            .register_type::<music::SoundEmitter>()
+            .register_type::<mob::Mob>()
+            .register_type::<mob::MobState>()
+            .register_type::<mover::Mover>()
+            .register_type::<interact::Interactable>()
+            .register_type::<trigger::Trigger>()
+            .register_type::<trigger::TagEffect>()
+            .register_type::<trigger::Effect>()
+            .register_type::<script::MapScript>()
+            .register_type::<animate::Animated>()
+            .register_type::<animate::AnimatedSurface>()
+            .register_type::<animate::ColorCycle>()
+            .register_type::<light::FlickerLight>()
+            .register_type::<light::PulseLight>()
+            .register_type::<mover::MoverKind>()
+            .register_type::<mover::MoverState>()
+            .register_type::<music::SoundLayer>()
+            .register_type::<migrate::MapVersion>()
+            .register_type::<vertex_pool::VertexId>()
+            .register_type::<Vec<vertex_pool::VertexId>>()
+            .register_type::<vertex_pool::VertexPool>()
+            .init_asset_loader::<load::MessagePackSceneLoader>()
+            .init_asset_loader::<migrate::MigratingSceneLoader>()
+            .init_resource::<PlayerStartSelection>()
+            .init_resource::<blockmap::Blockmap>()
+            .init_resource::<persist::Persistence>()
+            .init_resource::<hub::WorldState>()
+            .init_resource::<load::MapLoad>()
+            .init_resource::<time_scale::TimeScale>()
+            .init_resource::<mob::MobTarget>()
+            .add_event::<SectorEntered>()
+            .add_event::<hub::MapEntered>()
+            .add_event::<load::MapLoadEvent>()
+            .add_event::<mover::MoverCommand>()
+            .add_event::<mover::MoverStopped>()
+            .add_event::<interact::WallUsed>()
+            .add_event::<trigger::TagTriggered>()
+            .add_event::<weapons::FireWeapon>()
+            .add_event::<weapons::WeaponHit>()
+            .configure_sets((SectorSet::Simulation, SectorSet::PreDraw).chain())
+            .add_system(
+                vertex_pool::vertex_pool_system
+                    .after(bevy::scene::scene_spawner_system)
+                    .before(blockmap::blockmap_system)
+                    .before(SectorSet::Simulation),
+            )
+            .add_system(blockmap::blockmap_system.before(SectorSet::Simulation))
+            .add_systems(
+                (
+                    trigger::trigger_system,
+                    trigger::tag_effect_system,
+                    interact::mover_use_system,
+                    mover::mover_command_system,
+                    mover::mover_system,
+                )
+                    .chain()
+                    .in_set(SectorSet::Simulation),
+            )
+            .add_system(
+                physics::body_system
+                    .after(mover::mover_system)
+                    .in_set(SectorSet::Simulation),
+            )
+            .add_systems(
+                (weapons::fire_weapon_system, weapons::projectile_system)
+                    .chain()
+                    .after(mover::mover_system)
+                    .in_set(SectorSet::Simulation),
+            )
+            .add_system(light::light_system.in_set(SectorSet::Simulation))
+            .add_system(animate::animated_system.in_set(SectorSet::Simulation))
+            .add_system(
+                mob::mob_system
+                    .after(mover::mover_system)
+                    .in_set(SectorSet::Simulation),
+            )
+            .add_system(validate::closed_portals_system.in_set(SectorSet::PreDraw))
+            .add_system(validate::thing_clearance_system.in_set(SectorSet::PreDraw))
+            .add_system(persist::restore_persistent_system.in_base_set(CoreSet::PreUpdate))
+            .add_system(time_scale::time_scale_step_system.in_base_set(CoreSet::Last))
+            .add_system(
+                audit::determinism_audit_system
+                    .before(time_scale::time_scale_step_system)
+                    .in_base_set(CoreSet::Last),
+            )
+            .add_systems(
+                (
+                    hub::map_exit_system,
+                    load::map_reload_system,
+                    hub::map_transition_system,
+                    load::map_load_system,
+                )
+                    .chain()
+                    .in_base_set(CoreSet::PostUpdate),
+            );
+
+        #[cfg(feature = "audio")]
+        app.add_plugin(music::SoundPlugin);
+        #[cfg(feature = "scripting")]
+        app.add_plugin(script::ScriptingPlugin);
+    }
+}
+
+/// Convenience for adding systems to a [`SectorSet`].
+pub trait SectorAppExt {
+    fn add_sector_system<M>(
+        &mut self,
+        set: SectorSet,
+        system: impl IntoSystemConfig<M>,
+    ) -> &mut Self;
+}
+
+impl SectorAppExt for App {
+    fn add_sector_system<M>(
+        &mut self,
+        set: SectorSet,
+        system: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        self.add_system(system.in_set(set))
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{time_scale::*, *};
+
+use std::f32::consts::TAU;
+
+/// Light of sector switching between its base level and dim at random, once per
+/// interval, like a failing lamp.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct FlickerLight {
+    pub sector: SectorId,
+    pub base: f32,
+    pub dim: f32,
+    /// Seconds between chances to switch.
+    pub interval: f32,
+    /// Chance of being dim each interval, from 0 to 1.
+    pub dim_chance: f32,
+    /// Scaled seconds flickered for, kept so flickering persists with the map.
+    pub elapsed: f32,
+}
+
+impl Default for FlickerLight {
+    fn default() -> Self {
+        Self {
+            sector: SectorId::default(),
+            base: DEFAULT_LIGHT,
+            dim: 0.4,
+            interval: 0.1,
+            dim_chance: 0.3,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl FlickerLight {
+    /// Light level after elapsed, the same for the same sector and time on every run.
+    pub fn level(&self) -> f32 {
+        let step = (self.elapsed / self.interval.max(f32::EPSILON)) as u32;
+        let hash = (self.sector.0.wrapping_mul(0x9e37_79b9) ^ step)
+            .wrapping_mul(0x85eb_ca6b)
+            .rotate_left(13)
+            .wrapping_mul(0xc2b2_ae35);
+        let roll = (hash >> 8) as f32 / (1 << 24) as f32;
+        if roll < self.dim_chance {
+            self.dim
+        } else {
+            self.base
+        }
+    }
+}
+
+/// Light of sector pulsing smoothly between min and max, starting at min.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct PulseLight {
+    pub sector: SectorId,
+    pub min: f32,
+    pub max: f32,
+    /// Seconds for a full pulse, from min back to min.
+    pub period: f32,
+    /// Scaled seconds pulsed for, kept so pulsing persists with the map.
+    pub elapsed: f32,
+}
+
+impl Default for PulseLight {
+    fn default() -> Self {
+        Self {
+            sector: SectorId::default(),
+            min: 0.5,
+            max: DEFAULT_LIGHT,
+            period: 2.0,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl PulseLight {
+    /// Light level after elapsed.
+    pub fn level(&self) -> f32 {
+        let t = 0.5 - 0.5 * (TAU * self.elapsed / self.period.max(f32::EPSILON)).cos();
+        self.min + (self.max - self.min) * t
+    }
+}
+
+/// Animate the light of sectors with a [`FlickerLight`] or [`PulseLight`].
+pub fn light_system(
+    mut flicker_query: Query<&mut FlickerLight>,
+    mut pulse_query: Query<&mut PulseLight>,
+    mut sector_query: Query<&mut Sector>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+) {
+    let delta = time_scale.delta_seconds(&time);
+
+    let flickers = flicker_query.iter_mut().map(|mut flicker| {
+        flicker.elapsed += delta;
+        (flicker.sector, flicker.level())
+    });
+    let pulses = pulse_query.iter_mut().map(|mut pulse| {
+        pulse.elapsed += delta;
+        (pulse.sector, pulse.level())
+    });
+    for (id, light) in flickers.chain(pulses) {
+        let sector = sector_query.iter_mut().find(|s| s.id == id);
+        let Some(mut sector) = sector else { continue };
+        // Light changes nothing about geometry, so blockmap and validation which
+        // follow changes to sectors needn't rerun every frame
+        if sector.light != light {
+            sector.bypass_change_detection().light = light;
+        }
+    }
+}
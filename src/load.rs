@@ -0,0 +1,611 @@
+use crate::{hub::*, mover::Mover, validate::*, *};
+
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadState, LoadedAsset},
+    reflect::{TypeRegistryArc, TypeRegistryInternal},
+    scene::{
+        serde::{SceneDeserializer, SceneSerializer},
+        InstanceId, SceneInstance, SceneSpawner,
+    },
+    tasks::{ComputeTaskPool, TaskPool},
+    utils::{BoxedFuture, Duration, HashSet, Instant},
+};
+use serde::de::DeserializeSeed;
+
+/// Items handed to each task when work is split over the compute task pool.
+const PARALLEL_CHUNK: usize = 64;
+
+/// Start of a `.scn.mp` scene whose MessagePack is zstd compressed after it.
+pub const COMPRESSED_SCENE_MAGIC: &[u8; 4] = b"SCZ1";
+
+/// Compress MessagePack scene bytes with zstd, behind the magic header.
+#[cfg(feature = "zstd")]
+pub fn compress_scene(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut compressed = COMPRESSED_SCENE_MAGIC.to_vec();
+    compressed.extend(zstd::stream::encode_all(bytes, 0)?);
+    Ok(compressed)
+}
+
+/// Loads `.scn.mp` MessagePack scenes, as saved by the editor alongside the RON.
+/// Much faster to deserialize than RON for large maps. Scenes starting with
+/// [`COMPRESSED_SCENE_MAGIC`] are decompressed as they are read, which needs the
+/// `zstd` feature.
+#[derive(Debug)]
+pub struct MessagePackSceneLoader {
+    type_registry: TypeRegistryArc,
+}
+
+impl FromWorld for MessagePackSceneLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            type_registry: world.resource::<AppTypeRegistry>().0.clone(),
+        }
+    }
+}
+
+impl AssetLoader for MessagePackSceneLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let type_registry = self.type_registry.read();
+            let scene = deserialize_scene_mp(bytes, &type_registry).map_err(|error| {
+                Error::msg(format!("{error} at {}", load_context.path().display()))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(scene));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scn.mp"]
+    }
+}
+
+/// `.scn.mp` bytes of scene, compressed when built with `zstd`.
+pub fn serialize_scene_mp(
+    scene: &DynamicScene,
+    type_registry: &AppTypeRegistry,
+) -> Result<Vec<u8>, Error> {
+    let scene_serializer = SceneSerializer::new(scene, type_registry);
+    let bytes = rmp_serde::to_vec(&scene_serializer)?;
+    #[cfg(feature = "zstd")]
+    let bytes = compress_scene(&bytes)?;
+    Ok(bytes)
+}
+
+/// Scene from `.scn.mp` bytes, compressed or not, as read by
+/// [`MessagePackSceneLoader`].
+pub fn deserialize_scene_mp(
+    bytes: &[u8],
+    type_registry: &TypeRegistryInternal,
+) -> Result<DynamicScene, Error> {
+    let scene_deserializer = SceneDeserializer { type_registry };
+
+    let scene = match bytes.strip_prefix(COMPRESSED_SCENE_MAGIC) {
+        #[cfg(feature = "zstd")]
+        Some(compressed) => {
+            let decoder = zstd::stream::read::Decoder::new(compressed)?;
+            let mut deserializer = rmp_serde::Deserializer::new(decoder);
+            scene_deserializer.deserialize(&mut deserializer)?
+        }
+        #[cfg(not(feature = "zstd"))]
+        Some(_) => {
+            return Err(Error::msg(
+                "scene is compressed, which needs the `zstd` feature",
+            ))
+        }
+        None => {
+            let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+            scene_deserializer.deserialize(&mut deserializer)?
+        }
+    };
+    Ok(scene)
+}
+
+/// Scene asset to load for map, its MessagePack copy when there is one at least as
+/// new as the RON, otherwise the map itself. The web can't check, so only loads the
+/// copy when built with `zstd`, where it is expected to be deployed compressed, and
+/// the map hasn't been saved in the browser since.
+pub fn scene_asset_path(map: &str) -> String {
+    let Some(stem) = map.strip_suffix(".ron") else { return map.to_string() };
+    let mp = format!("{stem}.mp");
+
+    #[cfg(target_arch = "wasm32")]
+    return if cfg!(feature = "zstd") && crate::storage::load_scene(map).is_none() {
+        mp
+    } else {
+        map.to_string()
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let modified = |path: &str| {
+            std::fs::metadata(format!("assets/{path}"))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        match (modified(map), modified(&mp)) {
+            (Some(ron), Some(binary)) if binary >= ron => mp,
+            (None, Some(_)) => mp,
+            _ => map.to_string(),
+        }
+    }
+}
+
+/// Map f over items in chunks on the compute task pool, keeping order.
+pub fn par_chunk_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send + 'static,
+    F: Fn(&[T]) -> Vec<R> + Sync,
+{
+    let f = &f;
+    ComputeTaskPool::init(TaskPool::default)
+        .scope(|scope| {
+            for chunk in items.chunks(PARALLEL_CHUNK) {
+                scope.spawn(async move { f(chunk) });
+            }
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Time spent in each stage of loading a map.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MapLoadMetrics {
+    /// Loading and deserializing the scene and other assets.
+    pub assets: Duration,
+    /// Spawning the scene once loaded.
+    pub spawn: Duration,
+    /// Resolving portals and validating the spawned map.
+    pub checks: Duration,
+}
+
+impl MapLoadMetrics {
+    pub fn total(&self) -> Duration {
+        self.assets + self.spawn + self.checks
+    }
+}
+
+/// Step in loading a map, sent as an event and logged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapLoadEvent {
+    Started {
+        map: String,
+    },
+    /// Waiting on assets to load before the map can spawn.
+    AssetsPending {
+        count: usize,
+    },
+    AssetFailed {
+        path: String,
+    },
+    SectorsSpawned {
+        count: usize,
+    },
+    PortalsResolved {
+        resolved: usize,
+        unresolved: usize,
+    },
+    ValidationFinding {
+        message: String,
+    },
+    Finished {
+        map: String,
+        metrics: MapLoadMetrics,
+    },
+    /// Map scene itself failed to load, nothing was spawned.
+    Failed {
+        map: String,
+    },
+}
+
+/// Progress of the map being loaded. Events of the last load are kept for tests and
+/// tools to inspect.
+#[derive(Resource, Debug, Default)]
+pub struct MapLoad {
+    map: Option<String>,
+    scene: Handle<DynamicScene>,
+    /// Scene instance of the map being replaced, to tell when the new one spawns.
+    previous_instance: Option<InstanceId>,
+    pending: Vec<HandleUntyped>,
+    reported_pending: bool,
+    log: Vec<MapLoadEvent>,
+    /// Start of the current stage of the load.
+    stage_started: Option<Instant>,
+    metrics: MapLoadMetrics,
+}
+
+impl MapLoad {
+    /// Start tracking load of map from scene.
+    pub fn begin(
+        &mut self,
+        map: &str,
+        scene: Handle<DynamicScene>,
+        previous_instance: Option<InstanceId>,
+    ) {
+        *self = Self {
+            map: Some(map.to_string()),
+            scene: scene.clone(),
+            previous_instance,
+            pending: vec![scene.clone_untyped()],
+            stage_started: Some(Instant::now()),
+            ..default()
+        };
+    }
+
+    /// Wait on another asset before the map is considered loaded.
+    pub fn track(&mut self, handle: HandleUntyped) {
+        self.pending.push(handle);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.map.is_some()
+    }
+
+    /// Timings of the stages of the current or last load finished so far.
+    pub fn metrics(&self) -> MapLoadMetrics {
+        self.metrics
+    }
+
+    /// Time since the current stage started, starting the next.
+    fn end_stage(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = self
+            .stage_started
+            .map(|started| now - started)
+            .unwrap_or_default();
+        self.stage_started = Some(now);
+        elapsed
+    }
+
+    /// Events of the current or last load, in order.
+    pub fn log(&self) -> &[MapLoadEvent] {
+        &self.log
+    }
+
+    fn emit(&mut self, events: &mut EventWriter<MapLoadEvent>, event: MapLoadEvent) {
+        match &event {
+            MapLoadEvent::Started { map } => info!("loading map `{map}`"),
+            MapLoadEvent::AssetsPending { count } => info!("waiting on {count} asset(s)"),
+            MapLoadEvent::AssetFailed { path } => warn!("failed to load asset `{path}`"),
+            MapLoadEvent::SectorsSpawned { count } => info!("spawned {count} sector(s)"),
+            MapLoadEvent::PortalsResolved {
+                resolved,
+                unresolved,
+            } => {
+                if *unresolved > 0 {
+                    warn!("resolved {resolved} portal(s), {unresolved} lead to missing sectors");
+                } else {
+                    info!("resolved {resolved} portal(s)");
+                }
+            }
+            MapLoadEvent::ValidationFinding { message } => warn!("{message}"),
+            MapLoadEvent::Finished { map, metrics } => info!(
+                "loaded map `{map}` in {:.3}s (assets {:.3}s, spawn {:.3}s, checks {:.3}s)",
+                metrics.total().as_secs_f32(),
+                metrics.assets.as_secs_f32(),
+                metrics.spawn.as_secs_f32(),
+                metrics.checks.as_secs_f32(),
+            ),
+            MapLoadEvent::Failed { map } => warn!("failed to load map `{map}`"),
+        }
+        self.log.push(event.clone());
+        events.send(event);
+    }
+}
+
+/// Enter the current map again through the load path when its scene changes on disk,
+/// RON or MessagePack, as seen by the asset server when watching for changes. The
+/// scene spawner only updates entities in place, leaving any removed from the map
+/// and skipping the load report. The map's kept state is dropped, so it shows what
+/// changed rather than how it was left.
+pub fn map_reload_system(
+    mut scene_events: EventReader<AssetEvent<DynamicScene>>,
+    mut world_state: ResMut<WorldState>,
+    map_load: Res<MapLoad>,
+    root_query: Query<&Handle<DynamicScene>, With<MapRoot>>,
+) {
+    let Ok(root_handle) = root_query.get_single() else { return };
+    let modified = scene_events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { handle } if handle == root_handle));
+    if !modified || map_load.is_loading() {
+        return;
+    }
+    if let Some(map) = world_state.current_map() {
+        info!("map `{map}` changed on disk, reloading");
+        world_state.reload();
+    }
+}
+
+/// Follow the map being loaded through to spawned and report on it.
+#[allow(clippy::too_many_arguments)]
+pub fn map_load_system(
+    mut map_load: ResMut<MapLoad>,
+    mut map_load_events: EventWriter<MapLoadEvent>,
+    asset_server: Res<AssetServer>,
+    scene_spawner: Res<SceneSpawner>,
+    root_query: Query<&SceneInstance, With<MapRoot>>,
+    sector_query: Query<&Sector>,
+    thing_query: Query<&Thing>,
+    start_query: Query<&PlayerStart>,
+    initial_sector_query: Query<&InitialSector>,
+    mover_query: Query<&Mover>,
+) {
+    let Some(map) = map_load.map.clone() else { return };
+
+    if map_load.log.is_empty() {
+        map_load.emit(
+            &mut map_load_events,
+            MapLoadEvent::Started { map: map.clone() },
+        );
+    }
+
+    // Wait on assets
+    let mut failed = Vec::new();
+    map_load
+        .pending
+        .retain(|handle| match asset_server.get_load_state(handle) {
+            LoadState::Loaded => false,
+            LoadState::Failed => {
+                failed.push(handle.clone());
+                false
+            }
+            _ => true,
+        });
+
+    for handle in failed {
+        if handle.id() == map_load.scene.id() {
+            map_load.emit(&mut map_load_events, MapLoadEvent::Failed { map });
+            map_load.map = None;
+            return;
+        }
+        let path = asset_server
+            .get_handle_path(&handle)
+            .map(|path| path.path().display().to_string())
+            .unwrap_or_default();
+        map_load.emit(&mut map_load_events, MapLoadEvent::AssetFailed { path });
+    }
+
+    if !map_load.pending.is_empty() {
+        if !map_load.reported_pending {
+            let count = map_load.pending.len();
+            map_load.emit(&mut map_load_events, MapLoadEvent::AssetsPending { count });
+            map_load.reported_pending = true;
+        }
+        return;
+    }
+    if map_load.metrics.assets.is_zero() {
+        map_load.metrics.assets = map_load.end_stage();
+    }
+
+    // Wait on the scene to spawn
+    let Ok(instance) = root_query.get_single() else { return };
+    if Some(**instance) == map_load.previous_instance
+        || !scene_spawner.instance_is_ready(**instance)
+    {
+        return;
+    }
+
+    map_load.metrics.spawn = map_load.end_stage();
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    map_load.emit(
+        &mut map_load_events,
+        MapLoadEvent::SectorsSpawned {
+            count: sectors.len(),
+        },
+    );
+
+    let ids: HashSet<SectorId> = sectors.iter().map(|s| s.id).collect();
+    let (mut resolved, mut unresolved) = (0, 0);
+    for target in sectors
+        .iter()
+        .flat_map(|s| s.portal_sectors.iter().flatten())
+    {
+        if ids.contains(target) {
+            resolved += 1;
+        } else {
+            unresolved += 1;
+        }
+    }
+    map_load.emit(
+        &mut map_load_events,
+        MapLoadEvent::PortalsResolved {
+            resolved,
+            unresolved,
+        },
+    );
+
+    for finding in sector_findings(&sectors) {
+        let message = finding.to_string();
+        map_load.emit(
+            &mut map_load_events,
+            MapLoadEvent::ValidationFinding { message },
+        );
+    }
+
+    let moving: Vec<SectorId> = mover_query.iter().map(|m| m.sector).collect();
+    for portal in closed_portals(&sectors, &moving) {
+        let message = portal.to_string();
+        map_load.emit(
+            &mut map_load_events,
+            MapLoadEvent::ValidationFinding { message },
+        );
+    }
+
+    let starts: Vec<&PlayerStart> = start_query.iter().collect();
+    let initial_sector = initial_sector_query.iter().next();
+    for finding in spawn_findings(&sectors, &starts, initial_sector) {
+        let message = finding.to_string();
+        map_load.emit(
+            &mut map_load_events,
+            MapLoadEvent::ValidationFinding { message },
+        );
+    }
+
+    // Checking clearance of every thing is the slowest check on large maps
+    let things: Vec<&Thing> = thing_query.iter().collect();
+    let messages = par_chunk_map(&things, |chunk| {
+        chunk
+            .iter()
+            .filter_map(|thing| {
+                let problem = check_clearance(&sectors, thing).err()?;
+                Some(ThingFinding(thing, problem).to_string())
+            })
+            .collect()
+    });
+    for message in messages {
+        map_load.emit(
+            &mut map_load_events,
+            MapLoadEvent::ValidationFinding { message },
+        );
+    }
+
+    map_load.metrics.checks = map_load.end_stage();
+    let metrics = map_load.metrics;
+    map_load.emit(
+        &mut map_load_events,
+        MapLoadEvent::Finished { map, metrics },
+    );
+    map_load.map = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edit::*, migrate::migrate_scene_ron};
+
+    use bevy::{ecs::entity::EntityMap, scene::ScenePlugin};
+
+    /// App with the default map spawned, applying geometry edits as the game does.
+    fn default_map_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(ScenePlugin)
+            .add_plugin(SectorPlugin)
+            .add_event::<GeometryEdit>()
+            .add_system(geometry_edit_system);
+
+        let ron = std::fs::read_to_string(format!("assets/{DEFAULT_SCENE_RON_FILE_PATH}"))
+            .expect("failed to read default map");
+        let scene = {
+            let registry = app.world.resource::<AppTypeRegistry>().read();
+            let mut deserializer = ron::de::Deserializer::from_str(&ron).unwrap();
+            SceneDeserializer {
+                type_registry: &registry,
+            }
+            .deserialize(&mut deserializer)
+            .unwrap()
+        };
+        scene
+            .write_to_world(&mut app.world, &mut EntityMap::default())
+            .unwrap();
+        app.update();
+        app
+    }
+
+    /// RON of the world scene spawns into, with entities numbered as in scene. Scenes
+    /// are compared this way as Bevy can't serialize enums it deserialized again.
+    fn spawned_ron(scene: &DynamicScene, type_registry: &AppTypeRegistry) -> String {
+        let mut world = World::new();
+        world.insert_resource(type_registry.clone());
+        let mut entity_map = EntityMap::default();
+        scene.write_to_world(&mut world, &mut entity_map).unwrap();
+
+        let mut spawned = DynamicScene::from_world(&world, type_registry);
+        for entity in &mut spawned.entities {
+            let (from, _) = entity_map
+                .iter()
+                .find(|(_, to)| to.index() == entity.entity)
+                .unwrap();
+            entity.entity = from.index();
+        }
+        spawned.entities.sort_by_key(|entity| entity.entity);
+        spawned.serialize_ron(type_registry).unwrap()
+    }
+
+    /// Check the world survives saving and loading unchanged as RON and as
+    /// MessagePack, compressed or not.
+    fn assert_round_trips(world: &World) {
+        let type_registry = world.resource::<AppTypeRegistry>();
+        let scene = DynamicScene::from_world(world, type_registry);
+        let expected = spawned_ron(&scene, type_registry);
+
+        let scene_ron = scene.serialize_ron(type_registry).unwrap();
+        let from_ron = {
+            let registry = type_registry.read();
+            let mut deserializer = ron::de::Deserializer::from_str(&scene_ron).unwrap();
+            SceneDeserializer {
+                type_registry: &registry,
+            }
+            .deserialize(&mut deserializer)
+            .unwrap()
+        };
+        let scene_mp = rmp_serde::to_vec(&SceneSerializer::new(&scene, type_registry)).unwrap();
+        let from_mp = deserialize_scene_mp(&scene_mp, &type_registry.read()).unwrap();
+        #[allow(unused_mut)]
+        let mut loaded = vec![("RON", from_ron), ("MessagePack", from_mp)];
+        #[cfg(feature = "zstd")]
+        {
+            let compressed = compress_scene(&scene_mp).unwrap();
+            let from_zstd = deserialize_scene_mp(&compressed, &type_registry.read()).unwrap();
+            loaded.push(("compressed MessagePack", from_zstd));
+        }
+
+        for (format, loaded) in loaded {
+            let loaded_ron = spawned_ron(&loaded, type_registry);
+            assert!(
+                loaded_ron == expected,
+                "map changed by {format} round trip:\n{loaded_ron}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_map_is_current() {
+        let ron = std::fs::read_to_string(format!("assets/{DEFAULT_SCENE_RON_FILE_PATH}")).unwrap();
+        assert_eq!(migrate_scene_ron(&ron), Ok(None));
+    }
+
+    #[test]
+    fn default_map_round_trips() {
+        let app = default_map_app();
+        assert_round_trips(&app.world);
+    }
+
+    #[test]
+    fn edited_map_round_trips() {
+        let mut app = default_map_app();
+        app.world.send_event(GeometryEdit::MoveVertices {
+            from: Vec2::new(2.0, 10.0),
+            to: Vec2::new(3.0, 11.0),
+            sector: None,
+        });
+        app.world
+            .send_event(GeometryEdit::SplitWalls(Vec2::new(7.5, -8.0)));
+        app.world.send_event(GeometryEdit::AddSector(vec![
+            Vec2::new(11.0, -8.0),
+            Vec2::new(7.5, -8.0),
+            Vec2::new(9.0, -12.0),
+        ]));
+        app.update();
+        app.update();
+
+        let mut sector_query = app.world.query::<&mut Sector>();
+        for mut sector in sector_query.iter_mut(&mut app.world) {
+            sector.floor.0 += 0.5;
+            sector.floor_color = Some(RawColor([10, 20, 30]));
+        }
+        app.update();
+
+        assert_eq!(app.world.query::<&Sector>().iter(&app.world).count(), 4);
+        assert_round_trips(&app.world);
+    }
+}
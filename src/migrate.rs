@@ -0,0 +1,686 @@
+use crate::{edit::SPLIT_EPSILON, *};
+
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadedAsset},
+    reflect::TypeRegistryArc,
+    scene::serde::SceneDeserializer,
+    utils::BoxedFuture,
+};
+use serde::de::DeserializeSeed;
+use std::{borrow::Cow, fmt::Write as _};
+
+/// Type name of [`MapVersion`] as it appears in scene files.
+const MAP_VERSION_TYPE: &str = "sector::migrate::MapVersion";
+const SECTOR_TYPE: &str = "sector::Sector";
+const STABLE_ID_TYPE: &str = "sector::StableId";
+const VERTEX_POOL_TYPE: &str = "sector::vertex_pool::VertexPool";
+
+/// Upgrades to maps, in order. Maps are at the version of the last migration they
+/// have had applied, and those without a [`MapVersion`] at version 0. Add a migration
+/// whenever a change to a map type would stop older maps loading, such as renaming or
+/// removing a field. Fields added with a default need none.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "record map version",
+        apply: |_| {},
+    },
+    Migration {
+        version: 2,
+        description: "store sector corners in a vertex pool",
+        apply: pool_sector_vertices,
+    },
+];
+
+/// Version of maps saved by this build.
+pub const MAP_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Schema version of the map a scene was saved as, kept on its own entity.
+#[derive(Component, Reflect, Debug, Default, Copy, Clone)]
+#[reflect(Component)]
+pub struct MapVersion(pub u32);
+
+impl MapVersion {
+    pub fn current() -> Self {
+        Self(MAP_VERSION)
+    }
+}
+
+/// Upgrade of maps from the version before to `version`, made to the scene's RON.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut RonValue),
+}
+
+/// Scene RON parsed just enough to change and write back, keeping the distinction
+/// between structs, tuples and lists that the scene deserializer relies on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RonValue {
+    /// Number, string, char, bool or unit variant, as written.
+    Atom(String),
+    /// `name(field: value, ..)`, a struct or struct variant.
+    Struct(Option<String>, Vec<(String, RonValue)>),
+    /// `name(value, ..)`, a tuple, tuple struct or tuple variant.
+    Tuple(Option<String>, Vec<RonValue>),
+    List(Vec<RonValue>),
+    Map(Vec<(RonValue, RonValue)>),
+}
+
+impl RonValue {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, position: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Field of a struct.
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut RonValue> {
+        let Self::Struct(_, fields) = self else { return None };
+        fields.iter_mut().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn rename_field(&mut self, from: &str, to: &str) {
+        if let Self::Struct(_, fields) = self {
+            for (name, _) in fields.iter_mut().filter(|(n, _)| n == from) {
+                *name = to.to_string();
+            }
+        }
+    }
+
+    pub fn remove_field(&mut self, name: &str) {
+        if let Self::Struct(_, fields) = self {
+            fields.retain(|(n, _)| n != name);
+        }
+    }
+
+    /// Components map of every entity in a scene.
+    fn entity_components_mut(&mut self) -> impl Iterator<Item = &mut Vec<(RonValue, RonValue)>> {
+        let entities = match self.field_mut("entities") {
+            Some(RonValue::Map(entities)) => Some(entities),
+            _ => None,
+        };
+        entities.into_iter().flatten().filter_map(|(_, entity)| {
+            match entity.field_mut("components") {
+                Some(RonValue::Map(components)) => Some(components),
+                _ => None,
+            }
+        })
+    }
+
+    /// Every component of type in a scene, by its full type name.
+    pub fn components_mut<'a>(
+        &'a mut self,
+        type_name: &'a str,
+    ) -> impl Iterator<Item = &'a mut RonValue> {
+        let key = RonValue::Atom(format!("{type_name:?}"));
+        self.entity_components_mut().flat_map(move |components| {
+            let key = key.clone();
+            components
+                .iter_mut()
+                .filter(move |(k, _)| *k == key)
+                .map(|(_, v)| v)
+        })
+    }
+
+    /// Rename a component type throughout a scene.
+    pub fn rename_component(&mut self, from: &str, to: &str) {
+        let (from, to) = (
+            RonValue::Atom(format!("{from:?}")),
+            RonValue::Atom(format!("{to:?}")),
+        );
+        for components in self.entity_components_mut() {
+            for (key, _) in components.iter_mut().filter(|(k, _)| *k == from) {
+                *key = to.clone();
+            }
+        }
+    }
+
+    /// Version of the map this scene holds.
+    pub fn map_version(&mut self) -> u32 {
+        self.components_mut(MAP_VERSION_TYPE)
+            .find_map(|version| version.newtype_u32())
+            .unwrap_or(0)
+    }
+
+    /// Value of a tuple struct holding a single number, such as a `StableId`.
+    fn newtype_u32(&self) -> Option<u32> {
+        match self {
+            RonValue::Tuple(None, values) => match values.as_slice() {
+                [RonValue::Atom(atom)] => atom.parse().ok(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn set_map_version(&mut self, version: u32) {
+        let value = RonValue::Tuple(None, vec![RonValue::Atom(version.to_string())]);
+        let mut found = false;
+        for existing in self.components_mut(MAP_VERSION_TYPE) {
+            *existing = value.clone();
+            found = true;
+        }
+        if !found {
+            let components = vec![(RonValue::Atom(format!("{MAP_VERSION_TYPE:?}")), value)];
+            self.push_entity(components);
+        }
+    }
+
+    /// Add an entity with components, keyed after the others.
+    fn push_entity(&mut self, components: Vec<(RonValue, RonValue)>) {
+        let Some(RonValue::Map(entities)) = self.field_mut("entities") else { return };
+        let next = entities
+            .iter()
+            .filter_map(|(key, _)| match key {
+                RonValue::Atom(atom) => atom.parse::<u32>().ok(),
+                _ => None,
+            })
+            .max()
+            .map_or(0, |max| max + 1);
+        entities.push((
+            RonValue::Atom(next.to_string()),
+            RonValue::Struct(
+                None,
+                vec![("components".to_string(), RonValue::Map(components))],
+            ),
+        ));
+    }
+
+    /// RON in the layout scenes are saved in.
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, indent: usize| out.push_str(&"  ".repeat(indent));
+        match self {
+            Self::Atom(atom) => out.push_str(atom),
+            Self::Struct(name, fields) => {
+                out.push_str(name.as_deref().unwrap_or(""));
+                out.push('(');
+                if !fields.is_empty() {
+                    out.push('\n');
+                    for (field, value) in fields {
+                        pad(out, indent + 1);
+                        let _ = write!(out, "{field}: ");
+                        value.write(out, indent + 1);
+                        out.push_str(",\n");
+                    }
+                    pad(out, indent);
+                }
+                out.push(')');
+            }
+            Self::Tuple(name, values) => {
+                out.push_str(name.as_deref().unwrap_or(""));
+                out.push('(');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    value.write(out, indent);
+                }
+                out.push(')');
+            }
+            Self::List(values) => {
+                out.push('[');
+                if !values.is_empty() {
+                    out.push('\n');
+                    for value in values {
+                        pad(out, indent + 1);
+                        value.write(out, indent + 1);
+                        out.push_str(",\n");
+                    }
+                    pad(out, indent);
+                }
+                out.push(']');
+            }
+            Self::Map(entries) => {
+                out.push('{');
+                if !entries.is_empty() {
+                    out.push('\n');
+                    for (key, value) in entries {
+                        pad(out, indent + 1);
+                        key.write(out, indent + 1);
+                        out.push_str(": ");
+                        value.write(out, indent + 1);
+                        out.push_str(",\n");
+                    }
+                    pad(out, indent);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Replace the corner positions of sectors with ids of vertices in a pool on an entity
+/// of its own, welding corners at the same position as maps did while loading.
+fn pool_sector_vertices(scene: &mut RonValue) {
+    // Position2((x: .., y: ..))
+    let position = |value: &RonValue| -> Option<Vec2> {
+        let RonValue::Tuple(None, values) = value else { return None };
+        let [RonValue::Struct(None, fields)] = values.as_slice() else { return None };
+        let coordinate = |name: &str| match fields.iter().find(|(n, _)| n == name) {
+            Some((_, RonValue::Atom(atom))) => atom.parse().ok(),
+            _ => None,
+        };
+        Some(Vec2::new(coordinate("x")?, coordinate("y")?))
+    };
+
+    let mut pool: Vec<(Option<Vec2>, RonValue)> = Vec::new();
+    let mut any_sectors = false;
+    for sector in scene.components_mut(SECTOR_TYPE) {
+        any_sectors = true;
+        let Some(RonValue::List(vertices)) = sector.field_mut("vertices") else { continue };
+        let ids = vertices
+            .iter()
+            .map(|vertex| {
+                let point = position(vertex);
+                let welded = pool.iter().position(|(p, _)| match (p, point) {
+                    (Some(p), Some(point)) => p.distance(point) < SPLIT_EPSILON,
+                    _ => false,
+                });
+                let index = welded.unwrap_or_else(|| {
+                    pool.push((point, vertex.clone()));
+                    pool.len() - 1
+                });
+                RonValue::Tuple(None, vec![RonValue::Atom(index.to_string())])
+            })
+            .collect();
+        *vertices = ids;
+        sector.rename_field("vertices", "vertex_ids");
+    }
+    if !any_sectors {
+        return;
+    }
+
+    // Kept across visits to the map like the sectors
+    let stable_id = scene
+        .components_mut(STABLE_ID_TYPE)
+        .filter_map(|id| id.newtype_u32())
+        .max()
+        .map_or(0, |max| max + 1);
+    let positions = pool.into_iter().map(|(_, vertex)| vertex).collect();
+    let components = vec![
+        (
+            RonValue::Atom(format!("{VERTEX_POOL_TYPE:?}")),
+            RonValue::Struct(
+                None,
+                vec![("positions".to_string(), RonValue::List(positions))],
+            ),
+        ),
+        (
+            RonValue::Atom(format!("{STABLE_ID_TYPE:?}")),
+            RonValue::Tuple(None, vec![RonValue::Atom(stable_id.to_string())]),
+        ),
+    ];
+    scene.push_entity(components);
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.position].lines().count().max(1);
+        format!("{message} at line {line}")
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.position += trimmed.find("*/").map_or(trimmed.len(), |end| end + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{c}`")))
+        }
+    }
+
+    /// Consume a separating comma, returning whether the collection continues.
+    fn separator(&mut self, close: char) -> Result<bool, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(',') => {
+                self.position += 1;
+                self.skip_whitespace();
+                Ok(self.peek() != Some(close))
+            }
+            Some(c) if c == close => Ok(false),
+            _ => Err(self.error(&format!("expected `,` or `{close}`"))),
+        }
+    }
+
+    fn value(&mut self) -> Result<RonValue, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.parenthesized(None),
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() != Some(']') {
+                    loop {
+                        values.push(self.value()?);
+                        if !self.separator(']')? {
+                            break;
+                        }
+                    }
+                }
+                self.expect(']')?;
+                Ok(RonValue::List(values))
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut entries = Vec::new();
+                self.skip_whitespace();
+                if self.peek() != Some('}') {
+                    loop {
+                        let key = self.value()?;
+                        self.expect(':')?;
+                        entries.push((key, self.value()?));
+                        if !self.separator('}')? {
+                            break;
+                        }
+                    }
+                }
+                self.expect('}')?;
+                Ok(RonValue::Map(entries))
+            }
+            Some(quote @ ('"' | '\'')) => {
+                let start = self.position;
+                let mut escaped = false;
+                for (i, c) in self.rest().char_indices().skip(1) {
+                    if c == quote && !escaped {
+                        self.position += i + 1;
+                        return Ok(RonValue::Atom(self.text[start..self.position].to_string()));
+                    }
+                    escaped = c == '\\' && !escaped;
+                }
+                Err(self.error("unterminated string"))
+            }
+            Some(_) => {
+                let rest = self.rest();
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || ",:()[]{}".contains(c))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(self.error("expected value"));
+                }
+                let atom = rest[..end].to_string();
+                self.position += end;
+                if self.peek() == Some('(') {
+                    self.parenthesized(Some(atom))
+                } else {
+                    Ok(RonValue::Atom(atom))
+                }
+            }
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    /// Struct when it starts with a field name, otherwise a tuple.
+    fn parenthesized(&mut self, name: Option<String>) -> Result<RonValue, String> {
+        self.expect('(')?;
+        self.skip_whitespace();
+        let rest = self.rest();
+        let ident_end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let after_ident = rest[ident_end..].trim_start();
+        let is_struct =
+            ident_end > 0 && after_ident.starts_with(':') && !after_ident.starts_with("::");
+
+        if is_struct {
+            let mut fields = Vec::new();
+            loop {
+                self.skip_whitespace();
+                let rest = self.rest();
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let field = rest[..end].to_string();
+                self.position += end;
+                self.expect(':')?;
+                fields.push((field, self.value()?));
+                if !self.separator(')')? {
+                    break;
+                }
+            }
+            self.expect(')')?;
+            Ok(RonValue::Struct(name, fields))
+        } else {
+            let mut values = Vec::new();
+            if self.peek() != Some(')') {
+                loop {
+                    values.push(self.value()?);
+                    if !self.separator(')')? {
+                        break;
+                    }
+                }
+            }
+            self.expect(')')?;
+            Ok(RonValue::Tuple(name, values))
+        }
+    }
+}
+
+/// Scene RON upgraded to the current map version, with the version it was at, or
+/// `None` when it is already current.
+pub fn migrate_scene_ron(text: &str) -> Result<Option<(String, u32)>, String> {
+    let mut scene = RonValue::parse(text)?;
+    let version = scene.map_version();
+    if version == MAP_VERSION {
+        return Ok(None);
+    }
+    if version > MAP_VERSION {
+        return Err(format!(
+            "map version {version} is newer than this build supports ({MAP_VERSION})"
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        debug!(
+            "migrating map to version {}: {}",
+            migration.version, migration.description
+        );
+        (migration.apply)(&mut scene);
+    }
+    scene.set_map_version(MAP_VERSION);
+    Ok(Some((scene.to_pretty_string(), version)))
+}
+
+/// Upgrade the scene file at path in place, returning the version it was at, or
+/// `None` when it was already current.
+pub fn migrate_file(path: &str) -> Result<Option<u32>, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let Some((migrated, version)) = migrate_scene_ron(&text)? else { return Ok(None) };
+    std::fs::write(path, migrated).map_err(|error| error.to_string())?;
+    Ok(Some(version))
+}
+
+/// Loads `.scn.ron` scenes in place of Bevy's loader, upgrading maps saved at older
+/// versions as they load.
+#[derive(Debug)]
+pub struct MigratingSceneLoader {
+    type_registry: TypeRegistryArc,
+}
+
+impl FromWorld for MigratingSceneLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            type_registry: world.resource::<AppTypeRegistry>().0.clone(),
+        }
+    }
+}
+
+impl AssetLoader for MigratingSceneLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let path = load_context.path().display().to_string();
+            let text = std::str::from_utf8(bytes)?;
+            let text = match migrate_scene_ron(text)
+                .map_err(|error| Error::msg(format!("{error} in {path}")))?
+            {
+                Some((migrated, version)) => {
+                    warn!("map `{path}` upgraded from version {version}, save it with `--migrate`");
+                    Cow::Owned(migrated)
+                }
+                None => Cow::Borrowed(text),
+            };
+
+            let mut deserializer = ron::de::Deserializer::from_str(&text)?;
+            let scene_deserializer = SceneDeserializer {
+                type_registry: &self.type_registry.read(),
+            };
+            let scene = scene_deserializer
+                .deserialize(&mut deserializer)
+                .map_err(|error| {
+                    let span_error = deserializer.span_error(error);
+                    Error::msg(format!(
+                        "{} at {path}:{}",
+                        span_error.code, span_error.position
+                    ))
+                })?;
+            load_context.set_default_asset(LoadedAsset::new(scene));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scn.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scene of two unit squares sharing an edge, with corners stored on each sector as
+    /// maps were before version 2, and a map version entity when version is given.
+    fn unpooled_scene(version: Option<u32>) -> String {
+        let corners = |x: f32| {
+            [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]
+                .map(|(vx, vy)| format!("((x: {:?}, y: {vy:?}))", x + vx))
+                .join(", ")
+        };
+        let sector = |id: u32, x: f32| {
+            format!(
+                "{id}: (components: {{
+                    \"{SECTOR_TYPE}\": (id: ({id}), vertices: [{}], floor: (0.0)),
+                    \"{STABLE_ID_TYPE}\": ({id}),
+                }})",
+                corners(x)
+            )
+        };
+        let version = version.map_or(String::new(), |version| {
+            format!("2: (components: {{ \"{MAP_VERSION_TYPE}\": ({version}) }}),")
+        });
+        format!(
+            "(resources: {{}}, entities: {{ {}, {}, {version} }})",
+            sector(0, 0.0),
+            sector(1, 1.0)
+        )
+    }
+
+    fn ids(sector: &mut RonValue) -> Vec<u32> {
+        let Some(RonValue::List(ids)) = sector.field_mut("vertex_ids") else {
+            panic!("no vertex ids in {sector:?}")
+        };
+        ids.iter().map(|id| id.newtype_u32().unwrap()).collect()
+    }
+
+    #[test]
+    fn older_versions_upgrade() {
+        for (version, from) in [(None, 0), (Some(1), 1)] {
+            let (migrated, was) = migrate_scene_ron(&unpooled_scene(version))
+                .unwrap()
+                .expect("migrated");
+            assert_eq!(was, from);
+
+            let mut scene = RonValue::parse(&migrated).unwrap();
+            assert_eq!(scene.map_version(), MAP_VERSION);
+            assert_eq!(scene.components_mut(MAP_VERSION_TYPE).count(), 1);
+
+            let mut sectors: Vec<RonValue> = scene
+                .components_mut(SECTOR_TYPE)
+                .map(|sector| sector.clone())
+                .collect();
+            for sector in &mut sectors {
+                assert!(sector.field_mut("vertices").is_none());
+            }
+            let (left, right) = (ids(&mut sectors[0]), ids(&mut sectors[1]));
+            assert_eq!(left, [0, 1, 2, 3]);
+            // The shared edge is welded, the right square's left edge being wall 2
+            // of the left square reversed
+            assert_eq!(right, [3, 2, 4, 5]);
+
+            let mut pools: Vec<RonValue> = scene
+                .components_mut(VERTEX_POOL_TYPE)
+                .map(|pool| pool.clone())
+                .collect();
+            assert_eq!(pools.len(), 1);
+            let Some(RonValue::List(positions)) = pools[0].field_mut("positions") else {
+                panic!("no positions in {:?}", pools[0])
+            };
+            assert_eq!(positions.len(), 6);
+            let mut stable_ids: Vec<u32> = scene
+                .components_mut(STABLE_ID_TYPE)
+                .filter_map(|id| id.newtype_u32())
+                .collect();
+            stable_ids.sort();
+            assert_eq!(stable_ids, [0, 1, 2], "pool kept after the sectors");
+
+            assert_eq!(migrate_scene_ron(&migrated), Ok(None));
+        }
+    }
+
+    #[test]
+    fn current_map_is_unchanged() {
+        let scene = format!(
+            "(resources: {{}}, entities: {{ 0: (components: {{ \"{MAP_VERSION_TYPE}\": ({MAP_VERSION}) }}) }})"
+        );
+        assert_eq!(migrate_scene_ron(&scene), Ok(None));
+    }
+
+    #[test]
+    fn newer_map_is_rejected() {
+        let error = migrate_scene_ron(&unpooled_scene(Some(MAP_VERSION + 1))).unwrap_err();
+        assert!(error.contains("newer"), "{error}");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    blockmap::*, generate::Rng, interact::ray_crossing, pathfind::*, physics::*, time_scale::*, *,
+};
+
+/// Speed mobs use when not given one, in units per second.
+pub const DEFAULT_MOB_SPEED: f32 = 1.5;
+/// Distance mobs see when not given one.
+pub const DEFAULT_SIGHT_RANGE: f32 = 12.0;
+/// Distance from a point a mob counts as having reached it.
+const REACHED: f32 = 0.1;
+/// Gap a mob keeps between its edge and the target it closes in on.
+const CLOSE_DISTANCE: f32 = 0.5;
+/// Random points tried for somewhere inside its sector to wander to.
+const WANDER_TRIES: usize = 8;
+
+/// What a mob is doing.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone)]
+pub enum MobState {
+    /// About to pick somewhere to wander to.
+    #[default]
+    Idle,
+    /// Heading for a point in its sector.
+    Wandering { goal: Position2 },
+    /// Heading for where the target was last seen, in sector, through the portals
+    /// between.
+    Hunting {
+        last_seen: Position2,
+        sector: SectorId,
+    },
+}
+
+/// [`Thing`] that wanders its sector until it sees the [`MobTarget`], then chases it
+/// from sector to sector. State is kept on the component so it persists with the map.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct Mob {
+    /// Units per second.
+    pub speed: f32,
+    pub sight_range: f32,
+    pub state: MobState,
+    /// State of the random numbers picking where to wander.
+    pub seed: u64,
+}
+
+impl Default for Mob {
+    fn default() -> Self {
+        Self {
+            speed: DEFAULT_MOB_SPEED,
+            sight_range: DEFAULT_SIGHT_RANGE,
+            state: MobState::default(),
+            seed: 1,
+        }
+    }
+}
+
+/// What mobs hunt, kept up to date by the game from its view.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+pub struct MobTarget {
+    pub position: Position2,
+    /// `None` when outside the map, where mobs can't see or follow it.
+    pub sector: Option<SectorId>,
+}
+
+/// Whether the portal from sector into next is open.
+fn open_portal(sectors: &[&Sector], sector: &Sector, next: SectorId) -> bool {
+    sectors
+        .iter()
+        .find(|s| s.id == next)
+        .is_some_and(|next| opening(sector, next) > 0.0)
+}
+
+/// Whether to can be seen from from, starting in sector, with nothing but open
+/// portals between them.
+pub fn line_of_sight(
+    sectors: &[&Sector],
+    sector: SectorId,
+    from: Position2,
+    to: Position2,
+) -> bool {
+    let (mut current, mut entered) = (sector, -1.0);
+    // Each sector passed through once at most
+    for _ in 0..sectors.len() {
+        let Some(sector) = sectors.iter().find(|s| s.id == current) else { return false };
+        let crossing = sector
+            .to_walls()
+            .iter()
+            .filter_map(|wall| Some((wall.portal_sector, ray_crossing(from.0, to.0, wall)?)))
+            .filter(|&(_, t)| t > entered + f32::EPSILON)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        match crossing {
+            // Reached the end without leaving the sector
+            None => return true,
+            Some((Some(next), t)) => {
+                if !open_portal(sectors, sector, next) {
+                    return false;
+                }
+                (current, entered) = (next, t);
+            }
+            Some((None, _)) => return false,
+        }
+    }
+    false
+}
+
+/// Point just past the middle of the portal from sector into next, far enough in for
+/// a body of radius to have crossed it.
+fn through_portal(sector: &Sector, next: SectorId, radius: f32) -> Option<Position2> {
+    let wall = sector
+        .to_walls()
+        .into_iter()
+        .find(|wall| wall.portal_sector == Some(next))?;
+    let middle = (wall.left.0 + wall.right.0) / 2.0;
+    let mut normal = (wall.right.0 - wall.left.0).perp().normalize_or_zero();
+    if sector.contains(Position2(middle + normal * REACHED)) {
+        normal = -normal;
+    }
+    Some(Position2(middle + normal * (radius + REACHED)))
+}
+
+/// Random point inside sector, if one is found.
+fn wander_goal(sector: &Sector, rng: &mut Rng) -> Option<Position2> {
+    let (min, max) = sector.vertices.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), v| (min.min(v.0), max.max(v.0)),
+    );
+    (0..WANDER_TRIES)
+        .map(|_| Position2(Vec2::new(rng.range(min.x, max.x), rng.range(min.y, max.y))))
+        .find(|&point| sector.contains(point))
+}
+
+/// Look for the target, then move each mob towards wherever it's heading, sliding
+/// along walls.
+pub fn mob_system(
+    target: Res<MobTarget>,
+    blockmap: Res<Blockmap>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut mob_query: Query<(&mut Mob, &mut Thing)>,
+    sector_query: Query<&Sector>,
+) {
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let delta = time_scale.delta_seconds(&time);
+
+    for (mut mob, mut thing) in &mut mob_query {
+        let Some(sector) = sectors.iter().find(|s| s.contains(thing.position)) else { continue };
+        let position = thing.position;
+        let body = BodySize {
+            radius: thing.radius.0,
+            height: thing.height.0,
+            step: STEP_HEIGHT,
+        };
+
+        let sees = target.sector.filter(|_| {
+            position.0.distance(target.position.0) <= mob.sight_range
+                && line_of_sight(&sectors, sector.id, position, target.position)
+        });
+        if let Some(target_sector) = sees {
+            mob.state = MobState::Hunting {
+                last_seen: target.position,
+                sector: target_sector,
+            };
+        }
+
+        // Where to head for next, how close to get to it and whether to look for
+        // somewhere else on getting there
+        let (heading, stop, done_on_arrival) = match mob.state {
+            MobState::Idle => {
+                let mut rng = Rng::new(mob.seed);
+                let goal = wander_goal(sector, &mut rng);
+                mob.seed = rng.0;
+                if let Some(goal) = goal {
+                    mob.state = MobState::Wandering { goal };
+                }
+                continue;
+            }
+            MobState::Wandering { goal } => (goal, 0.0, true),
+            MobState::Hunting {
+                last_seen,
+                sector: last_sector,
+            } if last_sector == sector.id => match sees {
+                Some(_) => (last_seen, thing.radius.0 + CLOSE_DISTANCE, false),
+                None => (last_seen, 0.0, true),
+            },
+            MobState::Hunting {
+                sector: last_sector,
+                ..
+            } => {
+                let next = pathfind(&sectors, sector.id, last_sector, body)
+                    .and_then(|path| through_portal(sector, path[1], thing.radius.0));
+                match next {
+                    Some(next) => (next, 0.0, false),
+                    None => {
+                        mob.state = MobState::Idle;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let offset = heading.0 - position.0;
+        let distance = offset.length() - stop;
+        if distance <= REACHED {
+            if done_on_arrival {
+                mob.state = MobState::Idle;
+            }
+            continue;
+        }
+
+        let step = (mob.speed * delta).min(distance);
+        let end = Position2(position.0 + offset.normalize() * step);
+        let sweep = slide_circle(&blockmap, &sectors, sector.id, position, end, body);
+        thing.position = sweep.position;
+
+        // Wander somewhere else rather than along the wall in the way
+        if sweep.impact.is_some() && matches!(mob.state, MobState::Wandering { .. }) {
+            mob.state = MobState::Idle;
+        }
+    }
+}
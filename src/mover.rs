@@ -0,0 +1,174 @@
+use crate::{time_scale::*, *};
+
+/// Speed movers use when not given one, in units per second.
+pub const DEFAULT_MOVER_SPEED: f32 = 2.0;
+
+/// Which plane of its sector a mover moves.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum MoverKind {
+    /// Ceiling rising from the closed height to open.
+    #[default]
+    Door,
+    /// Floor lowering from the closed height to open.
+    Lift,
+}
+
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone)]
+pub enum MoverState {
+    #[default]
+    Stopped,
+    Moving {
+        target: Length,
+        speed: f32,
+    },
+}
+
+/// Moving ceiling or floor of sector, between closed and open heights, driven by
+/// [`MoverCommand`]s. State is kept on the component so it persists with the map.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct Mover {
+    pub sector: SectorId,
+    pub kind: MoverKind,
+    pub closed: Length,
+    pub open: Length,
+    /// Units per second moved by `Open`, `Close` and `Toggle`.
+    pub speed: f32,
+    pub state: MoverState,
+}
+
+impl Default for Mover {
+    fn default() -> Self {
+        Self {
+            sector: SectorId::default(),
+            kind: MoverKind::default(),
+            closed: Length(0.0),
+            open: Length(0.0),
+            speed: DEFAULT_MOVER_SPEED,
+            state: MoverState::default(),
+        }
+    }
+}
+
+impl Mover {
+    /// Height of the plane moved in sector.
+    pub fn height(&self, sector: &Sector) -> f32 {
+        match self.kind {
+            MoverKind::Door => sector.ceil.0,
+            MoverKind::Lift => sector.floor.0,
+        }
+    }
+
+    fn set_height(&self, sector: &mut Sector, height: f32) {
+        match self.kind {
+            MoverKind::Door => sector.ceil.0 = height,
+            MoverKind::Lift => sector.floor.0 = height,
+        }
+    }
+
+    /// Whether the mover is open or on its way to being open, given the height of
+    /// its plane.
+    pub fn is_opening(&self, height: f32) -> bool {
+        match self.state {
+            MoverState::Moving { target, .. } => target.0 == self.open.0,
+            MoverState::Stopped => (height - self.open.0).abs() < (height - self.closed.0).abs(),
+        }
+    }
+}
+
+/// Action for a mover to take.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MoverAction {
+    Open,
+    Close,
+    /// Close when open or opening, otherwise open.
+    Toggle,
+    /// Move to any height at speed in units per second.
+    GotoHeight {
+        height: f32,
+        speed: f32,
+    },
+}
+
+/// Sent by scripts, triggers or the console to drive the mover of sector.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MoverCommand {
+    pub sector: SectorId,
+    pub action: MoverAction,
+}
+
+/// Sent when a mover reaches its target height.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MoverStopped {
+    pub sector: SectorId,
+    pub height: f32,
+}
+
+/// Start movers moving as commanded.
+pub fn mover_command_system(
+    mut mover_command_events: EventReader<MoverCommand>,
+    mut mover_query: Query<&mut Mover>,
+    sector_query: Query<&Sector>,
+) {
+    for command in mover_command_events.iter() {
+        let mover = mover_query
+            .iter_mut()
+            .find(|mover| mover.sector == command.sector);
+        let sector = sector_query.iter().find(|s| s.id == command.sector);
+        let (mut mover, sector) = match (mover, sector) {
+            (Some(mover), Some(sector)) => (mover, sector),
+            _ => {
+                warn!("no mover in sector {}", command.sector.0);
+                continue;
+            }
+        };
+
+        let (target, speed) = match command.action {
+            MoverAction::Open => (mover.open, mover.speed),
+            MoverAction::Close => (mover.closed, mover.speed),
+            MoverAction::Toggle => {
+                if mover.is_opening(mover.height(sector)) {
+                    (mover.closed, mover.speed)
+                } else {
+                    (mover.open, mover.speed)
+                }
+            }
+            MoverAction::GotoHeight { height, speed } => (Length(height), speed),
+        };
+        mover.state = MoverState::Moving {
+            target,
+            speed: speed.abs(),
+        };
+    }
+}
+
+/// Move the planes of moving movers towards their targets.
+pub fn mover_system(
+    mut mover_query: Query<&mut Mover>,
+    mut sector_query: Query<&mut Sector>,
+    mut mover_stopped_events: EventWriter<MoverStopped>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+) {
+    let delta = time_scale.delta_seconds(&time);
+
+    for mut mover in &mut mover_query {
+        let MoverState::Moving { target, speed } = mover.state else { continue };
+        let sector = sector_query.iter_mut().find(|s| s.id == mover.sector);
+        let Some(mut sector) = sector else { continue };
+
+        let height = mover.height(&sector);
+        let step = speed * delta;
+        let remaining = target.0 - height;
+        if remaining.abs() <= step {
+            mover.set_height(&mut sector, target.0);
+            mover.state = MoverState::Stopped;
+            mover_stopped_events.send(MoverStopped {
+                sector: mover.sector,
+                height: target.0,
+            });
+        } else if step > 0.0 {
+            mover.set_height(&mut sector, height + step * remaining.signum());
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::*;
+
+#[cfg(feature = "audio")]
+mod mixer;
+
+#[cfg(feature = "audio")]
+pub use mixer::*;
+
+/// Crossfade time used by zones that don't set one, in seconds.
+pub const DEFAULT_FADE: f32 = 2.0;
+
+/// Sound playing at a point, panned and pitched for the listener by
+/// `sound_emitter_system`. An emitter on the same entity as a [`mover::Mover`] rides
+/// its moving plane, so lifts and doors are heard where they are. Only heard with the
+/// `audio` feature, but registered without it so maps with one still load.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct SoundEmitter {
+    pub sound: String,
+    pub position: Position2,
+    pub height: Length,
+}
+
+/// Independent channel of looping sound, each playing one track at a time.
+#[derive(Reflect, FromReflect, Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SoundLayer {
+    #[default]
+    Music,
+    Ambience,
+}
+
+/// Track to play on a layer while the view is in sector, or anywhere in the map when
+/// sector is `None`. Of the zones applying, the one with highest priority plays, a
+/// sector zone winning over a map wide one of equal priority. Leaving a zone falls
+/// back to whichever applies next. Only played with the `audio` feature, like
+/// [`SoundEmitter`].
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct SoundZone {
+    pub sector: Option<SectorId>,
+    pub layer: SoundLayer,
+    pub track: String,
+    pub priority: i32,
+    /// Seconds to crossfade over when this zone takes over.
+    pub fade: f32,
+}
+
+impl Default for SoundZone {
+    fn default() -> Self {
+        Self {
+            sector: None,
+            layer: SoundLayer::default(),
+            track: String::new(),
+            priority: 0,
+            fade: DEFAULT_FADE,
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use super::*;
+use crate::mover::*;
+
+use bevy::{math::vec2, utils::HashMap};
+use std::collections::VecDeque;
+
+/// Mixes the sound zones and emitters of maps into what the game should play.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundState>()
+            .init_resource::<AudioSettings>()
+            .init_resource::<SoundListener>()
+            .add_event::<SoundTrackChanged>()
+            .add_system(sound_zone_system.in_base_set(CoreSet::PostUpdate))
+            .add_system(sound_emitter_system.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+/// Tuning of how positional sound is heard.
+#[derive(Resource, Debug, Clone)]
+pub struct AudioSettings {
+    /// Pan emitters by where they are relative to the listener's facing.
+    pub stereo: bool,
+    /// Pitch emitters moving towards or away from the listener up or down.
+    pub doppler: bool,
+    /// Scale of the doppler shift, 1 for a physical one.
+    pub doppler_factor: f32,
+    /// Units per second.
+    pub speed_of_sound: f32,
+    /// Distance emitters fade to silence over.
+    pub max_distance: f32,
+    /// Gain kept by sound through each portal between an emitter's sector and the
+    /// listener's. Emitters in sectors not joined to the listener's are silent.
+    pub portal_attenuation: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            stereo: true,
+            doppler: false,
+            doppler_factor: 1.0,
+            speed_of_sound: 343.0,
+            max_distance: 32.0,
+            portal_attenuation: 0.5,
+        }
+    }
+}
+
+/// Where sound is heard from, kept up to date by the game from its view.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+pub struct SoundListener {
+    pub position: Position2,
+    pub height: Length,
+    pub direction: Direction,
+    pub sector: Option<SectorId>,
+}
+
+/// How an emitter is heard, for audio playback to follow.
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct EmitterMix {
+    /// From -1 fully left to 1 fully right.
+    pub pan: f32,
+    /// From 0 silent to 1 at the listener.
+    pub gain: f32,
+    /// Playback rate, 1 when neither emitter nor listener move.
+    pub pitch: f32,
+}
+
+impl EmitterMix {
+    /// Mix of emitter at position heard by listener, each moving at velocity in units
+    /// per second.
+    pub fn new(
+        settings: &AudioSettings,
+        listener: &SoundListener,
+        listener_velocity: Vec3,
+        position: Vec3,
+        velocity: Vec3,
+    ) -> Self {
+        let offset = position - listener.position.0.extend(listener.height.0);
+        let distance = offset.length();
+        let gain = if settings.max_distance > 0.0 {
+            (1.0 - distance / settings.max_distance).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let (sin, cos) = listener.direction.0.sin_cos();
+        let right = vec2(cos, sin);
+        let pan = if settings.stereo {
+            offset.truncate().normalize_or_zero().dot(right)
+        } else {
+            0.0
+        };
+
+        let pitch = if settings.doppler && distance > 0.0 && settings.speed_of_sound > 0.0 {
+            // Speeds of each towards the other, kept below the speed of sound
+            let towards = offset / distance;
+            let limit = settings.speed_of_sound * 0.5;
+            let listener_speed =
+                (listener_velocity.dot(towards) * settings.doppler_factor).clamp(-limit, limit);
+            let emitter_speed =
+                (-velocity.dot(towards) * settings.doppler_factor).clamp(-limit, limit);
+            (settings.speed_of_sound + listener_speed) / (settings.speed_of_sound - emitter_speed)
+        } else {
+            1.0
+        };
+
+        Self { pan, gain, pitch }
+    }
+}
+
+/// Sent when the track a layer is fading to changes, `None` for silence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundTrackChanged {
+    pub layer: SoundLayer,
+    pub track: Option<String>,
+}
+
+/// Track playing on a layer at a volume from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerTrack {
+    pub track: String,
+    pub volume: f32,
+}
+
+/// Tracks of a layer with their crossfade volumes.
+#[derive(Debug, Default)]
+pub struct LayerState {
+    target: Option<String>,
+    fade: f32,
+    tracks: Vec<LayerTrack>,
+}
+
+impl LayerState {
+    /// Track being faded to, `None` when fading to silence.
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Tracks audible or fading in.
+    pub fn tracks(&self) -> &[LayerTrack] {
+        &self.tracks
+    }
+
+    /// Start fading to track over fade seconds, fading out everything else. A track
+    /// still fading out fades back in from where it was.
+    fn fade_to(&mut self, track: Option<&str>, fade: f32) {
+        self.target = track.map(str::to_string);
+        self.fade = fade;
+        if let Some(track) = track {
+            if !self.tracks.iter().any(|t| t.track == track) {
+                self.tracks.push(LayerTrack {
+                    track: track.to_string(),
+                    volume: 0.0,
+                });
+            }
+        }
+    }
+
+    fn advance(&mut self, delta: f32) {
+        let step = if self.fade > 0.0 {
+            delta / self.fade
+        } else {
+            1.0
+        };
+        for track in &mut self.tracks {
+            if Some(&track.track) == self.target.as_ref() {
+                track.volume = (track.volume + step).min(1.0);
+            } else {
+                track.volume = (track.volume - step).max(0.0);
+            }
+        }
+
+        let target = &self.target;
+        self.tracks
+            .retain(|t| t.volume > 0.0 || Some(&t.track) == target.as_ref());
+    }
+}
+
+/// State of each layer, for audio playback to follow.
+#[derive(Resource, Debug, Default)]
+pub struct SoundState {
+    current_sector: Option<SectorId>,
+    music: LayerState,
+    ambience: LayerState,
+}
+
+impl SoundState {
+    pub fn layer(&self, layer: SoundLayer) -> &LayerState {
+        match layer {
+            SoundLayer::Music => &self.music,
+            SoundLayer::Ambience => &self.ambience,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: SoundLayer) -> &mut LayerState {
+        match layer {
+            SoundLayer::Music => &mut self.music,
+            SoundLayer::Ambience => &mut self.ambience,
+        }
+    }
+}
+
+/// Zone that applies in sector on layer, the highest priority one.
+fn active_zone<'a>(
+    zones: impl Iterator<Item = &'a SoundZone>,
+    layer: SoundLayer,
+    sector: Option<SectorId>,
+) -> Option<&'a SoundZone> {
+    zones
+        .filter(|zone| zone.layer == layer)
+        .filter(|zone| zone.sector.is_none() || zone.sector == sector)
+        .max_by_key(|zone| (zone.priority, zone.sector.is_some()))
+}
+
+/// Follow the sector entered to pick each layer's zone, crossfading between tracks
+/// when it changes.
+pub fn sound_zone_system(
+    mut sound_state: ResMut<SoundState>,
+    mut sector_entered_events: EventReader<SectorEntered>,
+    mut sound_track_changed_events: EventWriter<SoundTrackChanged>,
+    time: Res<Time>,
+    zone_query: Query<&SoundZone>,
+) {
+    if let Some(sector_entered) = sector_entered_events.iter().last() {
+        sound_state.current_sector = Some(sector_entered.to);
+    }
+
+    for layer in [SoundLayer::Music, SoundLayer::Ambience] {
+        let zone = active_zone(zone_query.iter(), layer, sound_state.current_sector);
+        let track = zone.map(|zone| zone.track.as_str());
+
+        if track != sound_state.layer(layer).target() {
+            let fade = zone.map_or(DEFAULT_FADE, |zone| zone.fade);
+            info!("{layer:?}: fading to {}", track.unwrap_or("silence"));
+            sound_state.layer_mut(layer).fade_to(track, fade);
+            sound_track_changed_events.send(SoundTrackChanged {
+                layer,
+                track: track.map(str::to_string),
+            });
+        }
+
+        sound_state.layer_mut(layer).advance(time.delta_seconds());
+    }
+}
+
+/// Fewest portals passed through from sector `from` to each sector reachable from it.
+fn portal_hops(sectors: &[&Sector], from: SectorId) -> HashMap<SectorId, i32> {
+    let mut hops = HashMap::from([(from, 0)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(id) = queue.pop_front() {
+        let Some(sector) = sectors.iter().find(|s| s.id == id) else { continue };
+        let next_hops = hops[&id] + 1;
+        for &next in sector.portal_sectors.iter().flatten() {
+            if !hops.contains_key(&next) {
+                hops.insert(next, next_hops);
+                queue.push_back(next);
+            }
+        }
+    }
+    hops
+}
+
+/// Mix every emitter for the listener, from their positions and how fast they and
+/// the listener moved since the last frame, quietened by the portals between them.
+#[allow(clippy::too_many_arguments)]
+pub fn sound_emitter_system(
+    mut commands: Commands,
+    mut last_positions: Local<HashMap<Entity, Vec3>>,
+    mut last_listener: Local<Option<Vec3>>,
+    settings: Res<AudioSettings>,
+    listener: Res<SoundListener>,
+    time: Res<Time>,
+    mut emitter_query: Query<(
+        Entity,
+        &SoundEmitter,
+        Option<&Mover>,
+        Option<&mut EmitterMix>,
+    )>,
+    sector_query: Query<&Sector>,
+) {
+    let delta = time.delta_seconds();
+    let velocity = |from: Option<Vec3>, to: Vec3| match from {
+        Some(from) if delta > 0.0 => (to - from) / delta,
+        _ => Vec3::ZERO,
+    };
+
+    let listener_position = listener.position.0.extend(listener.height.0);
+    let listener_velocity = velocity(*last_listener, listener_position);
+    *last_listener = Some(listener_position);
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let hops = listener.sector.map(|sector| portal_hops(&sectors, sector));
+
+    let mut positions = HashMap::new();
+    for (entity, emitter, mover, mix) in &mut emitter_query {
+        let height = mover
+            .and_then(|mover| {
+                let sector = sector_query.iter().find(|s| s.id == mover.sector)?;
+                Some(mover.height(sector))
+            })
+            .unwrap_or(emitter.height.0);
+        let position = emitter.position.0.extend(height);
+        let mut new_mix = EmitterMix::new(
+            &settings,
+            &listener,
+            listener_velocity,
+            position,
+            velocity(last_positions.get(&entity).copied(), position),
+        );
+
+        // Unattenuated when either end is outside the map
+        let sector = mover.map(|mover| mover.sector).or_else(|| {
+            sectors
+                .iter()
+                .find(|s| s.contains(emitter.position))
+                .map(|s| s.id)
+        });
+        if let (Some(hops), Some(sector)) = (&hops, sector) {
+            new_mix.gain *= hops
+                .get(&sector)
+                .map_or(0.0, |&hops| settings.portal_attenuation.powi(hops));
+        }
+        positions.insert(entity, position);
+
+        match mix {
+            Some(mut mix) => *mix = new_mix,
+            None => {
+                commands.entity(entity).insert(new_mix);
+            }
+        }
+    }
+    // Forget emitters since despawned
+    *last_positions = positions;
+}
@@ -0,0 +1,140 @@
+use crate::{physics::*, *};
+
+use bevy::utils::HashMap;
+use std::collections::VecDeque;
+
+/// Sectors passed through on the fewest portals from sector from to sector to,
+/// including both, `None` when to can't be reached. Only portals body can pass
+/// through are followed, see [`passable`].
+pub fn pathfind(
+    sectors: &[&Sector],
+    from: SectorId,
+    to: SectorId,
+    body: BodySize,
+) -> Option<Vec<SectorId>> {
+    let previous = search(sectors, from, Some(to), body);
+    previous.get(&to)?;
+
+    let mut path = vec![to];
+    while let Some(&last) = path.last().filter(|&&last| last != from) {
+        path.push(previous[&last]);
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Middle of the portal wall crossed between each sector of path and the next, for
+/// following it.
+pub fn waypoints(sectors: &[&Sector], path: &[SectorId]) -> Vec<Position2> {
+    path.windows(2)
+        .filter_map(|pair| {
+            let sector = sectors.iter().find(|s| s.id == pair[0])?;
+            let wall = sector
+                .to_walls()
+                .into_iter()
+                .find(|wall| wall.portal_sector == Some(pair[1]))?;
+            Some(Position2((wall.left.0 + wall.right.0) / 2.0))
+        })
+        .collect()
+}
+
+/// Sectors body can reach from sector from, including it.
+pub fn reachable(sectors: &[&Sector], from: SectorId, body: BodySize) -> Vec<SectorId> {
+    search(sectors, from, None, body).into_keys().collect()
+}
+
+/// Breadth-first search from sector from, until sector to is found if given, of the
+/// sector each sector reached was first entered from.
+fn search(
+    sectors: &[&Sector],
+    from: SectorId,
+    to: Option<SectorId>,
+    body: BodySize,
+) -> HashMap<SectorId, SectorId> {
+    let mut previous = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(id) = queue.pop_front() {
+        if Some(id) == to {
+            break;
+        }
+        let Some(sector) = sectors.iter().find(|s| s.id == id) else { continue };
+        for &next in sector.portal_sectors.iter().flatten() {
+            let open = sectors
+                .iter()
+                .find(|s| s.id == next)
+                .is_some_and(|next| passable(sector, next, body));
+            if open && !previous.contains_key(&next) {
+                previous.insert(next, id);
+                queue.push_back(next);
+            }
+        }
+    }
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    const BODY: BodySize = BodySize {
+        radius: 0.1,
+        height: 1.0,
+        step: 0.5,
+    };
+
+    /// Three rooms in a row, each linked to the next, with the floor of the last at
+    /// last_floor.
+    fn row(last_floor: f32) -> [Sector; 3] {
+        let (a, b) = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.0, 4.0));
+        let (b, c) = linked(b, square(2, 2.0, last_floor, 4.0));
+        [a, b, c]
+    }
+
+    fn refs(sectors: &[Sector]) -> Vec<&Sector> {
+        sectors.iter().collect()
+    }
+
+    #[test]
+    fn path_through_portals() {
+        let sectors = row(0.0);
+        let sectors = refs(&sectors);
+        let path = pathfind(&sectors, SectorId(0), SectorId(2), BODY).unwrap();
+        assert_eq!(path, [SectorId(0), SectorId(1), SectorId(2)]);
+
+        let waypoints: Vec<Vec2> = waypoints(&sectors, &path).iter().map(|p| p.0).collect();
+        assert_eq!(waypoints, [Vec2::new(1.0, 0.5), Vec2::new(2.0, 0.5)]);
+    }
+
+    #[test]
+    fn unreachable_target() {
+        let (a, b) = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.0, 4.0));
+        let sectors = [a, b, square(2, 4.0, 0.0, 4.0)];
+        let sectors = refs(&sectors);
+        assert_eq!(pathfind(&sectors, SectorId(0), SectorId(2), BODY), None);
+
+        let mut reached = reachable(&sectors, SectorId(0), BODY);
+        reached.sort_by_key(|id| id.0);
+        assert_eq!(reached, [SectorId(0), SectorId(1)]);
+    }
+
+    #[test]
+    fn start_is_the_goal() {
+        let sectors = row(0.0);
+        let sectors = refs(&sectors);
+        let path = pathfind(&sectors, SectorId(1), SectorId(1), BODY).unwrap();
+        assert_eq!(path, [SectorId(1)]);
+        assert!(waypoints(&sectors, &path).is_empty());
+    }
+
+    #[test]
+    fn step_too_high() {
+        let sectors = row(BODY.step + 0.25);
+        let sectors = refs(&sectors);
+        assert_eq!(pathfind(&sectors, SectorId(0), SectorId(2), BODY), None);
+
+        // Stepping down is fine
+        let path = pathfind(&sectors, SectorId(2), SectorId(0), BODY).unwrap();
+        assert_eq!(path, [SectorId(2), SectorId(1), SectorId(0)]);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::*;
+
+use bevy::{
+    ecs::system::SystemState,
+    reflect::{TypeRegistryArc, TypeRegistryInternal},
+    scene::{
+        serde::{SceneDeserializer, SceneSerializer},
+        DynamicEntity, DynamicSceneBuilder,
+    },
+    utils::{HashMap, HashSet},
+};
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, Visitor},
+    ser::{SerializeMap, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt;
+
+type AddedQuery = Query<'static, 'static, (Entity, &'static StableId), Added<StableId>>;
+
+/// Marks an entity from a map file as removed since the map was loaded, a
+/// collected pickup or a killed actor.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component)]
+pub struct Despawned;
+
+/// Where the player was in a saved game, restored along with the state of every map
+/// visited.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SavedPlayer {
+    pub map: String,
+    /// Maps visited in the hub, in order of first visit.
+    pub hub_maps: Vec<String>,
+    pub position: [f32; 3],
+    pub direction: f32,
+    pub pitch: f32,
+    /// Id of the sector the player was in.
+    pub sector: Option<u32>,
+}
+
+/// Dynamic state of persistent entities, those with a [`StableId`], for every map
+/// visited. State is captured on leaving a map and restored as the map's entities
+/// spawn again.
+#[derive(Resource, Default)]
+pub struct Persistence {
+    current_map: Option<String>,
+    /// Stable ids spawned in the current map, to tell which have since been removed.
+    seen: HashSet<StableId>,
+    /// Persistent entities of each map, keyed by stable id rather than entity.
+    maps: HashMap<String, DynamicScene>,
+}
+
+impl Persistence {
+    pub fn current_map(&self) -> Option<&str> {
+        self.current_map.as_deref()
+    }
+
+    /// Switch to map, capture the map being left with `capture_map` first.
+    pub fn enter_map(&mut self, map: &str) {
+        self.current_map = Some(map.to_string());
+        self.seen.clear();
+    }
+
+    /// Forget state of all maps, when moving on to a new hub.
+    pub fn forget_maps(&mut self) {
+        self.maps.clear();
+    }
+
+    /// Forget state of map, so it's entered as saved.
+    pub fn forget_map(&mut self, map: &str) {
+        self.maps.remove(map);
+    }
+
+    pub fn is_visited(&self, map: &str) -> bool {
+        self.maps.contains_key(map)
+    }
+
+    /// Serialize state of all visited maps to RON.
+    pub fn save(&self, registry: &TypeRegistryArc) -> Result<String, ron::Error> {
+        let maps = MapsSerializer {
+            maps: &self.maps,
+            registry,
+        };
+        ron::ser::to_string_pretty(&maps, ron::ser::PrettyConfig::default())
+    }
+
+    /// Serialize player with state of all visited maps to RON, for a saved game.
+    /// Capture the current map with `capture_map` first.
+    pub fn save_game(
+        &self,
+        registry: &TypeRegistryArc,
+        player: &SavedPlayer,
+    ) -> Result<String, ron::Error> {
+        let game = GameSerializer {
+            player,
+            maps: MapsSerializer {
+                maps: &self.maps,
+                registry,
+            },
+        };
+        ron::ser::to_string_pretty(&game, ron::ser::PrettyConfig::default())
+    }
+
+    /// Replace state of all maps with that of a saved game written by `save_game`,
+    /// returning where the player was. Travel to the player's map with
+    /// `WorldState::resume` to restore it.
+    pub fn load_game(
+        &mut self,
+        registry: &TypeRegistryInternal,
+        ron: &str,
+    ) -> Result<SavedPlayer, ron::Error> {
+        let mut deserializer = ron::de::Deserializer::from_str(ron).map_err(|e| e.code)?;
+        let (player, maps) = GameDeserializer { registry }.deserialize(&mut deserializer)?;
+        self.maps = maps;
+        Ok(player)
+    }
+
+    /// Replace state of all maps with that from RON written by `save`. Use
+    /// `restore_map` to apply it to the current map.
+    pub fn load(&mut self, registry: &TypeRegistryInternal, ron: &str) -> Result<(), ron::Error> {
+        let mut deserializer = ron::de::Deserializer::from_str(ron).map_err(|e| e.code)?;
+        self.maps = MapsDeserializer { registry }.deserialize(&mut deserializer)?;
+        Ok(())
+    }
+}
+
+/// Capture state of persistent entities in the current map.
+pub fn capture_map(world: &mut World) {
+    let mut query = world.query::<(Entity, &StableId)>();
+    let present: Vec<(Entity, StableId)> = query.iter(world).map(|(e, id)| (e, *id)).collect();
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let mut builder = DynamicSceneBuilder::from_world_with_type_registry(world, registry);
+    builder.extract_entities(present.iter().map(|(entity, _)| *entity));
+    let mut scene = builder.build();
+
+    for dynamic_entity in &mut scene.entities {
+        if let Some((_, id)) = present
+            .iter()
+            .find(|(entity, _)| entity.index() == dynamic_entity.entity)
+        {
+            dynamic_entity.entity = id.0;
+        }
+    }
+
+    let mut persistence = world.resource_mut::<Persistence>();
+    let Some(map) = persistence.current_map.clone() else { return };
+
+    for id in &persistence.seen {
+        if !present.iter().any(|(_, present_id)| present_id == id) {
+            scene.entities.push(DynamicEntity {
+                entity: id.0,
+                components: vec![Box::new(Despawned)],
+            });
+        }
+    }
+
+    persistence.maps.insert(map, scene);
+}
+
+/// Apply captured state to every persistent entity in the current map.
+pub fn restore_map(world: &mut World) {
+    let mut query = world.query::<(Entity, &StableId)>();
+    let entities: Vec<(Entity, StableId)> = query.iter(world).map(|(e, id)| (e, *id)).collect();
+    restore(world, &entities);
+}
+
+/// Apply captured state to persistent entities as they spawn.
+pub fn restore_persistent_system(
+    world: &mut World,
+    mut added_state: Local<SystemState<AddedQuery>>,
+) {
+    let added: Vec<(Entity, StableId)> = added_state
+        .get(world)
+        .iter()
+        .map(|(e, id)| (e, *id))
+        .collect();
+    if !added.is_empty() {
+        restore(world, &added);
+    }
+}
+
+fn restore(world: &mut World, entities: &[(Entity, StableId)]) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, mut persistence: Mut<Persistence>| {
+        persistence.seen.extend(entities.iter().map(|(_, id)| *id));
+
+        let Some(map) = &persistence.current_map else { return };
+        let Some(scene) = persistence.maps.get(map) else { return };
+
+        for &(entity, id) in entities {
+            let Some(saved) = scene.entities.iter().find(|e| e.entity == id.0) else { continue };
+
+            let despawned = std::any::type_name::<Despawned>();
+            if saved.components.iter().any(|c| c.type_name() == despawned) {
+                world.despawn(entity);
+                continue;
+            }
+
+            let mut entity_mut = world.entity_mut(entity);
+            for component in &saved.components {
+                let reflect_component = registry
+                    .get_with_name(component.type_name())
+                    .and_then(|registration| registration.data::<ReflectComponent>());
+                let Some(reflect_component) = reflect_component else {
+                    warn!(
+                        "cannot restore unregistered component `{}`",
+                        component.type_name()
+                    );
+                    continue;
+                };
+                reflect_component.apply_or_insert(&mut entity_mut, &**component);
+            }
+        }
+    });
+}
+
+struct MapsSerializer<'a> {
+    maps: &'a HashMap<String, DynamicScene>,
+    registry: &'a TypeRegistryArc,
+}
+
+impl<'a> Serialize for MapsSerializer<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_map(Some(self.maps.len()))?;
+        for (map, scene) in self.maps {
+            state.serialize_entry(map, &SceneSerializer::new(scene, self.registry))?;
+        }
+        state.end()
+    }
+}
+
+struct MapsDeserializer<'a> {
+    registry: &'a TypeRegistryInternal,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for MapsDeserializer<'a> {
+    type Value = HashMap<String, DynamicScene>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for MapsDeserializer<'a> {
+    type Value = HashMap<String, DynamicScene>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("map of scenes")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut maps = HashMap::default();
+        while let Some(map) = access.next_key::<String>()? {
+            let scene = access.next_value_seed(SceneDeserializer {
+                type_registry: self.registry,
+            })?;
+            maps.insert(map, scene);
+        }
+        Ok(maps)
+    }
+}
+
+struct GameSerializer<'a> {
+    player: &'a SavedPlayer,
+    maps: MapsSerializer<'a>,
+}
+
+impl<'a> Serialize for GameSerializer<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SavedGame", 2)?;
+        state.serialize_field("player", self.player)?;
+        state.serialize_field("maps", &self.maps)?;
+        state.end()
+    }
+}
+
+struct GameDeserializer<'a> {
+    registry: &'a TypeRegistryInternal,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for GameDeserializer<'a> {
+    type Value = (SavedPlayer, HashMap<String, DynamicScene>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("SavedGame", &["player", "maps"], self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for GameDeserializer<'a> {
+    type Value = (SavedPlayer, HashMap<String, DynamicScene>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("saved game")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "lowercase")]
+        enum Field {
+            Player,
+            Maps,
+        }
+
+        let (mut player, mut maps) = (None, None);
+        while let Some(key) = access.next_key::<Field>()? {
+            match key {
+                Field::Player => player = Some(access.next_value()?),
+                Field::Maps => {
+                    maps = Some(access.next_value_seed(MapsDeserializer {
+                        registry: self.registry,
+                    })?)
+                }
+            }
+        }
+        Ok((
+            player.ok_or_else(|| de::Error::missing_field("player"))?,
+            maps.ok_or_else(|| de::Error::missing_field("maps"))?,
+        ))
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{blockmap::*, time_scale::*, *};
+
+use std::collections::VecDeque;
+
+/// Downward acceleration of falling bodies, and the player walking, in units per
+/// second squared.
+pub const GRAVITY: f32 = 20.0;
+/// Distance kept between a body and the wall it hit, to stop it resting inside.
+const SKIN: f32 = 0.001;
+/// Most walls `slide_circle` will slide along in a single move.
+const SLIDE_ITERATIONS: usize = 3;
+/// Speed below which a body landing on the floor stops rather than bouncing.
+const REST_SPEED: f32 = 0.5;
+/// Highest a floor can rise from one sector to the next for a body on the floor to
+/// step up onto it.
+pub const STEP_HEIGHT: f32 = 0.5;
+
+/// Units per second a body with a [`Collider`] is moving at.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct BodyVelocity(pub Vec3);
+
+/// Upright cylinder a body moved by [`body_system`] collides as, with its base at
+/// the body's [`Position3`].
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Collider {
+    pub radius: f32,
+    pub height: f32,
+    /// Highest floor the body steps up onto from the floor it's on.
+    pub step: f32,
+    /// Fraction of speed kept bouncing off walls, floors and ceilings. Zero stops dead
+    /// against floors and ceilings and slides along walls.
+    pub bounce: f32,
+    /// Fraction of speed along the floor lost each second while on it.
+    pub friction: f32,
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self {
+            radius: 0.25,
+            height: 0.5,
+            step: STEP_HEIGHT,
+            bounce: 0.0,
+            friction: 4.0,
+        }
+    }
+}
+
+/// Upright cylinder moved through sectors by [`sweep_circle`] and [`slide_circle`],
+/// and how much higher a floor it can step up onto.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BodySize {
+    pub radius: f32,
+    pub height: f32,
+    pub step: f32,
+}
+
+impl BodySize {
+    /// The player walking, as tall as its eyes.
+    pub const PLAYER: Self = Self {
+        radius: 0.25,
+        height: EYE_HEIGHT,
+        step: STEP_HEIGHT,
+    };
+}
+
+/// First contact of a swept circle with a wall.
+#[derive(Debug, Copy, Clone)]
+pub struct Impact {
+    /// Fraction of the sweep travelled before contact, from 0 to 1.
+    pub time: f32,
+    /// Centre of the circle at contact.
+    pub position: Position2,
+    /// Unit normal of the contact, pointing back towards the circle.
+    pub normal: Vec2,
+    /// Sector owning the wall hit.
+    pub sector: SectorId,
+    pub wall: usize,
+}
+
+/// Outcome of moving a circle through sectors.
+#[derive(Debug, Copy, Clone)]
+pub struct Sweep {
+    /// Where the circle stopped.
+    pub position: Position2,
+    /// Sector containing the circle where it stopped.
+    pub sector: SectorId,
+    /// First wall hit on the way, if any.
+    pub impact: Option<Impact>,
+}
+
+/// Height of the gap a portal between two sectors leaves open, negative when their
+/// floors and ceilings don't overlap.
+pub fn opening(from: &Sector, to: &Sector) -> f32 {
+    from.ceil.0.min(to.ceil.0) - from.floor.0.max(to.floor.0)
+}
+
+/// Whether body can pass through a portal from one sector to another, fitting through
+/// the opening and stepping up onto the floor beyond.
+pub fn passable(from: &Sector, to: &Sector, body: BodySize) -> bool {
+    opening(from, to) >= body.height && to.floor.0 - from.floor.0 <= body.step
+}
+
+/// Sweep a circle in a straight line from start to end, starting in sector, and find
+/// the first solid wall it touches. Portals are followed into neighbouring sectors
+/// the circle reaches, portals body can't pass through are treated as solid. Only
+/// walls near the sweep in blockmap are considered.
+pub fn sweep_circle(
+    blockmap: &Blockmap,
+    sectors: &[&Sector],
+    sector: SectorId,
+    start: Position2,
+    end: Position2,
+    body: BodySize,
+) -> Sweep {
+    let find = |id: SectorId| sectors.iter().copied().find(|s| s.id == id);
+    let Some(start_sector) = find(sector) else {
+        return Sweep {
+            position: end,
+            sector,
+            impact: None,
+        };
+    };
+
+    let (start, end) = (start.0, end.0);
+    let delta = end - start;
+
+    let nearby = blockmap.walls_along(start, end, body.radius);
+
+    let mut impact: Option<Impact> = None;
+    let mut visited = vec![start_sector.id];
+    let mut queue = VecDeque::from([start_sector]);
+
+    while let Some(current) = queue.pop_front() {
+        for wall in nearby.iter().filter(|w| w.sector == current.id) {
+            let (a, b) = (wall.left, wall.right);
+
+            // Follow portals the circle comes within reach of
+            if let Some(next) = wall.portal_sector.and_then(find) {
+                if passable(current, next, body) {
+                    if !visited.contains(&next.id)
+                        && segment_distance(start, end, a, b) <= body.radius
+                    {
+                        visited.push(next.id);
+                        queue.push_back(next);
+                    }
+                    continue;
+                }
+            }
+
+            let Some((time, normal)) = sweep_segment(start, delta, body.radius, a, b) else { continue };
+            if !impact.map(|impact| impact.time <= time).unwrap_or(false) {
+                impact = Some(Impact {
+                    time,
+                    position: Position2(start + delta * time + normal * SKIN),
+                    normal,
+                    sector: current.id,
+                    wall: wall.wall,
+                });
+            }
+        }
+    }
+
+    let position = impact.map_or(Position2(end), |impact| impact.position);
+
+    // Visited in order from the starting sector, so it's kept when on a shared edge
+    let sector = visited
+        .iter()
+        .copied()
+        .find(|&id| sectors.iter().any(|s| s.id == id && s.contains(position)))
+        .unwrap_or(start_sector.id);
+
+    Sweep {
+        position,
+        sector,
+        impact,
+    }
+}
+
+/// Move a circle from start towards end, sliding along any walls hit rather than
+/// stopping dead.
+pub fn slide_circle(
+    blockmap: &Blockmap,
+    sectors: &[&Sector],
+    sector: SectorId,
+    start: Position2,
+    end: Position2,
+    body: BodySize,
+) -> Sweep {
+    let mut sweep = sweep_circle(blockmap, sectors, sector, start, end, body);
+    let mut end = end.0;
+
+    for _ in 1..SLIDE_ITERATIONS {
+        let Some(impact) = sweep.impact else { break };
+
+        // Remove the part of the remaining move heading into the wall
+        let remaining = end - impact.position.0;
+        let slide = remaining - impact.normal * remaining.dot(impact.normal);
+        if slide.length_squared() < SKIN * SKIN {
+            break;
+        }
+        end = impact.position.0 + slide;
+
+        let next = sweep_circle(
+            blockmap,
+            sectors,
+            sweep.sector,
+            sweep.position,
+            Position2(end),
+            body,
+        );
+        sweep = Sweep {
+            impact: next.impact.or(sweep.impact),
+            ..next
+        };
+        if next.impact.is_none() {
+            break;
+        }
+    }
+
+    sweep
+}
+
+/// Move bodies by their velocity, falling under gravity, sliding or bouncing off
+/// walls and landing on the floor of the sector they're over. Bodies with a
+/// [`Thing`] carry it along. Bodies outside every sector stay put.
+pub fn body_system(
+    blockmap: Res<Blockmap>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut body_query: Query<(
+        &mut Position3,
+        &mut BodyVelocity,
+        &Collider,
+        Option<&mut Thing>,
+    )>,
+    sector_query: Query<&Sector>,
+) {
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let delta = time_scale.delta_seconds(&time);
+    if delta == 0.0 {
+        return;
+    }
+
+    for (mut position, mut velocity, collider, thing) in &mut body_query {
+        let start = position.truncate();
+        let Some(sector) = sectors.iter().find(|s| s.contains(start)) else { continue };
+
+        // Across, along walls hit. Up off the floor, bodies clear ledges below them
+        let body = BodySize {
+            radius: collider.radius,
+            height: collider.height,
+            step: collider.step + (position.0.z - sector.floor.0).max(0.0),
+        };
+        let end = Position2(start.0 + velocity.0.truncate() * delta);
+        let sweep = slide_circle(&blockmap, &sectors, sector.id, start, end, body);
+        if let Some(impact) = sweep.impact {
+            let normal = impact.normal.extend(0.0);
+            let into = velocity.0.dot(normal);
+            if into < 0.0 {
+                velocity.0 -= normal * into * (1.0 + collider.bounce);
+            }
+        }
+        let sector = sectors
+            .iter()
+            .find(|s| s.id == sweep.sector)
+            .unwrap_or(sector);
+
+        // Up and down, between the floor and ceiling
+        velocity.0.z -= GRAVITY * delta;
+        let mut z = position.0.z + velocity.0.z * delta;
+        if z + collider.height > sector.ceil.0 {
+            z = sector.ceil.0 - collider.height;
+            if velocity.0.z > 0.0 {
+                velocity.0.z *= -collider.bounce;
+            }
+        }
+        if z <= sector.floor.0 {
+            z = sector.floor.0;
+            if velocity.0.z < 0.0 {
+                velocity.0.z *= -collider.bounce;
+            }
+            if velocity.0.z < REST_SPEED {
+                velocity.0.z = 0.0;
+                let kept = (1.0 - collider.friction * delta).max(0.0);
+                velocity.0.x *= kept;
+                velocity.0.y *= kept;
+            }
+        }
+
+        position.0 = sweep.position.0.extend(z);
+        if let Some(mut thing) = thing {
+            thing.position = sweep.position;
+        }
+    }
+}
+
+/// Time and normal of first contact between a circle moving from start by delta and
+/// segment a-b. Only contacts with the circle moving towards the segment count.
+fn sweep_segment(start: Vec2, delta: Vec2, radius: f32, a: Vec2, b: Vec2) -> Option<(f32, Vec2)> {
+    let mut first: Option<(f32, Vec2)> = None;
+    let mut consider = |time: f32, normal: Vec2| {
+        if (0.0..=1.0).contains(&time) && !first.map(|(t, _)| t <= time).unwrap_or(false) {
+            first = Some((time, normal));
+        }
+    };
+
+    // Face of the segment, from whichever side the circle starts on
+    let edge = b - a;
+    let mut normal = edge.perp().normalize_or_zero();
+    if normal.dot(start - a) < 0.0 {
+        normal = -normal;
+    }
+    let approach = -delta.dot(normal);
+    if approach > 0.0 {
+        let time = ((start - a).dot(normal) - radius) / approach;
+        let time = time.max(0.0);
+        let contact = start + delta * time;
+        let along = (contact - a).dot(edge) / edge.length_squared();
+        if (0.0..=1.0).contains(&along) {
+            consider(time, normal);
+        }
+    }
+
+    // Ends of the segment
+    for point in [a, b] {
+        let offset = start - point;
+        let qa = delta.length_squared();
+        let qb = 2.0 * offset.dot(delta);
+        let qc = offset.length_squared() - radius * radius;
+        let discriminant = qb * qb - 4.0 * qa * qc;
+        if qa == 0.0 || discriminant < 0.0 || qb >= 0.0 {
+            continue;
+        }
+        let time = ((-qb - discriminant.sqrt()) / (2.0 * qa)).max(0.0);
+        let normal = (start + delta * time - point).normalize_or_zero();
+        consider(time, normal);
+    }
+
+    first
+}
+
+/// Shortest distance between segments a1-a2 and b1-b2.
+fn segment_distance(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> f32 {
+    let crosses = |p1: Vec2, p2: Vec2, q1: Vec2, q2: Vec2| {
+        let d = p2 - p1;
+        d.perp_dot(q1 - p1) * d.perp_dot(q2 - p1) <= 0.0
+    };
+    if crosses(a1, a2, b1, b2) && crosses(b1, b2, a1, a2) {
+        return 0.0;
+    }
+
+    point_segment_distance(a1, b1, b2)
+        .min(point_segment_distance(a2, b1, b2))
+        .min(point_segment_distance(b1, a1, a2))
+        .min(point_segment_distance(b2, a1, a2))
+}
+
+pub(crate) fn point_segment_distance(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let edge = b - a;
+    let along = if edge.length_squared() > 0.0 {
+        ((point - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(a + edge * along)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    const BODY: BodySize = BodySize {
+        radius: 0.1,
+        height: 1.0,
+        step: 0.5,
+    };
+
+    /// Sweep BODY across from the middle of sector a into the middle of sector b.
+    fn sweep_across(a: &Sector, b: &Sector) -> Sweep {
+        let sectors = [a, b];
+        let blockmap = Blockmap::build(&sectors);
+        let start = Position2(Vec2::new(0.5, 0.5));
+        let end = Position2(Vec2::new(1.5, 0.5));
+        sweep_circle(&blockmap, &sectors, a.id, start, end, BODY)
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.distance(expected) < 1e-4,
+            "{actual} isn't {expected}"
+        );
+    }
+
+    #[test]
+    fn sweep_hits_the_face() {
+        let (a, b) = (Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0));
+        let (time, normal) = sweep_segment(Vec2::ZERO, Vec2::new(2.0, 0.0), 0.5, a, b).unwrap();
+        assert!((time - 0.25).abs() < 1e-6);
+        assert_near(normal, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn sweep_hits_an_end() {
+        // Passing just above the top end, which the face alone would miss
+        let (a, b) = (Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0));
+        let start = Vec2::new(0.0, 1.3);
+        let (time, normal) = sweep_segment(start, Vec2::new(2.0, 0.0), 0.5, a, b).unwrap();
+        assert!((time - 0.3).abs() < 1e-5);
+        assert_near(normal, Vec2::new(-0.8, 0.6));
+    }
+
+    #[test]
+    fn sweep_moving_away_misses() {
+        let (a, b) = (Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0));
+        assert!(sweep_segment(Vec2::ZERO, Vec2::new(-2.0, 0.0), 0.5, a, b).is_none());
+        // Even from touching it
+        assert!(sweep_segment(Vec2::new(0.6, 0.0), Vec2::new(-1.0, 0.0), 0.5, a, b).is_none());
+    }
+
+    #[test]
+    fn sweep_already_touching_hits_at_once() {
+        let (a, b) = (Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0));
+        let start = Vec2::new(0.6, 0.0);
+        let (time, normal) = sweep_segment(start, Vec2::new(1.0, 0.0), 0.5, a, b).unwrap();
+        assert_eq!(time, 0.0);
+        assert_near(normal, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn sweep_passes_through_an_open_portal() {
+        let (a, b) = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.25, 4.0));
+        let sweep = sweep_across(&a, &b);
+        assert!(sweep.impact.is_none());
+        assert_near(sweep.position.0, Vec2::new(1.5, 0.5));
+        assert_eq!(sweep.sector, SectorId(1));
+    }
+
+    #[test]
+    fn sweep_stops_at_portals_the_body_cant_pass() {
+        let low = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.0, 0.5));
+        let closed = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.0, 0.0));
+        let ledge = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 1.0, 4.0));
+        for (a, b) in [low, closed, ledge] {
+            let sweep = sweep_across(&a, &b);
+            let impact = sweep.impact.expect("stopped at the portal");
+            assert_eq!((impact.sector, impact.wall), (SectorId(0), 2));
+            assert_near(sweep.position.0, Vec2::new(0.9 - SKIN, 0.5));
+            assert_eq!(sweep.sector, SectorId(0));
+        }
+    }
+
+    #[test]
+    fn slide_stops_in_a_corner() {
+        let sector = square(0, 0.0, 0.0, 4.0);
+        let sectors = [&sector];
+        let blockmap = Blockmap::build(&sectors);
+        let start = Position2(Vec2::new(0.5, 0.5));
+        let end = Position2(Vec2::new(1.5, 1.2));
+        let sweep = slide_circle(&blockmap, &sectors, sector.id, start, end, BODY);
+        assert!(sweep.impact.is_some());
+        assert!(sweep.position.0.distance(Vec2::new(0.9, 0.9)) < 0.01);
+        assert_eq!(sweep.sector, SectorId(0));
+    }
+}
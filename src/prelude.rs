@@ -0,0 +1,30 @@
+//! Types, plugins, events and geometry helpers needed by most users of the crate,
+//! imported together with `use sector::prelude::*`.
+
+pub use crate::{
+    animate::{Animated, AnimatedSurface, ColorCycle},
+    hub::{MapEntered, WorldState},
+    interact::{Interactable, WallUsed},
+    light::{FlickerLight, PulseLight},
+    load::{MapLoadEvent, MapLoadMetrics},
+    migrate::{MapVersion, MAP_VERSION},
+    mover::{Mover, MoverAction, MoverCommand, MoverKind, MoverStopped},
+    physics::{passable, slide_circle, sweep_circle, BodySize},
+    time_scale::TimeScale,
+    trigger::{Effect, TagEffect, TagTriggered, Trigger},
+    vertex_pool::{VertexId, VertexPool},
+    Billboard, CameraPose, Direction, FloorMaterial, FogConfig, GameMode, InitialSector, Length,
+    Liquid, LiquidKind, MidTexture, PlayerStart, PlayerStartSelection, Portal, Position2,
+    Position3, RawColor, RawColorA, Sector, SectorAppExt, SectorEntered, SectorId, SectorPlugin,
+    SectorSet, StableId, TextureAlignment, Thing, Wall, WallAttachment, WallSurface, DEFAULT_LIGHT,
+    DEFAULT_SCENE_MP_FILE_PATH, DEFAULT_SCENE_RON_FILE_PATH, EYE_HEIGHT,
+};
+
+#[cfg(feature = "audio")]
+pub use crate::music::SoundTrackChanged;
+
+#[cfg(feature = "render")]
+pub use crate::render::{
+    between, clip_wall, intersect, point_behind, project, CameraConfig, FrameTarget, PostEffect,
+    PostProcess, RenderColors, RenderConfig, SectorRenderPlugin, View,
+};
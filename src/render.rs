@@ -0,0 +1,973 @@
+mod automap;
+mod cache;
+mod columns;
+mod draw;
+mod font;
+mod frame;
+mod hud;
+mod minimap;
+mod plugin;
+mod post;
+mod sprite;
+mod stats;
+mod texture;
+mod utils;
+mod visibility;
+
+pub use crate::render::{
+    automap::*, cache::*, columns::*, draw::*, font::*, frame::*, hud::*, minimap::*, plugin::*,
+    post::*, sprite::*, stats::*, texture::*, utils::*, visibility::*,
+};
+
+use crate::*;
+
+use bevy::{
+    math::{vec2, vec3},
+    utils::Instant,
+};
+use bevy_pixels::prelude::*;
+use palette::{Hsv, IntoColor, Srgb};
+use std::collections::VecDeque;
+
+/// Default frame width, see [`RenderConfig`].
+pub const WIDTH: u32 = 320;
+/// Default frame height, see [`RenderConfig`].
+pub const HEIGHT: u32 = 240;
+pub const GAP: isize = 1;
+pub const FOV_X_RADIANS: f32 = std::f32::consts::FRAC_PI_2;
+pub const MIN_FOV_X_DEGREES: f32 = 30.0;
+pub const MAX_FOV_X_DEGREES: f32 = 150.0;
+pub const NEAR: f32 = 0.1;
+pub const FAR: f32 = 50.0;
+pub const MINIMAP_SCALE: f32 = 8.0;
+/// Most portals deep sectors are drawn through by default, see [`RenderConfig`].
+pub const DEFAULT_MAX_PORTAL_DEPTH: usize = 64;
+/// Furthest a camera pitches up or down, in radians.
+pub const MAX_PITCH: f32 = 1.0;
+
+/// Perspective and clip boundaries for a horizontal field of view and aspect ratio,
+/// derived once rather than on every use.
+#[derive(Debug, Copy, Clone)]
+pub struct Projection {
+    pub fov_y_radians: f32,
+    pub perspective_matrix: Mat4,
+    /// Half width of the view at the near plane.
+    pub x_near: f32,
+    pub x_far: f32,
+    // Clip boundaries
+    pub back_clip_1: Vec2,
+    pub back_clip_2: Vec2,
+    pub left_clip_1: Vec2,
+    pub left_clip_2: Vec2,
+    pub right_clip_1: Vec2,
+    pub right_clip_2: Vec2,
+}
+
+impl Projection {
+    pub fn new(fov_x_radians: f32, aspect_ratio: f32) -> Self {
+        let fov_y_radians = 2.0 * ((fov_x_radians * 0.5).tan() / aspect_ratio).atan();
+        let tan_fac_fov_x_2 = (fov_x_radians / 2.0).tan();
+        let x_near = NEAR * tan_fac_fov_x_2;
+        let x_far = FAR * tan_fac_fov_x_2;
+
+        Self {
+            fov_y_radians,
+            perspective_matrix: Mat4::perspective_infinite_reverse_rh(
+                fov_y_radians,
+                aspect_ratio,
+                NEAR,
+            ),
+            x_near,
+            x_far,
+            back_clip_1: vec2(x_near, NEAR),
+            back_clip_2: vec2(-x_near, NEAR),
+            left_clip_1: vec2(-x_near, NEAR),
+            left_clip_2: vec2(-x_far, FAR),
+            right_clip_1: vec2(x_far, FAR),
+            right_clip_2: vec2(x_near, NEAR),
+        }
+    }
+}
+
+/// Frame resolution and field of view the renderer draws with, and the projection
+/// derived from them. Frame targets are resized to match by `resize_frame_system`.
+#[derive(Resource, Debug, Copy, Clone)]
+pub struct RenderConfig {
+    width: u32,
+    height: u32,
+    fov_x_radians: f32,
+    projection: Projection,
+    /// Most portals deep sectors are drawn through, so maps with portals looping back
+    /// on themselves can't stall a frame.
+    pub max_portal_depth: usize,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self::new(WIDTH, HEIGHT, FOV_X_RADIANS)
+    }
+}
+
+impl RenderConfig {
+    pub fn new(width: u32, height: u32, fov_x_radians: f32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        Self {
+            width,
+            height,
+            fov_x_radians,
+            projection: Projection::new(fov_x_radians, width as f32 / height as f32),
+            max_portal_depth: DEFAULT_MAX_PORTAL_DEPTH,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn fov_x_radians(&self) -> f32 {
+        self.fov_x_radians
+    }
+
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    /// Change the frame resolution, recomputing the projection for its aspect ratio.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        *self = Self {
+            max_portal_depth: self.max_portal_depth,
+            ..Self::new(width, height, self.fov_x_radians)
+        };
+    }
+
+    /// Config for drawing into a viewport of the frame, projected for its aspect ratio.
+    pub fn viewport(&self, frame: &Canvas) -> Self {
+        if (frame.width, frame.height) == (self.width, self.height) {
+            return *self;
+        }
+        let mut config = *self;
+        config.set_resolution(frame.width, frame.height);
+        config
+    }
+
+    /// Change the horizontal field of view, recomputing the projection.
+    pub fn set_fov_x_radians(&mut self, fov_x_radians: f32) {
+        *self = Self {
+            max_portal_depth: self.max_portal_depth,
+            ..Self::new(self.width, self.height, fov_x_radians)
+        };
+    }
+
+    /// Pixel at normalized screen coordinates.
+    pub fn pixel(&self, norm: Normalized) -> Pixel {
+        let (frac_width_2, frac_height_2) = (self.width / 2, self.height / 2);
+        Pixel {
+            x: frac_width_2 as isize + (frac_width_2 as f32 * norm.0.x).round() as isize,
+            y: frac_height_2 as isize - (frac_height_2 as f32 * norm.0.y).round() as isize,
+        }
+    }
+
+    /// Pixel at normalized screen coordinates seen by a camera pitched up by pitch
+    /// radians. Rows are sheared rather than the view tilted, so walls stay upright.
+    pub fn view_pixel(&self, norm: Normalized, pitch: f32) -> Pixel {
+        let pixel = self.pixel(norm);
+        let y_scale = self.projection.perspective_matrix.y_axis.y;
+        let shear = (self.height / 2) as f32 * pitch.clamp(-MAX_PITCH, MAX_PITCH).tan() * y_scale;
+        Pixel::new(pixel.x, pixel.y + shear.round() as isize)
+    }
+
+    /// Pixel of a minimap position, [`MINIMAP_SCALE`] pixels per unit from the centre.
+    pub fn minimap_pixel(&self, position: Position2) -> Pixel {
+        Pixel {
+            x: (self.width / 2) as isize + (MINIMAP_SCALE * position.0.x).round() as isize,
+            y: (self.height / 2) as isize - (MINIMAP_SCALE * position.0.y).round() as isize,
+        }
+    }
+
+    /// Minimap position under a point on the frame, in pixels from its top left.
+    pub fn minimap_position(&self, point: Vec2) -> Position2 {
+        Position2(vec2(
+            (point.x - (self.width / 2) as f32) / MINIMAP_SCALE,
+            ((self.height / 2) as f32 - point.y) / MINIMAP_SCALE,
+        ))
+    }
+}
+
+/// Camera settings adjustable while running, applied to [`RenderConfig`] by
+/// `camera_config_system`.
+#[derive(Resource, Debug, Copy, Clone, PartialEq)]
+pub struct CameraConfig {
+    /// Horizontal field of view, kept between [`MIN_FOV_X_DEGREES`] and
+    /// [`MAX_FOV_X_DEGREES`].
+    pub fov_x_radians: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            fov_x_radians: FOV_X_RADIANS,
+        }
+    }
+}
+
+impl CameraConfig {
+    pub fn fov_x_degrees(&self) -> f32 {
+        self.fov_x_radians.to_degrees()
+    }
+
+    pub fn set_fov_x_degrees(&mut self, degrees: f32) {
+        self.fov_x_radians = degrees
+            .clamp(MIN_FOV_X_DEGREES, MAX_FOV_X_DEGREES)
+            .to_radians();
+    }
+}
+
+/// Recompute the projection when the field of view changes.
+pub fn camera_config_system(camera: Res<CameraConfig>, mut config: ResMut<RenderConfig>) {
+    if !camera.is_changed() {
+        return;
+    }
+    let fov_x_radians = camera.fov_x_radians.clamp(
+        MIN_FOV_X_DEGREES.to_radians(),
+        MAX_FOV_X_DEGREES.to_radians(),
+    );
+    if fov_x_radians != config.fov_x_radians() {
+        config.set_fov_x_radians(fov_x_radians);
+    }
+}
+
+/// Normalized screen coordinates, right-handed coordinate system with z towards,
+/// origin at centre.
+///
+///   +y
+///   ^
+///   |
+/// +z.---> +x
+#[derive(Debug, Copy, Clone)]
+pub struct Normalized(Vec3);
+
+/// Pixel location, origin at top left.
+///
+///  .---> +x
+///  |
+///  v
+///  +y
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pixel {
+    pub x: isize,
+    pub y: isize,
+}
+
+impl Pixel {
+    pub fn new(x: isize, y: isize) -> Self {
+        Self { x, y }
+    }
+
+    pub fn to_tuple(self) -> (isize, isize) {
+        (self.x, self.y)
+    }
+}
+
+/// Viewpoint of the player, followed by cameras with [`FollowView`].
+#[derive(Resource, Debug)]
+pub struct View {
+    pub position: Position3,
+    pub direction: Direction,
+    /// Radians up from level, see [`SectorCamera::pitch`].
+    pub pitch: f32,
+    pub current_sector: Option<SectorId>,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            position: Position3(vec3(0.0, 0.0, EYE_HEIGHT)),
+            direction: Direction(0.0),
+            pitch: 0.0,
+            current_sector: None,
+        }
+    }
+}
+
+impl View {
+    /// Move to pose, as it was stored.
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.position = Position3(pose.position.0.extend(pose.height.0));
+        self.direction = pose.direction;
+        self.current_sector = Some(pose.sector);
+    }
+}
+
+/// Camera a frame target is drawn from, on the same entity as the target or its
+/// children, within its [`Viewport`]. Targets without one are given a camera following
+/// the [`View`].
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct SectorCamera {
+    pub position: Position3,
+    pub direction: Direction,
+    /// Radians up from level, up to [`MAX_PITCH`] either way.
+    pub pitch: f32,
+    pub current_sector: Option<SectorId>,
+}
+
+impl Default for SectorCamera {
+    fn default() -> Self {
+        Self::from(&View::default())
+    }
+}
+
+impl From<&View> for SectorCamera {
+    fn from(view: &View) -> Self {
+        Self {
+            position: view.position,
+            direction: view.direction,
+            pitch: view.pitch,
+            current_sector: view.current_sector,
+        }
+    }
+}
+
+impl SectorCamera {
+    /// Transform from world to camera relative positions, the camera looking along +y.
+    pub fn view_matrix(&self) -> Mat3 {
+        Mat3::from_rotation_z(-self.direction.0)
+            * Mat3::from_translation(-vec2(self.position.0.x, self.position.0.y))
+    }
+}
+
+/// Keeps a [`SectorCamera`] at the player's [`View`].
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct FollowView;
+
+/// Part of its frame target a [`SectorCamera`] draws into, as fractions of the frame
+/// from top left. Cameras without one fill the frame. Cameras on children of a target
+/// are drawn after its own, in order, so two halves make a split screen and a small
+/// viewport a picture in picture. Overlays follow the target's own camera.
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Viewport {
+    pub const FULL: Self = Self::new(0.0, 0.0, 1.0, 1.0);
+    pub const LEFT_HALF: Self = Self::new(0.0, 0.0, 0.5, 1.0);
+    pub const RIGHT_HALF: Self = Self::new(0.5, 0.0, 1.0, 1.0);
+    pub const TOP_HALF: Self = Self::new(0.0, 0.0, 1.0, 0.5);
+    pub const BOTTOM_HALF: Self = Self::new(0.0, 0.5, 1.0, 1.0);
+
+    pub const fn new(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Self {
+        Self {
+            min: Vec2::new(min_x, min_y),
+            max: Vec2::new(max_x, max_y),
+        }
+    }
+
+    /// Pixels of a frame covered, from min to max exclusive, `None` when empty.
+    pub fn pixels(&self, width: u32, height: u32) -> Option<(Pixel, Pixel)> {
+        let size = vec2(width as f32, height as f32);
+        let min = (self.min.clamp(Vec2::ZERO, Vec2::ONE) * size).round();
+        let max = (self.max.clamp(Vec2::ZERO, Vec2::ONE) * size).round();
+        (max.x > min.x && max.y > min.y).then(|| {
+            (
+                Pixel::new(min.x as isize, min.y as isize),
+                Pixel::new(max.x as isize, max.y as isize),
+            )
+        })
+    }
+}
+
+/// Entities whose cameras draw into a frame target, the target first then its
+/// children.
+pub fn target_cameras(
+    target: Entity,
+    children: Option<&Children>,
+) -> impl Iterator<Item = Entity> + '_ {
+    std::iter::once(target).chain(children.into_iter().flatten().copied())
+}
+
+/// Give frame targets without a camera, on themselves or their children, one
+/// following the view, and cameras a place to record where sprites are clipped.
+pub fn camera_setup_system<T: FrameTarget>(
+    mut commands: Commands,
+    target_query: Query<(Entity, Option<&Children>), With<T>>,
+    camera_query: Query<(Entity, Option<&SpriteClip>), With<SectorCamera>>,
+) {
+    for (entity, children) in &target_query {
+        if !target_cameras(entity, children).any(|e| camera_query.contains(e)) {
+            commands.entity(entity).insert((
+                SectorCamera::default(),
+                FollowView,
+                SpriteClip::default(),
+            ));
+        }
+    }
+    for (entity, sprite_clip) in &camera_query {
+        if sprite_clip.is_none() {
+            commands.entity(entity).insert(SpriteClip::default());
+        }
+    }
+}
+
+pub fn follow_view_system(
+    view: Res<View>,
+    mut camera_query: Query<&mut SectorCamera, With<FollowView>>,
+) {
+    for mut camera in &mut camera_query {
+        *camera = SectorCamera::from(&*view);
+    }
+}
+
+/// Place the view at the selected player start once a map has spawned, or just in
+/// the initial sector of maps without starts, at eye height above the floor.
+pub fn initial_sector_system(
+    mut view: ResMut<View>,
+    selection: Res<PlayerStartSelection>,
+    start_query: Query<&PlayerStart>,
+    initial_sector_query: Query<&InitialSector>,
+    sector_query: Query<&Sector>,
+) {
+    if view.current_sector.is_some() {
+        return;
+    }
+
+    let sector = if let Some(start) = selection.choose(&start_query) {
+        view.position.0.x = start.position.0.x;
+        view.position.0.y = start.position.0.y;
+        view.direction = start.direction;
+        start.sector
+    } else if let Ok(initial_sector) = initial_sector_query.get_single() {
+        initial_sector.0
+    } else { return };
+    view.current_sector = Some(sector);
+    if let Some(sector) = sector_query.iter().find(|s| s.id == sector) {
+        view.position.0.z = sector.floor.0 + EYE_HEIGHT;
+    }
+}
+
+pub fn sector_entered_system(
+    view: Res<View>,
+    mut last_sector: Local<Option<SectorId>>,
+    mut sector_entered_events: EventWriter<SectorEntered>,
+) {
+    if view.current_sector == *last_sector {
+        return;
+    }
+
+    if let Some(to) = view.current_sector {
+        sector_entered_events.send(SectorEntered {
+            from: *last_sector,
+            to,
+        });
+    }
+    *last_sector = view.current_sector;
+}
+
+pub fn present_frame_system<T: FrameTarget>(mut target_query: Query<&mut T>) {
+    for mut target in &mut target_query {
+        target.present();
+    }
+}
+
+pub fn draw_background_system<T: FrameTarget>(
+    colors: Res<RenderColors>,
+    mut stats: ResMut<RenderStats>,
+    mut target_query: Query<&mut T>,
+) {
+    let started = Instant::now();
+    *stats = RenderStats::default();
+    let [r, g, b] = colors.background.0;
+    for mut target in &mut target_query {
+        let frame = target.frame_mut();
+        frame.copy_from_slice(&[r, g, b, 0xff].repeat(frame.len() / 4));
+    }
+    stats.background = started.elapsed();
+}
+
+/// Draw the walls of each frame target from its cameras, or copy them from the
+/// [`WallCache`] when nothing they show has changed since.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_wall_system<T: FrameTarget>(
+    config: Res<RenderConfig>,
+    colors: Res<RenderColors>,
+    fog: Res<FogConfig>,
+    traversal: Res<PortalTraversal>,
+    mut target_query: Query<(Entity, &mut T, Option<&Children>)>,
+    mut camera_query: Query<(&SectorCamera, Option<&Viewport>, &mut SpriteClip)>,
+    sector_query: Query<&Sector>,
+    attachment_query: Query<&WallAttachment>,
+    texture_handles: Res<TextureHandles>,
+    textures: Res<Assets<Texture>>,
+    mut cache: ResMut<WallCache>,
+    mut changes: WallChanges,
+    mut stats: ResMut<RenderStats>,
+) {
+    let started = Instant::now();
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let appearance = sector_appearance(&sectors);
+    let resources_changed = [
+        config.is_changed(),
+        colors.is_changed(),
+        fog.is_changed(),
+        traversal.is_changed(),
+        texture_handles.is_changed(),
+    ];
+    if changes.any() || resources_changed.contains(&true) || !cache.enabled {
+        cache.clear();
+    }
+    cache.retain(|entity| target_query.contains(entity));
+
+    for (entity, mut target, children) in &mut target_query {
+        let cameras: Vec<(SectorCamera, Option<Viewport>)> = target_cameras(entity, children)
+            .filter_map(|e| camera_query.get(e).ok())
+            .map(|(camera, viewport, _)| (*camera, viewport.copied()))
+            .collect();
+        if cache.restore(entity, &cameras, appearance, target.frame_mut()) {
+            stats.walls_cached += 1;
+            continue;
+        }
+
+        let mut canvas = target.canvas();
+        for camera_entity in target_cameras(entity, children) {
+            let Ok((camera, viewport, mut sprite_clip)) = camera_query.get_mut(camera_entity)
+            else { continue };
+            let viewport = viewport.copied().unwrap_or_default();
+            let Some((min, max)) = viewport.pixels(canvas.width, canvas.height) else { continue };
+            let frame = &mut canvas.viewport(min, max);
+            draw_walls(
+                frame,
+                &config.viewport(frame),
+                &colors,
+                &fog,
+                camera,
+                *traversal,
+                &sectors,
+                &attachment_query,
+                &texture_handles,
+                &textures,
+                &mut sprite_clip,
+                &mut stats,
+            );
+        }
+        cache.store(entity, cameras, appearance, target.frame());
+    }
+    stats.walls = started.elapsed();
+}
+
+/// Draw the walls, floors and ceilings seen by camera through portals.
+#[allow(clippy::too_many_arguments)]
+fn draw_walls<'a>(
+    frame: &mut Canvas,
+    config: &RenderConfig,
+    colors: &RenderColors,
+    fog: &FogConfig,
+    view: &SectorCamera,
+    traversal: PortalTraversal,
+    sectors: &[&Sector],
+    attachment_query: &Query<&WallAttachment>,
+    texture_handles: &TextureHandles,
+    textures: &'a Assets<Texture>,
+    sprite_clip: &mut SpriteClip,
+    stats: &mut RenderStats,
+) {
+    let projection = config.projection();
+    let view_matrix = view.view_matrix();
+    let to_pixel = |norm: Normalized| config.view_pixel(norm, view.pitch);
+
+    let width = frame.width as usize;
+    let mut y_min_vec = vec![GAP; width];
+    let mut y_max_vec = vec![frame.height as isize; width];
+
+    // Near edge of the bridge deck in each column, from the walls facing away
+    let mut y_bridge_near_vec = vec![None; width];
+    // Same for the liquid surface
+    let mut y_liquid_near_vec = vec![None; width];
+    // Mid-texture and liquid surface spans with the visit they're from, filled over
+    // everything beyond them once all else is pushed
+    let mut overlay_spans = Vec::new();
+    let mut visit = 0;
+    sprite_clip.windows.clear();
+    let mut columns = ColumnSpans::default();
+
+    // Walls come grouped by the portal their sector is seen through
+    for visible in visible_walls(config, view, sectors).with_traversal(traversal) {
+        let sector = visible.sector;
+        let wall = visible.wall;
+        let (x_min, x_max) = visible.window;
+
+        // View relative floor and ceiling locations
+        let view_floor = Length(sector.floor.0 - view.position.0.z);
+        let view_ceil = Length(sector.ceil.0 - view.position.0.z);
+        let view_bridge = sector
+            .bridge
+            .map(|bridge| Length(bridge.0 - view.position.0.z));
+        let view_liquid = sector
+            .liquid
+            .filter(|liquid| sector.floor.0 < liquid.surface.0 && liquid.surface.0 < sector.ceil.0)
+            .map(|liquid| (liquid, Length(liquid.surface.0 - view.position.0.z)));
+
+        stats.walls_drawn += 1;
+        if visible.visit != visit {
+            visit = visible.visit;
+            stats.sectors_visited += 1;
+            sprite_clip.record(visit, sector.id, visible.window, &y_min_vec, &y_max_vec);
+            // Near edges come from the walls of this sector facing away from view
+            let near_edges = |view_height: Length, near_vec: &mut [Option<isize>]| {
+                near_vec.fill(None);
+                for wall in sector.to_walls() {
+                    // Reversed so walls facing away are treated as facing towards
+                    let view_left = wall.right.transform(view_matrix);
+                    let view_right = wall.left.transform(view_matrix);
+                    let Some((view_left, view_right)) =
+                        clip_wall(projection, view_left, view_right)
+                    else { continue };
+
+                    let left = to_pixel(project(projection, view_left, view_height));
+                    let right = to_pixel(project(projection, view_right, view_height));
+                    let dx = right.x - left.x;
+                    if dx <= 0 {
+                        continue;
+                    }
+
+                    let x_left = left.x.clamp(x_min, x_max);
+                    let x_right = right.x.clamp(x_min, x_max);
+                    for x in x_left..x_right {
+                        let x_t = (x - left.x) as f32 / dx as f32;
+                        near_vec[x as usize] = Some(lerpi(left.y, right.y, x_t));
+                    }
+                }
+            };
+            if let Some(view_bridge) = view_bridge {
+                near_edges(view_bridge, &mut y_bridge_near_vec);
+            }
+            if let Some((_, view_surface)) = view_liquid {
+                near_edges(view_surface, &mut y_liquid_near_vec);
+            }
+        }
+
+        let (view_left, view_right) = (visible.view_left, visible.view_right);
+
+        // Attachments on this wall, with where the clipped ends lie along the whole wall
+        let attachments: Vec<&WallAttachment> = attachment_query
+            .iter()
+            .filter(|a| a.sector == sector.id && a.wall == visible.index)
+            .collect();
+        let (wall_a, wall_b) = (
+            wall.left.transform(view_matrix).0,
+            wall.right.transform(view_matrix).0,
+        );
+        let wall_length = wall_a.distance(wall_b);
+        let wall_u = |point: Position2| {
+            (point.0 - wall_a).dot(wall_b - wall_a) / (wall_b - wall_a).length_squared()
+        };
+        let (u_left, u_right) = (wall_u(view_left), wall_u(view_right));
+        let texture = sector
+            .wall_texture(visible.index)
+            .and_then(|path| texture_handles.get(textures, path));
+        let mid_texture = visible
+            .portal_sector
+            .and(sector.mid_texture(visible.index))
+            .and_then(|mid| Some((texture_handles.get(textures, &mid.path)?, mid.opacity())));
+        // Color and texture above and below a portal's opening, the wall's own unless
+        // replaced
+        let portal_surface = |surface: Option<&WallSurface>| {
+            let surface = visible.portal_sector.and(surface);
+            let color = surface.and_then(|s| s.color).map_or(wall.color, |color| {
+                Srgb::<u8>::from(color).into_format().into_color()
+            });
+            let texture = surface
+                .and_then(|s| s.texture.as_deref())
+                .and_then(|path| texture_handles.get(textures, path))
+                .or(texture);
+            (color, texture)
+        };
+        let alignment = sector.texture_alignment(visible.index);
+        let upper_surface = portal_surface(sector.upper_wall(visible.index));
+        let lower_surface = portal_surface(sector.lower_wall(visible.index));
+
+        // Project from view to normalized screen coordinates
+        let norm_left_top = project(projection, view_left, view_ceil);
+        let norm_left_bottom = project(projection, view_left, view_floor);
+        let norm_right_top = project(projection, view_right, view_ceil);
+        let norm_right_bottom = project(projection, view_right, view_floor);
+
+        // Convert to pixel locations
+        let left_top = to_pixel(norm_left_top);
+        let left_bottom = to_pixel(norm_left_bottom);
+        let right_top = to_pixel(norm_right_top);
+        let right_bottom = to_pixel(norm_right_bottom);
+
+        let dx = right_top.x - left_top.x;
+
+        // Far edge of the bridge deck along this wall
+        let bridge_ends = view_bridge.map(|view_bridge| {
+            let left = to_pixel(project(projection, view_left, view_bridge));
+            let right = to_pixel(project(projection, view_right, view_bridge));
+            (left, right)
+        });
+        let liquid_ends = view_liquid.map(|(_, view_surface)| {
+            let left = to_pixel(project(projection, view_left, view_surface));
+            let right = to_pixel(project(projection, view_right, view_surface));
+            (left, right)
+        });
+
+        // TODO: Use `view_y_middle` in `distance` calculation below
+        // let view_y_middle = view_left_bottom.y + (view_y_top - view_left_bottom.y) / 2.0;
+
+        let (x_left, x_right) = (visible.x_left, visible.x_right);
+        let portal_sector = visible.portal_sector;
+        let ceiling_color = shade(sector.ceiling_color.unwrap_or(colors.ceiling), sector.light);
+        let floor_color = shade(sector.floor_color.unwrap_or(colors.floor), sector.light);
+
+        // Process adjacent portal sector
+        let (y_portal_top, y_portal_bottom) = if let Some(portal_sector) = portal_sector {
+            let view_portal_ceil = Length(portal_sector.ceil.0 - view.position.0.z);
+            let view_portal_floor = Length(portal_sector.floor.0 - view.position.0.z);
+
+            let y_portal_top = if view_portal_ceil.0 < view_ceil.0 {
+                let portal_ceil_t =
+                    (view_portal_ceil.0 - view_ceil.0) / (view_floor.0 - view_ceil.0);
+                Some((
+                    lerpi(left_top.y, left_bottom.y, portal_ceil_t),
+                    lerpi(right_top.y, right_bottom.y, portal_ceil_t),
+                ))
+            } else {
+                None
+            };
+
+            let y_portal_bottom = if view_portal_floor.0 > view_floor.0 {
+                let portal_floor_t =
+                    (view_portal_floor.0 - view_ceil.0) / (view_floor.0 - view_ceil.0);
+                Some((
+                    lerpi(left_top.y, left_bottom.y, portal_floor_t),
+                    lerpi(right_top.y, right_bottom.y, portal_floor_t),
+                ))
+            } else {
+                None
+            };
+
+            (y_portal_top, y_portal_bottom)
+        } else {
+            (None, None)
+        };
+
+        // Iterate through pixel columns
+        '_columns: for x in x_left..x_right {
+            let skip_floor_ceil = x >= x_max - GAP;
+            let skip_wall = x >= x_right - GAP;
+
+            let x_t = (x - left_top.x) as f32 / dx as f32;
+
+            // Interpolate z for distance
+            let view_z = lerp(view_left.0.y, view_right.0.y, x_t);
+            let distance = view_z.abs();
+
+            let fogged = Fogged::new(sector.fog.as_ref().unwrap_or(fog), distance, sector.light);
+
+            // Interpolate y
+            let y_top = lerpi(left_top.y, right_top.y, x_t);
+            let y_bottom = lerpi(left_bottom.y, right_bottom.y, x_t);
+
+            // Get y bounds
+            let y_min = y_min_vec[x as usize];
+            let y_max = y_max_vec[x as usize];
+
+            // Position along the wall, interpolated perspective correct unlike depth above
+            let inverse_z = lerp(1.0 / view_left.0.y, 1.0 / view_right.0.y, x_t);
+            let u = lerp(u_left / view_left.0.y, u_right / view_right.0.y, x_t) / inverse_z;
+
+            // Texture runs from ceiling to floor of the unclipped wall, lined up with
+            // world height so neighbouring walls match, then moved by the wall's
+            // alignment
+            let texture_column = |texture| {
+                let repeat = TEXTURE_REPEAT * alignment.scale;
+                let v_top = (alignment.v_offset - sector.ceil.0) / repeat;
+                let v_step = (sector.ceil.0 - sector.floor.0) / repeat / (y_bottom - y_top) as f32;
+                TextureColumn {
+                    texture,
+                    u: (u * wall_length + alignment.u_offset) / repeat,
+                    v_origin: v_top - y_top as f32 * v_step,
+                    v_step,
+                    fogged,
+                }
+            };
+            let mid_column = mid_texture
+                .filter(|_| y_bottom > y_top)
+                .map(|(texture, opacity)| (texture_column(texture), opacity));
+            // Texture, or color for brightness
+            let fill = |(color, texture): (Hsv, Option<&'a Texture>)| match texture
+                .filter(|_| y_bottom > y_top)
+            {
+                Some(texture) => SpanFill::Texture(texture_column(texture)),
+                None => SpanFill::Color(
+                    fogged.add_fog(Hsv::new(color.hue, color.saturation, fogged.brightness).into()),
+                ),
+            };
+            let wall_fill = fill((wall.color, texture));
+
+            // Spans of attachments in this column, drawn over the wall
+            let mut attachment_spans = Vec::new();
+            if !attachments.is_empty() && !skip_wall {
+                for attachment in &attachments {
+                    let half_width = attachment.width.0 / 2.0 / wall_length;
+                    if (u - attachment.u).abs() > half_width {
+                        continue;
+                    }
+                    let height_y = |height: f32| {
+                        let t = (height - sector.ceil.0) / (sector.floor.0 - sector.ceil.0);
+                        lerpi(y_top, y_bottom, t).clamp(y_min, y_max)
+                    };
+                    let color: RawColor = {
+                        let hsv: Hsv = Srgb::<u8>::from(attachment.color)
+                            .into_format()
+                            .into_color();
+                        fogged.add_fog(
+                            Hsv::new(hsv.hue, hsv.saturation, hsv.value * fogged.brightness).into(),
+                        )
+                    };
+                    attachment_spans.push((
+                        height_y(attachment.height.0 + attachment.tall.0),
+                        height_y(attachment.height.0),
+                        color,
+                    ));
+                }
+            }
+
+            // Clip y
+            let y_top = y_top.clamp(y_min, y_max);
+            let y_bottom = y_bottom.clamp(y_min, y_max);
+
+            let y_ceil_top = y_min;
+            let y_ceil_bottom = y_top;
+            let y_floor_top = y_bottom;
+            let y_floor_bottom = y_max;
+
+            // Draw ceiling
+            if !skip_floor_ceil {
+                columns.push_color(x, y_ceil_top, y_ceil_bottom - GAP, ceiling_color);
+            }
+
+            // if join_gap_column {
+            //     continue '_columns;
+            // }
+
+            if portal_sector.is_some() {
+                // Draw wall above portal if required
+                if let Some((y_portal_left_top, y_portal_right_top)) = y_portal_top {
+                    let y_portal_top =
+                        lerpi(y_portal_left_top, y_portal_right_top, x_t).clamp(y_min, y_bottom);
+                    if !skip_wall {
+                        columns.push(x, y_top, y_portal_top - GAP, fill(upper_surface));
+                    }
+                    y_min_vec[x as usize] = y_portal_top;
+                } else {
+                    y_min_vec[x as usize] = y_top;
+                }
+
+                // Draw wall below portal if required
+                if let Some((portal_left_bottom_y, portal_right_bottom_y)) = y_portal_bottom {
+                    let y_portal_bottom =
+                        lerpi(portal_left_bottom_y, portal_right_bottom_y, x_t).clamp(y_top, y_max);
+                    if !skip_wall {
+                        columns.push(x, y_portal_bottom, y_bottom - GAP, fill(lower_surface));
+                    }
+                    y_max_vec[x as usize] = y_portal_bottom;
+                } else {
+                    y_max_vec[x as usize] = y_bottom;
+                }
+
+                // Mid-texture across the opening, over what's seen through it
+                if let (Some((column, opacity)), false) = (mid_column, skip_wall) {
+                    let (y_opening_top, y_opening_bottom) =
+                        (y_min_vec[x as usize], y_max_vec[x as usize]);
+                    let fill = SpanFill::Masked(column, opacity);
+                    overlay_spans.push((visit, x, y_opening_top, y_opening_bottom - GAP, fill));
+                }
+            } else {
+                // Draw complete wall
+                if !skip_wall {
+                    columns.push(x, y_top, y_bottom - GAP, wall_fill);
+                }
+            }
+
+            for (y_attachment_top, y_attachment_bottom, color) in attachment_spans {
+                columns.push_color(x, y_attachment_top, y_attachment_bottom - GAP, color);
+            }
+
+            // Draw floor
+            if !skip_floor_ceil {
+                columns.push_color(x, y_floor_top, y_floor_bottom - GAP, floor_color);
+            }
+
+            // Liquid surface over what lies below and beyond it, within the bounds the
+            // column had before this sector's walls narrowed them
+            if let (Some((liquid, view_surface)), Some((liquid_left, liquid_right))) =
+                (view_liquid, liquid_ends)
+            {
+                let y_liquid_far = lerpi(liquid_left.y, liquid_right.y, x_t);
+                let y_liquid_near = y_liquid_near_vec[x as usize];
+                let (y_liquid_top, y_liquid_bottom) = if view_surface.0 < 0.0 {
+                    (y_liquid_far, y_liquid_near.unwrap_or(y_max))
+                } else {
+                    (y_liquid_near.unwrap_or(y_min), y_liquid_far)
+                };
+                if !skip_floor_ceil {
+                    let color = shade(liquid.kind.color(), sector.light);
+                    let fill = SpanFill::Translucent(color.with_opacity(Liquid::SURFACE_OPACITY));
+                    overlay_spans.push((
+                        visit,
+                        x,
+                        y_liquid_top.clamp(y_min, y_max),
+                        y_liquid_bottom.clamp(y_min, y_max) - GAP,
+                        fill,
+                    ));
+                }
+            }
+
+            // Draw bridge deck over everything else in the sector, its top when
+            // looking down on it, otherwise its underside
+            if let (Some(view_bridge), Some((bridge_left, bridge_right))) =
+                (view_bridge, bridge_ends)
+            {
+                let y_bridge_far = lerpi(bridge_left.y, bridge_right.y, x_t);
+                let y_bridge_near = y_bridge_near_vec[x as usize];
+
+                if view_bridge.0 < 0.0 {
+                    let y_bridge_top = y_bridge_far.clamp(y_min, y_max);
+                    let y_bridge_bottom = y_bridge_near.unwrap_or(y_max).clamp(y_min, y_max);
+                    if !skip_floor_ceil {
+                        columns.push_color(x, y_bridge_top, y_bridge_bottom - GAP, floor_color);
+                    }
+                    y_max_vec[x as usize] = y_max_vec[x as usize].min(y_bridge_top);
+                } else {
+                    let y_bridge_top = y_bridge_near.unwrap_or(y_min).clamp(y_min, y_max);
+                    let y_bridge_bottom = y_bridge_far.clamp(y_min, y_max);
+                    if !skip_floor_ceil {
+                        columns.push_color(x, y_bridge_top, y_bridge_bottom - GAP, ceiling_color);
+                    }
+                    y_min_vec[x as usize] = y_min_vec[x as usize].max(y_bridge_bottom);
+                }
+            }
+        }
+    }
+
+    // Farthest sectors' first, so nearer ones blend over them. Within a sector its
+    // mid-textures are pushed before its liquid surface, which lies in front of them
+    overlay_spans.sort_by_key(|&(visit, ..)| std::cmp::Reverse(visit));
+    for (_, x, y_top, y_bottom, fill) in overlay_spans {
+        columns.push(x, y_top, y_bottom, fill);
+    }
+
+    stats.column_spans += columns.len();
+    columns.fill(frame);
+}
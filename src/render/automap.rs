@@ -0,0 +1,120 @@
+use super::*;
+use crate::hub::*;
+
+use palette::named::*;
+use std::collections::HashSet;
+
+/// Least distance moved before another point is added to the trail.
+const TRAIL_SPACING: f32 = 0.5;
+/// Most points kept in the trail, oldest dropped first.
+const TRAIL_LENGTH: usize = 4096;
+/// Border left around the map when drawn.
+const AUTOMAP_MARGIN: f32 = 8.0;
+
+/// Sectors the view has been in and the path it took, for the current map.
+#[derive(Resource, Debug, Default)]
+pub struct Automap {
+    visited: HashSet<SectorId>,
+    trail: Vec<Position2>,
+}
+
+impl Automap {
+    pub fn is_visited(&self, sector: SectorId) -> bool {
+        self.visited.contains(&sector)
+    }
+
+    pub fn trail(&self) -> &[Position2] {
+        &self.trail
+    }
+}
+
+/// Record sectors visited and the trail, starting over on entering a map.
+pub fn automap_system(
+    mut automap: ResMut<Automap>,
+    mut map_entered_events: EventReader<MapEntered>,
+    view: Res<View>,
+) {
+    if map_entered_events.iter().count() > 0 {
+        *automap = Automap::default();
+    }
+    let Some(sector) = view.current_sector else { return };
+
+    automap.visited.insert(sector);
+
+    let position = view.position.truncate();
+    let moved = automap
+        .trail
+        .last()
+        .map(|last| last.0.distance(position.0) >= TRAIL_SPACING)
+        .unwrap_or(true);
+    if moved {
+        if automap.trail.len() >= TRAIL_LENGTH {
+            automap.trail.remove(0);
+        }
+        automap.trail.push(position);
+    }
+}
+
+/// Draw visited sectors and the trail in dark lines on white, scaled to fill the
+/// frame, with the view as a dot where it ends.
+pub fn draw_automap(frame: &mut Canvas, automap: &Automap, sectors: &[&Sector], view: &View) {
+    let white: RawColor = WHITE.into();
+    let black: RawColor = BLACK.into();
+    let gray: RawColor = DARKGRAY.into();
+    let red: RawColor = RED.into();
+    let background = [white.0[0], white.0[1], white.0[2], 0xff].repeat(frame.len() / 4);
+    frame.copy_from_slice(&background);
+
+    let visited: Vec<&Sector> = sectors
+        .iter()
+        .copied()
+        .filter(|s| automap.is_visited(s.id))
+        .collect();
+    let points = visited
+        .iter()
+        .flat_map(|s| s.vertices.iter())
+        .chain(automap.trail.iter())
+        .map(|p| p.0);
+    let (min, max) = points.fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), point| (min.min(point), max.max(point)),
+    );
+    if min.x > max.x {
+        return;
+    }
+
+    // Fit the map in the frame keeping its aspect, y up
+    let size = (max - min).max(Vec2::splat(1.0));
+    let frame_size = vec2(frame.width as f32, frame.height as f32);
+    let available = frame_size - 2.0 * AUTOMAP_MARGIN;
+    let scale = (available / size).min_element();
+    let offset = (frame_size - size * scale) / 2.0;
+    let to_pixel = |position: Position2| {
+        let point = (position.0 - min) * scale + offset;
+        Pixel::new(
+            point.x.round() as isize,
+            (frame_size.y - point.y).round() as isize,
+        )
+    };
+
+    // Portals drawn first so solid walls shared with them stay dark
+    for solid in [false, true] {
+        for sector in &visited {
+            for wall in sector.to_walls() {
+                if wall.portal_sector.is_none() == solid {
+                    let color = if solid { black } else { gray };
+                    draw_line(frame, to_pixel(wall.left), to_pixel(wall.right), color);
+                }
+            }
+        }
+    }
+
+    for pair in automap.trail.windows(2) {
+        draw_line(frame, to_pixel(pair[0]), to_pixel(pair[1]), red);
+    }
+
+    let end = to_pixel(view.position.truncate());
+    for (dx, dy) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)] {
+        draw_pixel(frame, Pixel::new(end.x + dx, end.y + dy), red);
+    }
+}
@@ -0,0 +1,121 @@
+use super::*;
+
+use bevy::{ecs::system::SystemParam, utils::HashMap};
+use std::hash::{Hash, Hasher};
+
+/// Frame of each target as its walls were last drawn, copied back rather than drawn
+/// again while its cameras stay put and nothing they see changes, so an idle view
+/// costs little more than clearing the frame. Disable to draw walls every frame, as
+/// when timing the renderer.
+#[derive(Resource, Debug)]
+pub struct WallCache {
+    pub enabled: bool,
+    targets: HashMap<Entity, CachedWalls>,
+}
+
+impl Default for WallCache {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            targets: HashMap::default(),
+        }
+    }
+}
+
+/// Frame of a target after its walls were drawn, with what they were drawn from.
+#[derive(Debug)]
+struct CachedWalls {
+    data: Vec<u8>,
+    cameras: Vec<(SectorCamera, Option<Viewport>)>,
+    appearance: u64,
+}
+
+impl WallCache {
+    /// Forget every target's walls, to draw them all again.
+    pub fn clear(&mut self) {
+        self.targets.clear();
+    }
+
+    /// Forget targets for which keep is false, such as those despawned.
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+        self.targets.retain(|&entity, _| keep(entity));
+    }
+
+    /// Copy the walls of target into frame when drawn from the same cameras and
+    /// appearance, returning whether they were.
+    pub fn restore(
+        &self,
+        target: Entity,
+        cameras: &[(SectorCamera, Option<Viewport>)],
+        appearance: u64,
+        frame: &mut [u8],
+    ) -> bool {
+        let Some(cached) = self.targets.get(&target) else { return false };
+        let reusable = self.enabled
+            && cached.appearance == appearance
+            && cached.cameras == cameras
+            && cached.data.len() == frame.len();
+        if reusable {
+            frame.copy_from_slice(&cached.data);
+        }
+        reusable
+    }
+
+    /// Keep frame as the walls of target, drawn from cameras and appearance.
+    pub fn store(
+        &mut self,
+        target: Entity,
+        cameras: Vec<(SectorCamera, Option<Viewport>)>,
+        appearance: u64,
+        frame: &[u8],
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let cached = self.targets.entry(target).or_insert_with(|| CachedWalls {
+            data: Vec::new(),
+            cameras: Vec::new(),
+            appearance,
+        });
+        cached.data.clear();
+        cached.data.extend_from_slice(frame);
+        cached.cameras = cameras;
+        cached.appearance = appearance;
+    }
+}
+
+/// Hash of the parts of sectors that lights and animation change without change
+/// detection, so geometry systems don't rerun, but which walls are drawn with.
+pub fn sector_appearance(sectors: &[&Sector]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for sector in sectors {
+        sector.id.hash(&mut hasher);
+        sector.light.to_bits().hash(&mut hasher);
+        sector.colors.hash(&mut hasher);
+        sector.floor_color.hash(&mut hasher);
+        sector.ceiling_color.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Changes to the map since walls were last drawn, other than to their cameras and
+/// [`sector_appearance`].
+#[derive(SystemParam)]
+pub struct WallChanges<'w, 's> {
+    sector_query: Query<'w, 's, (), Changed<Sector>>,
+    removed_sectors: RemovedComponents<'w, 's, Sector>,
+    attachment_query: Query<'w, 's, (), Changed<WallAttachment>>,
+    removed_attachments: RemovedComponents<'w, 's, WallAttachment>,
+    texture_events: EventReader<'w, 's, AssetEvent<Texture>>,
+}
+
+impl WallChanges<'_, '_> {
+    /// Whether sectors, attachments or textures were added, changed or removed.
+    pub fn any(&mut self) -> bool {
+        // Each read through so they aren't seen again next frame
+        let removed = self.removed_sectors.iter().count() > 0;
+        let removed = self.removed_attachments.iter().count() > 0 || removed;
+        let textures = self.texture_events.iter().count() > 0;
+        removed || textures || !self.sector_query.is_empty() || !self.attachment_query.is_empty()
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+/// How a column span is filled.
+#[derive(Debug, Copy, Clone)]
+pub enum SpanFill<'a> {
+    Color(RawColor),
+    Texture(TextureColumn<'a>),
+    /// Color blended over what's already drawn by its alpha.
+    Translucent(RawColorA),
+    /// Texture leaving what's already drawn where transparent, blended over it
+    /// elsewhere by opacity from 0 to 1.
+    Masked(TextureColumn<'a>, f32),
+}
+
+impl SpanFill<'_> {
+    /// Color at row y, ignoring the opacity of translucent fills.
+    pub fn color(&self, y: isize) -> RawColor {
+        match self {
+            SpanFill::Color(color) => *color,
+            SpanFill::Translucent(color) => color.color(),
+            SpanFill::Texture(column) | SpanFill::Masked(column, _) => column.color(y),
+        }
+    }
+}
+
+/// Rows `y_top..y_bottom` of a pixel column, to be filled.
+#[derive(Debug, Copy, Clone)]
+pub struct ColumnSpan<'a> {
+    pub x: isize,
+    pub y_top: isize,
+    pub y_bottom: isize,
+    pub fill: SpanFill<'a>,
+}
+
+/// Column spans in the order they're drawn, later ones over earlier ones. Walls are
+/// traversed into these first so filling the frame can be split across threads.
+#[derive(Debug, Default)]
+pub struct ColumnSpans<'a> {
+    spans: Vec<ColumnSpan<'a>>,
+}
+
+impl<'a> ColumnSpans<'a> {
+    pub fn push(&mut self, x: isize, y_top: isize, y_bottom: isize, fill: SpanFill<'a>) {
+        if y_bottom > y_top {
+            self.spans.push(ColumnSpan {
+                x,
+                y_top,
+                y_bottom,
+                fill,
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn push_color(&mut self, x: isize, y_top: isize, y_bottom: isize, color: RawColor) {
+        self.push(x, y_top, y_bottom, SpanFill::Color(color));
+    }
+
+    /// Fill the spans into frame, in bands of rows on the compute task pool.
+    pub fn fill(&self, frame: &mut Canvas) {
+        let (width, height, stride) = (frame.width, frame.height as usize, frame.stride);
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let pool = ComputeTaskPool::init(TaskPool::default);
+        let band_rows = height.div_ceil(pool.thread_num().max(1));
+        let spans = &self.spans;
+        pool.scope(|scope| {
+            // The last band ends at the canvas's last pixel, short of a full stride
+            let bands = frame.chunks_mut(band_rows * stride as usize * 4);
+            for (band, data) in bands.enumerate() {
+                scope.spawn(async move {
+                    let y_min = (band * band_rows) as isize;
+                    let mut band = Canvas {
+                        width,
+                        height: band_rows.min(height - y_min as usize) as u32,
+                        data,
+                        stride,
+                    };
+                    let y_max = y_min + band.height as isize;
+
+                    for span in spans {
+                        for y in span.y_top.max(y_min)..span.y_bottom.min(y_max) {
+                            let pixel = Pixel::new(span.x, y - y_min);
+                            let color = match span.fill {
+                                SpanFill::Translucent(color) => color,
+                                SpanFill::Masked(column, opacity) => {
+                                    let Some(color) = column.color_opaque(y) else { continue };
+                                    color.with_opacity(opacity)
+                                }
+                                fill => {
+                                    draw_pixel_unchecked(&mut band, pixel, fill.color(y));
+                                    continue;
+                                }
+                            };
+                            blend_pixel_unchecked(&mut band, pixel, color);
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
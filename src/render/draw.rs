@@ -0,0 +1,140 @@
+use super::*;
+
+use rust_bresenham::Bresenham;
+
+pub fn draw_vertical_line(
+    frame: &mut Canvas,
+    x: isize,
+    y_top: isize,
+    y_bottom: isize,
+    color: RawColor,
+) {
+    for y in y_top..y_bottom {
+        draw_pixel_unchecked(frame, Pixel::new(x, y), color);
+    }
+}
+
+// pub fn draw_image(frame: &mut [u8], location: Pixel, image: &RgbaImage) {
+//     let frame_offset = location.to_offset().unwrap();
+//     for (row_index, row) in image
+//         .as_raw()
+//         .chunks(image.dimensions().1 as usize * 4)
+//         .enumerate()
+//     {
+//         frame[frame_offset + row_index * WIDTH as usize * 4
+//             ..frame_offset + row_index * WIDTH as usize * 4 + image.dimensions().1 as usize * 4]
+//             .copy_from_slice(row);
+//     }
+// }
+
+pub fn draw_line(frame: &mut Canvas, a: Pixel, b: Pixel, color: RawColor) {
+    for (x, y) in Bresenham::new(a.to_tuple(), b.to_tuple()) {
+        draw_pixel(frame, Pixel::new(x, y), color);
+    }
+}
+
+/// Fill the polygon with corners at points, pixels whose centres lie inside it by
+/// the even-odd rule, a row at a time.
+pub fn fill_polygon(frame: &mut Canvas, points: &[Pixel], color: RawColor) {
+    for_polygon_pixels(frame, points, |frame, pixel| {
+        draw_pixel_unchecked(frame, pixel, color);
+    });
+}
+
+/// Fill the polygon with corners at points as `fill_polygon` does, blending color
+/// over what's beneath.
+pub fn blend_polygon(frame: &mut Canvas, points: &[Pixel], color: RawColorA) {
+    for_polygon_pixels(frame, points, |frame, pixel| {
+        blend_pixel_unchecked(frame, pixel, color);
+    });
+}
+
+fn for_polygon_pixels(frame: &mut Canvas, points: &[Pixel], mut f: impl FnMut(&mut Canvas, Pixel)) {
+    let Some(y_min) = points.iter().map(|p| p.y).min() else { return };
+    let y_max = points.iter().map(|p| p.y).max().unwrap_or(y_min);
+
+    let mut crossings = Vec::new();
+    for y in y_min.max(0)..y_max.min(frame.height as isize) {
+        let y_center = y as f32 + 0.5;
+        crossings.clear();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            let (a_y, b_y) = (a.y as f32, b.y as f32);
+            if (a_y <= y_center) != (b_y <= y_center) {
+                let t = (y_center - a_y) / (b_y - a_y);
+                crossings.push(a.x as f32 + t * (b.x - a.x) as f32);
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+
+        for pair in crossings.chunks_exact(2) {
+            let x_start = ((pair[0] - 0.5).ceil() as isize).max(0);
+            let x_end = ((pair[1] - 0.5).ceil() as isize).min(frame.width as isize);
+            for x in x_start..x_end {
+                f(frame, Pixel::new(x, y));
+            }
+        }
+    }
+}
+
+/// Fill the rectangle from min to max, exclusive, blending color over what's
+/// beneath. Clipped to the frame.
+pub fn blend_rect(frame: &mut Canvas, min: Pixel, max: Pixel, color: RawColorA) {
+    for y in min.y.max(0)..max.y.min(frame.height as isize) {
+        for x in min.x.max(0)..max.x.min(frame.width as isize) {
+            blend_pixel_unchecked(frame, Pixel::new(x, y), color);
+        }
+    }
+}
+
+/// Draw an opaque pixel, setting its alpha so the frame stays opaque wherever drawn.
+pub fn draw_pixel(frame: &mut Canvas, pixel: Pixel, color: RawColor) {
+    if let Some(offset) = frame.offset(pixel) {
+        frame[offset..offset + 4].copy_from_slice(&color.with_alpha(0xff).0);
+    }
+}
+
+pub fn draw_pixel_unchecked(frame: &mut Canvas, pixel: Pixel, color: RawColor) {
+    let offset = frame.offset_unchecked(pixel);
+    frame[offset..offset + 4].copy_from_slice(&color.with_alpha(0xff).0);
+}
+
+/// Blend color over the pixel by its alpha, skipping pixels outside the frame.
+pub fn blend_pixel(frame: &mut Canvas, pixel: Pixel, color: RawColorA) {
+    if frame.contains(pixel) {
+        blend_pixel_unchecked(frame, pixel, color);
+    }
+}
+
+pub fn blend_pixel_unchecked(frame: &mut Canvas, pixel: Pixel, color: RawColorA) {
+    let offset = frame.offset_unchecked(pixel);
+    let below = &mut frame[offset..offset + 4];
+    let blended = blend_over(color, [below[0], below[1], below[2], below[3]]);
+    below.copy_from_slice(&blended);
+}
+
+/// Source over compositing of color onto below, straight rather than
+/// premultiplied alpha. Opaque colors replace below, transparent ones leave it.
+pub fn blend_over(color: RawColorA, below: [u8; 4]) -> [u8; 4] {
+    let alpha = color.alpha() as u32;
+    match alpha {
+        0 => return below,
+        0xff => return color.0,
+        _ => {}
+    }
+
+    // Weights out of 255 * 255, rounding to nearest
+    let over = alpha * 0xff;
+    let under = below[3] as u32 * (0xff - alpha);
+    let out_alpha = over + under;
+    let channel = |i: usize| {
+        let sum = color.0[i] as u32 * over + below[i] as u32 * under;
+        ((sum + out_alpha / 2) / out_alpha) as u8
+    };
+    [
+        channel(0),
+        channel(1),
+        channel(2),
+        ((out_alpha + 0x7f) / 0xff) as u8,
+    ]
+}
@@ -0,0 +1,107 @@
+use super::*;
+
+pub const GLYPH_WIDTH: isize = 3;
+pub const GLYPH_HEIGHT: isize = 5;
+/// Horizontal distance between the start of one glyph and the next.
+pub const GLYPH_ADVANCE: isize = GLYPH_WIDTH + 1;
+
+/// Rows of a glyph from top, leftmost pixel in the highest of three bits. Lowercase
+/// letters are drawn as uppercase, unknown characters as blanks.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b110, 0b001, 0b010, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => [0b000; GLYPH_HEIGHT as usize],
+    }
+}
+
+/// Width in pixels of text drawn with `draw_text`.
+pub fn text_width(text: &str) -> isize {
+    scaled_text_width(text, 1)
+}
+
+/// Width in pixels of text drawn with `draw_scaled_text` at scale.
+pub fn scaled_text_width(text: &str, scale: isize) -> isize {
+    (text.chars().count() as isize * GLYPH_ADVANCE - 1).max(0) * scale
+}
+
+/// Draw text in the HUD font with its top left at position, clipped to the frame.
+pub fn draw_text(frame: &mut Canvas, position: Pixel, text: &str, color: RawColor) {
+    draw_scaled_text(frame, position, text, color, 1);
+}
+
+/// Draw text with each pixel of the HUD font a square of scale pixels, for text
+/// readable at higher resolutions.
+pub fn draw_scaled_text(
+    frame: &mut Canvas,
+    position: Pixel,
+    text: &str,
+    color: RawColor,
+    scale: isize,
+) {
+    for (index, c) in text.chars().enumerate() {
+        let x = position.x + index as isize * GLYPH_ADVANCE * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let (x, y) = (x + column * scale, position.y + row as isize * scale);
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        draw_pixel(frame, Pixel::new(x + dx, y + dy), color);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use super::*;
+
+use std::ops::{Deref, DerefMut};
+
+/// Something the renderer can draw a frame into, an RGBA8 buffer with origin at top
+/// left.
+pub trait FrameTarget: Component {
+    fn frame(&self) -> &[u8];
+    fn frame_mut(&mut self) -> &mut [u8];
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    /// Reallocate the frame at a new resolution.
+    fn resize(&mut self, width: u32, height: u32);
+    /// Called once drawing for a frame is complete.
+    fn present(&mut self) {}
+
+    /// Frame to draw into, knowing its own size.
+    fn canvas(&mut self) -> Canvas<'_> {
+        let (width, height) = (self.width(), self.height());
+        Canvas {
+            data: self.frame_mut(),
+            width,
+            height,
+            stride: width,
+        }
+    }
+}
+
+/// RGBA8 frame being drawn into, with its size in pixels. Derefs to the frame's bytes.
+#[derive(Debug)]
+pub struct Canvas<'a> {
+    pub data: &'a mut [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Pixels from one row to the next in data, wider than the canvas when it's a
+    /// viewport into a larger frame.
+    pub stride: u32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn contains(&self, pixel: Pixel) -> bool {
+        pixel.x >= 0
+            && pixel.x < self.width as isize
+            && pixel.y >= 0
+            && pixel.y < self.height as isize
+    }
+
+    /// Byte offset of pixel in the frame, `None` when outside it.
+    pub fn offset(&self, pixel: Pixel) -> Option<usize> {
+        self.contains(pixel).then(|| self.offset_unchecked(pixel))
+    }
+
+    pub fn offset_unchecked(&self, pixel: Pixel) -> usize {
+        (pixel.y as u32 * self.stride * 4 + pixel.x as u32 * 4) as usize
+    }
+
+    /// Part of the canvas from min to max, exclusive, with pixels relative to min. Both
+    /// are clamped to the canvas, and its data ends at its last pixel so nothing past
+    /// the part can be drawn over.
+    pub fn viewport(&mut self, min: Pixel, max: Pixel) -> Canvas<'_> {
+        let (right, bottom) = (self.width as isize, self.height as isize);
+        let min = Pixel::new(min.x.clamp(0, right), min.y.clamp(0, bottom));
+        let max = Pixel::new(max.x.clamp(min.x, right), max.y.clamp(min.y, bottom));
+        let (width, height) = ((max.x - min.x) as u32, (max.y - min.y) as u32);
+        let data = if width == 0 || height == 0 {
+            &mut []
+        } else {
+            let start = self.offset_unchecked(min);
+            let end = self.offset_unchecked(Pixel::new(max.x - 1, max.y - 1)) + 4;
+            &mut self.data[start..end]
+        };
+        Canvas {
+            data,
+            width,
+            height,
+            stride: self.stride,
+        }
+    }
+}
+
+impl<'a> Deref for Canvas<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl<'a> DerefMut for Canvas<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+}
+
+impl FrameTarget for PixelsWrapper {
+    fn frame(&self) -> &[u8] {
+        self.pixels.frame()
+    }
+
+    fn frame_mut(&mut self) -> &mut [u8] {
+        self.pixels.frame_mut()
+    }
+
+    fn width(&self) -> u32 {
+        self.pixels.context().texture_extent.width
+    }
+
+    fn height(&self) -> u32 {
+        self.pixels.context().texture_extent.height
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Err(error) = self.pixels.resize_buffer(width, height) {
+            error!("failed to resize frame to {width}x{height}: {error}");
+        }
+    }
+
+    // Presented by `bevy_pixels` itself in `PixelsSet::Render`
+}
+
+/// Frame held in memory, for headless rendering or copying elsewhere.
+#[derive(Component, Debug, Clone)]
+pub struct ImageFrame {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageFrame {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            data: vec![0; (width * height * 4) as usize],
+            width,
+            height,
+        }
+    }
+}
+
+impl FrameTarget for ImageFrame {
+    fn frame(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn frame_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        *self = Self::new(width, height);
+    }
+}
+
+/// Resize frame targets to the resolution in [`RenderConfig`] before drawing.
+pub fn resize_frame_system<T: FrameTarget>(
+    config: Res<RenderConfig>,
+    mut target_query: Query<&mut T>,
+) {
+    for mut target in &mut target_query {
+        let (width, height) = (config.width(), config.height());
+        if target.width() != width || target.height() != height {
+            info!("resizing frame to {width}x{height}");
+            target.resize(width, height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_ends_at_its_last_pixel() {
+        let mut frame = ImageFrame::new(4, 3);
+        let mut canvas = frame.canvas();
+        let mut viewport = canvas.viewport(Pixel::new(1, 1), Pixel::new(3, 3));
+        assert_eq!((viewport.width, viewport.height), (2, 2));
+        // Second row from x = 1, on to the third row up to x = 3
+        assert_eq!(viewport.len(), (4 + 2) * 4);
+
+        viewport.fill(0xff);
+        let drawn: Vec<bool> = frame.data.chunks(4).map(|pixel| pixel[0] == 0xff).collect();
+        let expected = [0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0].map(|drawn| drawn == 1);
+        assert_eq!(drawn, expected, "only rows between are drawn over");
+    }
+
+    #[test]
+    fn viewport_is_clamped() {
+        let mut frame = ImageFrame::new(4, 3);
+        let mut canvas = frame.canvas();
+        let viewport = canvas.viewport(Pixel::new(-2, 1), Pixel::new(9, 9));
+        assert_eq!((viewport.width, viewport.height), (4, 2));
+        assert_eq!(viewport.len(), 4 * 2 * 4);
+
+        let viewport = canvas.viewport(Pixel::new(3, 2), Pixel::new(1, 1));
+        assert_eq!((viewport.width, viewport.height), (0, 0));
+        assert!(viewport.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use super::*;
+use crate::{hub::*, persist::*, time_scale::*};
+
+use bevy::utils::Duration;
+use std::{collections::HashMap, fmt::Write};
+
+/// Pixels left clear around the centre of the crosshair.
+const CROSSHAIR_GAP: isize = 2;
+/// Pixels in each arm of the crosshair, past the gap.
+const CROSSHAIR_LENGTH: isize = 3;
+
+/// Value of a HUD data source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HudValue {
+    Text(String),
+    Number(f32),
+}
+
+impl From<&str> for HudValue {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<String> for HudValue {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<f32> for HudValue {
+    fn from(number: f32) -> Self {
+        Self::Number(number)
+    }
+}
+
+impl From<u32> for HudValue {
+    fn from(number: u32) -> Self {
+        Self::Number(number as f32)
+    }
+}
+
+impl From<usize> for HudValue {
+    fn from(number: usize) -> Self {
+        Self::Number(number as f32)
+    }
+}
+
+/// Named values widgets can show. `sector`, `map`, `time` and `map_time` are kept
+/// up to date by [`hud_data_system`], with the view's position as `x`, `y` and `z`
+/// and `heading` in degrees of its [`Direction`]. So are [`RenderStats`] of the last frame drawn,
+/// `draw_ms`, `background_ms`, `walls_ms`, `sprites_ms` and `minimap_ms` in
+/// milliseconds with `walls_drawn`, `sectors_visited`, `column_spans` and
+/// `walls_cached`. Games set
+/// anything else, such as `health`.
+#[derive(Resource, Debug, Default)]
+pub struct HudData(HashMap<String, HudValue>);
+
+impl HudData {
+    pub fn set(&mut self, name: &str, value: impl Into<HudValue>) {
+        self.0.insert(name.to_string(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HudValue> {
+        self.0.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+}
+
+/// Line of text drawn over the world. Text is a template where `{name}` is replaced
+/// with the data source of that name, and `{name:.N}` rounds numbers to N decimal
+/// places. Missing sources show as `-`.
+#[derive(Debug, Clone)]
+pub struct HudWidget {
+    pub position: Pixel,
+    pub text: String,
+    pub color: RawColor,
+    /// Size of each pixel of the font, larger for higher resolutions.
+    pub scale: isize,
+    /// Blended behind the text, a pixel of the font beyond it each side, if any.
+    pub background: Option<RawColorA>,
+}
+
+impl HudWidget {
+    pub fn new(position: Pixel, text: &str) -> Self {
+        Self {
+            position,
+            text: text.to_string(),
+            color: HUD_TEXT_COLOR,
+            scale: 1,
+            background: Some(HUD_BACKGROUND_COLOR),
+        }
+    }
+
+    pub fn with_color(mut self, color: RawColor) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: isize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn with_background(mut self, background: Option<RawColorA>) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Text with data sources filled in.
+    pub fn format(&self, data: &HudData) -> String {
+        let mut output = String::new();
+        let mut rest = self.text.as_str();
+        while let Some(start) = rest.find('{') {
+            output.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else { break };
+            let binding = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+
+            let (name, precision) = match binding.split_once(":.") {
+                Some((name, precision)) => (name, precision.parse().ok()),
+                None => (binding, None),
+            };
+            let _ = match (data.get(name), precision) {
+                (Some(HudValue::Number(number)), Some(precision)) => {
+                    write!(output, "{number:.precision$}")
+                }
+                (Some(HudValue::Number(number)), None) => write!(output, "{number}"),
+                (Some(HudValue::Text(text)), _) => write!(output, "{text}"),
+                (None, _) => write!(output, "-"),
+            };
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+/// Widgets drawn over the world in the overlay.
+#[derive(Resource, Debug)]
+pub struct Hud {
+    pub visible: bool,
+    pub widgets: Vec<HudWidget>,
+    /// Color of the crosshair drawn at the centre of the frame, if any.
+    pub crosshair: Option<RawColor>,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            widgets: Vec::new(),
+            crosshair: None,
+        }
+    }
+}
+
+/// Update built-in HUD data sources.
+#[allow(clippy::too_many_arguments)]
+pub fn hud_data_system(
+    mut hud_data: ResMut<HudData>,
+    mut map_time: Local<f32>,
+    mut map_entered_events: EventReader<MapEntered>,
+    view: Res<View>,
+    persistence: Res<Persistence>,
+    stats: Res<RenderStats>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+) {
+    if map_entered_events.iter().count() > 0 {
+        *map_time = 0.0;
+    }
+    *map_time += time_scale.delta_seconds(&time);
+
+    match view.current_sector {
+        Some(sector) => hud_data.set("sector", sector.0),
+        None => hud_data.remove("sector"),
+    }
+    hud_data.set("x", view.position.0.x);
+    hud_data.set("y", view.position.0.y);
+    hud_data.set("z", view.position.0.z);
+    hud_data.set("heading", view.direction.0.to_degrees().rem_euclid(360.0));
+    match persistence.current_map() {
+        Some(map) => hud_data.set("map", map),
+        None => hud_data.remove("map"),
+    }
+    hud_data.set("time", time.elapsed_seconds());
+    hud_data.set("map_time", *map_time);
+
+    let milliseconds = |duration: Duration| duration.as_secs_f32() * 1000.0;
+    hud_data.set("draw_ms", milliseconds(stats.total()));
+    hud_data.set("background_ms", milliseconds(stats.background));
+    hud_data.set("walls_ms", milliseconds(stats.walls));
+    hud_data.set("sprites_ms", milliseconds(stats.sprites));
+    hud_data.set("minimap_ms", milliseconds(stats.minimap));
+    hud_data.set("walls_drawn", stats.walls_drawn);
+    hud_data.set("sectors_visited", stats.sectors_visited);
+    hud_data.set("column_spans", stats.column_spans);
+    hud_data.set("walls_cached", stats.walls_cached);
+}
+
+pub fn draw_hud_system<T: FrameTarget>(
+    hud: Res<Hud>,
+    hud_data: Res<HudData>,
+    mut target_query: Query<&mut T, With<FollowView>>,
+) {
+    if !hud.visible {
+        return;
+    }
+    for mut target in &mut target_query {
+        let frame = &mut target.canvas();
+        if let Some(color) = hud.crosshair {
+            draw_crosshair(frame, color);
+        }
+        for widget in &hud.widgets {
+            let text = widget.format(&hud_data);
+            if let (Some(background), false) = (widget.background, text.is_empty()) {
+                let (position, scale) = (widget.position, widget.scale);
+                let min = Pixel::new(position.x - scale, position.y - scale);
+                let max = Pixel::new(
+                    position.x + scaled_text_width(&text, scale) + scale,
+                    position.y + (GLYPH_HEIGHT + 1) * scale,
+                );
+                blend_rect(frame, min, max, background);
+            }
+            draw_scaled_text(frame, widget.position, &text, widget.color, widget.scale);
+        }
+    }
+}
+
+/// Draw a cross with a gap in the middle at the centre of frame.
+fn draw_crosshair(frame: &mut Canvas, color: RawColor) {
+    let center = Pixel::new(frame.width as isize / 2, frame.height as isize / 2);
+    for offset in CROSSHAIR_GAP..=CROSSHAIR_GAP + CROSSHAIR_LENGTH {
+        for (dx, dy) in [(offset, 0), (-offset, 0), (0, offset), (0, -offset)] {
+            draw_pixel(frame, Pixel::new(center.x + dx, center.y + dy), color);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use super::*;
+
+use std::f32::consts::{PI, TAU};
+
+pub const LEGEND_WIDTH: isize = 48;
+pub const LEGEND_HEIGHT: isize = 4;
+/// Rate the absolute minimap turns towards its target rotation, higher is faster.
+pub const MINIMAP_ROTATION_SMOOTHING: f32 = 8.0;
+/// Centre of the north indicator, from the top right of the frame.
+const NORTH_INSET: isize = 12;
+const NORTH_LENGTH: f32 = 7.0;
+/// Brightness of the oldest breadcrumb, the newest being full.
+const BREADCRUMB_FADE: f32 = 0.2;
+/// Opacity of sector fills over the world, fainter than walls so they stand out.
+const FILL_OPACITY: f32 = 0.5;
+
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub enum Minimap {
+    #[default]
+    Off,
+    FirstPerson,
+    Absolute,
+}
+
+/// How walls are colored on the minimap.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub enum MinimapColoring {
+    /// Wall colors as drawn in the world.
+    #[default]
+    Walls,
+    /// Floor height of each sector along a gradient, low blue to high red, with a
+    /// legend.
+    Height,
+}
+
+/// Rotation of the absolute minimap. North is up unless aligned to heading, where
+/// the map turns smoothly so the view faces up.
+#[derive(Resource, Debug, Default)]
+pub struct MinimapRotation {
+    pub align_to_heading: bool,
+    /// Current rotation in radians, eased towards its target.
+    angle: f32,
+}
+
+impl MinimapRotation {
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+}
+
+/// Which sectors the minimap shows and how.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub struct MinimapSectors {
+    /// Fill sectors under their walls, with their floor color or height.
+    pub fill: bool,
+    /// Only show sectors the view has been in, as recorded by the automap.
+    pub explored_only: bool,
+}
+
+/// Trail of the view's recent positions drawn on the minimap, taken from the
+/// automap trail.
+#[derive(Resource, Debug, Copy, Clone)]
+pub struct Breadcrumbs {
+    pub enabled: bool,
+    /// Most recent positions shown.
+    pub length: usize,
+}
+
+impl Default for Breadcrumbs {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            length: 64,
+        }
+    }
+}
+
+/// Mapping from world and view space to pixels on the minimap.
+#[derive(Debug, Copy, Clone)]
+pub struct MinimapTransform {
+    config: RenderConfig,
+    world_to_map: Mat3,
+    view_to_map: Mat3,
+}
+
+impl MinimapTransform {
+    /// Transform for minimap mode, `None` when off.
+    pub fn new(
+        config: &RenderConfig,
+        minimap: Minimap,
+        view: &SectorCamera,
+        rotation: &MinimapRotation,
+    ) -> Option<Self> {
+        let view_matrix = view.view_matrix();
+        let reverse_view_matrix =
+            Mat3::from_translation(vec2(view.position.0.x, view.position.0.y))
+                * Mat3::from_rotation_z(view.direction.0);
+
+        let world_to_map = match minimap {
+            Minimap::Off => return None,
+            Minimap::FirstPerson => view_matrix,
+            Minimap::Absolute => Mat3::from_rotation_z(-rotation.angle),
+        };
+
+        Some(Self {
+            config: *config,
+            world_to_map,
+            view_to_map: world_to_map * reverse_view_matrix,
+        })
+    }
+
+    pub fn world_to_map(&self, position: Position2) -> Pixel {
+        self.config
+            .minimap_pixel(position.transform(self.world_to_map))
+    }
+
+    pub fn view_to_map(&self, position: Position2) -> Pixel {
+        self.config
+            .minimap_pixel(position.transform(self.view_to_map))
+    }
+
+    /// World position under a point on the frame, in pixels from its top left.
+    pub fn map_to_world(&self, point: Vec2) -> Position2 {
+        self.config
+            .minimap_position(point)
+            .transform(self.world_to_map.inverse())
+    }
+
+    /// Direction of world north on the minimap, as a unit vector with y up.
+    pub fn north(&self) -> Vec2 {
+        self.world_to_map
+            .transform_vector2(Vec2::Y)
+            .normalize_or_zero()
+    }
+}
+
+/// Color along the height gradient, t from 0 for lowest to 1 for highest.
+pub fn height_color(t: f32) -> RawColor {
+    Hsv::new(240.0 * (1.0 - t.clamp(0.0, 1.0)), 1.0, 1.0).into()
+}
+
+/// Ease the absolute minimap rotation towards north or the view heading.
+pub fn minimap_rotation_system(
+    mut rotation: ResMut<MinimapRotation>,
+    view: Res<View>,
+    time: Res<Time>,
+) {
+    let target = if rotation.align_to_heading {
+        view.direction.0
+    } else {
+        0.0
+    };
+
+    // Turn the shortest way round
+    let difference = (target - rotation.angle + PI).rem_euclid(TAU) - PI;
+    let t = 1.0 - (-MINIMAP_ROTATION_SMOOTHING * time.delta_seconds()).exp();
+    rotation.angle = (rotation.angle + difference * t).rem_euclid(TAU);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn draw_minimap_system<T: FrameTarget>(
+    config: Res<RenderConfig>,
+    colors: Res<RenderColors>,
+    minimap: Res<Minimap>,
+    coloring: Res<MinimapColoring>,
+    minimap_sectors: Res<MinimapSectors>,
+    rotation: Res<MinimapRotation>,
+    breadcrumbs: Res<Breadcrumbs>,
+    automap: Res<Automap>,
+    mut target_query: Query<(&mut T, &SectorCamera)>,
+    sector_query: Query<&Sector>,
+    mut stats: ResMut<RenderStats>,
+) {
+    let started = Instant::now();
+    for (mut target, view) in &mut target_query {
+        let Some(transform) = MinimapTransform::new(&config, *minimap, view, &rotation) else { continue };
+        let frame = &mut target.canvas();
+        let projection = config.projection();
+        let view_matrix = view.view_matrix();
+
+        let floor_min = sector_query
+            .iter()
+            .map(|s| s.floor.0)
+            .fold(f32::MAX, f32::min);
+        let floor_max = sector_query
+            .iter()
+            .map(|s| s.floor.0)
+            .fold(f32::MIN, f32::max);
+        let floor_t = |floor: f32| {
+            if floor_max > floor_min {
+                (floor - floor_min) / (floor_max - floor_min)
+            } else {
+                0.5
+            }
+        };
+
+        let shown = || {
+            sector_query
+                .iter()
+                .filter(|s| !minimap_sectors.explored_only || automap.is_visited(s.id))
+        };
+
+        // Fill sectors under walls
+        if minimap_sectors.fill {
+            for sector in shown() {
+                let color = match *coloring {
+                    MinimapColoring::Walls => {
+                        shade(sector.floor_color.unwrap_or(colors.floor), sector.light)
+                    }
+                    MinimapColoring::Height => height_color(floor_t(sector.floor.0)),
+                };
+                let points: Vec<Pixel> = sector
+                    .vertices
+                    .iter()
+                    .map(|&vertex| transform.world_to_map(vertex))
+                    .collect();
+                blend_polygon(frame, &points, color.with_opacity(FILL_OPACITY));
+            }
+        }
+
+        // Draw walls
+        for sector in shown() {
+            for wall in sector.to_walls() {
+                let color: RawColor = match *coloring {
+                    MinimapColoring::Walls => wall.color.into(),
+                    MinimapColoring::Height => height_color(floor_t(sector.floor.0)),
+                };
+                let left = transform.world_to_map(wall.left);
+                let right = transform.world_to_map(wall.right);
+
+                // Clipped in view space
+                let view_left = wall.left.transform(view_matrix);
+                let view_right = wall.right.transform(view_matrix);
+                let Some((view_left, view_right)) = clip_wall(projection, view_left, view_right)
+                else {
+                    draw_line(frame, left, right, colors.wall_clipped);
+                    continue;
+                };
+                let left_after_clip = transform.view_to_map(view_left);
+                let right_after_clip = transform.view_to_map(view_right);
+
+                if left_after_clip != left {
+                    draw_line(frame, left, left_after_clip, colors.wall_clipped);
+                }
+                if right_after_clip != right {
+                    draw_line(frame, right_after_clip, right, colors.wall_clipped);
+                }
+                draw_line(frame, left_after_clip, right_after_clip, color);
+            }
+        }
+
+        // Draw breadcrumbs, fading with age
+        if breadcrumbs.enabled {
+            let trail = automap.trail();
+            let recent = &trail[trail.len().saturating_sub(breadcrumbs.length)..];
+            for (i, &position) in recent.iter().enumerate() {
+                let age = 1.0 - (i + 1) as f32 / recent.len() as f32;
+                let value = 1.0 - age * (1.0 - BREADCRUMB_FADE);
+                let color: RawColor = Hsv::new(60.0, 1.0, value).into();
+                draw_pixel(frame, transform.world_to_map(position), color);
+            }
+        }
+
+        // Draw frustum and player
+        let player = transform.view_to_map(Position2(vec2(0.0, 0.0)));
+        let near_left = transform.view_to_map(Position2(projection.left_clip_1));
+        let near_right = transform.view_to_map(Position2(projection.right_clip_2));
+        let far_left = transform.view_to_map(Position2(projection.left_clip_2));
+        let far_right = transform.view_to_map(Position2(projection.right_clip_1));
+
+        draw_line(frame, near_left, far_left, colors.frustum);
+        draw_line(frame, near_right, far_right, colors.frustum);
+        draw_line(frame, near_left, near_right, colors.frustum);
+        draw_pixel(frame, player, colors.player);
+
+        // Label frustum with field of view, centred beyond its far edge
+        let fov_text = format!("{:.0}", config.fov_x_radians().to_degrees());
+        let label = transform.view_to_map(Position2(vec2(0.0, FAR + 0.5)));
+        draw_text(
+            frame,
+            Pixel::new(
+                label.x - text_width(&fov_text) / 2,
+                label.y - GLYPH_HEIGHT / 2,
+            ),
+            &fov_text,
+            colors.frustum,
+        );
+
+        // Draw north indicator
+        if *minimap == Minimap::Absolute {
+            let north = transform.north() * NORTH_LENGTH;
+            let centre = Pixel::new(frame.width as isize - NORTH_INSET, NORTH_INSET);
+            let tip = Pixel::new(
+                centre.x + north.x.round() as isize,
+                centre.y - north.y.round() as isize,
+            );
+            draw_line(frame, centre, tip, colors.text);
+            draw_pixel(frame, centre, colors.player);
+
+            let label = Pixel::new(
+                centre.x + (north.x * 1.6).round() as isize - GLYPH_WIDTH / 2,
+                centre.y - (north.y * 1.6).round() as isize - GLYPH_HEIGHT / 2,
+            );
+            draw_text(frame, label, "N", colors.text);
+        }
+
+        // Draw legend of the height gradient, lowest and highest floor either side
+        if *coloring == MinimapColoring::Height && !sector_query.is_empty() {
+            let y = frame.height as isize - GLYPH_HEIGHT - 4;
+            let min_text = format!("{floor_min:.1}");
+            draw_text(frame, Pixel::new(4, y), &min_text, colors.text);
+
+            let bar_x = 4 + text_width(&min_text) + 3;
+            for x in 0..LEGEND_WIDTH {
+                let color = height_color(x as f32 / (LEGEND_WIDTH - 1) as f32);
+                for row in 1..=LEGEND_HEIGHT {
+                    draw_pixel(frame, Pixel::new(bar_x + x, y + row), color);
+                }
+            }
+
+            let max_text = format!("{floor_max:.1}");
+            draw_text(
+                frame,
+                Pixel::new(bar_x + LEGEND_WIDTH + 3, y),
+                &max_text,
+                colors.text,
+            );
+        }
+    }
+    stats.minimap = started.elapsed();
+}
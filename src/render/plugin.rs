@@ -0,0 +1,128 @@
+use super::*;
+
+use std::marker::PhantomData;
+
+/// Colors the renderer fills with, other than those of the map itself.
+#[derive(Resource, Debug, Copy, Clone, PartialEq)]
+pub struct RenderColors {
+    /// Behind everything, where no sector is drawn.
+    pub background: RawColor,
+    pub ceiling: RawColor,
+    pub floor: RawColor,
+    /// Minimap walls, or their parts, outside the view frustum.
+    pub wall_clipped: RawColor,
+    pub frustum: RawColor,
+    pub player: RawColor,
+    /// Minimap labels.
+    pub text: RawColor,
+}
+
+impl Default for RenderColors {
+    fn default() -> Self {
+        Self {
+            background: RawColor([0x00, 0x00, 0x00]),
+            ceiling: CEILING_COLOR,
+            floor: FLOOR_COLOR,
+            wall_clipped: WALL_CLIPPED_COLOR,
+            frustum: FRUSTUM_COLOR,
+            player: PLAYER_COLOR,
+            text: HUD_TEXT_COLOR,
+        }
+    }
+}
+
+/// Software portal renderer drawing the view of the map, its minimap and HUD into a
+/// frame target, `PixelsWrapper` unless another is given. Needs [`SectorPlugin`] for
+/// the map and the sets it draws in.
+#[derive(Debug, Clone)]
+pub struct SectorRenderPlugin<T: FrameTarget = PixelsWrapper> {
+    pub config: RenderConfig,
+    pub camera: CameraConfig,
+    pub colors: RenderColors,
+    _target: PhantomData<fn() -> T>,
+}
+
+impl<T: FrameTarget> Default for SectorRenderPlugin<T> {
+    fn default() -> Self {
+        Self {
+            config: RenderConfig::default(),
+            camera: CameraConfig::default(),
+            colors: RenderColors::default(),
+            _target: PhantomData,
+        }
+    }
+}
+
+impl<T: FrameTarget> Plugin for SectorRenderPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            (SectorSet::Draw, SectorSet::Overlay)
+                .chain()
+                .in_set(PixelsSet::Draw),
+        )
+        .add_asset::<Texture>()
+        .init_asset_loader::<TextureLoader>()
+        .insert_resource(self.config)
+        .insert_resource(self.camera)
+        .insert_resource(self.colors)
+        .init_resource::<View>()
+        .init_resource::<Minimap>()
+        .init_resource::<MinimapColoring>()
+        .init_resource::<MinimapSectors>()
+        .init_resource::<TextureHandles>()
+        .init_resource::<PortalTraversal>()
+        .init_resource::<MinimapRotation>()
+        .init_resource::<Breadcrumbs>()
+        .init_resource::<Automap>()
+        .init_resource::<Hud>()
+        .init_resource::<HudData>()
+        .init_resource::<PostProcess>()
+        .init_resource::<FogConfig>()
+        .init_resource::<RenderStats>()
+        .init_resource::<WallCache>()
+        .add_systems(
+            (
+                camera_config_system,
+                camera_setup_system::<T>,
+                follow_view_system,
+                texture_handle_system,
+                sector_entered_system,
+                hud_data_system,
+                automap_system,
+                minimap_rotation_system,
+                liquid_tint_system,
+            )
+                .in_set(SectorSet::PreDraw),
+        )
+        .add_system(
+            resize_frame_system::<T>
+                .after(camera_config_system)
+                .in_set(SectorSet::PreDraw),
+        )
+        .add_systems(
+            (
+                draw_background_system::<T>,
+                draw_wall_system::<T>,
+                draw_sprite_system::<T>,
+            )
+                .chain()
+                .in_set(SectorSet::Draw),
+        )
+        .add_systems(
+            (draw_minimap_system::<T>, draw_hud_system::<T>)
+                .chain()
+                .in_set(SectorSet::Overlay),
+        )
+        .add_system(
+            post_process_system::<T>
+                .after(draw_minimap_system::<T>)
+                .before(draw_hud_system::<T>)
+                .in_set(SectorSet::Overlay),
+        )
+        .add_system(
+            present_frame_system::<T>
+                .after(SectorSet::Overlay)
+                .in_set(PixelsSet::Draw),
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use super::*;
+
+/// Color effect applied to every pixel of the frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostEffect {
+    /// Brown monochrome, blended in by amount from 0 for none to 1 for full.
+    Sepia(f32),
+    /// Blend towards color by amount, such as blue underwater.
+    Tint { color: RawColor, amount: f32 },
+    /// Tint fading out over duration seconds, such as red on taking damage. Removed
+    /// by [`post_process_system`] once faded.
+    Flash {
+        color: RawColor,
+        amount: f32,
+        duration: f32,
+        remaining: f32,
+    },
+    /// Closest color of a limited palette. Left unchanged when empty.
+    Palette(Vec<RawColor>),
+    /// Each of red, green and blue looked up in its own table, for color grading.
+    Lut(Box<[[u8; 256]; 3]>),
+}
+
+impl PostEffect {
+    pub fn flash(color: RawColor, amount: f32, duration: f32) -> Self {
+        Self::Flash {
+            color,
+            amount,
+            duration,
+            remaining: duration,
+        }
+    }
+
+    /// Table grading each channel by a curve over 0 to 1, for [`PostEffect::Lut`].
+    pub fn lut(curve: impl Fn(usize, f32) -> f32) -> Self {
+        let mut table = Box::new([[0; 256]; 3]);
+        for (channel, entries) in table.iter_mut().enumerate() {
+            for (i, entry) in entries.iter_mut().enumerate() {
+                let value = curve(channel, i as f32 / 255.0).clamp(0.0, 1.0);
+                *entry = (value * 255.0).round() as u8;
+            }
+        }
+        Self::Lut(table)
+    }
+
+    pub fn apply(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        match self {
+            Self::Sepia(amount) => {
+                let [r, g, b] = [r, g, b].map(f32::from);
+                let sepia = [
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                ];
+                blend([r, g, b], sepia, *amount)
+            }
+            Self::Tint { color, amount } => blend_color([r, g, b], *color, *amount),
+            Self::Flash {
+                color,
+                amount,
+                duration,
+                remaining,
+            } => {
+                let fade = (remaining / duration.max(f32::EPSILON)).clamp(0.0, 1.0);
+                blend_color([r, g, b], *color, amount * fade)
+            }
+            Self::Palette(palette) => {
+                let distance = |color: &&RawColor| {
+                    let [pr, pg, pb] = color.0.map(i32::from);
+                    let (dr, dg, db) = (pr - r as i32, pg - g as i32, pb - b as i32);
+                    dr * dr + dg * dg + db * db
+                };
+                palette
+                    .iter()
+                    .min_by_key(distance)
+                    .map_or([r, g, b], |c| c.0)
+            }
+            Self::Lut(table) => [
+                table[0][r as usize],
+                table[1][g as usize],
+                table[2][b as usize],
+            ],
+        }
+    }
+}
+
+fn blend(from: [f32; 3], to: [f32; 3], amount: f32) -> [u8; 3] {
+    let amount = amount.clamp(0.0, 1.0);
+    [0, 1, 2].map(|i| lerp(from[i], to[i], amount).clamp(0.0, 255.0) as u8)
+}
+
+fn blend_color(from: [u8; 3], to: RawColor, amount: f32) -> [u8; 3] {
+    blend(from.map(f32::from), to.0.map(f32::from), amount)
+}
+
+/// Effects applied over the frame in order, each to the result of the last, after
+/// the world and minimap are drawn but before the HUD.
+#[derive(Resource, Debug, Default)]
+pub struct PostProcess {
+    pub effects: Vec<PostEffect>,
+}
+
+impl PostProcess {
+    pub fn push(&mut self, effect: PostEffect) {
+        self.effects.push(effect);
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+}
+
+/// Fade out flashes, then apply post-processing effects to every frame target.
+pub fn post_process_system<T: FrameTarget>(
+    mut post_process: ResMut<PostProcess>,
+    mut target_query: Query<&mut T>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    post_process.effects.retain_mut(|effect| match effect {
+        PostEffect::Flash { remaining, .. } => {
+            *remaining -= delta;
+            *remaining > 0.0
+        }
+        _ => true,
+    });
+    if post_process.effects.is_empty() {
+        return;
+    }
+
+    for mut target in &mut target_query {
+        for pixel in target.frame_mut().chunks_exact_mut(4) {
+            let color = post_process
+                .effects
+                .iter()
+                .fold([pixel[0], pixel[1], pixel[2]], |color, effect| {
+                    effect.apply(color)
+                });
+            pixel[..3].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Tint the frame the color of the liquid the view is under, while it is.
+pub fn liquid_tint_system(
+    view: Res<View>,
+    sector_query: Query<&Sector>,
+    mut post_process: ResMut<PostProcess>,
+    mut tint: Local<Option<PostEffect>>,
+) {
+    let liquid = view
+        .current_sector
+        .and_then(|id| sector_query.iter().find(|s| s.id == id))
+        .and_then(|sector| sector.liquid_at(view.position.0.z));
+    let wanted = liquid.map(|liquid| PostEffect::Tint {
+        color: liquid.kind.color(),
+        amount: Liquid::TINT,
+    });
+    if *tint == wanted {
+        return;
+    }
+
+    if let Some(old) = tint.take() {
+        if let Some(i) = post_process.effects.iter().position(|e| *e == old) {
+            post_process.effects.remove(i);
+        }
+    }
+    if let Some(new) = wanted.clone() {
+        post_process.push(new);
+    }
+    *tint = wanted;
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+/// Bounds a sector was drawn within when wall drawing reached it through a portal.
+#[derive(Debug, Clone)]
+pub struct ClipWindow {
+    pub visit: usize,
+    pub sector: SectorId,
+    pub x_min: isize,
+    pub x_max: isize,
+    /// Rows each column of the frame was open between, from top.
+    pub y_min: Vec<isize>,
+    pub y_max: Vec<isize>,
+}
+
+/// Windows sectors were seen through in the last frame drawn by the camera it's on,
+/// so sprites are hidden by the walls in front of them.
+#[derive(Component, Debug, Default)]
+pub struct SpriteClip {
+    pub windows: Vec<ClipWindow>,
+}
+
+impl SpriteClip {
+    pub fn record(
+        &mut self,
+        visit: usize,
+        sector: SectorId,
+        (x_min, x_max): (isize, isize),
+        y_min: &[isize],
+        y_max: &[isize],
+    ) {
+        // Depth first traversal comes back to a visit after those seen through it
+        if self.windows.iter().any(|w| w.visit == visit) {
+            return;
+        }
+        self.windows.push(ClipWindow {
+            visit,
+            sector,
+            x_min,
+            x_max,
+            y_min: y_min.to_vec(),
+            y_max: y_max.to_vec(),
+        });
+    }
+}
+
+/// Draw things with billboards standing on the floor of their sector, or at the height
+/// of their [`Position3`] when they have one, furthest first, clipped to the windows
+/// their sector was seen through.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_sprite_system<T: FrameTarget>(
+    config: Res<RenderConfig>,
+    fog: Res<FogConfig>,
+    mut target_query: Query<(Entity, &mut T, Option<&Children>)>,
+    camera_query: Query<(&SectorCamera, Option<&Viewport>, &SpriteClip)>,
+    sector_query: Query<&Sector>,
+    billboard_query: Query<(&Thing, &Billboard, Option<&Position3>)>,
+    texture_handles: Res<TextureHandles>,
+    textures: Res<Assets<Texture>>,
+    mut stats: ResMut<RenderStats>,
+) {
+    let started = Instant::now();
+    for (entity, mut target, children) in &mut target_query {
+        let mut canvas = target.canvas();
+        for camera_entity in target_cameras(entity, children) {
+            let Ok((camera, viewport, sprite_clip)) = camera_query.get(camera_entity) else { continue };
+            let viewport = viewport.copied().unwrap_or_default();
+            let Some((min, max)) = viewport.pixels(canvas.width, canvas.height) else { continue };
+            let frame = &mut canvas.viewport(min, max);
+            draw_sprites(
+                frame,
+                &config.viewport(frame),
+                &fog,
+                camera,
+                sprite_clip,
+                &sector_query,
+                &billboard_query,
+                &texture_handles,
+                &textures,
+            );
+        }
+    }
+    stats.sprites = started.elapsed();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_sprites(
+    frame: &mut Canvas,
+    config: &RenderConfig,
+    fog: &FogConfig,
+    view: &SectorCamera,
+    sprite_clip: &SpriteClip,
+    sector_query: &Query<&Sector>,
+    billboard_query: &Query<(&Thing, &Billboard, Option<&Position3>)>,
+    texture_handles: &TextureHandles,
+    textures: &Assets<Texture>,
+) {
+    let projection = config.projection();
+    let view_matrix = view.view_matrix();
+
+    let mut sprites: Vec<(Position2, &Sector, f32, &Thing, &Billboard)> = billboard_query
+        .iter()
+        .filter_map(|(thing, billboard, position)| {
+            let sector = sector_query.iter().find(|s| s.contains(thing.position))?;
+            let bottom = position.map_or(sector.floor.0, |p| p.0.z);
+            let center = thing.position.transform(view_matrix);
+            (center.0.y > NEAR).then_some((center, sector, bottom, thing, billboard))
+        })
+        .collect();
+    sprites.sort_by(|a, b| b.0 .0.y.total_cmp(&a.0 .0.y));
+
+    for (center, sector, bottom, thing, billboard) in sprites {
+        // Turned to face the view, so the same depth across
+        let view_bottom = Length(bottom - view.position.0.z);
+        let view_top = Length(bottom + thing.height.0 - view.position.0.z);
+        let half_width = vec2(thing.radius.0, 0.0);
+        let left = config.view_pixel(
+            project(projection, Position2(center.0 - half_width), view_bottom),
+            view.pitch,
+        );
+        let right = config.view_pixel(
+            project(projection, Position2(center.0 + half_width), view_bottom),
+            view.pitch,
+        );
+        let y_top = config
+            .view_pixel(project(projection, center, view_top), view.pitch)
+            .y;
+        let y_bottom = left.y;
+        if right.x <= left.x || y_bottom <= y_top {
+            continue;
+        }
+
+        let fogged = Fogged::new(sector.fog.as_ref().unwrap_or(fog), center.0.y, sector.light);
+        let color = fogged.color(billboard.color);
+        let texture = billboard
+            .texture
+            .as_deref()
+            .and_then(|path| texture_handles.get(textures, path));
+
+        for window in sprite_clip.windows.iter().filter(|w| w.sector == sector.id) {
+            for x in left.x.max(window.x_min)..right.x.min(window.x_max) {
+                let u = (x - left.x) as f32 / (right.x - left.x) as f32;
+                let y_min = y_top.max(window.y_min[x as usize]);
+                let y_max = y_bottom.min(window.y_max[x as usize]);
+                for y in y_min..y_max {
+                    let color = match texture {
+                        Some(texture) => {
+                            let v = (y - y_top) as f32 / (y_bottom - y_top) as f32;
+                            let Some(color) = texture.sample_opaque(u, v) else { continue };
+                            fogged.color(color)
+                        }
+                        None => color,
+                    };
+                    draw_pixel_unchecked(frame, Pixel::new(x, y), color);
+                }
+            }
+        }
+    }
+}
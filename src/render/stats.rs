@@ -0,0 +1,31 @@
+use super::*;
+
+use bevy::utils::Duration;
+
+/// Time spent in each draw system and how much was drawn, for the last frame drawn,
+/// across every frame target and camera. Reset as each frame starts drawing.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub struct RenderStats {
+    /// Clearing the frame to the background color.
+    pub background: Duration,
+    /// Walking portals and filling walls, floors and ceilings.
+    pub walls: Duration,
+    pub sprites: Duration,
+    pub minimap: Duration,
+    /// Walls drawn, seen through however many portals.
+    pub walls_drawn: usize,
+    /// Times a sector was entered through a portal, including the one each camera is
+    /// in.
+    pub sectors_visited: usize,
+    /// Column spans filled for walls, floors and ceilings.
+    pub column_spans: usize,
+    /// Frame targets whose walls were copied from the [`WallCache`] rather than
+    /// drawn, nothing they show having changed.
+    pub walls_cached: usize,
+}
+
+impl RenderStats {
+    pub fn total(&self) -> Duration {
+        self.background + self.walls + self.sprites + self.minimap
+    }
+}
@@ -0,0 +1,144 @@
+use super::*;
+
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+
+/// World units covered by one repeat of a wall texture, across and up.
+pub const TEXTURE_REPEAT: f32 = 2.0;
+
+/// Image drawn on walls, repeating in both directions, or on billboards.
+#[derive(TypeUuid, Debug, Clone)]
+#[uuid = "5b0e3f2c-8d4a-4c61-9f57-2a7e6c1d0b93"]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    /// Colors in rows from top left.
+    pub pixels: Vec<RawColor>,
+    /// Whether each pixel is drawn on billboards and mid-textures, false where the
+    /// image is transparent. Walls draw every pixel.
+    pub opaque: Vec<bool>,
+}
+
+impl Texture {
+    /// Color at u across and v down, in repeats of the texture.
+    pub fn sample(&self, u: f32, v: f32) -> RawColor {
+        self.pixels[self.index(u, v)]
+    }
+
+    /// Color at u across and v down, `None` where transparent.
+    pub fn sample_opaque(&self, u: f32, v: f32) -> Option<RawColor> {
+        let index = self.index(u, v);
+        self.opaque[index].then_some(self.pixels[index])
+    }
+
+    fn index(&self, u: f32, v: f32) -> usize {
+        let x = (u.rem_euclid(1.0) * self.width as f32) as usize;
+        let y = (v.rem_euclid(1.0) * self.height as f32) as usize;
+        y.min(self.height - 1) * self.width + x.min(self.width - 1)
+    }
+}
+
+/// Column of a wall texture as it lies in the frame, shaded for distance in fog.
+#[derive(Debug, Copy, Clone)]
+pub struct TextureColumn<'a> {
+    pub texture: &'a Texture,
+    pub u: f32,
+    /// v at the top row of the frame, continuing down by `v_step` each row.
+    pub v_origin: f32,
+    pub v_step: f32,
+    pub fogged: Fogged,
+}
+
+impl TextureColumn<'_> {
+    pub fn color(&self, y: isize) -> RawColor {
+        let color = self
+            .texture
+            .sample(self.u, self.v_origin + y as f32 * self.v_step);
+        self.fogged.color(color)
+    }
+
+    /// Color at row y, `None` where the texture is transparent.
+    pub fn color_opaque(&self, y: isize) -> Option<RawColor> {
+        let color = self
+            .texture
+            .sample_opaque(self.u, self.v_origin + y as f32 * self.v_step)?;
+        Some(self.fogged.color(color))
+    }
+}
+
+#[derive(Default)]
+pub struct TextureLoader;
+
+impl AssetLoader for TextureLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let image = image::load_from_memory(bytes)?.to_rgba8();
+            if image.width() == 0 || image.height() == 0 {
+                return Err(Error::msg("texture has no pixels"));
+            }
+            let texture = Texture {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                pixels: image
+                    .pixels()
+                    .map(|pixel| RawColor([pixel.0[0], pixel.0[1], pixel.0[2]]))
+                    .collect(),
+                opaque: image.pixels().map(|pixel| pixel.0[3] >= 128).collect(),
+            };
+            load_context.set_default_asset(LoadedAsset::new(texture));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["png"]
+    }
+}
+
+/// Handles of textures used by walls and billboards, by asset path, keeping them
+/// loaded.
+#[derive(Resource, Debug, Default)]
+pub struct TextureHandles(pub HashMap<String, Handle<Texture>>);
+
+impl TextureHandles {
+    pub fn get<'a>(&self, textures: &'a Assets<Texture>, path: &str) -> Option<&'a Texture> {
+        textures.get(self.0.get(path)?)
+    }
+}
+
+/// Load textures of walls, mid-textures, upper and lower walls and billboards as they
+/// come to use them.
+pub fn texture_handle_system(
+    mut texture_handles: ResMut<TextureHandles>,
+    asset_server: Res<AssetServer>,
+    sector_query: Query<&Sector, Changed<Sector>>,
+    billboard_query: Query<&Billboard, Changed<Billboard>>,
+) {
+    let wall_paths = sector_query.iter().flat_map(|s| {
+        let mid_paths = s.mid_textures.iter().flatten().map(|m| &m.path);
+        let portal_paths = (s.upper_walls.iter().chain(&s.lower_walls))
+            .flatten()
+            .filter_map(|w| w.texture.as_ref());
+        s.wall_textures
+            .iter()
+            .flatten()
+            .chain(mid_paths)
+            .chain(portal_paths)
+    });
+    let billboard_paths = billboard_query.iter().filter_map(|b| b.texture.as_ref());
+    for path in wall_paths.chain(billboard_paths) {
+        if !texture_handles.0.contains_key(path) {
+            debug!("loading texture `{path}`");
+            texture_handles
+                .0
+                .insert(path.clone(), asset_server.load(path.as_str()));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use super::*;
+
+pub fn clip_wall(
+    projection: &Projection,
+    mut view_left: Position2,
+    mut view_right: Position2,
+) -> Option<(Position2, Position2)> {
+    // Skip entirely behind back
+    if view_left.0.y < NEAR && view_right.0.y < NEAR {
+        return None;
+    }
+
+    // Clip left side
+    if let Some(intersection) = intersect(
+        view_left.0,
+        view_right.0,
+        projection.left_clip_1,
+        projection.left_clip_2,
+    ) {
+        if intersection.x < -projection.x_near {
+            if point_behind(view_left.0, projection.left_clip_1, projection.left_clip_2) {
+                view_left = Position2(intersection);
+            } else {
+                view_right = Position2(intersection);
+            }
+        }
+    }
+
+    // Clip right side
+    if let Some(intersection) = intersect(
+        view_left.0,
+        view_right.0,
+        projection.right_clip_1,
+        projection.right_clip_2,
+    ) {
+        if intersection.x > projection.x_near {
+            if point_behind(
+                view_left.0,
+                projection.right_clip_1,
+                projection.right_clip_2,
+            ) {
+                view_left = Position2(intersection);
+            } else {
+                view_right = Position2(intersection);
+            }
+        }
+    }
+
+    // Clip behind back
+    if view_left.0.y < NEAR || view_right.0.y < NEAR {
+        if let Some(intersection) = intersect(
+            view_left.0,
+            view_right.0,
+            projection.back_clip_1,
+            projection.back_clip_2,
+        ) {
+            if point_behind(view_left.0, projection.back_clip_1, projection.back_clip_2) {
+                view_left = Position2(intersection);
+            } else {
+                view_right = Position2(intersection);
+            }
+        }
+    }
+
+    // Skip entirely behind left side
+    if point_behind(view_right.0, projection.left_clip_1, projection.left_clip_2) {
+        return None;
+    }
+
+    // Skip entirely behind right side
+    if point_behind(
+        view_left.0,
+        projection.right_clip_1,
+        projection.right_clip_2,
+    ) {
+        return None;
+    }
+
+    Some((view_left, view_right))
+}
+
+pub fn project(projection: &Projection, position: Position2, height: Length) -> Normalized {
+    Normalized(projection.perspective_matrix.project_point3(vec3(
+        position.0.x,
+        height.0,
+        -position.0.y,
+    )))
+}
+
+/// Shading of a surface at a distance from the view in fog, the surface darkened by
+/// its light and the fog in front of it, then the fog's color added over it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fogged {
+    pub brightness: f32,
+    /// Fog color scaled by how much of it is in front of the surface.
+    pub fog: RawColor,
+}
+
+impl Fogged {
+    /// Shading at distance in fog of a surface in light, how clear it is rounded to
+    /// hundredths.
+    pub fn new(fog: &FogConfig, distance: f32, light: f32) -> Self {
+        let clear = ((1.0 - fog.amount(distance)) * 100.0).round() / 100.0;
+        Self {
+            brightness: clear * light,
+            fog: shade(fog.color, 1.0 - clear),
+        }
+    }
+
+    pub fn color(&self, color: RawColor) -> RawColor {
+        self.add_fog(shade(color, self.brightness))
+    }
+
+    /// Fog added to a color already darkened to brightness.
+    pub fn add_fog(&self, color: RawColor) -> RawColor {
+        RawColor(std::array::from_fn(|i| {
+            color.0[i].saturating_add(self.fog.0[i])
+        }))
+    }
+}
+
+/// Color darkened to brightness, from 0 for black to 1 for unchanged.
+pub fn shade(color: RawColor, brightness: f32) -> RawColor {
+    RawColor(color.0.map(|c| (c as f32 * brightness) as u8))
+}
+
+pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
+    start * (1.0 - t) + end * t
+}
+
+pub fn lerpi(start: isize, end: isize, t: f32) -> isize {
+    (start as f32 * (1.0 - t) + end as f32 * t).round() as isize
+}
+
+pub fn intersect(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> Option<Vec2> {
+    let a_perp_dot = a1.perp_dot(a2);
+    let b_perp_dot = b1.perp_dot(b2);
+
+    let divisor = vec2(a1.x - a2.x, a1.y - a2.y).perp_dot(vec2(b1.x - b2.x, b1.y - b2.y));
+    if divisor == 0.0 {
+        return None;
+    };
+
+    let result = vec2(
+        vec2(a_perp_dot, a1.x - a2.x).perp_dot(vec2(b_perp_dot, b1.x - b2.x)) / divisor,
+        vec2(a_perp_dot, a1.y - a2.y).perp_dot(vec2(b_perp_dot, b1.y - b2.y)) / divisor,
+    );
+
+    if between(result.x, a1.x, a2.x) && between(result.y, a1.y, a2.y) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+pub fn between(test: f32, a: f32, b: f32) -> bool {
+    test >= a.min(b) && test <= a.max(b)
+}
+
+pub fn point_behind(point: Vec2, a: Vec2, b: Vec2) -> bool {
+    vec2(b.x - a.x, b.y - a.y).perp_dot(vec2(point.x - a.x, point.y - a.y)) > 0.0
+}
@@ -0,0 +1,203 @@
+use super::*;
+
+use bevy::utils::HashSet;
+
+/// Wall seen from a view, clipped to the view frustum and the portals it's seen
+/// through.
+#[derive(Debug, Copy, Clone)]
+pub struct VisibleWall<'a> {
+    pub sector: &'a Sector,
+    pub wall: Wall,
+    /// Index of the wall within its sector.
+    pub index: usize,
+    /// Wall ends in view space, clipped to the frustum.
+    pub view_left: Position2,
+    pub view_right: Position2,
+    /// Screen columns covered by the wall.
+    pub x_left: isize,
+    pub x_right: isize,
+    /// Screen columns of the portal the sector is seen through.
+    pub window: (isize, isize),
+    /// Sector on the far side when the wall is a portal.
+    pub portal_sector: Option<&'a Sector>,
+    /// Identifies the portal the sector is seen through, shared by walls seen
+    /// through the same one.
+    pub visit: usize,
+}
+
+/// Order sectors are visited through portals.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub enum PortalTraversal {
+    /// All sectors seen through one portal before any seen through those.
+    #[default]
+    BreadthFirst,
+    /// Sectors seen through a portal straight after the portal wall, so side rooms
+    /// are finished before anything seen beyond them.
+    DepthFirst,
+}
+
+/// Sector seen through a portal, with its walls still to visit.
+struct Visit<'a> {
+    portal: Portal<'a>,
+    walls: std::iter::Enumerate<std::vec::IntoIter<Wall>>,
+    id: usize,
+    /// Portals passed through to reach the sector, 0 for the view's own.
+    depth: usize,
+}
+
+/// Iterator over walls seen from a view, see [`visible_walls`].
+pub struct VisibleWalls<'a> {
+    sectors: &'a [&'a Sector],
+    config: RenderConfig,
+    view_matrix: Mat3,
+    traversal: PortalTraversal,
+    /// Queue when breadth first, stack when depth first.
+    visits: VecDeque<Visit<'a>>,
+    visit_count: usize,
+    /// Sectors and the columns they've been seen through this frame, so a sector
+    /// seen again through the same columns isn't visited twice.
+    visited: HashSet<(SectorId, isize, isize)>,
+}
+
+/// Walls seen by camera, through portals starting from its current sector, in
+/// the order the renderer draws them. Runs the same portal traversal and clipping as
+/// the renderer without drawing.
+pub fn visible_walls<'a>(
+    config: &RenderConfig,
+    camera: &SectorCamera,
+    sectors: &'a [&'a Sector],
+) -> VisibleWalls<'a> {
+    let view_matrix = camera.view_matrix();
+
+    let mut visible_walls = VisibleWalls {
+        sectors,
+        config: *config,
+        view_matrix,
+        traversal: PortalTraversal::default(),
+        visits: VecDeque::new(),
+        visit_count: 0,
+        visited: HashSet::new(),
+    };
+    if let Some(sector) = camera
+        .current_sector
+        .and_then(|id| sectors.iter().copied().find(|s| s.id == id))
+    {
+        visible_walls.push(
+            Portal {
+                sector,
+                x_min: GAP,
+                x_max: config.width() as isize,
+            },
+            0,
+        );
+    }
+
+    visible_walls
+}
+
+impl<'a> VisibleWalls<'a> {
+    pub fn with_traversal(mut self, traversal: PortalTraversal) -> Self {
+        self.traversal = traversal;
+        self
+    }
+
+    /// Visit a sector through portal, unless it's too deep, has no columns left or was
+    /// already seen through the same ones.
+    fn push(&mut self, portal: Portal<'a>, depth: usize) {
+        if depth > self.config.max_portal_depth
+            || portal.x_min >= portal.x_max
+            || !self
+                .visited
+                .insert((portal.sector.id, portal.x_min, portal.x_max))
+        {
+            return;
+        }
+
+        self.visit_count += 1;
+        self.visits.push_back(Visit {
+            portal,
+            walls: portal.sector.to_walls().into_iter().enumerate(),
+            id: self.visit_count,
+            depth,
+        });
+    }
+
+    fn current(&mut self) -> Option<&mut Visit<'a>> {
+        match self.traversal {
+            PortalTraversal::BreadthFirst => self.visits.front_mut(),
+            PortalTraversal::DepthFirst => self.visits.back_mut(),
+        }
+    }
+
+    fn finish_current(&mut self) {
+        match self.traversal {
+            PortalTraversal::BreadthFirst => self.visits.pop_front(),
+            PortalTraversal::DepthFirst => self.visits.pop_back(),
+        };
+    }
+}
+
+impl<'a> Iterator for VisibleWalls<'a> {
+    type Item = VisibleWall<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let view_matrix = self.view_matrix;
+            let visit = self.current()?;
+            let (portal, id, depth) = (visit.portal, visit.id, visit.depth);
+            let Some((index, wall)) = visit.walls.next() else {
+                self.finish_current();
+                continue;
+            };
+
+            // Transform wall ends to view relative positions and clip by view frustum
+            let view_left = wall.left.transform(view_matrix);
+            let view_right = wall.right.transform(view_matrix);
+            let projection = self.config.projection();
+            let Some((view_left, view_right)) = clip_wall(projection, view_left, view_right) else { continue };
+
+            // Skip wall if looking at backside
+            let left = self
+                .config
+                .pixel(project(projection, view_left, Length(0.0)));
+            let right = self
+                .config
+                .pixel(project(projection, view_right, Length(0.0)));
+            if right.x - left.x <= 0 {
+                continue;
+            }
+
+            // Clip x by portal sides
+            let x_left = left.x.clamp(portal.x_min, portal.x_max);
+            let x_right = right.x.clamp(portal.x_min, portal.x_max);
+
+            // Visit adjacent sector through this wall, next if depth first
+            let portal_sector = wall
+                .portal_sector
+                .and_then(|id| self.sectors.iter().copied().find(|s| s.id == id));
+            if let Some(portal_sector) = portal_sector {
+                self.push(
+                    Portal {
+                        sector: portal_sector,
+                        x_min: x_left,
+                        x_max: x_right,
+                    },
+                    depth + 1,
+                );
+            }
+
+            return Some(VisibleWall {
+                sector: portal.sector,
+                wall,
+                index,
+                view_left,
+                view_right,
+                x_left,
+                x_right,
+                window: (portal.x_min, portal.x_max),
+                portal_sector,
+                visit: id,
+            });
+        }
+    }
+}
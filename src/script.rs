@@ -0,0 +1,33 @@
+use crate::*;
+
+#[cfg(feature = "scripting")]
+mod runner;
+
+#[cfg(feature = "scripting")]
+pub use runner::*;
+
+/// Runs the [Rhai](https://rhai.rs) script at path, an asset path such as
+/// `scripts/e1m1.rhai`, while the map it's in is loaded. Only run with the
+/// `scripting` feature, but registered without it so maps with one still load.
+///
+/// The script's top level runs as it loads. After that it's called on:
+///
+/// - `on_enter_sector(sector)`, as the view enters a sector.
+/// - `on_use_wall(sector, wall)`, as the view uses a wall.
+/// - `on_tick(delta)`, each frame the game isn't paused, with scaled seconds
+///   since the last.
+///
+/// It may call:
+///
+/// - `set_floor_height(sector, height)` and `set_ceiling_height(sector, height)`.
+/// - `set_light(sector, level)`.
+/// - `spawn_thing(x, y, radius, height, color)`, with color as `0xRRGGBB`.
+/// - `trigger(tag)`, firing tag as a [`trigger::Trigger`] would.
+///
+/// Scripts are limited in how long each call runs, how deep functions recurse and
+/// how much data they make, and stop with a warning once over.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct MapScript {
+    pub path: String,
+}
@@ -0,0 +1,414 @@
+use super::*;
+use crate::{interact::*, time_scale::*, trigger::*};
+
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// Most operations a script runs each time it's called, so a script looping forever
+/// stops rather than hanging the game.
+pub const MAX_SCRIPT_OPERATIONS: u64 = 100_000;
+/// Deepest script functions call one another, so runaway recursion stops before
+/// overflowing the stack.
+pub const MAX_SCRIPT_CALL_LEVELS: usize = 32;
+/// Longest string, and most items in an array or object map, a script makes.
+pub const MAX_SCRIPT_DATA_SIZE: usize = 4096;
+/// Most things a script spawns each time it's called, so a script can't flood the map.
+pub const MAX_SCRIPT_SPAWNS: usize = 64;
+
+/// Source of a map script.
+#[derive(TypeUuid, Debug, Clone)]
+#[uuid = "a4f1c2d7-3b6e-4e08-9c15-7d2b8e0f6a41"]
+pub struct ScriptSource(pub String);
+
+#[derive(Default)]
+pub struct ScriptLoader;
+
+impl AssetLoader for ScriptLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?.to_string();
+            load_context.set_default_asset(LoadedAsset::new(ScriptSource(source)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rhai"]
+    }
+}
+
+/// Call made by a script, carried out once it returns.
+#[derive(Debug, Clone)]
+enum ScriptCall {
+    SetFloorHeight { sector: SectorId, height: f32 },
+    SetCeilingHeight { sector: SectorId, height: f32 },
+    SetLight { sector: SectorId, level: f32 },
+    SpawnThing { thing: Thing, color: RawColor },
+    Trigger { tag: u32 },
+}
+
+/// Script of the current map, compiled once its source loads.
+struct RunningScript {
+    /// Entity with the [`MapScript`], things the script spawns being its children so
+    /// they leave with the map.
+    entity: Entity,
+    handle: Handle<ScriptSource>,
+    /// Whether the source loaded or changed since it was last compiled.
+    stale: bool,
+    compiled: Option<(AST, Scope<'static>)>,
+}
+
+/// Rhai engine running the current map's [`MapScript`].
+#[derive(Resource)]
+pub struct MapScripting {
+    engine: Engine,
+    calls: Arc<Mutex<Vec<ScriptCall>>>,
+    /// Things spawned since the script was last called.
+    spawned: Arc<AtomicUsize>,
+    running: Option<RunningScript>,
+}
+
+impl Default for MapScripting {
+    fn default() -> Self {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let mut engine = Engine::new();
+        // Maps come from anywhere, so scripts are kept from stalling or exhausting
+        // the game
+        engine
+            .set_max_operations(MAX_SCRIPT_OPERATIONS)
+            .set_max_call_levels(MAX_SCRIPT_CALL_LEVELS)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(MAX_SCRIPT_DATA_SIZE)
+            .set_max_array_size(MAX_SCRIPT_DATA_SIZE)
+            .set_max_map_size(MAX_SCRIPT_DATA_SIZE);
+
+        // Numbers are taken as either integers or floats, so `1` works like `1.0`
+        let number = |value: Dynamic| {
+            value
+                .as_float()
+                .or_else(|_| value.as_int().map(|int| int as f64))
+                .unwrap_or_default() as f32
+        };
+        let sector = |sector: i64| SectorId(sector as u32);
+        let push = |calls: &Arc<Mutex<Vec<ScriptCall>>>, call| {
+            if let Ok(mut calls) = calls.lock() {
+                calls.push(call);
+            }
+        };
+
+        let c = calls.clone();
+        engine.register_fn("set_floor_height", move |id: i64, height: Dynamic| {
+            let (sector, height) = (sector(id), number(height));
+            push(&c, ScriptCall::SetFloorHeight { sector, height });
+        });
+        let c = calls.clone();
+        engine.register_fn("set_ceiling_height", move |id: i64, height: Dynamic| {
+            let (sector, height) = (sector(id), number(height));
+            push(&c, ScriptCall::SetCeilingHeight { sector, height });
+        });
+        let c = calls.clone();
+        engine.register_fn("set_light", move |id: i64, level: Dynamic| {
+            let (sector, level) = (sector(id), number(level));
+            push(&c, ScriptCall::SetLight { sector, level });
+        });
+        let (c, s) = (calls.clone(), spawned.clone());
+        engine.register_fn(
+            "spawn_thing",
+            move |x: Dynamic,
+                  y: Dynamic,
+                  radius: Dynamic,
+                  height: Dynamic,
+                  color: i64|
+                  -> Result<(), Box<EvalAltResult>> {
+                let (x, y, radius, height) = (number(x), number(y), number(radius), number(height));
+                if ![x, y, radius, height].iter().all(|n| n.is_finite()) {
+                    return Err("spawn_thing: numbers must be finite".into());
+                }
+                if radius <= 0.0 || height <= 0.0 {
+                    return Err("spawn_thing: radius and height must be above 0".into());
+                }
+                if s.fetch_add(1, Ordering::Relaxed) >= MAX_SCRIPT_SPAWNS {
+                    return Err(format!(
+                        "spawn_thing: more than {MAX_SCRIPT_SPAWNS} things spawned in one call"
+                    )
+                    .into());
+                }
+                let thing = Thing {
+                    position: Position2(Vec2::new(x, y)),
+                    radius: Length(radius),
+                    height: Length(height),
+                };
+                let [_, r, g, b] = (color as u32).to_be_bytes();
+                let color = RawColor([r, g, b]);
+                push(&c, ScriptCall::SpawnThing { thing, color });
+                Ok(())
+            },
+        );
+        let c = calls.clone();
+        engine.register_fn("trigger", move |tag: i64| {
+            push(&c, ScriptCall::Trigger { tag: tag as u32 });
+        });
+
+        Self {
+            engine,
+            calls,
+            spawned,
+            running: None,
+        }
+    }
+}
+
+impl MapScripting {
+    /// Call the script's function name with args when it defines one.
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs + Copy, arity: usize) {
+        let Some(running) = &mut self.running else { return };
+        let Some((ast, scope)) = &mut running.compiled else { return };
+        let defined = ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == arity);
+        if !defined {
+            return;
+        }
+        self.spawned.store(0, Ordering::Relaxed);
+        let Err(error) = self.engine.call_fn::<Dynamic>(scope, ast, name, args) else { return };
+        if exceeds_limits(&error) {
+            // Would only hit the limit again each time it's called
+            warn!("map script `{name}` stopped: {error}");
+            running.compiled = None;
+        } else {
+            warn!("map script `{name}` failed: {error}");
+        }
+    }
+
+    fn take_calls(&self) -> Vec<ScriptCall> {
+        self.calls
+            .lock()
+            .map(|mut calls| std::mem::take(&mut *calls))
+            .unwrap_or_default()
+    }
+}
+
+/// Whether error is from a script going past the engine's limits.
+fn exceeds_limits(error: &EvalAltResult) -> bool {
+    matches!(
+        error,
+        EvalAltResult::ErrorTooManyOperations(..)
+            | EvalAltResult::ErrorStackOverflow(..)
+            | EvalAltResult::ErrorDataTooLarge(..)
+            | EvalAltResult::ErrorTooManyVariables(..)
+            | EvalAltResult::ErrorTooManyModules(..)
+    )
+}
+
+/// Runs the [`MapScript`] of each map as it loads.
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ScriptSource>()
+            .init_asset_loader::<ScriptLoader>()
+            .init_resource::<MapScripting>()
+            .add_systems(
+                (map_script_load_system, map_script_system)
+                    .chain()
+                    .after(trigger_system)
+                    .before(tag_effect_system)
+                    .in_set(SectorSet::Simulation),
+            );
+    }
+}
+
+/// Load the script of a map as it spawns, compiling and running its top level once
+/// its source loads, and again whenever the source changes. Scripts of maps left
+/// stop.
+pub fn map_script_load_system(
+    mut scripting: ResMut<MapScripting>,
+    mut script_events: EventReader<AssetEvent<ScriptSource>>,
+    asset_server: Res<AssetServer>,
+    sources: Res<Assets<ScriptSource>>,
+    script_query: Query<(Entity, &MapScript)>,
+    added_query: Query<(Entity, &MapScript), Added<MapScript>>,
+) {
+    let scripting = &mut *scripting;
+    let left = scripting
+        .running
+        .as_ref()
+        .is_some_and(|running| !script_query.contains(running.entity));
+    if left {
+        scripting.running = None;
+    }
+    for (entity, script) in &added_query {
+        if scripting.running.is_some() {
+            warn!("map has more than one script, running `{}`", script.path);
+        }
+        scripting.running = Some(RunningScript {
+            entity,
+            handle: asset_server.load(script.path.as_str()),
+            stale: true,
+            compiled: None,
+        });
+    }
+
+    let Some(running) = &mut scripting.running else { return };
+    for event in script_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            running.stale |= *handle == running.handle;
+        }
+    }
+    if !running.stale {
+        return;
+    }
+    // Sources already loaded for an earlier visit are ready at once
+    let Some(source) = sources.get(&running.handle) else { return };
+    running.stale = false;
+    let path = script_query
+        .get(running.entity)
+        .map_or("", |(_, script)| script.path.as_str());
+
+    let mut scope = Scope::new();
+    scripting.spawned.store(0, Ordering::Relaxed);
+    running.compiled = match scripting.engine.compile(&source.0) {
+        Ok(ast) => match scripting.engine.run_ast_with_scope(&mut scope, &ast) {
+            Ok(()) => {
+                info!("running map script `{path}`");
+                Some((ast, scope))
+            }
+            Err(error) => {
+                warn!("map script `{path}` failed: {error}");
+                None
+            }
+        },
+        Err(error) => {
+            warn!("map script `{path}` doesn't compile: {error}");
+            None
+        }
+    };
+}
+
+/// Call the map script for sectors entered, walls used and the passing of time, then
+/// carry out what it asked for.
+#[allow(clippy::too_many_arguments)]
+pub fn map_script_system(
+    mut commands: Commands,
+    mut scripting: ResMut<MapScripting>,
+    mut sector_entered_events: EventReader<SectorEntered>,
+    mut wall_used_events: EventReader<WallUsed>,
+    mut tag_triggered_events: EventWriter<TagTriggered>,
+    mut sector_query: Query<&mut Sector>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+) {
+    for entered in sector_entered_events.iter() {
+        scripting.call("on_enter_sector", (entered.to.0 as i64,), 1);
+    }
+    for used in wall_used_events.iter() {
+        let args = (used.sector.0 as i64, used.wall_index as i64);
+        scripting.call("on_use_wall", args, 2);
+    }
+    let delta = time_scale.delta_seconds(&time);
+    if delta > 0.0 {
+        scripting.call("on_tick", (delta as f64,), 1);
+    }
+
+    let entity = scripting.running.as_ref().map(|running| running.entity);
+    for call in scripting.take_calls() {
+        match call {
+            ScriptCall::SetFloorHeight { sector, height } => {
+                if let Some(mut sector) = find_sector(&mut sector_query, sector) {
+                    sector.floor.0 = height;
+                }
+            }
+            ScriptCall::SetCeilingHeight { sector, height } => {
+                if let Some(mut sector) = find_sector(&mut sector_query, sector) {
+                    sector.ceil.0 = height;
+                }
+            }
+            ScriptCall::SetLight { sector, level } => {
+                if let Some(mut sector) = find_sector(&mut sector_query, sector) {
+                    // As with animated light, nothing about geometry changes
+                    sector.bypass_change_detection().light = level;
+                }
+            }
+            ScriptCall::SpawnThing { thing, color } => {
+                let Some(entity) = entity else { continue };
+                commands.entity(entity).with_children(|parent| {
+                    parent.spawn((
+                        thing,
+                        Billboard {
+                            color,
+                            texture: None,
+                        },
+                    ));
+                });
+            }
+            ScriptCall::Trigger { tag } => tag_triggered_events.send(TagTriggered { tag }),
+        }
+    }
+}
+
+fn find_sector<'a>(
+    sector_query: &'a mut Query<&mut Sector>,
+    id: SectorId,
+) -> Option<Mut<'a, Sector>> {
+    let sector = sector_query.iter_mut().find(|s| s.id == id);
+    if sector.is_none() {
+        warn!("map script: no sector {}", id.0);
+    }
+    sector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spawns asked for by running source as a script, or the error it stopped with.
+    fn spawns(source: &str) -> (usize, Option<String>) {
+        let scripting = MapScripting::default();
+        let result = scripting.engine.run(source);
+        let spawns = scripting
+            .take_calls()
+            .iter()
+            .filter(|call| matches!(call, ScriptCall::SpawnThing { .. }))
+            .count();
+        (spawns, result.err().map(|error| error.to_string()))
+    }
+
+    #[test]
+    fn spawn_thing() {
+        assert_eq!(spawns("spawn_thing(1, 2.5, 0.25, 1, 0xff0000)"), (1, None));
+    }
+
+    #[test]
+    fn bad_things_are_refused() {
+        for args in [
+            "0, 0, 0, 1",
+            "0, 0, 0.25, -1",
+            "0, 0, 1e39, 1",
+            "0.0 / 0.0, 0, 0.25, 1",
+        ] {
+            let (count, error) = spawns(&format!("spawn_thing({args}, 0)"));
+            assert_eq!(count, 0, "spawned with {args}");
+            assert!(error.is_some_and(|e| e.contains("spawn_thing")), "{args}");
+        }
+    }
+
+    #[test]
+    fn spawns_are_capped() {
+        let (count, error) = spawns("for i in 0..1000 { spawn_thing(i, 0, 0.25, 1, 0); }");
+        assert_eq!(count, MAX_SCRIPT_SPAWNS);
+        assert!(error.is_some());
+    }
+}
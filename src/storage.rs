@@ -0,0 +1,139 @@
+use bevy::{
+    asset::{AssetIo, AssetIoError, FileType, Metadata},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use std::path::{Path, PathBuf};
+
+/// Directory map scenes are saved to and loaded from, as the asset directory.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_DIR: &str = "assets";
+/// Start of the local storage key of each map scene saved on the web, followed by
+/// its path under the asset directory.
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY_PREFIX: &str = "sector/assets/";
+
+/// Save a map scene file at path under the asset directory. On the web it's kept in
+/// the browser's local storage instead, which only holds text, so only RON scenes can
+/// be saved there.
+pub fn save_scene(path: &str, bytes: &[u8]) -> Result<(), String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| "only RON maps can be saved in the browser".to_string())?;
+        local_storage()?
+            .set_item(&format!("{STORAGE_KEY_PREFIX}{path}"), text)
+            .map_err(|_| "browser storage is full".to_string())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = Path::new(ASSET_DIR).join(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+        }
+        std::fs::write(path, bytes).map_err(|error| error.to_string())
+    }
+}
+
+/// Map scene file at path under the asset directory, as saved by [`save_scene`],
+/// `None` when there isn't one. On the web only those in local storage are found.
+pub fn load_scene(path: &str) -> Option<Vec<u8>> {
+    #[cfg(target_arch = "wasm32")]
+    return local_storage()
+        .ok()?
+        .get_item(&format!("{STORAGE_KEY_PREFIX}{path}"))
+        .ok()?
+        .map(String::into_bytes);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    std::fs::read(Path::new(ASSET_DIR).join(path)).ok()
+}
+
+/// Paths under the asset directory of map scenes saved in local storage, none other
+/// than on the web.
+pub fn stored_scenes() -> Vec<String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let Ok(storage) = local_storage() else { return Vec::new() };
+        let length = storage.length().unwrap_or(0);
+        (0..length)
+            .filter_map(|index| storage.key(index).ok().flatten())
+            .filter_map(|key| Some(key.strip_prefix(STORAGE_KEY_PREFIX)?.to_string()))
+            .collect()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    Vec::new()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, String> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| "browser storage isn't available".to_string())
+}
+
+/// Asset server of asset_plugin reading map scenes through [`StoredSceneAssetIo`], to
+/// insert before adding it on the web. Elsewhere its own asset IO is kept, which
+/// watches for changes.
+pub fn stored_scene_asset_server(asset_plugin: &AssetPlugin) -> AssetServer {
+    AssetServer::with_boxed_io(Box::new(StoredSceneAssetIo::new(
+        asset_plugin.create_platform_default_asset_io(),
+    )))
+}
+
+/// Asset IO reading map scenes saved in local storage ahead of source, so maps saved
+/// on the web load through the asset server like any other.
+pub struct StoredSceneAssetIo {
+    source: Box<dyn AssetIo>,
+}
+
+impl StoredSceneAssetIo {
+    pub fn new(source: Box<dyn AssetIo>) -> Self {
+        Self { source }
+    }
+
+    fn stored(path: &Path) -> Option<Vec<u8>> {
+        if cfg!(target_arch = "wasm32") {
+            load_scene(&path.to_string_lossy().replace('\\', "/"))
+        } else {
+            None
+        }
+    }
+}
+
+impl AssetIo for StoredSceneAssetIo {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        match Self::stored(path) {
+            Some(bytes) => Box::pin(async move { Ok(bytes) }),
+            None => self.source.load_path(path),
+        }
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        self.source.read_directory(path)
+    }
+
+    fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+        match Self::stored(path) {
+            Some(_) => Ok(Metadata::new(FileType::File)),
+            None => self.source.get_metadata(path),
+        }
+    }
+
+    fn watch_path_for_changes(
+        &self,
+        to_watch: &Path,
+        to_reload: Option<PathBuf>,
+    ) -> Result<(), AssetIoError> {
+        self.source.watch_path_for_changes(to_watch, to_reload)
+    }
+
+    fn watch_for_changes(&self) -> Result<(), AssetIoError> {
+        self.source.watch_for_changes()
+    }
+}
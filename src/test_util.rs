@@ -0,0 +1,25 @@
+//! Sectors shared by unit tests.
+
+use crate::*;
+
+/// Unit square with its left edge at x, wall 2 its right edge and wall 0 its left,
+/// with floor and ceil.
+pub fn square(id: u32, x: f32, floor: f32, ceil: f32) -> Sector {
+    Sector {
+        id: SectorId(id),
+        vertices: [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]
+            .map(|(vx, vy)| Position2(Vec2::new(x + vx, vy)))
+            .to_vec(),
+        portal_sectors: vec![None; 4],
+        floor: Length(floor),
+        ceil: Length(ceil),
+        ..default()
+    }
+}
+
+/// Sectors a and b side by side, linked through the edge between them.
+pub fn linked(mut a: Sector, mut b: Sector) -> (Sector, Sector) {
+    a.portal_sectors[2] = Some(b.id);
+    b.portal_sectors[0] = Some(a.id);
+    (a, b)
+}
@@ -0,0 +1,47 @@
+use crate::*;
+
+/// Scale applied to simulation, but not rendering, for slow motion and stepping
+/// through single frames while paused.
+#[derive(Resource, Debug)]
+pub struct TimeScale {
+    pub scale: f32,
+    pub paused: bool,
+    step: bool,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+            step: false,
+        }
+    }
+}
+
+impl TimeScale {
+    /// Factor for simulation this frame, zero while paused unless stepping a frame.
+    pub fn factor(&self) -> f32 {
+        if self.paused && !self.step {
+            0.0
+        } else {
+            self.scale
+        }
+    }
+
+    /// Scaled time passed this frame.
+    pub fn delta_seconds(&self, time: &Time) -> f32 {
+        time.delta_seconds() * self.factor()
+    }
+
+    /// Advance a single frame while paused.
+    pub fn step(&mut self) {
+        self.step = true;
+    }
+}
+
+pub fn time_scale_step_system(mut time_scale: ResMut<TimeScale>) {
+    if time_scale.step {
+        time_scale.step = false;
+    }
+}
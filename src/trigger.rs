@@ -0,0 +1,160 @@
+use crate::{hub::*, interact::*, mover::*, *};
+
+/// Sends [`TagTriggered`] with its tag once the view enters sector, or when wall is
+/// given, once that wall of sector is used. Trigger walls are usable as if
+/// [`Interactable`].
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct Trigger {
+    pub sector: SectorId,
+    /// Index of a wall of sector to use, rather than entering sector.
+    pub wall: Option<usize>,
+    pub tag: u32,
+    /// Whether the trigger only fires the first time.
+    pub once: bool,
+    /// Whether the trigger has fired, kept so once triggers stay spent with the map.
+    pub fired: bool,
+}
+
+impl Trigger {
+    /// Wall of the trigger as an interactable, `None` for triggers entering a sector.
+    pub fn interactable(&self) -> Option<Interactable> {
+        Some(Interactable {
+            sector: self.sector,
+            wall: self.wall?,
+        })
+    }
+}
+
+/// Sent when a [`Trigger`] fires, for each [`TagEffect`] with its tag to act on the
+/// sectors with that tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TagTriggered {
+    pub tag: u32,
+}
+
+/// What a [`TagEffect`] does to the sectors tagged with its tag.
+#[derive(Reflect, FromReflect, Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Set the light of the sectors, from 0 for black to 1 for fully lit.
+    SetLight { level: f32 },
+    /// Move the floors of the sectors to height at speed in units per second.
+    MoveFloor { height: f32, speed: f32 },
+    /// Move the ceilings of the sectors to height at speed in units per second.
+    MoveCeiling { height: f32, speed: f32 },
+    /// Leave for another map, as through a [`MapExit`].
+    EndLevel { map: String, new_hub: bool },
+}
+
+impl Default for Effect {
+    fn default() -> Self {
+        Self::SetLight {
+            level: DEFAULT_LIGHT,
+        }
+    }
+}
+
+/// Acts on the sectors with tag each time a [`Trigger`] with that tag fires.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct TagEffect {
+    pub tag: u32,
+    pub effect: Effect,
+}
+
+/// Fire triggers entered or used by the view.
+pub fn trigger_system(
+    mut sector_entered_events: EventReader<SectorEntered>,
+    mut wall_used_events: EventReader<WallUsed>,
+    mut tag_triggered_events: EventWriter<TagTriggered>,
+    mut trigger_query: Query<&mut Trigger>,
+) {
+    let entered: Vec<SectorId> = sector_entered_events.iter().map(|e| e.to).collect();
+    let used: Vec<WallUsed> = wall_used_events.iter().copied().collect();
+
+    for mut trigger in &mut trigger_query {
+        if trigger.once && trigger.fired {
+            continue;
+        }
+        let fires = match trigger.wall {
+            Some(wall) => used
+                .iter()
+                .any(|u| u.sector == trigger.sector && u.wall_index == wall),
+            None => entered.contains(&trigger.sector),
+        };
+        if fires {
+            debug!("trigger fired with tag {}", trigger.tag);
+            trigger.fired = true;
+            tag_triggered_events.send(TagTriggered { tag: trigger.tag });
+        }
+    }
+}
+
+/// Carry out the effects of triggered tags. Floors and ceilings move as movers, the
+/// sector's own of that kind when it has one, otherwise one added to the sector.
+pub fn tag_effect_system(
+    mut commands: Commands,
+    mut tag_triggered_events: EventReader<TagTriggered>,
+    mut world_state: ResMut<WorldState>,
+    effect_query: Query<&TagEffect>,
+    mut sector_query: Query<(Entity, &mut Sector)>,
+    mut mover_query: Query<(Entity, &mut Mover)>,
+) {
+    for triggered in tag_triggered_events.iter() {
+        let effects = effect_query.iter().filter(|e| e.tag == triggered.tag);
+        for effect in effects {
+            let (kind, height, speed) = match effect.effect {
+                Effect::SetLight { level } => {
+                    for (_, mut sector) in &mut sector_query {
+                        if sector.tag == triggered.tag {
+                            sector.bypass_change_detection().light = level;
+                        }
+                    }
+                    continue;
+                }
+                Effect::EndLevel { ref map, new_hub } => {
+                    info!("leaving by trigger to `{map}`");
+                    world_state.travel(map, new_hub);
+                    continue;
+                }
+                Effect::MoveFloor { height, speed } => (MoverKind::Lift, height, speed),
+                Effect::MoveCeiling { height, speed } => (MoverKind::Door, height, speed),
+            };
+
+            let state = MoverState::Moving {
+                target: Length(height),
+                speed: speed.abs(),
+            };
+            for (entity, sector) in &sector_query {
+                if sector.tag != triggered.tag {
+                    continue;
+                }
+                let has_mover = mover_query.contains(entity);
+                let mover = mover_query
+                    .iter_mut()
+                    .find(|(_, m)| m.sector == sector.id && m.kind == kind);
+                match (mover, has_mover) {
+                    (Some((_, mut mover)), _) => mover.state = state,
+                    (None, false) => {
+                        let mover = Mover {
+                            sector: sector.id,
+                            kind,
+                            speed: speed.abs(),
+                            state,
+                            ..default()
+                        };
+                        let closed = Length(mover.height(sector));
+                        commands.entity(entity).insert(Mover {
+                            closed,
+                            open: Length(height),
+                            ..mover
+                        });
+                    }
+                    (None, true) => {
+                        warn!("sector {} already has a mover of another kind", sector.id.0);
+                    }
+                }
+            }
+        }
+    }
+}
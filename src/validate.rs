@@ -0,0 +1,649 @@
+use crate::{edit::SPLIT_EPSILON, load::*, mover::Mover, physics::point_segment_distance, *};
+
+use std::fmt;
+
+/// Opening left by `ClosedPortal::fix`.
+pub const PORTAL_FIX_OPENING: f32 = 0.5;
+/// Largest floor or ceiling adjustment `ClosedPortal::fix` will make.
+pub const PORTAL_FIX_TOLERANCE: f32 = 1.0;
+
+/// Portal whose shared opening, from the higher floor to the lower ceiling of the
+/// sectors either side, is zero or negative.
+#[derive(Debug, Copy, Clone)]
+pub struct ClosedPortal {
+    pub sector: SectorId,
+    pub wall: usize,
+    pub target: SectorId,
+    pub opening: f32,
+    /// Change to one of the sectors reopening the portal by [`PORTAL_FIX_OPENING`].
+    pub fix: Option<PortalFix>,
+}
+
+impl ClosedPortal {
+    /// Whether this is the same portal as other, ignoring heights.
+    pub fn same_portal(&self, other: &Self) -> bool {
+        self.sector == other.sector && self.wall == other.wall && self.target == other.target
+    }
+}
+
+impl fmt::Display for ClosedPortal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sector {} wall {}: portal to sector {} has no opening ({:.2})",
+            self.sector.0, self.wall, self.target.0, self.opening
+        )
+    }
+}
+
+/// Height to move a floor down or ceiling up to, reopening a closed portal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PortalFix {
+    Floor(SectorId, Length),
+    Ceiling(SectorId, Length),
+}
+
+impl PortalFix {
+    pub fn sector(&self) -> SectorId {
+        match self {
+            Self::Floor(sector, _) | Self::Ceiling(sector, _) => *sector,
+        }
+    }
+
+    pub fn apply(&self, sector: &mut Sector) {
+        match *self {
+            Self::Floor(_, floor) => sector.floor = floor,
+            Self::Ceiling(_, ceil) => sector.ceil = ceil,
+        }
+    }
+}
+
+/// Floor and ceiling of sector, once fix is applied when given.
+fn heights(sector: &Sector, fix: Option<&PortalFix>) -> (f32, f32) {
+    match fix.copied() {
+        Some(PortalFix::Floor(id, floor)) if id == sector.id => (floor.0, sector.ceil.0),
+        Some(PortalFix::Ceiling(id, ceil)) if id == sector.id => (sector.floor.0, ceil.0),
+        _ => (sector.floor.0, sector.ceil.0),
+    }
+}
+
+/// Opening of a portal between sectors a and b, from the higher floor to the lower
+/// ceiling, once fix is applied when given.
+fn opening(a: &Sector, b: &Sector, fix: Option<&PortalFix>) -> f32 {
+    let ((a_floor, a_ceil), (b_floor, b_ceil)) = (heights(a, fix), heights(b, fix));
+    a_ceil.min(b_ceil) - a_floor.max(b_floor)
+}
+
+/// Smallest change to either of a and b that leaves [`PORTAL_FIX_OPENING`] between
+/// them, lowering the higher floor or raising the lower ceiling, within
+/// [`PORTAL_FIX_TOLERANCE`].
+fn portal_fix(a: &Sector, b: &Sector) -> Option<PortalFix> {
+    let high_floor = if a.floor.0 >= b.floor.0 { a } else { b };
+    let low_ceil = if a.ceil.0 <= b.ceil.0 { a } else { b };
+
+    let floor = low_ceil.ceil.0 - PORTAL_FIX_OPENING;
+    let ceil = high_floor.floor.0 + PORTAL_FIX_OPENING;
+    let candidates = [
+        (
+            PortalFix::Floor(high_floor.id, Length(floor)),
+            high_floor.floor.0 - floor,
+        ),
+        (
+            PortalFix::Ceiling(low_ceil.id, Length(ceil)),
+            ceil - low_ceil.ceil.0,
+        ),
+    ];
+
+    candidates
+        .into_iter()
+        .filter(|(_, adjustment)| *adjustment <= PORTAL_FIX_TOLERANCE)
+        // Moving one surface only helps as far as the other sector allows
+        .filter(|(fix, _)| (opening(a, b, Some(fix)) - PORTAL_FIX_OPENING).abs() < SPLIT_EPSILON)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(fix, _)| fix)
+}
+
+/// Find every portal without an opening, each reported once though there's a wall
+/// either side. Portals into sectors in moving, doors and lifts closing as they
+/// should, are left out.
+pub fn closed_portals(sectors: &[&Sector], moving: &[SectorId]) -> Vec<ClosedPortal> {
+    let mut closed = Vec::new();
+
+    for sector in sectors {
+        if moving.contains(&sector.id) {
+            continue;
+        }
+        let walls = sector.to_walls();
+        for (wall, target) in sector.portal_sectors.iter().enumerate() {
+            let Some(target) = target else { continue };
+            if moving.contains(target) {
+                continue;
+            }
+            let Some(other) = sectors.iter().find(|s| s.id == *target) else { continue };
+
+            let opening = opening(sector, other, None);
+            if opening > 0.0 {
+                continue;
+            }
+
+            // Reported from the side with the lower id when there's a way back
+            let near = |a: Position2, b: Position2| a.0.distance(b.0) < SPLIT_EPSILON;
+            let back = other.to_walls().into_iter().enumerate().any(|(i, back)| {
+                other.portal_sectors.get(i) == Some(&Some(sector.id))
+                    && near(back.left, walls[wall].right)
+                    && near(back.right, walls[wall].left)
+            });
+            if back && other.id.0 < sector.id.0 {
+                continue;
+            }
+
+            closed.push(ClosedPortal {
+                sector: sector.id,
+                wall,
+                target: *target,
+                opening,
+                fix: portal_fix(sector, other),
+            });
+        }
+    }
+
+    closed
+}
+
+/// Warn about portals that have closed since last checked. Portals closed in maps
+/// as they load are reported by `map_load_system` instead.
+pub fn closed_portals_system(
+    mut reported: Local<Vec<ClosedPortal>>,
+    map_load: Res<MapLoad>,
+    changed_query: Query<(), Changed<Sector>>,
+    sector_query: Query<&Sector>,
+    mover_query: Query<&Mover>,
+) {
+    if changed_query.is_empty() {
+        return;
+    }
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let moving: Vec<SectorId> = mover_query.iter().map(|m| m.sector).collect();
+    let closed = closed_portals(&sectors, &moving);
+
+    if !map_load.is_loading() {
+        for portal in &closed {
+            if !reported.iter().any(|r| r.same_portal(portal)) {
+                warn!("{portal}");
+            }
+        }
+    }
+
+    *reported = closed;
+}
+
+/// What's wrong with the shape of a sector or how it's linked to others.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SectorProblem {
+    /// Fewer than three vertices, or no area.
+    Degenerate,
+    /// Vertices wind counter-clockwise, where sectors wind clockwise.
+    CounterClockwise,
+    PortalCount {
+        portals: usize,
+        walls: usize,
+    },
+    ColorCount {
+        colors: usize,
+        walls: usize,
+    },
+    /// Two walls cross each other.
+    SelfIntersecting {
+        walls: (usize, usize),
+    },
+    MissingPortalTarget {
+        wall: usize,
+        target: SectorId,
+    },
+    /// Portal whose target has no wall along the same edge leading back.
+    OneWayPortal {
+        wall: usize,
+        target: SectorId,
+    },
+    /// Corner at vertex turns the wrong way, so walls of the sector can hide each
+    /// other. Only a warning, see [`SectorProblem::is_warning`].
+    Concave {
+        vertex: usize,
+    },
+}
+
+impl SectorProblem {
+    /// Whether the sector still works, if not as intended.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Self::Concave { .. })
+    }
+}
+
+impl fmt::Display for SectorProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Degenerate => write!(f, "has no area"),
+            Self::CounterClockwise => write!(f, "vertices wind counter-clockwise"),
+            Self::PortalCount { portals, walls } => {
+                write!(f, "{portals} portal(s) for {walls} wall(s)")
+            }
+            Self::ColorCount { colors, walls } => {
+                write!(f, "{colors} color(s) for {walls} wall(s)")
+            }
+            Self::SelfIntersecting { walls: (a, b) } => write!(f, "walls {a} and {b} cross"),
+            Self::MissingPortalTarget { wall, target } => {
+                write!(f, "wall {wall}: portal to missing sector {}", target.0)
+            }
+            Self::OneWayPortal { wall, target } => write!(
+                f,
+                "wall {wall}: portal to sector {} doesn't lead back",
+                target.0
+            ),
+            Self::Concave { vertex } => write!(f, "concave at vertex {vertex}"),
+        }
+    }
+}
+
+/// Problem with a sector, displayed with the sector it's in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SectorFinding {
+    pub sector: SectorId,
+    pub problem: SectorProblem,
+}
+
+impl fmt::Display for SectorFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sector {}: {}", self.sector.0, self.problem)
+    }
+}
+
+/// Check the winding, wall counts, shape and portals of every sector.
+pub fn sector_findings(sectors: &[&Sector]) -> Vec<SectorFinding> {
+    sectors
+        .iter()
+        .flat_map(|sector| {
+            check_sector(sectors, sector)
+                .into_iter()
+                .map(|problem| SectorFinding {
+                    sector: sector.id,
+                    problem,
+                })
+        })
+        .collect()
+}
+
+/// Check sector on its own and against the sectors its portals lead to.
+pub fn check_sector(sectors: &[&Sector], sector: &Sector) -> Vec<SectorProblem> {
+    let mut problems = Vec::new();
+    let vertices: Vec<Vec2> = sector.vertices.iter().map(|v| v.0).collect();
+    let walls = vertices.len();
+
+    let area: f32 = (0..walls)
+        .map(|i| vertices[i].perp_dot(vertices[(i + 1) % walls]))
+        .sum::<f32>()
+        / 2.0;
+    if walls < 3 || area.abs() < SPLIT_EPSILON {
+        problems.push(SectorProblem::Degenerate);
+        return problems;
+    }
+    if area > 0.0 {
+        problems.push(SectorProblem::CounterClockwise);
+    }
+
+    if sector.portal_sectors.len() != walls {
+        problems.push(SectorProblem::PortalCount {
+            portals: sector.portal_sectors.len(),
+            walls,
+        });
+    }
+    if sector.colors.len() != walls {
+        problems.push(SectorProblem::ColorCount {
+            colors: sector.colors.len(),
+            walls,
+        });
+    }
+
+    // Walls either side of each other share a vertex, so only others can cross
+    let edge = |i: usize| (vertices[i], vertices[(i + 1) % walls]);
+    for a in 0..walls {
+        for b in a + 2..walls {
+            if a == 0 && b == walls - 1 {
+                continue;
+            }
+            if segments_cross(edge(a), edge(b)) {
+                problems.push(SectorProblem::SelfIntersecting { walls: (a, b) });
+            }
+        }
+    }
+
+    for (wall, target) in sector.portal_sectors.iter().take(walls).enumerate() {
+        let Some(target) = *target else { continue };
+        let Some(other) = sectors.iter().find(|s| s.id == target) else {
+            problems.push(SectorProblem::MissingPortalTarget { wall, target });
+            continue;
+        };
+        let (left, right) = edge(wall);
+        let same = |p: Vec2, q: Vec2| p.distance(q) < SPLIT_EPSILON;
+        let leads_back = other.to_walls().iter().any(|w| {
+            w.portal_sector == Some(sector.id) && same(w.left.0, right) && same(w.right.0, left)
+        });
+        if !leads_back {
+            problems.push(SectorProblem::OneWayPortal { wall, target });
+        }
+    }
+
+    // Corners turning against the winding, collinear ones left by splits aside
+    for vertex in 0..walls {
+        let before = vertices[(vertex + walls - 1) % walls];
+        let (at, after) = edge(vertex);
+        let turn = (at - before).perp_dot(after - at);
+        if turn * area.signum() < -SPLIT_EPSILON {
+            problems.push(SectorProblem::Concave { vertex });
+        }
+    }
+
+    problems
+}
+
+/// Whether segments a and b cross at a point inside both.
+fn segments_cross((a1, a2): (Vec2, Vec2), (b1, b2): (Vec2, Vec2)) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(b1, b2, a1), side(b1, b2, a2));
+    let (d3, d4) = (side(a1, a2, b1), side(a1, a2, b2));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Why a thing doesn't fit where it's placed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClearanceProblem {
+    OutsideSectors,
+    /// Sector is lower than the thing is tall.
+    TooLow {
+        sector: SectorId,
+        opening: f32,
+    },
+    /// Thing overlaps a wall it can't pass through.
+    WallOverlap {
+        sector: SectorId,
+        wall: usize,
+    },
+}
+
+impl fmt::Display for ClearanceProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutsideSectors => write!(f, "outside of every sector"),
+            Self::TooLow { sector, opening } => {
+                write!(f, "sector {} too low ({:.2})", sector.0, opening)
+            }
+            Self::WallOverlap { sector, wall } => {
+                write!(f, "overlaps sector {} wall {}", sector.0, wall)
+            }
+        }
+    }
+}
+
+/// Thing placed somewhere it doesn't fit, actors shouldn't be spawned for it.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Embedded(pub ClearanceProblem);
+
+/// Check thing fits between floor and ceiling of the sector it's in without
+/// overlapping any wall it couldn't pass through, returning that sector.
+pub fn check_clearance(sectors: &[&Sector], thing: &Thing) -> Result<SectorId, ClearanceProblem> {
+    let Some(sector) = sectors.iter().find(|s| s.contains(thing.position)) else { return Err(ClearanceProblem::OutsideSectors) };
+
+    let opening = sector.ceil.0 - sector.floor.0;
+    if opening < thing.height.0 {
+        return Err(ClearanceProblem::TooLow {
+            sector: sector.id,
+            opening,
+        });
+    }
+
+    for other in sectors {
+        for (wall_index, wall) in other.to_walls().into_iter().enumerate() {
+            // Portals block unless the thing fits through from where it stands
+            let fits_through = other.portal_sectors[wall_index]
+                .and_then(|target| sectors.iter().find(|s| s.id == target))
+                .map(|target| {
+                    let floor = sector.floor.0.max(other.floor.0).max(target.floor.0);
+                    let ceil = other.ceil.0.min(target.ceil.0);
+                    ceil - floor >= thing.height.0
+                })
+                .unwrap_or(false);
+            if fits_through {
+                continue;
+            }
+
+            let distance = point_segment_distance(thing.position.0, wall.left.0, wall.right.0);
+            if distance < thing.radius.0 {
+                return Err(ClearanceProblem::WallOverlap {
+                    sector: other.id,
+                    wall: wall_index,
+                });
+            }
+        }
+    }
+
+    Ok(sector.id)
+}
+
+/// Mark things that don't fit where they're placed as `Embedded`, warning about
+/// them. Things in maps as they load are reported by `map_load_system` instead.
+pub fn thing_clearance_system(
+    mut commands: Commands,
+    map_load: Res<MapLoad>,
+    thing_query: Query<(Entity, &Thing, Option<&Embedded>), Changed<Thing>>,
+    sector_query: Query<&Sector>,
+) {
+    if thing_query.is_empty() {
+        return;
+    }
+
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    for (entity, thing, embedded) in &thing_query {
+        match check_clearance(&sectors, thing) {
+            Ok(_) => {
+                if embedded.is_some() {
+                    commands.entity(entity).remove::<Embedded>();
+                }
+            }
+            Err(problem) => {
+                if !map_load.is_loading() {
+                    warn!("{}", ThingFinding(thing, problem));
+                }
+                commands.entity(entity).insert(Embedded(problem));
+            }
+        }
+    }
+}
+
+/// Clearance problem of a thing, displayed with where the thing is.
+pub struct ThingFinding<'a>(pub &'a Thing, pub ClearanceProblem);
+
+impl fmt::Display for ThingFinding<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let position = self.0.position.0;
+        write!(
+            f,
+            "thing at ({:.2}, {:.2}): {}",
+            position.x, position.y, self.1
+        )
+    }
+}
+
+/// Why the view would enter a map somewhere it can't see from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpawnProblem {
+    MissingSector(SectorId),
+    /// Position isn't inside the polygon of the sector declared for it.
+    OutsideSector(SectorId),
+    /// Eye height isn't between floor and ceiling of sector.
+    EyeOutsideOpening {
+        sector: SectorId,
+        floor: f32,
+        ceil: f32,
+    },
+}
+
+impl fmt::Display for SpawnProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingSector(sector) => write!(f, "sector {} doesn't exist", sector.0),
+            Self::OutsideSector(sector) => write!(f, "outside sector {}", sector.0),
+            Self::EyeOutsideOpening {
+                sector,
+                floor,
+                ceil,
+            } => write!(
+                f,
+                "eye height {EYE_HEIGHT:.2} outside sector {} ({floor:.2} to {ceil:.2})",
+                sector.0
+            ),
+        }
+    }
+}
+
+/// Player start, or the initial sector of a map without starts, the view would
+/// enter somewhere it can't see from.
+#[derive(Debug, Clone)]
+pub struct SpawnFinding {
+    /// Name of the player start, `None` for the initial sector.
+    pub start: Option<String>,
+    pub problem: SpawnProblem,
+    /// Centroid of the sector, where moving the start fixes it.
+    pub fix: Option<Position2>,
+}
+
+impl fmt::Display for SpawnFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.start {
+            Some(name) => write!(f, "player start `{name}`: {}", self.problem),
+            None => write!(f, "initial sector origin: {}", self.problem),
+        }
+    }
+}
+
+/// Check position lies inside sector id and the eye is between its floor and ceiling.
+pub fn check_spawn(
+    sectors: &[&Sector],
+    id: SectorId,
+    position: Position2,
+) -> Result<(), SpawnProblem> {
+    let Some(sector) = sectors.iter().find(|s| s.id == id) else { return Err(SpawnProblem::MissingSector(id)) };
+
+    if !sector.contains(position) {
+        return Err(SpawnProblem::OutsideSector(sector.id));
+    }
+    if EYE_HEIGHT <= sector.floor.0 || EYE_HEIGHT >= sector.ceil.0 {
+        return Err(SpawnProblem::EyeOutsideOpening {
+            sector: sector.id,
+            floor: sector.floor.0,
+            ceil: sector.ceil.0,
+        });
+    }
+    Ok(())
+}
+
+/// Check every player start, or the origin of the initial sector where the view
+/// enters maps without any.
+pub fn spawn_findings(
+    sectors: &[&Sector],
+    starts: &[&PlayerStart],
+    initial_sector: Option<&InitialSector>,
+) -> Vec<SpawnFinding> {
+    let spawns: Vec<(Option<String>, SectorId, Position2)> = if starts.is_empty() {
+        initial_sector
+            .map(|initial| (None, initial.0, Position2(Vec2::ZERO)))
+            .into_iter()
+            .collect()
+    } else {
+        starts
+            .iter()
+            .map(|start| (Some(start.name.clone()), start.sector, start.position))
+            .collect()
+    };
+
+    spawns
+        .into_iter()
+        .filter_map(|(start, sector, position)| {
+            let problem = check_spawn(sectors, sector, position).err()?;
+            // Concave sectors can have their centroid outside them
+            let fix = match problem {
+                SpawnProblem::OutsideSector(id) => sectors
+                    .iter()
+                    .find(|s| s.id == id)
+                    .map(|s| s.centroid())
+                    .filter(|&centroid| check_spawn(sectors, sector, centroid).is_ok()),
+                _ => None,
+            };
+            Some(SpawnFinding {
+                start,
+                problem,
+                fix,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn closed_door_raises_its_ceiling() {
+        let (door, room) = linked(square(0, 0.0, 0.0, 0.0), square(1, 1.0, 0.0, 4.0));
+        let closed = closed_portals(&[&door, &room], &[]);
+
+        assert_eq!(closed.len(), 1, "reported once, not from each side");
+        assert_eq!((closed[0].sector, closed[0].wall), (SectorId(0), 2));
+        let fix = closed[0].fix.expect("fixable");
+        assert_eq!(
+            fix,
+            PortalFix::Ceiling(SectorId(0), Length(PORTAL_FIX_OPENING))
+        );
+
+        assert_eq!(opening(&door, &room, Some(&fix)), PORTAL_FIX_OPENING);
+        assert_eq!(heights(&door, Some(&fix)).0, door.floor.0);
+    }
+
+    #[test]
+    fn high_floor_is_lowered() {
+        // Raising the room's ceiling would meet the ledge's own low ceiling first
+        let (ledge, room) = linked(square(0, 0.0, 4.2, 4.5), square(1, 1.0, 0.0, 4.0));
+        let closed = closed_portals(&[&ledge, &room], &[]);
+
+        assert_eq!(closed.len(), 1);
+        let fix = closed[0].fix.expect("fixable");
+        assert!(matches!(fix, PortalFix::Floor(SectorId(0), _)));
+        let opening = opening(&ledge, &room, Some(&fix));
+        assert!((opening - PORTAL_FIX_OPENING).abs() < SPLIT_EPSILON);
+    }
+
+    #[test]
+    fn no_fix_beyond_tolerance() {
+        let (ledge, room) = linked(square(0, 0.0, 8.0, 12.0), square(1, 1.0, 0.0, 4.0));
+        let closed = closed_portals(&[&ledge, &room], &[]);
+
+        assert_eq!(closed.len(), 1);
+        assert!(closed[0].fix.is_none());
+    }
+
+    #[test]
+    fn moving_sectors_are_skipped() {
+        let (door, room) = linked(square(0, 0.0, 0.0, 0.0), square(1, 1.0, 0.0, 4.0));
+        assert!(closed_portals(&[&door, &room], &[door.id]).is_empty());
+    }
+
+    #[test]
+    fn one_way_portals_are_reported() {
+        let (mut room, door) = linked(square(0, 0.0, 0.0, 4.0), square(1, 1.0, 0.0, 0.0));
+        room.portal_sectors[2] = None;
+        let closed = closed_portals(&[&room, &door], &[]);
+
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].sector, SectorId(1));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{edit::SPLIT_EPSILON, *};
+
+use bevy::ecs::query::QuerySingleError;
+
+/// Index of a vertex in the `VertexPool`.
+#[derive(Reflect, FromReflect, Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VertexId(pub u32);
+
+/// Vertices of every sector in a map, each shared by all the sector corners at it so
+/// moving one moves them together and keeps portals joined. Sectors refer to it
+/// through `Sector::vertex_ids`, and it is saved with the map on an entity of its own,
+/// as scenes can't hold resources.
+///
+/// Move vertices here rather than through `Sector::vertices`, which
+/// `vertex_pool_system` derives from the pool.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+pub struct VertexPool {
+    pub positions: Vec<Position2>,
+}
+
+impl VertexPool {
+    pub fn get(&self, id: VertexId) -> Option<Position2> {
+        self.positions.get(id.0 as usize).copied()
+    }
+
+    pub fn set(&mut self, id: VertexId, position: Position2) {
+        if let Some(p) = self.positions.get_mut(id.0 as usize) {
+            *p = position;
+        }
+    }
+
+    /// Add a vertex at position, shared with nothing yet.
+    pub fn add(&mut self, position: Position2) -> VertexId {
+        self.positions.push(position);
+        VertexId(self.positions.len() as u32 - 1)
+    }
+
+    /// Vertex at position, adding one when there is none.
+    pub fn weld(&mut self, position: Position2) -> VertexId {
+        match self.find(position) {
+            Some(id) => id,
+            None => self.add(position),
+        }
+    }
+
+    /// Every vertex at position, more than one where welds were broken.
+    pub fn find_all(&self, position: Position2) -> impl Iterator<Item = VertexId> + '_ {
+        self.positions
+            .iter()
+            .enumerate()
+            .filter(move |(_, p)| p.0.distance(position.0) < SPLIT_EPSILON)
+            .map(|(i, _)| VertexId(i as u32))
+    }
+
+    pub fn find(&self, position: Position2) -> Option<VertexId> {
+        self.find_all(position).next()
+    }
+
+    /// Position of each of ids, the origin for any not in the pool.
+    pub fn positions_of(&self, ids: &[VertexId]) -> Vec<Position2> {
+        ids.iter()
+            .map(|&id| self.get(id).unwrap_or_default())
+            .collect()
+    }
+
+    /// Weld the vertices of a sector spawned with no vertex ids into the pool.
+    pub fn weld_sector(&mut self, sector: &mut Sector) {
+        sector.vertex_ids = sector.vertices.iter().map(|&v| self.weld(v)).collect();
+    }
+
+    /// Move the vertices of sector to its pool vertices, only changing it when they
+    /// moved. Sectors not yet welded are left alone.
+    pub fn update_vertices(&self, sector: &mut Mut<Sector>) {
+        if sector.vertex_ids.is_empty() {
+            return;
+        }
+        let vertices = self.positions_of(&sector.vertex_ids);
+        let moved = !vertices
+            .iter()
+            .map(|v| v.0)
+            .eq(sector.vertices.iter().map(|v| v.0));
+        if moved {
+            sector.vertices = vertices;
+        }
+    }
+}
+
+/// Weld sectors spawned with vertices but no vertex ids into the map's pool, as
+/// generated and imported maps are, adding a pool when there is none. Otherwise move
+/// sector vertices to match the pool as it or they change. Does nothing while more
+/// than one pool is spawned.
+pub fn vertex_pool_system(
+    mut commands: Commands,
+    mut pool_query: Query<&mut VertexPool>,
+    mut sector_query: Query<&mut Sector>,
+    stable_id_query: Query<&StableId>,
+) {
+    let unwelded = |s: &Sector| s.vertex_ids.is_empty() && !s.vertices.is_empty();
+    if sector_query.iter().any(unwelded) {
+        match pool_query.get_single_mut() {
+            Ok(mut pool) => {
+                for mut sector in &mut sector_query {
+                    if unwelded(&sector) {
+                        pool.weld_sector(&mut sector);
+                    }
+                }
+            }
+            Err(QuerySingleError::NoEntities(_)) => {
+                let mut pool = VertexPool::default();
+                for mut sector in &mut sector_query {
+                    pool.weld_sector(&mut sector);
+                }
+                debug!("welded {} vertices into a new pool", pool.positions.len());
+                // Kept across visits to the map like the sectors
+                let stable_id = stable_id_query.iter().map(|s| s.0 + 1).max().unwrap_or(0);
+                commands.spawn((StableId(stable_id), pool));
+                return;
+            }
+            Err(QuerySingleError::MultipleEntities(_)) => return,
+        }
+    }
+
+    let Ok(pool) = pool_query.get_single_mut() else { return };
+    let pool_changed = pool.is_changed();
+    for mut sector in &mut sector_query {
+        if pool_changed || sector.is_changed() {
+            pool.update_vertices(&mut sector);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{interact::ray_crossing, load::MapLoadEvent, time_scale::*, *};
+
+/// Distance the hitscan weapon reaches.
+pub const HITSCAN_RANGE: f32 = 64.0;
+/// Seconds a projectile flies before it's removed without hitting anything.
+const PROJECTILE_LIFETIME: f32 = 10.0;
+
+/// Way a weapon hits what it's fired at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Weapon {
+    /// Hits the first wall, floor, ceiling or thing along a ray at once.
+    Hitscan { range: f32 },
+    /// Fires a [`Projectile`], drawn as a ball of color, exploding on the first
+    /// wall, floor, ceiling or thing it reaches.
+    Projectile {
+        /// Units per second.
+        speed: f32,
+        radius: f32,
+        color: RawColor,
+    },
+}
+
+impl Weapon {
+    pub const ALL: [Self; 2] = [
+        Self::Hitscan {
+            range: HITSCAN_RANGE,
+        },
+        Self::Projectile {
+            speed: 12.0,
+            radius: 0.15,
+            color: RawColor([0xff, 0x80, 0x20]),
+        },
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hitscan { .. } => "hitscan",
+            Self::Projectile { .. } => "projectile",
+        }
+    }
+}
+
+/// Sent to fire weapon from origin, in sector, facing direction and pitched up by
+/// pitch radians.
+#[derive(Debug, Copy, Clone)]
+pub struct FireWeapon {
+    pub weapon: Weapon,
+    pub origin: Position3,
+    pub sector: SectorId,
+    pub direction: Direction,
+    pub pitch: f32,
+    /// Thing firing, never hit by its own shots.
+    pub shooter: Option<Entity>,
+}
+
+impl FireWeapon {
+    /// Unit vector fired along.
+    pub fn aim(&self) -> Vec3 {
+        let (sin, cos) = self.direction.0.sin_cos();
+        Vec3::new(-sin, cos, self.pitch.tan()).normalize()
+    }
+}
+
+/// What a shot hit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HitTarget {
+    /// Solid wall, or the wall above or below a portal, by index within its sector.
+    Wall {
+        sector: SectorId,
+        wall: usize,
+    },
+    Floor(SectorId),
+    Ceiling(SectorId),
+    Thing(Entity),
+}
+
+/// Sent when a hitscan shot or a projectile hits something, for gameplay to apply
+/// damage or spawn decals.
+#[derive(Debug, Copy, Clone)]
+pub struct WeaponHit {
+    pub weapon: Weapon,
+    pub point: Position3,
+    pub target: HitTarget,
+    pub shooter: Option<Entity>,
+}
+
+/// Shot in flight, moved by [`projectile_system`]. Its [`Position3`] is the bottom
+/// of its ball, as for other things.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Projectile {
+    pub weapon: Weapon,
+    /// Units per second.
+    pub velocity: Vec3,
+    pub radius: f32,
+    pub shooter: Option<Entity>,
+    /// Seconds since fired.
+    pub age: f32,
+}
+
+/// First wall, floor, ceiling or thing hit by a ray from origin, in sector, along unit
+/// vector aim within range, and where. The ray follows portals through their open
+/// part, between the higher floor and lower ceiling of the sectors either side.
+/// Things stand on the floor of the sector they're in.
+pub fn hitscan(
+    sectors: &[&Sector],
+    things: &[(Entity, &Thing)],
+    sector: SectorId,
+    origin: Vec3,
+    aim: Vec3,
+    range: f32,
+) -> Option<(Position3, HitTarget)> {
+    let end = origin + aim * range;
+    let (from, to) = (origin.truncate(), end.truncate());
+    let z_at = |t: f32| origin.z + (end.z - origin.z) * t;
+    let find = |id: SectorId| sectors.iter().copied().find(|s| s.id == id);
+
+    // Walls, floors and ceilings, as the fraction of the ray travelled to reach them
+    let mut world_hit = None;
+    let (mut current, mut entered) = (sector, 0.0);
+    // Each sector passed through once at most
+    for _ in 0..sectors.len() {
+        let sector = find(current)?;
+        let exit = sector
+            .to_walls()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, wall)| Some((index, *wall, ray_crossing(from, to, wall)?)))
+            .filter(|&(_, _, t)| t > entered + f32::EPSILON)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        let leaves = exit.map_or(1.0, |(_, _, t)| t);
+
+        let surface = if z_at(leaves) < sector.floor.0 {
+            Some((sector.floor.0, HitTarget::Floor(sector.id)))
+        } else if z_at(leaves) > sector.ceil.0 {
+            Some((sector.ceil.0, HitTarget::Ceiling(sector.id)))
+        } else {
+            None
+        };
+        if let Some((height, target)) = surface {
+            // Level rays only reach a surface they start beyond
+            let rise = end.z - origin.z;
+            let t = if rise == 0.0 {
+                entered
+            } else {
+                ((height - origin.z) / rise).clamp(entered, leaves)
+            };
+            world_hit = Some((t, target));
+            break;
+        }
+
+        let Some((index, wall, t)) = exit else { break };
+        let z = z_at(t);
+        let open = wall.portal_sector.and_then(find).filter(|next| {
+            z > sector.floor.0.max(next.floor.0) && z < sector.ceil.0.min(next.ceil.0)
+        });
+        match open {
+            Some(next) => (current, entered) = (next.id, t),
+            None => {
+                let target = HitTarget::Wall {
+                    sector: sector.id,
+                    wall: index,
+                };
+                world_hit = Some((t, target));
+                break;
+            }
+        }
+    }
+
+    // Things in the way before that
+    let limit = world_hit.map_or(1.0, |(t, _)| t);
+    let thing_hit = things
+        .iter()
+        .filter_map(|&(entity, thing)| {
+            let floor = sectors.iter().find(|s| s.contains(thing.position))?.floor.0;
+            let t = ray_circle(from, to, thing.position.0, thing.radius.0)?;
+            let z = z_at(t);
+            (t <= limit && z >= floor && z <= floor + thing.height.0)
+                .then_some((t, HitTarget::Thing(entity)))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+
+    let (t, target) = thing_hit.or(world_hit)?;
+    Some((Position3(origin + (end - origin) * t), target))
+}
+
+/// Fraction of the way from from to to where the segment enters the circle at centre
+/// of radius, `None` when it misses or starts inside.
+fn ray_circle(from: Vec2, to: Vec2, centre: Vec2, radius: f32) -> Option<f32> {
+    let (ray, offset) = (to - from, from - centre);
+    let a = ray.length_squared();
+    let b = 2.0 * offset.dot(ray);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || c < 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Things that can be hit, less the shooter.
+fn targets<'a>(
+    thing_query: &'a Query<(Entity, &Thing), Without<Projectile>>,
+    shooter: Option<Entity>,
+) -> Vec<(Entity, &'a Thing)> {
+    thing_query
+        .iter()
+        .filter(|&(entity, _)| Some(entity) != shooter)
+        .collect()
+}
+
+/// Hit what hitscan weapons are fired at, and launch projectiles.
+pub fn fire_weapon_system(
+    mut commands: Commands,
+    mut fire_weapon_events: EventReader<FireWeapon>,
+    mut weapon_hit_events: EventWriter<WeaponHit>,
+    thing_query: Query<(Entity, &Thing), Without<Projectile>>,
+    sector_query: Query<&Sector>,
+) {
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    for fire in fire_weapon_events.iter() {
+        let aim = fire.aim();
+        match fire.weapon {
+            Weapon::Hitscan { range } => {
+                let things = targets(&thing_query, fire.shooter);
+                let hit = hitscan(&sectors, &things, fire.sector, fire.origin.0, aim, range);
+                if let Some((point, target)) = hit {
+                    weapon_hit_events.send(WeaponHit {
+                        weapon: fire.weapon,
+                        point,
+                        target,
+                        shooter: fire.shooter,
+                    });
+                }
+            }
+            Weapon::Projectile {
+                speed,
+                radius,
+                color,
+            } => {
+                let bottom = fire.origin.0 - Vec3::Z * radius;
+                commands.spawn((
+                    Projectile {
+                        weapon: fire.weapon,
+                        velocity: aim * speed,
+                        radius,
+                        shooter: fire.shooter,
+                        age: 0.0,
+                    },
+                    Position3(bottom),
+                    Thing {
+                        position: Position2(bottom.truncate()),
+                        radius: Length(radius),
+                        height: Length(radius * 2.0),
+                    },
+                    Billboard {
+                        color,
+                        texture: None,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+/// Fly projectiles in a straight line, exploding them on the first thing they reach
+/// within their radius. Those leaving the map, flying too long or left over from
+/// the last map are removed.
+#[allow(clippy::too_many_arguments)]
+pub fn projectile_system(
+    mut commands: Commands,
+    mut map_load_events: EventReader<MapLoadEvent>,
+    mut weapon_hit_events: EventWriter<WeaponHit>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Position3, &mut Thing)>,
+    thing_query: Query<(Entity, &Thing), Without<Projectile>>,
+    sector_query: Query<&Sector>,
+) {
+    let map_started = map_load_events
+        .iter()
+        .any(|event| matches!(event, MapLoadEvent::Started { .. }));
+    let sectors: Vec<&Sector> = sector_query.iter().collect();
+    let delta = time_scale.delta_seconds(&time);
+
+    for (entity, mut projectile, mut position, mut thing) in &mut projectile_query {
+        projectile.age += delta;
+        let centre = position.0 + Vec3::Z * projectile.radius;
+        let sector = sectors
+            .iter()
+            .find(|s| s.contains(Position2(centre.truncate())));
+        let (Some(sector), false) = (sector, map_started || projectile.age > PROJECTILE_LIFETIME)
+        else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+
+        let step = projectile.velocity * delta;
+        let distance = step.length();
+        if distance == 0.0 {
+            continue;
+        }
+        let aim = step / distance;
+        let things = targets(&thing_query, projectile.shooter);
+        let reach = distance + projectile.radius;
+        match hitscan(&sectors, &things, sector.id, centre, aim, reach) {
+            Some((point, target)) => {
+                weapon_hit_events.send(WeaponHit {
+                    weapon: projectile.weapon,
+                    point,
+                    target,
+                    shooter: projectile.shooter,
+                });
+                commands.entity(entity).despawn();
+            }
+            None => {
+                position.0 += step;
+                thing.position = position.truncate();
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Frames drawn headless compared against golden images in `tests/golden`, each with
+//! breadth-first and depth-first portal traversal, which must draw the same frame.
+//! `just golden` runs them, and `UPDATE_GOLDEN=1 just golden` writes the images
+//! again after an intended change to how frames look. Frames that differ are saved
+//! in `target/golden` to compare.
+
+use sector::{generate::*, hub::*, load::*, prelude::*, render::*};
+
+use bevy::{asset::LoadState, prelude::*};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+const GOLDEN_DIR: &str = "tests/golden";
+const ACTUAL_DIR: &str = "target/golden";
+const TRAVERSALS: [PortalTraversal; 2] =
+    [PortalTraversal::BreadthFirst, PortalTraversal::DepthFirst];
+
+/// Headless app drawing into an [`ImageFrame`], with the simulation paused so nothing
+/// moves between frames.
+fn app(traversal: PortalTraversal) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(bevy::scene::ScenePlugin)
+        .add_plugin(SectorPlugin)
+        .add_plugin(SectorRenderPlugin::<ImageFrame>::default())
+        .insert_resource(RenderConfig::new(WIDTH, HEIGHT, FOV_X_RADIANS))
+        .insert_resource(traversal);
+    app.world.resource_mut::<TimeScale>().paused = true;
+    app.world.spawn(ImageFrame::new(WIDTH, HEIGHT));
+    app
+}
+
+/// Update app until map has loaded through the load path, with its textures.
+fn load_map(app: &mut App, map: &str) {
+    app.world.resource_mut::<WorldState>().travel(map, true);
+    // Textures are asked for the update after sectors spawn
+    let mut spawned = false;
+    for _ in 0..1000 {
+        app.update();
+        let textures = app.world.resource::<TextureHandles>().0.values();
+        let textures = app
+            .world
+            .resource::<AssetServer>()
+            .get_group_load_state(textures.map(|handle| handle.id()));
+        assert_ne!(
+            textures,
+            LoadState::Failed,
+            "failed to load textures of `{map}`"
+        );
+        if spawned && textures == LoadState::Loaded {
+            return;
+        }
+        spawned = !app.world.resource::<MapLoad>().is_loading();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    panic!("timed out loading `{map}`");
+}
+
+/// Frame drawn from the view as it is.
+fn draw(app: &mut App) -> Vec<u8> {
+    // Sectors just spawned are welded into the pool, then the camera follows the
+    // view, then walls are drawn from it
+    for _ in 0..3 {
+        app.update();
+    }
+    app.world
+        .query::<&ImageFrame>()
+        .single(&app.world)
+        .data
+        .clone()
+}
+
+/// Check frame matches the golden image name, or write it when updating them.
+fn assert_golden(name: &str, frame: &[u8]) {
+    let golden = format!("{GOLDEN_DIR}/{name}.png");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        image::save_buffer(&golden, frame, WIDTH, HEIGHT, image::ColorType::Rgba8).unwrap();
+        return;
+    }
+
+    let expected = image::open(&golden)
+        .unwrap_or_else(|error| panic!("failed to open `{golden}`: {error}"))
+        .to_rgba8();
+    let differing = expected
+        .as_raw()
+        .chunks(4)
+        .zip(frame.chunks(4))
+        .filter(|(a, b)| a != b)
+        .count();
+    if differing > 0 || expected.as_raw().len() != frame.len() {
+        std::fs::create_dir_all(ACTUAL_DIR).unwrap();
+        let actual = format!("{ACTUAL_DIR}/{name}.png");
+        image::save_buffer(&actual, frame, WIDTH, HEIGHT, image::ColorType::Rgba8).unwrap();
+        panic!("{differing} pixel(s) differ from `{golden}`, drawn `{actual}`");
+    }
+}
+
+#[test]
+fn default_map_poses() {
+    for traversal in TRAVERSALS {
+        let mut app = app(traversal);
+        load_map(&mut app, DEFAULT_SCENE_RON_FILE_PATH);
+
+        let mut poses: Vec<CameraPose> = app
+            .world
+            .query::<&CameraPose>()
+            .iter(&app.world)
+            .cloned()
+            .collect();
+        assert!(!poses.is_empty(), "default map has no camera poses");
+        poses.sort_by(|a, b| a.name.cmp(&b.name));
+        for pose in poses {
+            app.world.resource_mut::<View>().set_pose(&pose);
+            let frame = draw(&mut app);
+            assert_golden(&format!("default-{}", pose.name), &frame);
+        }
+    }
+}
+
+#[test]
+fn maze() {
+    for traversal in TRAVERSALS {
+        let mut app = app(traversal);
+        // Looking across the maze from the middle of a corner room, where the
+        // breadth-first order reaches far rooms before side rooms
+        let sectors = generate_maze(7, 32, 32);
+        let first = &sectors[0];
+        let centre = first.vertices.iter().map(|v| v.0).sum::<Vec2>() / first.vertices.len() as f32;
+        *app.world.resource_mut::<View>() = View {
+            position: Position3(centre.extend(first.floor.0 + EYE_HEIGHT)),
+            direction: Direction(-std::f32::consts::FRAC_PI_4),
+            pitch: 0.0,
+            current_sector: Some(first.id),
+        };
+        for sector in sectors {
+            app.world.spawn(sector);
+        }
+        let frame = draw(&mut app);
+        assert_golden("maze", &frame);
+    }
+}